@@ -2,12 +2,13 @@
 
 #[cfg(feature = "discovery")]
 use crate::discovery::mdns::get_ip_and_port_by_mdns;
-use async_std::net::TcpStream;
 use chrono::{DateTime, Utc};
 use pigdef::config::HardwareConfig;
 use pigdef::config::HardwareConfigMessage::GetConfig;
 use pigdef::description::{HardwareDescription, SerialNumber};
+use pignet::credentials::Credentials;
 use pignet::tcp_host;
+use pignet::tcp_host::TcpConnection;
 use serial_test::serial;
 use std::future::Future;
 use std::net::IpAddr;
@@ -15,10 +16,12 @@ use std::time::Duration;
 
 async fn connect_tcp<F, Fut>(serial: &SerialNumber, ip: &IpAddr, port: u16, test: F)
 where
-    F: FnOnce(HardwareDescription, HardwareConfig, TcpStream) -> Fut,
+    F: FnOnce(HardwareDescription, HardwareConfig, TcpConnection) -> Fut,
     Fut: Future<Output = ()>,
 {
-    match tcp_host::connect(*ip, port).await {
+    // Devices that require authentication must have been paired with before, e.g. with piggcli
+    let mut credentials = Credentials::load_default().expect("Could not load TCP credentials");
+    match tcp_host::connect(*ip, port, &mut credentials).await {
         Ok((hw_desc, hw_config, tcp_stream)) => {
             assert!(
                 hw_desc.details.model.contains("Pi"),
@@ -18,8 +18,9 @@ where
     F: FnOnce(HardwareDescription, HardwareConfig, Connection) -> Fut,
     Fut: Future<Output = ()>,
 {
-    match iroh_host::connect(endpoint_id, relay_url).await {
-        Ok((hw_desc, hw_config, connection, _endpoint)) => {
+    let secret_key = iroh_host::load_default_client_key().expect("Could not load client key");
+    match iroh_host::connect(endpoint_id, relay_url, secret_key).await {
+        Ok((hw_desc, hw_config, connection, _endpoint, _capabilities)) => {
             assert!(
                 hw_desc.details.model.contains("Pi"),
                 "Didn't connect to fake hardware pigglet"
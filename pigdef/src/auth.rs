@@ -0,0 +1,391 @@
+//! Authentication of clients connecting to a device (pigglet or porky) over TCP.
+//!
+//! Each device has a random [AuthKey] that is shared with the clients paired with it. When a
+//! client connects, after the [Hello][crate::hello::Hello] the device sends a [Challenge] with
+//! a fresh [Nonce], and the client replies with an [AuthResponse]:
+//!    * [AuthResponse::Key] - proof it has the key, from pairing with the device before
+//!    * [AuthResponse::Pair] - proof it knows the one-time [PairingCode] the device shows
+//!
+//! The device replies with an [AuthResult]. When pairing, that carries the device's key sealed
+//! with the pairing code, so only a client that knows the code can open it and save it for the
+//! next connection. A client that is rejected is disconnected.
+//!
+//! All proofs are HMAC-SHA256 tags, and nothing secret is ever sent in the clear.
+//!
+//! # Threat model
+//!
+//! This protects a device from clients on the network that have not been paired with it, by a
+//! user who could see the pairing code it shows:
+//!    * A client that is not paired can only guess the current code, one connection at a time.
+//!      With 60 random bits, and a new code after each pairing, that will not succeed.
+//!    * Someone who records a connection of a paired client learns nothing about the key, and
+//!      cannot replay the proof, as each challenge has a fresh nonce.
+//!    * Someone who records a pairing can try codes offline, against the proof and the sealed
+//!      key. To make that too slow to succeed, the [PairingSecret] that proofs are made and keys
+//!      sealed with is derived from the code using PBKDF2-HMAC-SHA256 with [PAIRING_ROUNDS],
+//!      salted with the serial number of the device, so trying every code costs about 2^74
+//!      HMACs for each device.
+//!
+//! It does not protect what is sent once a client is authenticated. Over plain TCP, someone on
+//! the network can read the messages, or change them, so pigglet also accepts connections over
+//! TLS, that encrypts them. A user who pairs over TLS also keeps the pairing out of sight.
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+#[cfg(not(feature = "std"))]
+use core::clone::Clone;
+#[cfg(not(feature = "std"))]
+use core::cmp::PartialEq;
+#[cfg(not(feature = "std"))]
+use core::marker::Copy;
+#[cfg(not(feature = "std"))]
+use core::option::Option;
+#[cfg(not(feature = "std"))]
+use core::prelude::rust_2024::derive;
+
+/// The secret a device shares with the clients paired with it
+pub type AuthKey = [u8; 32];
+
+/// A random value used once, so a proof cannot be replayed on another connection
+pub type Nonce = [u8; 16];
+
+/// An HMAC-SHA256 tag proving knowledge of a key or [PairingCode]
+pub type Tag = [u8; 32];
+
+/// The secret derived from a [PairingCode] with [PairingCode::secret], that pairing proofs are
+/// made and keys sealed with
+pub type PairingSecret = [u8; 32];
+
+/// The number of PBKDF2 rounds used to derive a [PairingSecret]. Each one is an HMAC-SHA256,
+/// so this makes trying codes against a recorded pairing that many times slower, while taking
+/// no more than a second or so on a microcontroller
+pub const PAIRING_ROUNDS: u32 = 16_384;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Labels that make each kind of proof different, even when made with the same key and nonces
+const KEY_LABEL: &[u8] = b"pigg-auth";
+const PAIR_LABEL: &[u8] = b"pigg-pair";
+const SEAL_LABEL: &[u8] = b"pigg-seal";
+
+/// The characters a [PairingCode] is written with - Crockford's base32, that has no letters
+/// that are easily confused with digits
+const CODE_ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// The number of characters in a [PairingCode]
+pub const PAIRING_CODE_LENGTH: usize = 12;
+
+/// A one-time code shown by a device, that a user enters in a client to pair it with the device.
+/// It is 12 characters of [CODE_ALPHABET], holding 60 random bits
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct PairingCode([u8; PAIRING_CODE_LENGTH]);
+
+impl PairingCode {
+    /// Create a [PairingCode] from `random` bytes, which should come from a secure source
+    pub fn new(random: [u8; 8]) -> Self {
+        let mut bits = u64::from_le_bytes(random);
+        let mut code = [0; PAIRING_CODE_LENGTH];
+        for c in code.iter_mut() {
+            *c = CODE_ALPHABET[(bits & 0x1f) as usize];
+            bits >>= 5;
+        }
+        PairingCode(code)
+    }
+
+    /// Parse a [PairingCode] typed by a user. Case, spaces and dashes are ignored, and the
+    /// letters 'O', 'I' and 'L' are read as the digits they look like
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut code = [0; PAIRING_CODE_LENGTH];
+        let mut length = 0;
+        for c in text.bytes() {
+            let c = match c.to_ascii_uppercase() {
+                b'-' | b' ' | b'\t' => continue,
+                b'O' => b'0',
+                b'I' | b'L' => b'1',
+                c if CODE_ALPHABET.contains(&c) => c,
+                _ => return None,
+            };
+            *code.get_mut(length)? = c;
+            length += 1;
+        }
+        (length == PAIRING_CODE_LENGTH).then_some(PairingCode(code))
+    }
+
+    /// The code as a string, without separators
+    pub fn as_str(&self) -> &str {
+        // Only ever contains characters of CODE_ALPHABET
+        core::str::from_utf8(&self.0).unwrap_or_default()
+    }
+
+    /// Derive the [PairingSecret] of this code, for the device with `serial`. This is slow on
+    /// purpose, so a device should derive it once for each code it shows
+    pub fn secret(&self, serial: &str) -> PairingSecret {
+        pbkdf2::pbkdf2_hmac_array::<Sha256, 32>(&self.0, serial.as_bytes(), PAIRING_ROUNDS)
+    }
+}
+
+#[cfg(feature = "std")]
+/// Shown in groups of four characters, to make it easier to read and type
+impl std::fmt::Display for PairingCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let code = self.as_str();
+        write!(f, "{}-{}-{}", &code[0..4], &code[4..8], &code[8..12])
+    }
+}
+
+/// Sent by a device after the [Hello][crate::hello::Hello], when it requires authentication
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Challenge {
+    /// The serial number of the device, that clients save its key under
+    pub serial: String,
+    pub nonce: Nonce,
+}
+
+/// Sent by a device after the [Hello][crate::hello::Hello], when it requires authentication
+#[cfg(not(feature = "std"))]
+#[derive(Clone, PartialEq, Serialize)]
+pub struct Challenge<'a> {
+    /// The serial number of the device, that clients save its key under
+    pub serial: &'a str,
+    pub nonce: Nonce,
+}
+
+/// A client's reply to a [Challenge]
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum AuthResponse {
+    /// Proof the client has the key of the device, made with [key_proof]
+    Key(Tag),
+    /// A nonce chosen by the client, and proof it knows the pairing code, made with [pair_proof]
+    Pair(Nonce, Tag),
+}
+
+/// A device's reply to an [AuthResponse]
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum AuthResult {
+    /// The client proved it has the key of the device
+    Accepted,
+    /// The client proved it knows the pairing code, and this is the key of the device sealed
+    /// with [seal_key], for the client to open with [open_key]
+    Paired(AuthKey),
+    /// The client could not prove it has the key or knows the pairing code, and will be
+    /// disconnected
+    Rejected,
+}
+
+/// Compute the HMAC-SHA256 of the concatenation of `parts` with `key`
+fn mac(key: &[u8], parts: &[&[u8]]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key)
+        .unwrap_or_else(|_| unreachable!("HMAC accepts keys of any length"));
+    for part in parts {
+        mac.update(part);
+    }
+    mac
+}
+
+/// Compute the tag of `parts` with `key`
+fn tag(key: &[u8], parts: &[&[u8]]) -> Tag {
+    mac(key, parts).finalize().into_bytes().into()
+}
+
+/// Check `tag` is that of `parts` with `key`, taking the same time whatever the tag is
+fn verify(key: &[u8], parts: &[&[u8]], tag: &Tag) -> bool {
+    mac(key, parts).verify_slice(tag).is_ok()
+}
+
+/// Proof of having `key`, for the challenge with `nonce`
+pub fn key_proof(key: &AuthKey, nonce: &Nonce) -> Tag {
+    tag(key, &[KEY_LABEL, nonce])
+}
+
+/// Proof of knowing the code that `secret` was derived from, for the challenge with
+/// `device_nonce`, with a `client_nonce` chosen by the client
+pub fn pair_proof(secret: &PairingSecret, device_nonce: &Nonce, client_nonce: &Nonce) -> Tag {
+    tag(secret, &[PAIR_LABEL, device_nonce, client_nonce])
+}
+
+/// Seal `key` with `secret` so it can be sent to a client pairing with the nonces given, and
+/// only opened by one that knows the code it was derived from
+pub fn seal_key(
+    key: &AuthKey,
+    secret: &PairingSecret,
+    device_nonce: &Nonce,
+    client_nonce: &Nonce,
+) -> AuthKey {
+    let mask = tag(secret, &[SEAL_LABEL, device_nonce, client_nonce]);
+    let mut sealed = *key;
+    for (byte, mask) in sealed.iter_mut().zip(mask) {
+        *byte ^= mask;
+    }
+    sealed
+}
+
+/// Open a key sealed by [seal_key] with the same `secret` and nonces
+pub fn open_key(
+    sealed: &AuthKey,
+    secret: &PairingSecret,
+    device_nonce: &Nonce,
+    client_nonce: &Nonce,
+) -> AuthKey {
+    // Sealing is an XOR with a mask that depends only on the secret and nonces, so it is undone
+    // by sealing again
+    seal_key(sealed, secret, device_nonce, client_nonce)
+}
+
+/// Check the `response` of a client to the challenge with `nonce`, made by a device with `key`
+/// that is showing the code `secret` was derived from. The device should show a new code after
+/// a client has [AuthResult::Paired] with it, so each code can only be used once
+pub fn check(
+    response: &AuthResponse,
+    key: &AuthKey,
+    secret: &PairingSecret,
+    nonce: &Nonce,
+) -> AuthResult {
+    match response {
+        AuthResponse::Key(proof) => {
+            if verify(key, &[KEY_LABEL, nonce], proof) {
+                AuthResult::Accepted
+            } else {
+                AuthResult::Rejected
+            }
+        }
+        AuthResponse::Pair(client_nonce, proof) => {
+            if verify(secret, &[PAIR_LABEL, nonce, client_nonce], proof) {
+                AuthResult::Paired(seal_key(key, secret, nonce, client_nonce))
+            } else {
+                AuthResult::Rejected
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+#[allow(clippy::expect_used)]
+mod test {
+    use super::*;
+
+    const KEY: AuthKey = [7; 32];
+    const DEVICE_NONCE: Nonce = [1; 16];
+    const CLIENT_NONCE: Nonce = [2; 16];
+    const SERIAL: &str = "1234";
+
+    fn code() -> PairingCode {
+        PairingCode::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0])
+    }
+
+    fn secret() -> PairingSecret {
+        code().secret(SERIAL)
+    }
+
+    #[test]
+    fn key_accepted() {
+        let response = AuthResponse::Key(key_proof(&KEY, &DEVICE_NONCE));
+        assert_eq!(
+            check(&response, &KEY, &secret(), &DEVICE_NONCE),
+            AuthResult::Accepted
+        );
+    }
+
+    #[test]
+    fn wrong_key_rejected() {
+        let response = AuthResponse::Key(key_proof(&[8; 32], &DEVICE_NONCE));
+        assert_eq!(
+            check(&response, &KEY, &secret(), &DEVICE_NONCE),
+            AuthResult::Rejected
+        );
+    }
+
+    #[test]
+    fn replayed_key_proof_rejected() {
+        let response = AuthResponse::Key(key_proof(&KEY, &DEVICE_NONCE));
+        assert_eq!(
+            check(&response, &KEY, &secret(), &[3; 16]),
+            AuthResult::Rejected
+        );
+    }
+
+    #[test]
+    fn pairing_sends_key() {
+        let secret = secret();
+        let response = AuthResponse::Pair(
+            CLIENT_NONCE,
+            pair_proof(&secret, &DEVICE_NONCE, &CLIENT_NONCE),
+        );
+        let AuthResult::Paired(sealed) = check(&response, &KEY, &secret, &DEVICE_NONCE) else {
+            panic!("Pairing failed");
+        };
+        assert_ne!(sealed, KEY);
+        assert_eq!(
+            open_key(&sealed, &secret, &DEVICE_NONCE, &CLIENT_NONCE),
+            KEY
+        );
+    }
+
+    #[test]
+    fn wrong_code_rejected() {
+        let wrong_secret = PairingCode::new([0; 8]).secret(SERIAL);
+        let response = AuthResponse::Pair(
+            CLIENT_NONCE,
+            pair_proof(&wrong_secret, &DEVICE_NONCE, &CLIENT_NONCE),
+        );
+        assert_eq!(
+            check(&response, &KEY, &secret(), &DEVICE_NONCE),
+            AuthResult::Rejected
+        );
+    }
+
+    #[test]
+    fn code_for_other_device_rejected() {
+        let response = AuthResponse::Pair(
+            CLIENT_NONCE,
+            pair_proof(&code().secret("5678"), &DEVICE_NONCE, &CLIENT_NONCE),
+        );
+        assert_eq!(
+            check(&response, &KEY, &secret(), &DEVICE_NONCE),
+            AuthResult::Rejected
+        );
+    }
+
+    #[test]
+    fn code_uses_alphabet() {
+        let code = code();
+        assert_eq!(code.as_str().len(), PAIRING_CODE_LENGTH);
+        assert!(code.as_str().bytes().all(|c| CODE_ALPHABET.contains(&c)));
+    }
+
+    #[test]
+    fn parse_code() {
+        let code = code();
+        assert_eq!(PairingCode::parse(code.as_str()), Some(code));
+        assert_eq!(PairingCode::parse(&code.to_string()), Some(code));
+        assert_eq!(
+            PairingCode::parse(&code.to_string().to_lowercase()),
+            Some(code)
+        );
+    }
+
+    #[test]
+    fn parse_confusable_characters() {
+        assert_eq!(
+            PairingCode::parse("o1lI-0000-0000"),
+            PairingCode::parse("0111-0000-0000")
+        );
+    }
+
+    #[test]
+    fn parse_invalid_codes() {
+        assert_eq!(PairingCode::parse("0000-0000-000"), None);
+        assert_eq!(PairingCode::parse("0000-0000-00000"), None);
+        assert_eq!(PairingCode::parse("0000-0000-000U"), None);
+    }
+
+    #[test]
+    fn display_code() {
+        let code = PairingCode::parse("ABCDEFGHJKMN").expect("Could not parse code");
+        assert_eq!(code.to_string(), "ABCD-EFGH-JKMN");
+    }
+}
@@ -1,5 +1,9 @@
-use crate::description::{BCMPinNumber, PinLevel};
+use crate::description::{AnalogValue, BCMPinNumber, PinLevel};
+use crate::i2c::{I2cRequest, I2cResponse};
 use crate::pin_function::PinFunction;
+use crate::sequence::{Schedule, Sequence, SequenceId, SequenceState};
+use crate::spi::{SpiRequest, SpiResponse};
+use crate::uart::UartBytes;
 use serde::{Deserialize, Serialize};
 
 #[cfg(not(feature = "std"))]
@@ -26,15 +30,24 @@ use std::collections::HashMap;
 #[cfg(feature = "std")]
 use std::time::Duration;
 
-/// [HardwareConfig] captures the current configuration of programmable GPIO pins
-#[cfg_attr(feature = "std", derive(Debug))]
-#[cfg_attr(all(debug_assertions, feature = "std"), derive(PartialEq))]
+/// [HardwareConfig] captures the current configuration of programmable GPIO pins, and the
+/// level each output should be driven to when the client controlling it goes away
+#[cfg_attr(feature = "std", derive(Debug, PartialEq))]
 #[derive(Clone, Serialize, Deserialize, Default)] // jonesy:allow(overflow)
 pub struct HardwareConfig {
     #[cfg(feature = "std")]
     pub pin_functions: HashMap<BCMPinNumber, PinFunction>,
     #[cfg(not(feature = "std"))]
     pub pin_functions: FnvIndexMap<BCMPinNumber, PinFunction, 32>,
+    /// The safe level of outputs, applied when a client disconnects or the device shuts down.
+    /// Those of pins not configured as outputs are ignored. Config files saved before safe
+    /// levels were added have none.
+    #[cfg(feature = "std")]
+    #[serde(default)]
+    pub safe_levels: HashMap<BCMPinNumber, PinLevel>,
+    #[cfg(not(feature = "std"))]
+    #[serde(default)]
+    pub safe_levels: FnvIndexMap<BCMPinNumber, PinLevel, 32>,
 }
 
 #[cfg(feature = "std")]
@@ -45,7 +58,13 @@ impl std::fmt::Display for HardwareConfig {
         } else {
             writeln!(f, "Configured Pins:")?;
             for (bcm_pin_number, pin_function) in &self.pin_functions {
-                writeln!(f, "\tBCM Pin #: {bcm_pin_number} - {pin_function}")?;
+                match self.safe_levels.get(bcm_pin_number) {
+                    Some(level) => writeln!(
+                        f,
+                        "\tBCM Pin #: {bcm_pin_number} - {pin_function} (safe level: {level})"
+                    )?,
+                    None => writeln!(f, "\tBCM Pin #: {bcm_pin_number} - {pin_function}")?,
+                }
             }
             Ok(())
         }
@@ -57,6 +76,24 @@ impl std::fmt::Display for HardwareConfig {
 ///    * NewConfig
 ///    * NewPinConfig
 ///    * OutputLevelChanged
+///    * SafeLevelChanged
+///    * DutyCycleChanged
+///    * AnalogSampled
+///    * I2cRequest
+///    * I2cResponse
+///    * SpiRequest
+///    * SpiResponse
+///    * UartSend
+///    * UartReceived
+///    * StartSequence
+///    * StartSchedule
+///    * StopSequence
+///    * GetSequenceStatus
+///    * SequenceStatus
+///    * GetClientCounts
+///    * ClientCounts
+///    * Ping
+///    * Pong
 #[cfg_attr(feature = "std", derive(Debug))]
 #[derive(Clone, Serialize, Deserialize)]
 #[allow(clippy::large_enum_variant)]
@@ -68,10 +105,76 @@ pub enum HardwareConfigMessage {
     NewPinConfig(BCMPinNumber, Option<PinFunction>),
     /// The level of a pin has changed
     IOLevelChanged(BCMPinNumber, LevelChange),
+    /// The safe level of an output has been set, or cleared if `None`
+    SafeLevelChanged(BCMPinNumber, Option<PinLevel>),
     /// A request for the device to send back the hardware config
     GetConfig,
     /// A message sent from the GUI to the device to ask it to disconnect, as GUI will disconnect
     Disconnect,
+    /// The duty cycle (from 0.0 to 1.0) of a PWM output has changed
+    DutyCycleChanged(BCMPinNumber, f64),
+    /// A new sample of the value of an analog input has been taken
+    AnalogSampled(BCMPinNumber, AnalogSample),
+    /// A request from a client to the devices on the I2C bus
+    I2cRequest(I2cRequest),
+    /// The response to an [I2cRequest], sent only to the client that made the request
+    I2cResponse(I2cResponse),
+    /// A request from a client to the device selected on the SPI bus
+    SpiRequest(SpiRequest),
+    /// The response to an [SpiRequest], sent only to the client that made the request
+    SpiResponse(SpiResponse),
+    /// Bytes from a client to be transmitted by the UART
+    UartSend(UartBytes),
+    /// Bytes received by the UART, sent to all clients
+    UartReceived(UartBytes),
+    /// Start running a [Sequence] on the device, replacing any with the same id
+    StartSequence(SequenceId, Sequence),
+    /// Start running a [Schedule] on the device, replacing any with the same id
+    StartSchedule(SequenceId, Schedule),
+    /// Stop a running [Sequence] or [Schedule]
+    StopSequence(SequenceId),
+    /// A request for the device to send back the [SequenceState] of a sequence or schedule
+    GetSequenceStatus(SequenceId),
+    /// The [SequenceState] of a sequence or schedule, sent to all clients when it changes and to
+    /// the client that requested it
+    SequenceStatus(SequenceId, SequenceState),
+    /// A request for the device to send back the number of clients connected to it
+    GetClientCounts,
+    /// The [ClientCounts] of the device, sent only to the client that requested it
+    ClientCounts(ClientCounts),
+    /// A heartbeat sent by a client, with a sequence number, to check the link is still alive
+    Ping(u32),
+    /// The response to a [HardwareConfigMessage::Ping], with the same sequence number, sent only
+    /// to the client that sent it
+    Pong(u32),
+}
+
+/// The interval, in milliseconds, at which clients send a [HardwareConfigMessage::Ping] to
+/// devices that advertise [Capabilities::HEARTBEAT][crate::hello::Capabilities::HEARTBEAT]
+pub const HEARTBEAT_INTERVAL_MS: u64 = 2000;
+
+/// The default number of heartbeats that can be missed before the link is declared dead
+pub const MISSED_HEARTBEATS: u32 = 3;
+
+/// The number of clients connected to a device over each transport
+#[cfg_attr(feature = "std", derive(Debug))]
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct ClientCounts {
+    pub tcp: u32,
+    pub iroh: u32,
+    pub websocket: u32,
+    pub mqtt: u32,
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for ClientCounts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Connected Clients:")?;
+        writeln!(f, "\tTCP: {}", self.tcp)?;
+        writeln!(f, "\tIroh: {}", self.iroh)?;
+        writeln!(f, "\tWebSocket: {}", self.websocket)?;
+        writeln!(f, "\tMQTT: {}", self.mqtt)
+    }
 }
 
 #[cfg(not(feature = "std"))]
@@ -129,6 +232,23 @@ impl std::fmt::Display for LevelChange {
     }
 }
 
+/// AnalogSample is a value read from an analog input and when it was read
+/// - `value`: [AnalogValue]
+/// - `timestamp`: [Duration]
+#[cfg_attr(feature = "std", derive(Debug))]
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AnalogSample {
+    pub value: AnalogValue,
+    pub timestamp: Duration,
+}
+
+impl AnalogSample {
+    /// Create a new AnalogSample
+    pub fn new(value: AnalogValue, timestamp: Duration) -> Self {
+        Self { value, timestamp }
+    }
+}
+
 /// An input can be configured to have an optional pull-up or pull-down or neither
 #[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)] // jonesy:allow(overflow)
 pub enum InputPull {
@@ -46,6 +46,12 @@ pub type BoardPinNumber = u8;
 /// [PinLevel] describes whether a Pin's logical level is High(true) or Low(false)
 pub type PinLevel = bool;
 
+/// [AnalogValue] is a value read from an analog input, by an ADC with up to 16 bits resolution
+pub type AnalogValue = u16;
+
+/// The largest [AnalogValue] read from a 12-bit ADC, such as the one in the Pi Pico
+pub const ANALOG_MAX: AnalogValue = 4095;
+
 #[cfg(feature = "std")]
 /// A 16 character String represents a serial number for a device
 pub type SerialNumber = String;
@@ -0,0 +1,374 @@
+//! Framing of messages sent over byte streams, such as TCP, between piggui, pigglet and porky.
+//!
+//! A byte stream does not preserve message boundaries: a single read can return several
+//! messages coalesced together, or only part of one. Each message is therefore sent as a frame
+//! consisting of the length of the postcard serialized message, encoded as an unsigned LEB128
+//! varint, followed by the serialized message itself.
+//!
+//! [encode] writes a frame into a buffer, and a [FrameDecoder] accumulates bytes read from
+//! the stream and returns complete messages as they become available.
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+#[cfg(not(feature = "std"))]
+use core::cmp::PartialEq;
+#[cfg(not(feature = "std"))]
+use core::fmt::Debug;
+#[cfg(not(feature = "std"))]
+use core::marker::Copy;
+#[cfg(not(feature = "std"))]
+use core::option::Option;
+#[cfg(not(feature = "std"))]
+use core::prelude::rust_2024::derive;
+#[cfg(not(feature = "std"))]
+use core::result::Result;
+
+#[cfg(feature = "std")]
+use std::vec::Vec;
+
+/// The size of the buffer used to receive frames, which limits the size of the largest
+/// message (including its length prefix) that can be received
+pub const FRAME_BUFFER_SIZE: usize = 4096;
+
+/// The maximum number of bytes used by the varint length prefix of a frame
+pub const MAX_HEADER_SIZE: usize = 5;
+
+/// Errors that can occur while framing or de-framing messages
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FramingError {
+    /// The message could not be serialized, or did not fit in the buffer provided
+    Serialization,
+    /// A complete frame was received, but the message in it could not be deserialized
+    Deserialization,
+    /// The length prefix of a frame was not a valid varint
+    InvalidHeader,
+    /// A frame larger than the receive buffer was announced by its length prefix
+    FrameTooLarge,
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for FramingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FramingError::Serialization => write!(f, "Could not serialize message"),
+            FramingError::Deserialization => write!(f, "Could not deserialize message"),
+            FramingError::InvalidHeader => write!(f, "Invalid frame length prefix"),
+            FramingError::FrameTooLarge => write!(f, "Frame is too large to be received"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FramingError {}
+
+/// Write `value` as an unsigned LEB128 varint into `header`, returning the number of bytes used
+fn encode_header(mut value: usize, header: &mut [u8; MAX_HEADER_SIZE]) -> usize {
+    let mut index = 0;
+    for byte in header.iter_mut() {
+        index += 1;
+        if value < 0x80 {
+            *byte = value as u8;
+            break;
+        }
+        *byte = (value as u8 & 0x7f) | 0x80;
+        value >>= 7;
+    }
+    index
+}
+
+/// Try to read an unsigned LEB128 varint from the start of `bytes`.
+/// Returns `Ok(None)` if more bytes are needed, or `Ok(Some((value, header_length)))`
+fn decode_header(bytes: &[u8]) -> Result<Option<(usize, usize)>, FramingError> {
+    let mut value: usize = 0;
+    for (index, byte) in bytes.iter().take(MAX_HEADER_SIZE).enumerate() {
+        value |= ((byte & 0x7f) as usize) << (7 * index);
+        if byte & 0x80 == 0 {
+            return Ok(Some((value, index + 1)));
+        }
+    }
+
+    if bytes.len() >= MAX_HEADER_SIZE {
+        Err(FramingError::InvalidHeader)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Serialize `message` into `buf` as a frame, returning the slice of `buf` that holds the frame
+pub fn encode<'a, T: Serialize>(message: &T, buf: &'a mut [u8]) -> Result<&'a [u8], FramingError> {
+    let body = buf
+        .get_mut(MAX_HEADER_SIZE..)
+        .ok_or(FramingError::Serialization)?;
+    let body_length = postcard::to_slice(message, body)
+        .map_err(|_| FramingError::Serialization)?
+        .len();
+
+    let mut header = [0u8; MAX_HEADER_SIZE];
+    let header_length = encode_header(body_length, &mut header);
+    let start = MAX_HEADER_SIZE - header_length;
+    buf.get_mut(start..MAX_HEADER_SIZE)
+        .ok_or(FramingError::Serialization)?
+        .copy_from_slice(
+            header
+                .get(..header_length)
+                .ok_or(FramingError::Serialization)?,
+        );
+
+    buf.get(start..MAX_HEADER_SIZE + body_length)
+        .ok_or(FramingError::Serialization)
+}
+
+#[cfg(feature = "std")]
+/// Serialize `message` into a newly allocated [Vec] as a frame
+pub fn encode_to_vec<T: Serialize>(message: &T) -> Result<Vec<u8>, FramingError> {
+    let body = postcard::to_allocvec(message).map_err(|_| FramingError::Serialization)?;
+    let mut header = [0u8; MAX_HEADER_SIZE];
+    let header_length = encode_header(body.len(), &mut header);
+    let mut frame = Vec::with_capacity(header_length + body.len());
+    frame.extend_from_slice(header.get(..header_length).unwrap_or(&[]));
+    frame.extend_from_slice(&body);
+    Ok(frame)
+}
+
+/// [FrameDecoder] accumulates bytes received from a stream and returns the messages in it
+/// once the complete frame for each one has been received.
+///
+/// Bytes should be read directly into the slice returned by [FrameDecoder::spare], and then
+/// the number of bytes read reported using [FrameDecoder::filled]
+pub struct FrameDecoder<const N: usize = FRAME_BUFFER_SIZE> {
+    buf: [u8; N],
+    length: usize,
+}
+
+impl<const N: usize> FrameDecoder<N> {
+    /// Create a new, empty [FrameDecoder]
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            length: 0,
+        }
+    }
+
+    /// Return the unused part of the receive buffer, for bytes to be read into
+    pub fn spare(&mut self) -> &mut [u8] {
+        self.buf.get_mut(self.length..).unwrap_or(&mut [])
+    }
+
+    /// Record that `count` bytes were read into the slice returned by [FrameDecoder::spare]
+    pub fn filled(&mut self, count: usize) {
+        self.length = (self.length + count).min(N);
+    }
+
+    /// Return true if there are no received bytes waiting to be decoded
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// Try to decode the next message from the bytes received so far.
+    /// Returns `Ok(None)` if a complete frame has not been received yet.
+    pub fn decode<T: DeserializeOwned>(&mut self) -> Result<Option<T>, FramingError> {
+        let received = self.buf.get(..self.length).unwrap_or(&[]);
+        let (body_length, header_length) = match decode_header(received)? {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+
+        let frame_length = header_length.saturating_add(body_length);
+        if frame_length > N {
+            return Err(FramingError::FrameTooLarge);
+        }
+
+        if self.length < frame_length {
+            return Ok(None);
+        }
+
+        let result = postcard::from_bytes(
+            self.buf
+                .get(header_length..frame_length)
+                .ok_or(FramingError::Deserialization)?,
+        )
+        .map_err(|_| FramingError::Deserialization);
+
+        // Remove the frame, keeping any bytes of following frames already received
+        self.buf.copy_within(frame_length..self.length, 0);
+        self.length -= frame_length;
+
+        result.map(Some)
+    }
+}
+
+impl<const N: usize> Default for FrameDecoder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod test {
+    use crate::config::HardwareConfigMessage::{GetConfig, IOLevelChanged, NewConfig};
+    use crate::config::{HardwareConfig, HardwareConfigMessage, LevelChange};
+    use crate::description::{
+        HardwareDescription, HardwareDetails, PinDescription, PinDescriptionSet,
+    };
+    use crate::framing::{encode, encode_to_vec, FrameDecoder, FramingError};
+    use crate::pin_function::PinFunction;
+    use std::borrow::Cow;
+    use std::time::Duration;
+
+    /// Feed `bytes` into the decoder, as if read from a stream, in chunks of `chunk_size`,
+    /// collecting all the messages decoded along the way
+    fn feed<const N: usize>(
+        decoder: &mut FrameDecoder<N>,
+        bytes: &[u8],
+        chunk_size: usize,
+    ) -> Vec<HardwareConfigMessage> {
+        let mut messages = vec![];
+        for chunk in bytes.chunks(chunk_size) {
+            decoder.spare()[..chunk.len()].copy_from_slice(chunk);
+            decoder.filled(chunk.len());
+            while let Some(message) = decoder.decode().expect("Could not decode") {
+                messages.push(message);
+            }
+        }
+        messages
+    }
+
+    fn level_change(bcm: u8, level: bool) -> HardwareConfigMessage {
+        IOLevelChanged(
+            bcm,
+            LevelChange::new(level, Duration::from_millis(bcm as u64)),
+        )
+    }
+
+    fn assert_level_change(message: &HardwareConfigMessage, bcm: u8, level: bool) {
+        match message {
+            IOLevelChanged(pin, level_change) => {
+                assert_eq!(*pin, bcm);
+                assert_eq!(level_change.new_level, level);
+            }
+            _ => panic!("Expected IOLevelChanged but got {message:?}"),
+        }
+    }
+
+    #[test]
+    fn single_frame() {
+        let frame = encode_to_vec(&GetConfig).expect("Could not encode");
+        let mut decoder: FrameDecoder = FrameDecoder::new();
+        let messages = feed(&mut decoder, &frame, frame.len());
+        assert_eq!(messages.len(), 1);
+        assert!(matches!(messages[0], GetConfig));
+        assert!(decoder.is_empty());
+    }
+
+    #[test]
+    fn coalesced_frames() {
+        let mut bytes = encode_to_vec(&level_change(2, true)).expect("Could not encode");
+        bytes.extend(encode_to_vec(&level_change(3, false)).expect("Could not encode"));
+        bytes.extend(encode_to_vec(&GetConfig).expect("Could not encode"));
+
+        let mut decoder: FrameDecoder = FrameDecoder::new();
+        let messages = feed(&mut decoder, &bytes, bytes.len());
+        assert_eq!(messages.len(), 3);
+        assert_level_change(&messages[0], 2, true);
+        assert_level_change(&messages[1], 3, false);
+        assert!(matches!(messages[2], GetConfig));
+        assert!(decoder.is_empty());
+    }
+
+    #[test]
+    fn fragmented_frames() {
+        let mut bytes = encode_to_vec(&level_change(17, true)).expect("Could not encode");
+        bytes.extend(encode_to_vec(&level_change(26, false)).expect("Could not encode"));
+
+        for chunk_size in 1..bytes.len() {
+            let mut decoder: FrameDecoder = FrameDecoder::new();
+            let messages = feed(&mut decoder, &bytes, chunk_size);
+            assert_eq!(messages.len(), 2, "Failed with chunk size {chunk_size}");
+            assert_level_change(&messages[0], 17, true);
+            assert_level_change(&messages[1], 26, false);
+            assert!(decoder.is_empty());
+        }
+    }
+
+    #[test]
+    fn frame_larger_than_one_kib() {
+        let pins: Vec<PinDescription> = (0..40)
+            .map(|bpn| PinDescription {
+                bpn,
+                bcm: Some(bpn),
+                name: Cow::Owned(format!("A long descriptive name for GPIO pin {bpn}")),
+                options: Cow::Borrowed(&[PinFunction::Input(None), PinFunction::Output(None)]),
+            })
+            .collect();
+        let description = HardwareDescription {
+            details: HardwareDetails::default(),
+            pins: PinDescriptionSet::new(&pins),
+        };
+        let frame =
+            encode_to_vec(&(&description, HardwareConfig::default())).expect("Could not encode");
+        assert!(frame.len() > 1024);
+
+        let mut decoder: FrameDecoder = FrameDecoder::new();
+        let mut received = None;
+        for chunk in frame.chunks(1024) {
+            decoder.spare()[..chunk.len()].copy_from_slice(chunk);
+            decoder.filled(chunk.len());
+            if let Some(message) = decoder.decode().expect("Could not decode") {
+                received = Some(message);
+            }
+        }
+        let (description, _config): (HardwareDescription, HardwareConfig) =
+            received.expect("Did not receive the HardwareDescription");
+        assert_eq!(description.pins.pins().len(), 40);
+    }
+
+    #[test]
+    fn encode_into_slice_matches_vec() {
+        let mut buf = [0u8; 64];
+        let frame = encode(&level_change(5, true), &mut buf).expect("Could not encode");
+        assert_eq!(
+            frame,
+            encode_to_vec(&level_change(5, true))
+                .expect("Could not encode")
+                .as_slice()
+        );
+    }
+
+    #[test]
+    fn encode_into_small_buffer_fails() {
+        let mut buf = [0u8; 4];
+        assert_eq!(
+            encode(&level_change(5, true), &mut buf),
+            Err(FramingError::Serialization)
+        );
+    }
+
+    #[test]
+    fn frame_too_large() {
+        let mut config = HardwareConfig::default();
+        for bcm in 0..100 {
+            config.pin_functions.insert(bcm, PinFunction::Output(None));
+        }
+        let frame = encode_to_vec(&NewConfig(config)).expect("Could not encode");
+
+        let mut decoder: FrameDecoder<64> = FrameDecoder::new();
+        decoder.spare().copy_from_slice(&frame[..64]);
+        decoder.filled(64);
+        assert_eq!(
+            decoder.decode::<HardwareConfigMessage>().err(),
+            Some(FramingError::FrameTooLarge)
+        );
+    }
+
+    #[test]
+    fn invalid_header() {
+        let mut decoder: FrameDecoder = FrameDecoder::new();
+        decoder.spare()[..5].copy_from_slice(&[0xff; 5]);
+        decoder.filled(5);
+        assert_eq!(
+            decoder.decode::<HardwareConfigMessage>().err(),
+            Some(FramingError::InvalidHeader)
+        );
+    }
+}
@@ -0,0 +1,276 @@
+//! The [Hello] message is the first message a device (pigglet or porky) sends to a client
+//! (such as piggui) when a connection is opened, over any transport, before the
+//! [HardwareDescription][crate::description::HardwareDescription] and
+//! [HardwareConfig][crate::config::HardwareConfig].
+//!
+//! It carries the version of the protocol the device speaks, the oldest version it can still
+//! talk to, and the set of optional [Capabilities] it supports. The client uses it to refuse
+//! devices it cannot talk to with a clear error, instead of failing to deserialize later
+//! messages, and to avoid using features the device does not support.
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(feature = "std"))]
+use core::clone::Clone;
+#[cfg(not(feature = "std"))]
+use core::cmp::PartialEq;
+#[cfg(not(feature = "std"))]
+use core::fmt::Debug;
+#[cfg(not(feature = "std"))]
+use core::marker::Copy;
+#[cfg(not(feature = "std"))]
+use core::prelude::rust_2024::derive;
+#[cfg(not(feature = "std"))]
+use core::result::Result;
+#[cfg(not(feature = "std"))]
+use core::result::Result::{Err, Ok};
+
+/// The version of the protocol (the set of messages and their serialized format) implemented
+/// by this build. Increment it whenever a change is made that older builds cannot understand.
+///    * 1 - The [Hello] handshake
+///    * 2 - PWM outputs
+///    * 3 - Analog inputs
+///    * 4 - The I2C bus
+///    * 5 - The SPI bus
+///    * 6 - The UART
+///    * 7 - Output sequences and schedules
+///    * 8 - Safe levels of outputs
+///    * 9 - Counts of connected clients
+///    * 10 - Authentication of TCP clients
+///    * 11 - Heartbeats
+pub const PROTOCOL_VERSION: u16 = 11;
+
+/// The oldest protocol version this build can still talk to. Versions 2 to 6 added pin
+/// functions, and version 8 safe levels, that can be in the config sent on connection, which
+/// older builds cannot deserialize, so this is raised along with [PROTOCOL_VERSION] when that
+/// happens. Versions 7 and 9 only added messages that older builds never send, and are not sent
+/// to devices without [Capabilities::SEQUENCES] or [Capabilities::CLIENT_COUNTS]. Version 10
+/// only added messages exchanged with devices that advertise [Capabilities::AUTH], which older
+/// builds cannot connect to, as the device requires them to authenticate. Version 11 only added
+/// heartbeats, which are not sent to devices without [Capabilities::HEARTBEAT]
+pub const MIN_PROTOCOL_VERSION: u16 = 8;
+
+/// Bytes at the start of every [Hello], used to detect devices that predate the handshake
+pub const HELLO_MAGIC: [u8; 4] = *b"PIGG";
+
+/// A set of optional features supported by one end of a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+    /// No optional features
+    pub const NONE: Capabilities = Capabilities(0);
+    /// Pins can be configured as digital inputs, and report level changes
+    pub const GPIO_INPUT: Capabilities = Capabilities(1 << 0);
+    /// Pins can be configured as digital outputs, and have their level set
+    pub const GPIO_OUTPUT: Capabilities = Capabilities(1 << 1);
+    /// The Wi-Fi details of the device can be read and configured
+    pub const WIFI: Capabilities = Capabilities(1 << 2);
+    /// Pins can be configured as PWM outputs, and have their duty cycle set
+    pub const PWM: Capabilities = Capabilities(1 << 3);
+    /// Pins can be configured as analog inputs, and report sampled values
+    pub const ANALOG_INPUT: Capabilities = Capabilities(1 << 4);
+    /// Pins can be configured as an I2C bus, and requests made of the devices on it
+    pub const I2C: Capabilities = Capabilities(1 << 5);
+    /// Pins can be configured as an SPI bus, and transfers made with the device on it
+    pub const SPI: Capabilities = Capabilities(1 << 6);
+    /// Pins can be configured as a UART, and bytes sent and received through it
+    pub const UART: Capabilities = Capabilities(1 << 7);
+    /// Output sequences and schedules can be run on the device
+    pub const SEQUENCES: Capabilities = Capabilities(1 << 8);
+    /// Outputs are driven to their safe level when a client disconnects or the device shuts down
+    pub const SAFE_LEVELS: Capabilities = Capabilities(1 << 9);
+    /// The number of clients connected to the device, over each transport, can be requested
+    pub const CLIENT_COUNTS: Capabilities = Capabilities(1 << 10);
+    /// Clients must authenticate, as described in `pigdef::auth`, before the description is sent
+    pub const AUTH: Capabilities = Capabilities(1 << 11);
+    /// The device answers heartbeat pings, and drops clients that stop sending them
+    pub const HEARTBEAT: Capabilities = Capabilities(1 << 12);
+
+    /// Return the set of capabilities in either `self` or `other`
+    pub const fn union(self, other: Capabilities) -> Capabilities {
+        Capabilities(self.0 | other.0)
+    }
+
+    /// Return the set of capabilities in both `self` and `other`
+    pub const fn intersection(self, other: Capabilities) -> Capabilities {
+        Capabilities(self.0 & other.0)
+    }
+
+    /// Return true if all the capabilities in `other` are also in `self`
+    pub const fn contains(self, other: Capabilities) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Return the raw bits of the set
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+/// The capabilities this build knows about, used to mask off unknown bits sent by newer peers
+pub const KNOWN_CAPABILITIES: Capabilities = Capabilities::GPIO_INPUT
+    .union(Capabilities::GPIO_OUTPUT)
+    .union(Capabilities::WIFI)
+    .union(Capabilities::PWM)
+    .union(Capabilities::ANALOG_INPUT)
+    .union(Capabilities::I2C)
+    .union(Capabilities::SPI)
+    .union(Capabilities::UART)
+    .union(Capabilities::SEQUENCES)
+    .union(Capabilities::SAFE_LEVELS)
+    .union(Capabilities::CLIENT_COUNTS)
+    .union(Capabilities::AUTH)
+    .union(Capabilities::HEARTBEAT);
+
+/// The first message sent by a device on a new connection
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Hello {
+    /// Always [HELLO_MAGIC]
+    pub magic: [u8; 4],
+    /// The protocol version the sender implements
+    pub protocol_version: u16,
+    /// The oldest protocol version the sender can still talk to
+    pub min_protocol_version: u16,
+    /// The optional features the sender supports
+    pub capabilities: Capabilities,
+}
+
+/// Reasons a connection to a device is refused during the handshake
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HandshakeError {
+    /// The device did not send a valid [Hello], so it predates the version handshake
+    MissingHello,
+    /// The device implements a protocol version older than this build can talk to
+    DeviceTooOld { device_version: u16 },
+    /// The device only talks protocol versions newer than this build implements
+    DeviceTooNew { device_min_version: u16 },
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HandshakeError::MissingHello => write!(
+                f,
+                "Device is too old: it does not report a protocol version. \
+                Please update the software on the device"
+            ),
+            HandshakeError::DeviceTooOld { device_version } => write!(
+                f,
+                "Device is too old: it uses protocol version {device_version}, but at least \
+                version {MIN_PROTOCOL_VERSION} is required. Please update the software on the device"
+            ),
+            HandshakeError::DeviceTooNew { device_min_version } => write!(
+                f,
+                "Device is too new: it requires protocol version {device_min_version} or later, \
+                but this is version {PROTOCOL_VERSION}. Please update this application"
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for HandshakeError {}
+
+impl Hello {
+    /// Create a [Hello] for this build, advertising `capabilities`
+    pub const fn new(capabilities: Capabilities) -> Self {
+        Hello {
+            magic: HELLO_MAGIC,
+            protocol_version: PROTOCOL_VERSION,
+            min_protocol_version: MIN_PROTOCOL_VERSION,
+            capabilities,
+        }
+    }
+
+    /// Check that a [Hello] received from a device is compatible with this build.
+    /// Returns the [Capabilities] the device supports that this build also knows how to use,
+    /// or a [HandshakeError] describing why the device cannot be used
+    pub fn negotiate(&self) -> Result<Capabilities, HandshakeError> {
+        if self.magic != HELLO_MAGIC {
+            return Err(HandshakeError::MissingHello);
+        }
+
+        if self.protocol_version < MIN_PROTOCOL_VERSION {
+            return Err(HandshakeError::DeviceTooOld {
+                device_version: self.protocol_version,
+            });
+        }
+
+        if self.min_protocol_version > PROTOCOL_VERSION {
+            return Err(HandshakeError::DeviceTooNew {
+                device_min_version: self.min_protocol_version,
+            });
+        }
+
+        Ok(self.capabilities.intersection(KNOWN_CAPABILITIES))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn same_version_negotiates() {
+        let hello = Hello::new(Capabilities::GPIO_INPUT.union(Capabilities::GPIO_OUTPUT));
+        assert_eq!(
+            hello.negotiate(),
+            Ok(Capabilities::GPIO_INPUT.union(Capabilities::GPIO_OUTPUT))
+        );
+    }
+
+    #[test]
+    fn unknown_capabilities_are_masked() {
+        let mut hello = Hello::new(Capabilities::GPIO_INPUT);
+        hello.capabilities = Capabilities(u32::MAX);
+        assert_eq!(hello.negotiate(), Ok(KNOWN_CAPABILITIES));
+    }
+
+    #[test]
+    fn newer_compatible_device_negotiates() {
+        let mut hello = Hello::new(Capabilities::GPIO_OUTPUT);
+        hello.protocol_version = PROTOCOL_VERSION + 1;
+        assert_eq!(hello.negotiate(), Ok(Capabilities::GPIO_OUTPUT));
+    }
+
+    #[test]
+    fn device_too_old() {
+        let mut hello = Hello::new(Capabilities::NONE);
+        hello.protocol_version = MIN_PROTOCOL_VERSION - 1;
+        assert_eq!(
+            hello.negotiate(),
+            Err(HandshakeError::DeviceTooOld {
+                device_version: MIN_PROTOCOL_VERSION - 1
+            })
+        );
+    }
+
+    #[test]
+    fn device_too_new() {
+        let mut hello = Hello::new(Capabilities::NONE);
+        hello.protocol_version = PROTOCOL_VERSION + 2;
+        hello.min_protocol_version = PROTOCOL_VERSION + 1;
+        assert_eq!(
+            hello.negotiate(),
+            Err(HandshakeError::DeviceTooNew {
+                device_min_version: PROTOCOL_VERSION + 1
+            })
+        );
+    }
+
+    #[test]
+    fn missing_magic() {
+        let mut hello = Hello::new(Capabilities::NONE);
+        hello.magic = [0; 4];
+        assert_eq!(hello.negotiate(), Err(HandshakeError::MissingHello));
+    }
+
+    #[test]
+    fn capabilities_contains() {
+        let capabilities = Capabilities::GPIO_INPUT.union(Capabilities::WIFI);
+        assert!(capabilities.contains(Capabilities::GPIO_INPUT));
+        assert!(capabilities.contains(Capabilities::NONE));
+        assert!(!capabilities.contains(Capabilities::GPIO_OUTPUT));
+    }
+}
@@ -0,0 +1,73 @@
+//! Requests that can be made of the devices on an I2C bus, and the responses to them, sent
+//! between a client (such as piggui) and a device whose pins have been configured as an I2C bus
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(feature = "std"))]
+use core::clone::Clone;
+#[cfg(not(feature = "std"))]
+use core::cmp::PartialEq;
+#[cfg(not(feature = "std"))]
+use core::prelude::rust_2024::derive;
+
+/// The 7-bit address of a device on an I2C bus
+pub type I2cAddress = u8;
+
+/// The lowest address scanned for devices, lower ones are reserved
+pub const I2C_FIRST_ADDRESS: I2cAddress = 0x08;
+
+/// The highest address scanned for devices, higher ones are reserved
+pub const I2C_LAST_ADDRESS: I2cAddress = 0x77;
+
+/// The most bytes that can be read or written in one request
+pub const I2C_MAX_BYTES: usize = 128;
+
+/// The bytes read from, or to be written to, a device. Also used for the list of addresses
+/// found in a scan, which will always fit in [I2C_MAX_BYTES]
+#[cfg(feature = "std")]
+pub type I2cBytes = Vec<u8>;
+#[cfg(not(feature = "std"))]
+pub type I2cBytes = heapless::Vec<u8, I2C_MAX_BYTES>;
+
+/// A description of why a request failed
+#[cfg(feature = "std")]
+pub type I2cError = String;
+#[cfg(not(feature = "std"))]
+pub type I2cError = heapless::String<64>;
+
+/// A request, made by a client, of the devices on the I2C bus
+#[cfg_attr(feature = "std", derive(Debug))]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub enum I2cRequest {
+    /// Find the addresses of all the devices on the bus that acknowledge their address
+    Scan,
+    /// Read `length` bytes, starting at `register`, from the device at `address`
+    Read {
+        address: I2cAddress,
+        register: u8,
+        length: u8,
+    },
+    /// Write `data`, starting at `register`, to the device at `address`
+    Write {
+        address: I2cAddress,
+        register: u8,
+        data: I2cBytes,
+    },
+}
+
+/// The response to an [I2cRequest], sent only to the client that made it
+#[cfg_attr(feature = "std", derive(Debug))]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub enum I2cResponse {
+    /// The addresses of the devices found by a [I2cRequest::Scan]
+    Devices(I2cBytes),
+    /// The `data` read from `register` of the device at `address`
+    Read {
+        address: I2cAddress,
+        register: u8,
+        data: I2cBytes,
+    },
+    /// The data was written to `register` of the device at `address`
+    Written { address: I2cAddress, register: u8 },
+    /// The request failed
+    Error(I2cError),
+}
@@ -3,10 +3,21 @@
 #![deny(clippy::expect_used)]
 
 //! This module provides definition structs for hardware, hardware events and configuration of hardware
+#[cfg(feature = "tcp")]
+pub mod auth;
 pub mod config;
 pub mod description;
+#[cfg(feature = "tcp")]
+pub mod framing;
+pub mod hello;
+pub mod i2c;
 #[cfg(feature = "iroh")]
 pub mod net_values;
 pub mod pin_function;
+pub mod sequence;
+pub mod spi;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod uart;
 #[cfg(feature = "usb")]
 pub mod usb_values;
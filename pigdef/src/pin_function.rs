@@ -1,5 +1,8 @@
 use crate::config::InputPull;
 use crate::description::PinLevel;
+use crate::hello::Capabilities;
+use crate::spi::SpiConfig;
+use crate::uart::UartConfig;
 use serde::{Deserialize, Serialize};
 
 #[cfg(not(feature = "std"))]
@@ -15,6 +18,19 @@ use core::option::Option;
 #[cfg(not(feature = "std"))]
 use core::prelude::rust_2024::derive;
 
+/// The frequency (in Hz) a pin is configured with when PWM is first selected for it
+pub const DEFAULT_PWM_FREQUENCY: f64 = 1000.0;
+
+/// The frequencies (in Hz) that can be selected for a PWM output, including 50 Hz for servos
+pub const PWM_FREQUENCIES: [f64; 6] = [50.0, 100.0, 500.0, 1000.0, 5000.0, 10000.0];
+
+/// The number of samples per second an analog input is configured with when first selected
+pub const DEFAULT_SAMPLE_RATE: u32 = 10;
+
+/// The most samples per second an analog input will be sampled at, to limit the traffic
+/// of samples sent to clients
+pub const MAX_SAMPLE_RATE: u32 = 100;
+
 /// For SPI interfaces see [here](https://www.raspberrypi.com/documentation/computers/raspberry-pi.html#serial-peripheral-interface-spi)
 ///
 /// Standard mode
@@ -38,6 +54,28 @@ pub enum PinFunction {
     /// GPIO functions
     Input(Option<InputPull>),
     Output(Option<PinLevel>),
+    /// Pulse Width Modulated output, with the `frequency` of the pulses in Hz and the
+    /// `duty_cycle` (the fraction of each period the output is high) from 0.0 to 1.0
+    Pwm {
+        frequency: f64,
+        duty_cycle: f64,
+    },
+    /// Analog input read by an ADC, sampled `sample_rate` times per second
+    AnalogInput {
+        sample_rate: u32,
+    },
+    /// I2C bus #1 functions, when both pins are configured the bus can be used
+    I2C1_SDA,
+    I2C1_SCL,
+    /// SPI Interface #0 functions, when all three pins are configured the bus can be used.
+    /// The settings of the bus are those of its clock pin
+    SPI0_MOSI,
+    SPI0_MISO,
+    SPI0_SCLK(SpiConfig),
+    /// UART #0 functions, when both pins are configured the UART can be used.
+    /// The settings of the UART are those of its transmit pin
+    UART0_TXD(UartConfig),
+    UART0_RXD,
     /*
     /// General Purpose Clock functions (from https://pinout.xyz/pinout/gpclk)
     GPCLK0,
@@ -45,8 +83,6 @@ pub enum PinFunction {
     GPCLK2,
 
     /// I2C bus functions
-    I2C1_SDA,
-    I2C1_SCL,
     I2C3_SDA,
     I2C3_SCL,
     I2C4_SDA,
@@ -57,11 +93,8 @@ pub enum PinFunction {
     I2C6_SCL,
 
     /// SPI Interface #0
-    SPI0_MOSI,
     /// Bi-directional mode
     SPI0_MOMI,
-    SPI0_MISO,
-    SPI0_SCLK,
     SPI0_CE0_N,
     SPI0_CE1_N,
 
@@ -79,12 +112,6 @@ pub enum PinFunction {
     PWM0,
     PWM1,
 
-    /// UART functions
-    /// UART0 - Transmit
-    UART0_TXD,
-    /// UART0 - Receive
-    UART0_RXD,
-
     /// PCM functions - how uncompressed digital audio is encoded
     PCM_FS,
     /// PCM Data In
@@ -96,19 +123,43 @@ pub enum PinFunction {
      */
 }
 
+impl PinFunction {
+    /// The [Capabilities] a device must support for a pin to be configured with this function
+    pub const fn capability(&self) -> Capabilities {
+        match self {
+            PinFunction::Input(_) => Capabilities::GPIO_INPUT,
+            PinFunction::Output(_) => Capabilities::GPIO_OUTPUT,
+            PinFunction::Pwm { .. } => Capabilities::PWM,
+            PinFunction::AnalogInput { .. } => Capabilities::ANALOG_INPUT,
+            PinFunction::I2C1_SDA | PinFunction::I2C1_SCL => Capabilities::I2C,
+            PinFunction::SPI0_MOSI | PinFunction::SPI0_MISO | PinFunction::SPI0_SCLK(_) => {
+                Capabilities::SPI
+            }
+            PinFunction::UART0_TXD(_) | PinFunction::UART0_RXD => Capabilities::UART,
+        }
+    }
+}
+
 #[cfg(feature = "std")]
 impl std::fmt::Display for PinFunction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        // Remove anything after the first opening bracket of debug representation
+        // Remove anything after the variant name in the debug representation
         let full = format!("{self:?}");
-        write!(f, "{}", full.split_once('(').unwrap_or((&full, "")).0)
+        write!(
+            f,
+            "{}",
+            full.split_once(['(', ' ']).unwrap_or((&full, "")).0
+        )
     }
 }
 
 #[cfg(all(test, feature = "std"))]
 mod test {
     use crate::config::InputPull::{PullDown, PullUp};
-    use crate::pin_function::PinFunction;
+    use crate::hello::Capabilities;
+    use crate::pin_function::{PinFunction, DEFAULT_PWM_FREQUENCY, DEFAULT_SAMPLE_RATE};
+    use crate::spi::DEFAULT_SPI_CONFIG;
+    use crate::uart::DEFAULT_UART_CONFIG;
 
     #[test]
     fn display_pin_function() {
@@ -119,10 +170,52 @@ mod test {
             PinFunction::Input(None),
             PinFunction::Input(Some(PullUp)),
             PinFunction::Input(Some(PullDown)),
+            PinFunction::Pwm {
+                frequency: DEFAULT_PWM_FREQUENCY,
+                duty_cycle: 0.5,
+            },
+            PinFunction::AnalogInput {
+                sample_rate: DEFAULT_SAMPLE_RATE,
+            },
+            PinFunction::I2C1_SDA,
+            PinFunction::I2C1_SCL,
+            PinFunction::SPI0_MOSI,
+            PinFunction::SPI0_MISO,
+            PinFunction::SPI0_SCLK(DEFAULT_SPI_CONFIG),
+            PinFunction::UART0_TXD(DEFAULT_UART_CONFIG),
+            PinFunction::UART0_RXD,
         ];
 
         for function in functions {
             println!("{function}");
         }
     }
+
+    #[test]
+    fn capability_of_pin_functions() {
+        assert_eq!(
+            PinFunction::Input(Some(PullUp)).capability(),
+            Capabilities::GPIO_INPUT
+        );
+        assert_eq!(PinFunction::I2C1_SCL.capability(), Capabilities::I2C);
+        assert_eq!(
+            PinFunction::SPI0_SCLK(DEFAULT_SPI_CONFIG).capability(),
+            Capabilities::SPI
+        );
+    }
+
+    #[test]
+    fn display_pwm_has_no_fields() {
+        let function = PinFunction::Pwm {
+            frequency: 50.0,
+            duty_cycle: 0.075,
+        };
+        assert_eq!(function.to_string(), "Pwm");
+    }
+
+    #[test]
+    fn display_spi_clock_has_no_config() {
+        let function = PinFunction::SPI0_SCLK(DEFAULT_SPI_CONFIG);
+        assert_eq!(function.to_string(), "SPI0_SCLK");
+    }
 }
@@ -0,0 +1,279 @@
+//! Output sequences and schedules, sent by a client (such as piggui) to a device to be run on
+//! the device itself, so their timing does not depend on the connection to the client.
+//!
+//! A [Sequence] sets outputs to levels in timed steps, e.g. to produce a pulse train, and can be
+//! repeated. A [Schedule] sets outputs to levels at times of day, e.g. "on at 08:00, off at
+//! 18:00", every day.
+use crate::description::{BCMPinNumber, PinLevel};
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(feature = "std"))]
+use core::clone::Clone;
+#[cfg(not(feature = "std"))]
+use core::cmp::PartialEq;
+#[cfg(not(feature = "std"))]
+use core::fmt::Debug;
+#[cfg(not(feature = "std"))]
+use core::iter::Iterator;
+#[cfg(not(feature = "std"))]
+use core::marker::Copy;
+#[cfg(not(feature = "std"))]
+use core::option::Option;
+#[cfg(not(feature = "std"))]
+use core::prelude::rust_2024::derive;
+#[cfg(not(feature = "std"))]
+use core::result::Result::{self, Err, Ok};
+
+/// The most steps in a [Sequence]
+pub const SEQUENCE_MAX_STEPS: usize = 32;
+
+/// The most entries in a [Schedule]
+pub const SCHEDULE_MAX_ENTRIES: usize = 16;
+
+/// The number of seconds in a day, times of day are less than this
+pub const SECONDS_PER_DAY: u32 = 24 * 60 * 60;
+
+/// Identifies a sequence or schedule running on a device, chosen by the client that starts it.
+/// Starting another with the same id replaces it.
+pub type SequenceId = u8;
+
+/// One step of a [Sequence]: set the output `bcm_pin_number` to `level`, then wait `delay_ms`
+/// milliseconds before the next step
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct SequenceStep {
+    pub bcm_pin_number: BCMPinNumber,
+    pub level: PinLevel,
+    pub delay_ms: u32,
+}
+
+/// The steps of a [Sequence]
+#[cfg(feature = "std")]
+pub type SequenceSteps = Vec<SequenceStep>;
+#[cfg(not(feature = "std"))]
+pub type SequenceSteps = heapless::Vec<SequenceStep, SEQUENCE_MAX_STEPS>;
+
+/// Steps that are run in order, `repeat` times, or until stopped if `repeat` is 0
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Sequence {
+    pub steps: SequenceSteps,
+    pub repeat: u32,
+}
+
+impl Sequence {
+    /// Check the sequence can be run: it has steps, no more than [SEQUENCE_MAX_STEPS], and one
+    /// that repeats takes some time to run each time, so it does not hog the device
+    pub fn check(&self) -> Result<(), &'static str> {
+        if self.steps.is_empty() {
+            return Err("Sequence has no steps");
+        }
+        if self.steps.len() > SEQUENCE_MAX_STEPS {
+            return Err("Sequence has too many steps");
+        }
+        if self.repeat != 1 && self.steps.iter().all(|step| step.delay_ms == 0) {
+            return Err("Sequence that repeats has no delays");
+        }
+        Ok(())
+    }
+}
+
+/// An output `bcm_pin_number` is set to `level` at `time_of_day`, in seconds since midnight
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct ScheduleEntry {
+    pub time_of_day: u32,
+    pub bcm_pin_number: BCMPinNumber,
+    pub level: PinLevel,
+}
+
+impl ScheduleEntry {
+    /// Create an entry setting `bcm_pin_number` to `level` at `hours`:`minutes` each day
+    pub const fn new(
+        hours: u32,
+        minutes: u32,
+        bcm_pin_number: BCMPinNumber,
+        level: PinLevel,
+    ) -> Self {
+        ScheduleEntry {
+            time_of_day: (hours * 60 + minutes) * 60,
+            bcm_pin_number,
+            level,
+        }
+    }
+}
+
+/// The entries of a [Schedule]
+#[cfg(feature = "std")]
+pub type ScheduleEntries = Vec<ScheduleEntry>;
+#[cfg(not(feature = "std"))]
+pub type ScheduleEntries = heapless::Vec<ScheduleEntry, SCHEDULE_MAX_ENTRIES>;
+
+/// Entries run at their time of day, every day, until stopped.
+/// Not all devices have a clock, so `now` is the time of day (in seconds since midnight) on the
+/// client when it was sent, and the device counts time from it.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Schedule {
+    pub entries: ScheduleEntries,
+    pub now: u32,
+}
+
+impl Schedule {
+    /// Check the schedule can be run: it has entries, no more than [SCHEDULE_MAX_ENTRIES], and
+    /// all times of day are less than [SECONDS_PER_DAY]
+    pub fn check(&self) -> Result<(), &'static str> {
+        if self.entries.is_empty() {
+            return Err("Schedule has no entries");
+        }
+        if self.entries.len() > SCHEDULE_MAX_ENTRIES {
+            return Err("Schedule has too many entries");
+        }
+        if self.now >= SECONDS_PER_DAY
+            || self
+                .entries
+                .iter()
+                .any(|entry| entry.time_of_day >= SECONDS_PER_DAY)
+        {
+            return Err("Schedule has an invalid time of day");
+        }
+        Ok(())
+    }
+
+    /// The entries that run at `time_of_day`
+    pub fn entries_at(&self, time_of_day: u32) -> impl Iterator<Item = &ScheduleEntry> {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.time_of_day == time_of_day)
+    }
+
+    /// The level each output should be at, at `time_of_day`: that of its most recent entry,
+    /// which may have been yesterday. Used to set outputs when the schedule is started.
+    pub fn levels_at(
+        &self,
+        time_of_day: u32,
+    ) -> impl Iterator<Item = (BCMPinNumber, PinLevel)> + '_ {
+        // How long before `time_of_day` an entry last ran
+        let age = move |entry: &ScheduleEntry| {
+            (time_of_day + SECONDS_PER_DAY - entry.time_of_day) % SECONDS_PER_DAY
+        };
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(move |(index, entry)| {
+                // Skip entries for the same output that ran more recently, or at the same time but
+                // later in the schedule
+                let superseded = self.entries.iter().enumerate().any(|(other_index, other)| {
+                    other.bcm_pin_number == entry.bcm_pin_number
+                        && (age(other) < age(entry)
+                            || (age(other) == age(entry) && other_index > index))
+                });
+                (!superseded).then_some((entry.bcm_pin_number, entry.level))
+            })
+    }
+
+    /// The number of seconds after `time_of_day` until entries next run, which is a day if
+    /// they all run at `time_of_day`. None if there are no entries.
+    pub fn seconds_until_next(&self, time_of_day: u32) -> Option<u32> {
+        self.entries
+            .iter()
+            .map(|entry| {
+                match (entry.time_of_day + SECONDS_PER_DAY - time_of_day) % SECONDS_PER_DAY {
+                    0 => SECONDS_PER_DAY,
+                    seconds => seconds,
+                }
+            })
+            .min()
+    }
+}
+
+/// Whether a [Sequence] or [Schedule] is running on a device
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum SequenceState {
+    /// Not running, it was never started, has finished, or was stopped
+    Stopped,
+    /// A [Sequence] is running
+    Running,
+    /// A [Schedule] is running
+    Scheduled,
+}
+
+#[cfg(all(test, feature = "std"))]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod test {
+    use super::*;
+
+    fn schedule() -> Schedule {
+        Schedule {
+            entries: vec![
+                ScheduleEntry::new(8, 0, 17, true),
+                ScheduleEntry::new(18, 0, 17, false),
+                ScheduleEntry::new(12, 30, 26, true),
+            ],
+            now: 0,
+        }
+    }
+
+    #[test]
+    fn check_sequence() {
+        let step = SequenceStep {
+            bcm_pin_number: 17,
+            level: true,
+            delay_ms: 0,
+        };
+        assert!(Sequence {
+            steps: vec![],
+            repeat: 1
+        }
+        .check()
+        .is_err());
+        assert!(Sequence {
+            steps: vec![step],
+            repeat: 1
+        }
+        .check()
+        .is_ok());
+        assert!(Sequence {
+            steps: vec![step],
+            repeat: 0
+        }
+        .check()
+        .is_err());
+        assert!(Sequence {
+            steps: vec![step],
+            repeat: u32::MAX
+        }
+        .check()
+        .is_err());
+    }
+
+    #[test]
+    fn check_schedule() {
+        assert!(schedule().check().is_ok());
+        let mut invalid = schedule();
+        invalid.entries[0].time_of_day = SECONDS_PER_DAY;
+        assert!(invalid.check().is_err());
+    }
+
+    #[test]
+    fn levels_at() {
+        let schedule = schedule();
+        // Before 08:00 the most recent entry for pin 17 was at 18:00 yesterday
+        assert_eq!(
+            schedule.levels_at(7 * 3600).collect::<Vec<_>>(),
+            vec![(17, false), (26, true)]
+        );
+        assert_eq!(
+            schedule.levels_at(8 * 3600).collect::<Vec<_>>(),
+            vec![(17, true), (26, true)]
+        );
+    }
+
+    #[test]
+    fn seconds_until_next() {
+        let schedule = schedule();
+        assert_eq!(schedule.seconds_until_next(7 * 3600), Some(3600));
+        assert_eq!(schedule.seconds_until_next(8 * 3600), Some(4 * 3600 + 1800));
+        assert_eq!(schedule.seconds_until_next(19 * 3600), Some(13 * 3600));
+        assert_eq!(
+            schedule.entries_at(18 * 3600).collect::<Vec<_>>(),
+            vec![&ScheduleEntry::new(18, 0, 17, false)]
+        );
+    }
+}
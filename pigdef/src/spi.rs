@@ -0,0 +1,90 @@
+//! The settings of an SPI bus, and the full-duplex transfers that can be made on it, sent
+//! between a client (such as piggui) and a device whose pins have been configured as an SPI bus.
+//!
+//! Only SPI bus #0 (BCM 9, 10 and 11, with chip selects CE0 and CE1) is supported. SPI bus #1,
+//! which needs the `spi1` device tree overlay and shares BCM 19 with a PWM channel, is not.
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(feature = "std"))]
+use core::clone::Clone;
+#[cfg(not(feature = "std"))]
+use core::cmp::PartialEq;
+#[cfg(not(feature = "std"))]
+use core::fmt::Debug;
+#[cfg(not(feature = "std"))]
+use core::marker::Copy;
+#[cfg(not(feature = "std"))]
+use core::prelude::rust_2024::derive;
+
+/// The clock speeds (in Hz) that can be selected for an SPI bus
+pub const SPI_CLOCK_SPEEDS: [u32; 4] = [100_000, 1_000_000, 4_000_000, 8_000_000];
+
+/// The most bytes that can be sent in one transfer
+pub const SPI_MAX_BYTES: usize = 128;
+
+/// The settings an SPI bus is configured with when it is first selected
+pub const DEFAULT_SPI_CONFIG: SpiConfig = SpiConfig {
+    mode: SpiMode::Mode0,
+    clock_speed: 1_000_000,
+    chip_select: SpiChipSelect::CE0,
+};
+
+/// The clock polarity (CPOL) and phase (CPHA) used by the bus
+///    * Mode0 - CPOL = 0, CPHA = 0
+///    * Mode1 - CPOL = 0, CPHA = 1
+///    * Mode2 - CPOL = 1, CPHA = 0
+///    * Mode3 - CPOL = 1, CPHA = 1
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum SpiMode {
+    Mode0,
+    Mode1,
+    Mode2,
+    Mode3,
+}
+
+/// The chip enable (chip select) line that is driven low during a transfer, to select the
+/// device on the bus that the transfer is with
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum SpiChipSelect {
+    CE0,
+    CE1,
+}
+
+/// The settings of an SPI bus, set from the menu of its clock (SCLK) pin
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct SpiConfig {
+    pub mode: SpiMode,
+    /// The clock speed in Hz
+    pub clock_speed: u32,
+    pub chip_select: SpiChipSelect,
+}
+
+/// The bytes sent to, or received from, a device
+#[cfg(feature = "std")]
+pub type SpiBytes = Vec<u8>;
+#[cfg(not(feature = "std"))]
+pub type SpiBytes = heapless::Vec<u8, SPI_MAX_BYTES>;
+
+/// A description of why a transfer failed
+#[cfg(feature = "std")]
+pub type SpiError = String;
+#[cfg(not(feature = "std"))]
+pub type SpiError = heapless::String<64>;
+
+/// A request, made by a client, of the device selected on the SPI bus
+#[cfg_attr(feature = "std", derive(Debug))]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub enum SpiRequest {
+    /// Send the bytes to the device, while receiving the same number of bytes from it
+    Transfer(SpiBytes),
+}
+
+/// The response to an [SpiRequest], sent only to the client that made it
+#[cfg_attr(feature = "std", derive(Debug))]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub enum SpiResponse {
+    /// The bytes `written` to the device and the bytes `read` from it at the same time
+    Transferred { written: SpiBytes, read: SpiBytes },
+    /// The request failed
+    Error(SpiError),
+}
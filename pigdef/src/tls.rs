@@ -0,0 +1,123 @@
+//! Fingerprints of the self-signed certificates that devices (pigglet) serve TLS connections
+//! with. There is no certificate authority to check them against, so a client checks the
+//! certificate a device presents has the [Fingerprint] it expects, learnt from its mDNS TXT
+//! record or from connecting to it before.
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The SHA-256 hash of a certificate in DER form
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fingerprint([u8; 32]);
+
+/// The error returned when text is not a valid [Fingerprint]
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidFingerprint;
+
+impl Display for InvalidFingerprint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid certificate fingerprint, expected 64 hex digits")
+    }
+}
+
+impl std::error::Error for InvalidFingerprint {}
+
+impl Fingerprint {
+    /// The [Fingerprint] of `certificate`, which is in DER form
+    pub fn of(certificate: &[u8]) -> Self {
+        Fingerprint(Sha256::digest(certificate).into())
+    }
+}
+
+/// Shown as 64 lowercase hex digits
+impl Display for Fingerprint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for byte in self.0 {
+            write!(f, "{byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parsed from 64 hex digits, in either case, ignoring any ':' separators between them
+impl FromStr for Fingerprint {
+    type Err = InvalidFingerprint;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut digits = text
+            .chars()
+            .filter(|c| *c != ':')
+            .map(|c| c.to_digit(16).ok_or(InvalidFingerprint));
+        let mut bytes = [0; 32];
+        for byte in bytes.iter_mut() {
+            let high = digits.next().ok_or(InvalidFingerprint)??;
+            let low = digits.next().ok_or(InvalidFingerprint)??;
+            *byte = (high << 4 | low) as u8;
+        }
+        match digits.next() {
+            None => Ok(Fingerprint(bytes)),
+            Some(_) => Err(InvalidFingerprint),
+        }
+    }
+}
+
+/// Serialized as its hex digits, so it is readable in JSON files
+impl Serialize for Fingerprint {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Fingerprint {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        Fingerprint::from_str(&text).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::expect_used)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn display_and_parse() {
+        let fingerprint = Fingerprint::of(b"certificate");
+        let text = fingerprint.to_string();
+        assert_eq!(text.len(), 64);
+        assert_eq!(
+            Fingerprint::from_str(&text).expect("Could not parse fingerprint"),
+            fingerprint
+        );
+    }
+
+    #[test]
+    fn parse_with_separators() {
+        let fingerprint = Fingerprint::of(b"certificate");
+        let bytes: Vec<String> = fingerprint
+            .0
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect();
+        assert_eq!(
+            Fingerprint::from_str(&bytes.join(":")).expect("Could not parse fingerprint"),
+            fingerprint
+        );
+    }
+
+    #[test]
+    fn parse_invalid() {
+        let text = Fingerprint::of(b"certificate").to_string();
+        assert_eq!(Fingerprint::from_str(&text[1..]), Err(InvalidFingerprint));
+        assert_eq!(
+            Fingerprint::from_str(&format!("{text}0")),
+            Err(InvalidFingerprint)
+        );
+        assert_eq!(
+            Fingerprint::from_str(&text.replace('0', "g")),
+            Err(InvalidFingerprint)
+        );
+    }
+}
@@ -0,0 +1,57 @@
+//! The settings of a UART, and the chunks of bytes sent and received through it, sent between
+//! a client (such as piggui) and a device whose pins have been configured as a UART
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(feature = "std"))]
+use core::clone::Clone;
+#[cfg(not(feature = "std"))]
+use core::cmp::PartialEq;
+#[cfg(not(feature = "std"))]
+use core::fmt::Debug;
+#[cfg(not(feature = "std"))]
+use core::marker::Copy;
+#[cfg(not(feature = "std"))]
+use core::prelude::rust_2024::derive;
+
+/// The baud rates that can be selected for a UART
+pub const UART_BAUD_RATES: [u32; 5] = [9_600, 19_200, 38_400, 57_600, 115_200];
+
+/// The most bytes sent or received in one chunk
+pub const UART_MAX_BYTES: usize = 128;
+
+/// The settings a UART is configured with when it is first selected: 115200 baud, 8N1
+pub const DEFAULT_UART_CONFIG: UartConfig = UartConfig {
+    baud_rate: 115_200,
+    parity: UartParity::None,
+    stop_bits: UartStopBits::One,
+};
+
+/// The parity bit sent after the data bits of each byte, used to detect errors
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum UartParity {
+    None,
+    Even,
+    Odd,
+}
+
+/// The number of stop bits sent after each byte
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum UartStopBits {
+    One,
+    Two,
+}
+
+/// The settings of a UART, set from the menu of its transmit (TXD) pin. Bytes always have
+/// eight data bits
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct UartConfig {
+    pub baud_rate: u32,
+    pub parity: UartParity,
+    pub stop_bits: UartStopBits,
+}
+
+/// A chunk of bytes sent to, or received from, a UART
+#[cfg(feature = "std")]
+pub type UartBytes = Vec<u8>;
+#[cfg(not(feature = "std"))]
+pub type UartBytes = heapless::Vec<u8, UART_MAX_BYTES>;
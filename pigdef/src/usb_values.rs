@@ -21,6 +21,9 @@ pub const GET_HARDWARE_DETAILS_VALUE: u16 = 205;
 /// Command Value to send a [HardwareConfigMessage] to device
 pub const HW_CONFIG_MESSAGE: u16 = 206;
 
+/// Command Value to get the [Hello] from porky, which must be the first request on connecting
+pub const GET_HELLO_VALUE: u16 = 207;
+
 #[allow(dead_code)]
 /// A constant used in USB packet sizes
 pub const USB_PACKET_SIZE: u16 = 64;
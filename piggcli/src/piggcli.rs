@@ -0,0 +1,354 @@
+#![deny(clippy::unwrap_used)]
+#![deny(clippy::expect_used)]
+
+use anyhow::{anyhow, bail};
+use clap::{Arg, ArgMatches};
+#[cfg(feature = "iroh")]
+use iroh::{EndpointId, RelayUrl};
+use pigdef::auth::PairingCode;
+use pigdef::config::InputPull;
+use pigdef::description::{BCMPinNumber, PinLevel};
+use pigdef::pin_function::{PinFunction, DEFAULT_PWM_FREQUENCY, DEFAULT_SAMPLE_RATE};
+use pignet::credentials::Credentials;
+use pignet::{Client, HardwareConnection};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// piggcli is a headless client for remote pigglet and porky devices, so that they can be
+/// driven from scripts and CI rigs without a display. Results are written to stdout as JSON.
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let matches = get_matches();
+    let target = hardware_connection(&matches)?;
+    let client = connect(&matches, &target).await?;
+
+    let result = match matches.subcommand() {
+        Some(("describe", _)) => print_json(client.description()),
+        Some(("config", _)) => print_json(&client.config()),
+        Some(("set", args)) => set_pin_function(&client, args).await,
+        Some(("output", args)) => set_output_level(&client, args).await,
+        Some(("watch", args)) => watch(&client, args).await,
+        _ => Err(anyhow!("No command given, see 'piggcli --help'")),
+    };
+
+    client.disconnect().await?;
+    result
+}
+
+/// Connect to the device, pairing with it first if it is connected to over TCP and a pairing
+/// code was given, saving the key it sends for the next time
+async fn connect(matches: &ArgMatches, target: &HardwareConnection) -> anyhow::Result<Client> {
+    match (target, matches.get_one::<String>("pair")) {
+        (HardwareConnection::Tcp(ip, port), Some(code)) => {
+            let code = PairingCode::parse(code)
+                .ok_or_else(|| anyhow!("Could not parse pairing code '{code}'"))?;
+            let mut credentials = Credentials::load_default()?;
+            credentials.set_pairing_code(code);
+            Client::connect_tcp(*ip, *port, &mut credentials).await
+        }
+        _ => Client::connect(target).await,
+    }
+}
+
+/// Print a value to stdout as a single line of JSON
+fn print_json<T: serde::Serialize>(value: &T) -> anyhow::Result<()> {
+    println!("{}", serde_json::to_string(value)?);
+    Ok(())
+}
+
+/// Get the BCM pin number the command applies to
+fn bcm_pin_number(args: &ArgMatches) -> anyhow::Result<BCMPinNumber> {
+    let bcm = args
+        .get_one::<String>("pin")
+        .ok_or_else(|| anyhow!("No pin number given"))?;
+    Ok(BCMPinNumber::from_str(bcm)?)
+}
+
+/// Configure a pin with a new function, or none
+async fn set_pin_function(client: &Client, args: &ArgMatches) -> anyhow::Result<()> {
+    let bcm = bcm_pin_number(args)?;
+    let function = args
+        .get_one::<String>("function")
+        .ok_or_else(|| anyhow!("No pin function given"))?;
+    let pin_function = parse_pin_function(function)?;
+    client.set_pin_function(bcm, pin_function).await
+}
+
+/// Set the level of an output, toggling it if requested
+async fn set_output_level(client: &Client, args: &ArgMatches) -> anyhow::Result<()> {
+    let bcm = bcm_pin_number(args)?;
+    let current_level = match client.config().pin_functions.get(&bcm) {
+        Some(PinFunction::Output(level)) => level.unwrap_or(false),
+        _ => bail!("Pin #{bcm} is not configured as an output"),
+    };
+    let level: PinLevel = match args.get_one::<String>("level").map(String::as_str) {
+        Some("high") => true,
+        Some("low") => false,
+        Some("toggle") => !current_level,
+        _ => bail!("The level must be one of 'high', 'low' or 'toggle'"),
+    };
+    client.set_output(bcm, level).await
+}
+
+/// Print each message received from the device, such as input level changes, as a line of
+/// JSON, until `count` messages have been received, if a count was given
+async fn watch(client: &Client, args: &ArgMatches) -> anyhow::Result<()> {
+    let count = args
+        .get_one::<String>("count")
+        .map(|count| usize::from_str(count))
+        .transpose()?;
+
+    let mut received = 0;
+    while count != Some(received) {
+        let message = client.receive().await?;
+        print_json(&message)?;
+        received += 1;
+    }
+
+    Ok(())
+}
+
+/// Parse a pin function given on the command line. A short form is accepted for the common
+/// functions, for example "input:pullup", "output:high", "pwm:50:0.075" or "analog:10", and
+/// "none" to leave the pin unused. Any other function can be given as JSON.
+fn parse_pin_function(function: &str) -> anyhow::Result<Option<PinFunction>> {
+    let mut parts = function.split(':');
+    let name = parts.next().unwrap_or_default().to_lowercase();
+    let params: Vec<&str> = parts.collect();
+
+    let pin_function = match (name.as_str(), params.as_slice()) {
+        ("none" | "unused", []) => None,
+        ("input", []) => Some(PinFunction::Input(None)),
+        ("input", ["pullup"]) => Some(PinFunction::Input(Some(InputPull::PullUp))),
+        ("input", ["pulldown"]) => Some(PinFunction::Input(Some(InputPull::PullDown))),
+        ("input", ["none"]) => Some(PinFunction::Input(Some(InputPull::None))),
+        ("output", []) => Some(PinFunction::Output(None)),
+        ("output", ["high"]) => Some(PinFunction::Output(Some(true))),
+        ("output", ["low"]) => Some(PinFunction::Output(Some(false))),
+        ("pwm", []) => Some(PinFunction::Pwm {
+            frequency: DEFAULT_PWM_FREQUENCY,
+            duty_cycle: 0.0,
+        }),
+        ("pwm", [frequency, duty_cycle]) => Some(PinFunction::Pwm {
+            frequency: f64::from_str(frequency)?,
+            duty_cycle: f64::from_str(duty_cycle)?,
+        }),
+        ("analog", []) => Some(PinFunction::AnalogInput {
+            sample_rate: DEFAULT_SAMPLE_RATE,
+        }),
+        ("analog", [sample_rate]) => Some(PinFunction::AnalogInput {
+            sample_rate: u32::from_str(sample_rate)?,
+        }),
+        _ => Some(
+            serde_json::from_str(function)
+                .map_err(|_| anyhow!("Could not parse the pin function '{function}'"))?,
+        ),
+    };
+
+    Ok(pin_function)
+}
+
+/// Determine the [HardwareConnection] to the device from the command line options
+fn hardware_connection(matches: &ArgMatches) -> anyhow::Result<HardwareConnection> {
+    #[cfg(feature = "iroh")]
+    if let Some(endpoint_id) = matches.get_one::<String>("endpoint_id") {
+        let relay_url = matches
+            .get_one::<String>("relay")
+            .map(|relay| RelayUrl::from_str(relay))
+            .transpose()?;
+        return Ok(HardwareConnection::Iroh(
+            EndpointId::from_str(endpoint_id)?,
+            relay_url,
+        ));
+    }
+
+    #[cfg(feature = "usb")]
+    if let Some(serial_number) = matches.get_one::<String>("usb") {
+        return Ok(HardwareConnection::Usb(serial_number.to_string()));
+    }
+
+    if let Some(ip_port) = matches.get_one::<String>("ip") {
+        let (ip, port) = ip_port
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Could not parse ip:port"))?;
+        return Ok(HardwareConnection::Tcp(
+            IpAddr::from_str(ip)?,
+            u16::from_str(port)?,
+        ));
+    }
+
+    bail!("No device to connect to was given, see 'piggcli --help'")
+}
+
+/// Create an [Arg] for the pin number a command applies to
+fn pin_arg() -> Arg {
+    Arg::new("pin")
+        .required(true)
+        .value_name("BCM_PIN")
+        .help("BCM number of the pin")
+}
+
+/// Parse the command line arguments using clap into a set of [ArgMatches]
+fn get_matches() -> ArgMatches {
+    let app = clap::Command::new(env!("CARGO_BIN_NAME"))
+        .version(env!("CARGO_PKG_VERSION"))
+        .about(
+            "'piggcli' - for scripting remote 'pigglet' and 'porky' devices from the command line",
+        )
+        .subcommand_required(true)
+        .subcommand(
+            clap::Command::new("describe")
+                .about("Print the description of the device's hardware as JSON"),
+        )
+        .subcommand(
+            clap::Command::new("config").about("Print the current config of the device as JSON"),
+        )
+        .subcommand(
+            clap::Command::new("set")
+                .about("Set the function of a pin")
+                .arg(pin_arg())
+                .arg(
+                    Arg::new("function")
+                        .required(true)
+                        .value_name("FUNCTION")
+                        .help(
+                            "'input[:pullup|:pulldown]', 'output[:high|:low]', \
+                            'pwm[:frequency:duty_cycle]', 'analog[:sample_rate]', 'none' \
+                            or any pin function as JSON",
+                        ),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("output")
+                .about("Set the level of an output")
+                .arg(pin_arg())
+                .arg(
+                    Arg::new("level")
+                        .required(true)
+                        .value_name("LEVEL")
+                        .help("'high', 'low' or 'toggle'"),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("watch")
+                .about("Print input level changes and other events from the device as JSON lines")
+                .arg(
+                    Arg::new("count")
+                        .short('n')
+                        .long("count")
+                        .num_args(1)
+                        .number_of_values(1)
+                        .value_name("COUNT")
+                        .help("Exit after this many events, instead of watching until killed"),
+                ),
+        );
+
+    #[cfg(feature = "iroh")]
+    let app = app.arg(
+        Arg::new("endpoint_id")
+            .short('e')
+            .long("endpoint_id")
+            .global(true)
+            .num_args(1)
+            .number_of_values(1)
+            .value_name("ENDPOINT_ID")
+            .conflicts_with("ip")
+            .help("Endpoint Id of the device to connect to via Iroh"),
+    );
+
+    #[cfg(feature = "iroh")]
+    let app = app.arg(
+        Arg::new("relay")
+            .short('r')
+            .long("relay")
+            .global(true)
+            .num_args(1)
+            .number_of_values(1)
+            .value_name("RELAY")
+            .requires("endpoint_id")
+            .help("RelayURL of the device to connect to via Iroh"),
+    );
+
+    let app = app.arg(
+        Arg::new("ip")
+            .short('i')
+            .long("ip")
+            .global(true)
+            .num_args(1)
+            .number_of_values(1)
+            .value_name("IP")
+            .help("'IP:port' of device to connect to via TCP"),
+    );
+
+    let app = app.arg(
+        Arg::new("pair")
+            .short('p')
+            .long("pair")
+            .global(true)
+            .num_args(1)
+            .number_of_values(1)
+            .value_name("CODE")
+            .requires("ip")
+            .help("Pairing code shown by the device, to pair with it before connecting via TCP"),
+    );
+
+    #[cfg(feature = "usb")]
+    let app = app.arg(
+        Arg::new("usb")
+            .short('u')
+            .long("usb")
+            .global(true)
+            .num_args(1)
+            .number_of_values(1)
+            .value_name("Serial")
+            .conflicts_with("ip")
+            .help("Serial Number of a device to connect to via USB"),
+    );
+
+    // Conflicts can only name arguments that exist, so this is only added with both transports
+    #[cfg(all(feature = "iroh", feature = "usb"))]
+    let app = app.mut_arg("usb", |arg| arg.conflicts_with("endpoint_id"));
+
+    app.get_matches()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parse_pin_function;
+    use pigdef::config::InputPull;
+    use pigdef::pin_function::PinFunction;
+
+    #[test]
+    fn parse_short_pin_functions() {
+        assert_eq!(parse_pin_function("none").ok(), Some(None));
+        assert_eq!(
+            parse_pin_function("input:pullup").ok(),
+            Some(Some(PinFunction::Input(Some(InputPull::PullUp))))
+        );
+        assert_eq!(
+            parse_pin_function("output:high").ok(),
+            Some(Some(PinFunction::Output(Some(true))))
+        );
+        assert_eq!(
+            parse_pin_function("pwm:50:0.075").ok(),
+            Some(Some(PinFunction::Pwm {
+                frequency: 50.0,
+                duty_cycle: 0.075
+            }))
+        );
+    }
+
+    #[test]
+    fn parse_json_pin_function() {
+        assert_eq!(
+            parse_pin_function("\"I2C1_SDA\"").ok(),
+            Some(Some(PinFunction::I2C1_SDA))
+        );
+    }
+
+    #[test]
+    fn parse_invalid_pin_function() {
+        assert!(parse_pin_function("output:maybe").is_err());
+        assert!(parse_pin_function("pwm:fast:0.5").is_err());
+    }
+}
@@ -0,0 +1,109 @@
+use crate::support::{delete_configs, kill_all, parse_pigglet, pass, run, wait_for_stdout};
+use serde_json::Value;
+use serial_test::serial;
+use std::net::IpAddr;
+
+#[path = "../../piggui/tests/support.rs"]
+mod support;
+
+/// Run piggcli connected to the pigglet at `ip`:`port` with `args`, wait for it to exit
+/// successfully and return what it printed to stdout
+fn piggcli(ip: IpAddr, port: u16, args: &[&str]) -> String {
+    let mut options = vec!["--ip".to_string(), format!("{ip}:{port}")];
+    options.extend(args.iter().map(|arg| arg.to_string()));
+    let output = run("piggcli", options, None)
+        .wait_with_output()
+        .expect("Could not wait for piggcli");
+    assert!(output.status.success(), "piggcli {args:?} failed");
+    String::from_utf8(output.stdout).expect("piggcli output was not UTF-8")
+}
+
+/// Run piggcli with `args` and parse the single line of JSON it printed
+fn piggcli_json(ip: IpAddr, port: u16, args: &[&str]) -> Value {
+    serde_json::from_str(&piggcli(ip, port, args)).expect("piggcli output was not JSON")
+}
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn describe_tcp() {
+    kill_all("pigglet");
+    let mut pigglet = run("pigglet", vec!["--no_auth".into()], None);
+    let (ip, port, _, _relay) = parse_pigglet(&mut pigglet).await;
+
+    let description = piggcli_json(ip, port, &["describe"]);
+    assert!(description["details"]["model"]
+        .as_str()
+        .expect("No model in description")
+        .contains("Fake"));
+
+    pass(&mut pigglet);
+}
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn set_and_toggle_output_tcp() {
+    kill_all("pigglet");
+    delete_configs();
+    let mut pigglet = run("pigglet", vec!["--no_auth".into()], None);
+    let (ip, port, _, _relay) = parse_pigglet(&mut pigglet).await;
+
+    piggcli(ip, port, &["set", "17", "output:low"]);
+    let config = piggcli_json(ip, port, &["config"]);
+    assert_eq!(config["pin_functions"]["17"]["Output"], Value::Bool(false));
+
+    piggcli(ip, port, &["output", "17", "toggle"]);
+    let config = piggcli_json(ip, port, &["config"]);
+    assert_eq!(config["pin_functions"]["17"]["Output"], Value::Bool(true));
+
+    pass(&mut pigglet);
+}
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn watch_input_tcp() {
+    kill_all("pigglet");
+    delete_configs();
+    let mut pigglet = run("pigglet", vec!["--no_auth".into()], None);
+    let (ip, port, _, _relay) = parse_pigglet(&mut pigglet).await;
+
+    // The fake hardware changes the level of inputs at random, and reports it each time
+    piggcli(ip, port, &["set", "4", "input:pullup"]);
+    let events = piggcli(ip, port, &["watch", "--count", "2"]);
+    let lines: Vec<&str> = events.lines().collect();
+    assert_eq!(lines.len(), 2);
+    for line in lines {
+        let event: Value = serde_json::from_str(line).expect("Event was not JSON");
+        assert_eq!(event["IOLevelChanged"][0], Value::from(4));
+    }
+
+    pass(&mut pigglet);
+}
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn pair_tcp() {
+    kill_all("pigglet");
+    let port = portpicker::pick_unused_port().expect("Could not find a free port");
+    let mut pigglet = run(
+        "pigglet",
+        vec![
+            "--bind".into(),
+            "127.0.0.1".into(),
+            "--port".into(),
+            port.to_string(),
+        ],
+        None,
+    );
+    let line = wait_for_stdout(&mut pigglet, "Pairing code:", Some("Error:"));
+    let (_, code) = line.split_once(':').expect("Could not parse pairing code");
+    let ip = IpAddr::from([127, 0, 0, 1]);
+
+    let description = piggcli_json(ip, port, &["--pair", code.trim(), "describe"]);
+    assert!(description["details"]["model"].is_string());
+
+    // The key saved when pairing is used to connect again
+    let config = piggcli_json(ip, port, &["config"]);
+    assert!(config["pin_functions"].is_object());
+
+    pass(&mut pigglet);
+}
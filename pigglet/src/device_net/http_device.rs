@@ -0,0 +1,195 @@
+use crate::device_net::hub::{ClientId, ClientMessage, Clients, HTTP_CLIENT};
+use crate::settings::HttpSettings;
+use pigdef::config::HardwareConfigMessage::{IOLevelChanged, NewPinConfig};
+use pigdef::config::{HardwareConfig, HardwareConfigMessage, LevelChange};
+use pigdef::description::{BCMPinNumber, HardwareDescription, PinLevel};
+use pigdef::pin_function::PinFunction;
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::Response;
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
+use futures::channel::mpsc::{Receiver, UnboundedSender};
+use futures::StreamExt;
+use log::{debug, info};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::net::TcpListener;
+
+/// The state shared by the handlers of all HTTP requests
+#[derive(Clone)]
+struct HttpState {
+    desc: Arc<HardwareDescription>,
+    clients: Clients,
+    to_hub: UnboundedSender<ClientMessage>,
+}
+
+impl HttpState {
+    /// Check `bcm_pin_number` is a pin of the hardware, before sending a change to it
+    fn check_pin(&self, bcm_pin_number: BCMPinNumber) -> Result<(), StatusCode> {
+        if self
+            .desc
+            .pins
+            .pins()
+            .iter()
+            .any(|pin| pin.bcm == Some(bcm_pin_number))
+        {
+            Ok(())
+        } else {
+            Err(StatusCode::NOT_FOUND)
+        }
+    }
+
+    /// Send a config change to the hub, to be applied in order with those from other clients.
+    /// It is accepted, not applied, when this returns, so the response does not say if it worked.
+    fn send(&self, message: HardwareConfigMessage) -> StatusCode {
+        match self.to_hub.unbounded_send((HTTP_CLIENT, message)) {
+            Ok(()) => StatusCode::ACCEPTED,
+            Err(_) => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+/// Bind a listener for the HTTP API to the address and port in `settings`
+pub async fn get_listener(settings: &HttpSettings) -> anyhow::Result<TcpListener> {
+    let address = SocketAddr::new(settings.bind_address, settings.port);
+    let listener = TcpListener::bind(address).await?;
+    println!("HTTP API @ {address}");
+    info!("Serving HTTP API @ {address}");
+    Ok(listener)
+}
+
+/// Serve the HTTP API on `listener` for as long as it works. Changes requested are sent to the
+/// hub, and each WebSocket connected is added to `clients` to stream level changes to it
+pub async fn serve(
+    listener: TcpListener,
+    desc: HardwareDescription,
+    clients: Clients,
+    to_hub: UnboundedSender<ClientMessage>,
+) -> anyhow::Result<()> {
+    let state = HttpState {
+        desc: Arc::new(desc),
+        clients,
+        to_hub,
+    };
+    axum::serve(listener, router(state)).await?;
+    Ok(())
+}
+
+/// The routes of the HTTP API
+fn router(state: HttpState) -> Router {
+    Router::new()
+        .route("/description", get(get_description))
+        .route("/config", get(get_config))
+        .route("/pins/{bcm}", put(put_pin_function))
+        .route("/pins/{bcm}/level", post(post_output_level))
+        .route("/events", get(events))
+        .route("/metrics", get(get_metrics))
+        .with_state(state)
+}
+
+/// `GET /description` - the [HardwareDescription] of the hardware
+async fn get_description(State(state): State<HttpState>) -> Json<HardwareDescription> {
+    Json(state.desc.as_ref().clone())
+}
+
+/// `GET /config` - the current [HardwareConfig], with all changes applied so far
+async fn get_config(State(state): State<HttpState>) -> Result<Json<HardwareConfig>, StatusCode> {
+    state
+        .clients
+        .config()
+        .map(Json)
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// `GET /metrics` - counts of clients, messages, input edges and decode errors, and output
+/// levels, in the Prometheus text format
+async fn get_metrics(
+    State(state): State<HttpState>,
+) -> Result<([(header::HeaderName, &'static str); 1], String), StatusCode> {
+    let metrics = state
+        .clients
+        .metrics()
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics,
+    ))
+}
+
+/// `PUT /pins/{bcm}` - configure a pin with the [PinFunction] in the body, or unconfigure it
+/// if the body is `null`
+async fn put_pin_function(
+    State(state): State<HttpState>,
+    Path(bcm_pin_number): Path<BCMPinNumber>,
+    Json(pin_function): Json<Option<PinFunction>>,
+) -> StatusCode {
+    if let Err(status) = state.check_pin(bcm_pin_number) {
+        return status;
+    }
+    state.send(NewPinConfig(bcm_pin_number, pin_function))
+}
+
+/// `POST /pins/{bcm}/level` - set an output to the level in the body, `true` or `false`
+async fn post_output_level(
+    State(state): State<HttpState>,
+    Path(bcm_pin_number): Path<BCMPinNumber>,
+    Json(level): Json<PinLevel>,
+) -> StatusCode {
+    if let Err(status) = state.check_pin(bcm_pin_number) {
+        return status;
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    state.send(IOLevelChanged(bcm_pin_number, LevelChange::new(level, now)))
+}
+
+/// `GET /events` - upgrade to a WebSocket that streams [IOLevelChanged] messages as JSON.
+/// It is added as a client before the upgrade completes, so it misses no changes made after.
+async fn events(
+    State(state): State<HttpState>,
+    upgrade: WebSocketUpgrade,
+) -> Result<Response, StatusCode> {
+    let (client_id, outgoing, _config) = state
+        .clients
+        .add("websocket")
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(upgrade.on_upgrade(move |socket| stream_events(socket, client_id, outgoing, state.clients)))
+}
+
+/// Send the level changes of inputs and outputs queued for a client to its WebSocket, until it
+/// is closed or the client is removed for not keeping up. Anything sent by it is ignored.
+async fn stream_events(
+    mut socket: WebSocket,
+    client_id: ClientId,
+    mut outgoing: Receiver<HardwareConfigMessage>,
+    clients: Clients,
+) {
+    debug!("WebSocket connected as client #{client_id}");
+    loop {
+        tokio::select! {
+            message = outgoing.next() => match message {
+                Some(message @ IOLevelChanged(..)) => {
+                    let Ok(json) = serde_json::to_string(&message) else {
+                        continue;
+                    };
+                    if socket.send(Message::Text(json.into())).await.is_err() {
+                        break;
+                    }
+                }
+                Some(_) => {}
+                None => break,
+            },
+            received = socket.recv() => match received {
+                Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+
+    clients.remove(client_id);
+}
@@ -0,0 +1,650 @@
+use crate::metrics::Metrics;
+use crate::rules::Rules;
+use crate::sequencer::{SequenceEvent, Sequencer};
+use anyhow::bail;
+use futures::channel::mpsc::{channel, Receiver, Sender};
+use futures::{Stream, StreamExt};
+use log::{info, trace, warn};
+use pigdef::config::HardwareConfigMessage::{
+    DutyCycleChanged, IOLevelChanged, NewConfig, NewPinConfig, SafeLevelChanged, SequenceStatus,
+};
+use pigdef::config::{ClientCounts, HardwareConfig, HardwareConfigMessage, LevelChange};
+use pigdef::description::{BCMPinNumber, PinLevel};
+use pigdef::i2c::I2cResponse;
+use pigdef::pin_function::PinFunction;
+use pigdef::pin_function::PinFunction::{Output, Pwm};
+use pigdef::sequence::SequenceState;
+use pigdef::spi::SpiResponse;
+use piggpio::config::store_config;
+use piggpio::HW;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::path::Path;
+use std::pin::pin;
+use std::sync::{Arc, Mutex};
+
+/// Identifies one connected client, unique for the life of the pigglet process
+pub type ClientId = u64;
+
+/// A message received from a client, to be applied to the hardware by the hub
+pub type ClientMessage = (ClientId, HardwareConfigMessage);
+
+/// The [ClientId] used for changes made by [Rules], that all connected clients are informed of
+pub const RULES_CLIENT: ClientId = ClientId::MAX;
+
+/// The [ClientId] used for changes made by running sequences and schedules
+const SEQUENCER_CLIENT: ClientId = ClientId::MAX - 1;
+
+/// The [ClientId] used for changes requested using the HTTP API, that all connected clients
+/// are informed of
+#[cfg(feature = "http")]
+pub const HTTP_CLIENT: ClientId = ClientId::MAX - 2;
+
+/// The [ClientId] used for outputs set by commands received from the MQTT broker, so the
+/// MQTT bridge is informed of them like all other clients
+#[cfg(feature = "mqtt")]
+pub const MQTT_CLIENT: ClientId = ClientId::MAX - 3;
+
+/// The [ClientId] used for outputs driven to their safe level when pigglet shuts down. It is
+/// the lowest of those used by pigglet itself, rather than by clients that connect to it
+const SHUTDOWN_CLIENT: ClientId = ClientId::MAX - 4;
+
+/// The most messages that can be queued to be sent to a client. A client that falls this far
+/// behind (e.g. with a stalled connection while analog samples are being sent) is disconnected,
+/// so it cannot make pigglet's memory use grow without limit
+const CLIENT_QUEUE_LENGTH: usize = 512;
+
+/// The set of connected clients, each with a channel of messages to be sent to it, plus a copy
+/// of the current [HardwareConfig] to send to new clients when they connect, and the [Metrics]
+/// of messages passed between them and the hardware. Clones share the same set of clients.
+#[derive(Clone)]
+pub struct Clients {
+    shared: Arc<Mutex<Shared>>,
+    /// Run on each input level change, before it is sent to clients
+    rules: Option<Arc<Rules>>,
+}
+
+struct Shared {
+    next_id: ClientId,
+    config: HardwareConfig,
+    senders: HashMap<ClientId, Client>,
+    metrics: Metrics,
+}
+
+/// A connected client
+struct Client {
+    /// The name of the transport it is connected over
+    transport: &'static str,
+    sender: Sender<HardwareConfigMessage>,
+}
+
+/// Queue `message` to be sent to the `client` with `id`, counting it in `metrics`. Returns false
+/// if the client should be removed, as its queue is full or it has disconnected
+fn queue(
+    id: ClientId,
+    client: &mut Client,
+    message: HardwareConfigMessage,
+    metrics: &mut Metrics,
+) -> bool {
+    metrics.sent(&message);
+    match client.sender.try_send(message) {
+        Ok(()) => true,
+        Err(e) if e.is_full() => {
+            warn!("Client #{id} is not keeping up with messages sent to it, disconnecting it");
+            false
+        }
+        Err(_) => false,
+    }
+}
+
+impl Clients {
+    /// Create an empty set of clients for hardware with the initial `config`
+    pub fn new(config: HardwareConfig) -> Self {
+        Clients {
+            shared: Arc::new(Mutex::new(Shared {
+                next_id: 0,
+                config,
+                senders: HashMap::new(),
+                metrics: Metrics::new(),
+            })),
+            rules: None,
+        }
+    }
+
+    /// Create an empty set of clients for hardware with the initial `config`, whose input
+    /// level changes are also run through `rules`
+    pub fn with_rules(config: HardwareConfig, rules: Rules) -> Self {
+        Clients {
+            rules: Some(Arc::new(rules)),
+            ..Self::new(config)
+        }
+    }
+
+    /// Register a new client connected over `transport`. Returns its [ClientId], the receiver
+    /// of messages to be sent to it and the current [HardwareConfig] it should be sent first.
+    /// Changes made after this are queued in the receiver, so the client does not miss any.
+    pub fn add(
+        &self,
+        transport: &'static str,
+    ) -> Option<(ClientId, Receiver<HardwareConfigMessage>, HardwareConfig)> {
+        let mut shared = self.shared.lock().ok()?;
+        let id = shared.next_id;
+        shared.next_id = shared.next_id.wrapping_add(1);
+        let (sender, receiver) = channel(CLIENT_QUEUE_LENGTH);
+        shared.senders.insert(id, Client { transport, sender });
+        info!(
+            "Client #{id} connected via {transport}, {} connected",
+            shared.senders.len()
+        );
+        Some((id, receiver, shared.config.clone()))
+    }
+
+    /// Remove a client that has disconnected. Dropping its sender ends the task sending
+    /// messages to it, which closes the connection if it is still open
+    pub fn remove(&self, id: ClientId) {
+        if let Ok(mut shared) = self.shared.lock() {
+            if let Some(client) = shared.senders.remove(&id) {
+                info!(
+                    "Client #{id} via {} disconnected, {} connected",
+                    client.transport,
+                    shared.senders.len()
+                );
+            }
+        }
+    }
+
+    /// The number of clients connected over each transport
+    fn counts(&self) -> ClientCounts {
+        let mut counts = ClientCounts::default();
+        if let Ok(shared) = self.shared.lock() {
+            for client in shared.senders.values() {
+                let count = match client.transport {
+                    // TLS is TCP, only encrypted
+                    "tcp" | "tls" => &mut counts.tcp,
+                    "iroh" => &mut counts.iroh,
+                    "websocket" => &mut counts.websocket,
+                    "mqtt" => &mut counts.mqtt,
+                    _ => continue,
+                };
+                *count += 1;
+            }
+        }
+        counts
+    }
+
+    /// The current [HardwareConfig], with all changes applied so far
+    #[cfg(feature = "http")]
+    pub fn config(&self) -> Option<HardwareConfig> {
+        self.shared.lock().ok().map(|shared| shared.config.clone())
+    }
+
+    /// Queue `message` to be sent to the client `id`
+    pub fn send_to(&self, id: ClientId, message: HardwareConfigMessage) {
+        if let Ok(mut shared) = self.shared.lock() {
+            let Shared {
+                senders, metrics, ..
+            } = &mut *shared;
+            if let Some(client) = senders.get_mut(&id) {
+                if !queue(id, client, message, metrics) {
+                    senders.remove(&id);
+                }
+            }
+        }
+    }
+
+    /// Queue `message` to be sent to all connected clients
+    pub fn broadcast(&self, message: HardwareConfigMessage) {
+        if let Ok(mut shared) = self.shared.lock() {
+            let Shared {
+                senders, metrics, ..
+            } = &mut *shared;
+            senders.retain(|id, client| queue(*id, client, message.clone(), metrics));
+        }
+    }
+
+    /// Count a message received by the hub in the [Metrics]
+    fn received(&self, message: &HardwareConfigMessage) {
+        if let Ok(mut shared) = self.shared.lock() {
+            shared.metrics.received(message);
+        }
+    }
+
+    /// Count a message received over `transport` that could not be decoded in the [Metrics]
+    pub fn decode_error(&self, transport: &'static str) {
+        if let Ok(mut shared) = self.shared.lock() {
+            shared.metrics.decode_error(transport);
+        }
+    }
+
+    /// The [Metrics] in the Prometheus text format
+    #[cfg(feature = "http")]
+    pub fn metrics(&self) -> Option<String> {
+        let shared = self.shared.lock().ok()?;
+        let transports = shared.senders.values().map(|client| client.transport);
+        Some(shared.metrics.render(transports, &shared.config))
+    }
+
+    /// Record the new `config` after a change requested by client `from`, and queue `message`
+    /// describing the change to be sent to all the other clients
+    fn config_changed(
+        &self,
+        from: ClientId,
+        config: &HardwareConfig,
+        message: HardwareConfigMessage,
+    ) {
+        if let Some(rules) = self.rules.as_ref().filter(|_| from != RULES_CLIENT) {
+            rules.changed_by_client(&message);
+        }
+        if let Ok(mut shared) = self.shared.lock() {
+            shared.config = config.clone();
+            let Shared {
+                senders, metrics, ..
+            } = &mut *shared;
+            metrics.config_changed(&message);
+            senders
+                .retain(|id, client| *id == from || queue(*id, client, message.clone(), metrics));
+        }
+    }
+
+    /// The level an output is set to in the current config, low if it has not been set
+    fn output_level(&self, bcm_pin_number: BCMPinNumber) -> PinLevel {
+        self.shared.lock().is_ok_and(|shared| {
+            matches!(
+                shared.config.pin_functions.get(&bcm_pin_number),
+                Some(Output(Some(true)))
+            )
+        })
+    }
+
+    /// Return a callback for the hardware that runs any rules on input level changes, and
+    /// sends input level changes and analog samples to all clients
+    pub fn input_callback(
+        &self,
+    ) -> impl FnMut(HardwareConfigMessage) + Send + Sync + Clone + 'static {
+        let clients = self.clone();
+        move |input_event| {
+            trace!("Input event: {input_event:?}");
+            if let IOLevelChanged(bcm, level_change) = &input_event {
+                if let Ok(mut shared) = clients.shared.lock() {
+                    shared.metrics.input_level(*bcm, level_change.new_level);
+                }
+                if let Some(rules) = &clients.rules {
+                    rules.input_changed(*bcm, level_change, |output| clients.output_level(output));
+                }
+            }
+            clients.broadcast(input_event);
+        }
+    }
+}
+
+/// Apply the messages received from all clients to the single [HW], in the order received,
+/// storing each change in the config file and sending it to the other clients. Sequences and
+/// schedules started by clients are run until stopped, or this returns.
+/// Returns when there are no more senders of [ClientMessage]s, or when `shutdown` completes,
+/// after driving outputs to their safe levels.
+pub async fn run(
+    hardware: &mut HW,
+    hardware_config: &mut HardwareConfig,
+    config_file_path: &Path,
+    clients: &Clients,
+    mut from_clients: impl Stream<Item = ClientMessage> + Unpin,
+    shutdown: impl Future<Output = ()>,
+) {
+    let (mut sequencer, mut sequence_events) = Sequencer::new();
+    let mut shutdown = pin!(shutdown);
+    // Connected clients that have sent a change. Outputs are driven to safe levels when the last
+    // of them goes away. Those that only ask about the device, such as `pigglet --status`, and
+    // pigglet's own rules, HTTP API and MQTT bridge are not included.
+    let mut controlling = HashSet::new();
+    loop {
+        tokio::select! {
+            client_message = from_clients.next() => {
+                let Some((from, config_message)) = client_message else {
+                    break;
+                };
+                clients.received(&config_message);
+                let query = is_query(&config_message);
+                match config_message {
+                    HardwareConfigMessage::Disconnect if !controlling.remove(&from) => continue,
+                    HardwareConfigMessage::Disconnect if !controlling.is_empty() => continue,
+                    HardwareConfigMessage::Disconnect => {}
+                    _ if query || from >= SHUTDOWN_CLIENT => {}
+                    _ => {
+                        controlling.insert(from);
+                    }
+                }
+                match apply_config_change(
+                    hardware,
+                    from,
+                    config_message,
+                    hardware_config,
+                    clients,
+                    &mut sequencer,
+                )
+                .await
+                {
+                    Ok(()) if query => {}
+                    Ok(()) => {
+                        let _ = store_config(hardware_config, config_file_path).await;
+                    }
+                    Err(e) if from == RULES_CLIENT => {
+                        warn!("Could not apply change made by rules: {e}")
+                    }
+                    Err(e) => info!("Could not apply change from client #{from}: {e}"),
+                }
+            }
+            Some(event) = sequence_events.next() => {
+                sequence_event(hardware, hardware_config, clients, &mut sequencer, event).await;
+            }
+            () = &mut shutdown => {
+                info!("Shutting down");
+                apply_safe_levels(hardware, SHUTDOWN_CLIENT, hardware_config, clients);
+                let _ = store_config(hardware_config, config_file_path).await;
+                break;
+            }
+        }
+    }
+}
+
+/// Apply the output change made by a running sequence or schedule, stopping it if that fails,
+/// or inform clients that a sequence has finished. Output changes made by sequences can be
+/// frequent, so they are not stored in the config file.
+async fn sequence_event(
+    hardware: &mut HW,
+    hardware_config: &mut HardwareConfig,
+    clients: &Clients,
+    sequencer: &mut Sequencer,
+    event: SequenceEvent,
+) {
+    if !sequencer.is_current(&event) {
+        return;
+    }
+
+    match event.step {
+        Some((bcm, level)) => {
+            let level_change = LevelChange::new(level, hardware.get_time_since_boot());
+            if let Err(e) = apply_config_change(
+                hardware,
+                SEQUENCER_CLIENT,
+                IOLevelChanged(bcm, level_change),
+                hardware_config,
+                clients,
+                sequencer,
+            )
+            .await
+            {
+                warn!("Could not apply change made by sequence #{}: {e}", event.id);
+                sequencer.stop(event.id);
+                clients.broadcast(SequenceStatus(event.id, SequenceState::Stopped));
+            }
+        }
+        None => clients.broadcast(SequenceStatus(event.id, SequenceState::Stopped)),
+    }
+}
+
+/// Apply a config change requested by client `from` to the hardware, then inform the other
+/// clients of the change
+async fn apply_config_change(
+    hardware: &mut HW,
+    from: ClientId,
+    config_change: HardwareConfigMessage,
+    hardware_config: &mut HardwareConfig,
+    clients: &Clients,
+    sequencer: &mut Sequencer,
+) -> anyhow::Result<()> {
+    match config_change {
+        NewConfig(config) => {
+            info!("New config applied");
+            hardware
+                .apply_config(&config, clients.input_callback())
+                .await?;
+
+            // replace the entire config with the new one
+            *hardware_config = config;
+            clients.config_changed(from, hardware_config, NewConfig(hardware_config.clone()));
+            send_current_input_levels(clients, hardware_config, hardware);
+        }
+        NewPinConfig(bcm, pin_function) => {
+            info!("New pin config for pin #{bcm}: {pin_function:?}");
+            hardware
+                .apply_pin_config(bcm, &pin_function, clients.input_callback())
+                .await?;
+
+            if let Some(function) = pin_function {
+                // add/replace the new pin config to the hardware config
+                hardware_config.pin_functions.insert(bcm, function);
+            } else {
+                // if No new function was set (None), then remove from the current hardware_config
+                info!("Removing pin from pin_functions");
+                hardware_config.pin_functions.remove(&bcm);
+            }
+            clients.config_changed(from, hardware_config, NewPinConfig(bcm, pin_function));
+
+            if let Some(function) = pin_function {
+                // if a new config was set, send the new input state for that pin
+                send_current_input_level(clients, &bcm, &function, hardware);
+            }
+        }
+        IOLevelChanged(bcm, level_change) => {
+            trace!("Pin #{bcm} Output level change: {level_change:?}");
+            set_output_level(hardware, from, bcm, level_change, hardware_config, clients)?;
+        }
+        SafeLevelChanged(bcm, level) => {
+            info!("Safe level for pin #{bcm}: {level:?}");
+            match level {
+                Some(level) => {
+                    if !matches!(hardware_config.pin_functions.get(&bcm), Some(Output(_))) {
+                        bail!("Pin #{bcm} is not configured as an output");
+                    }
+                    hardware_config.safe_levels.insert(bcm, level);
+                }
+                None => {
+                    hardware_config.safe_levels.remove(&bcm);
+                }
+            }
+            clients.config_changed(from, hardware_config, SafeLevelChanged(bcm, level));
+        }
+        DutyCycleChanged(bcm, duty_cycle) => {
+            trace!("Pin #{bcm} Duty cycle change: {duty_cycle}");
+            hardware.set_duty_cycle(bcm, duty_cycle)?;
+            // update the duty cycle, keeping the frequency, of the pin in the hardware config
+            if let Some(Pwm {
+                duty_cycle: config_duty_cycle,
+                ..
+            }) = hardware_config.pin_functions.get_mut(&bcm)
+            {
+                *config_duty_cycle = duty_cycle;
+            }
+            clients.config_changed(from, hardware_config, DutyCycleChanged(bcm, duty_cycle));
+        }
+        HardwareConfigMessage::GetConfig => {
+            clients.send_to(from, NewConfig(hardware_config.clone()));
+        }
+        HardwareConfigMessage::I2cRequest(request) => {
+            trace!("I2C request: {request:?}");
+            // Errors are sent back to the client that made the request, not returned
+            let response = hardware
+                .i2c_request(&request)
+                .unwrap_or_else(|e| I2cResponse::Error(e.to_string()));
+            clients.send_to(from, HardwareConfigMessage::I2cResponse(response));
+        }
+        HardwareConfigMessage::SpiRequest(request) => {
+            trace!("SPI request: {request:?}");
+            let response = hardware
+                .spi_request(&request)
+                .unwrap_or_else(|e| SpiResponse::Error(e.to_string()));
+            clients.send_to(from, HardwareConfigMessage::SpiResponse(response));
+        }
+        HardwareConfigMessage::UartSend(bytes) => {
+            trace!("UART send: {bytes:?}");
+            // Bytes received in reply are sent to all clients by the hardware's callback
+            hardware.uart_send(&bytes)?;
+        }
+        HardwareConfigMessage::StartSequence(id, sequence) => {
+            sequencer.start_sequence(id, sequence)?;
+            clients.broadcast(SequenceStatus(id, sequencer.status(id)));
+        }
+        HardwareConfigMessage::StartSchedule(id, schedule) => {
+            sequencer.start_schedule(id, schedule)?;
+            clients.broadcast(SequenceStatus(id, sequencer.status(id)));
+        }
+        HardwareConfigMessage::StopSequence(id) => {
+            if sequencer.stop(id) {
+                clients.broadcast(SequenceStatus(id, SequenceState::Stopped));
+            }
+        }
+        HardwareConfigMessage::GetSequenceStatus(id) => {
+            clients.send_to(from, SequenceStatus(id, sequencer.status(id)));
+        }
+        HardwareConfigMessage::GetClientCounts => {
+            clients.send_to(from, HardwareConfigMessage::ClientCounts(clients.counts()));
+        }
+        HardwareConfigMessage::Ping(sequence) => {
+            clients.send_to(from, HardwareConfigMessage::Pong(sequence));
+        }
+        // Sent by the transport when a client disconnects, or its connection is lost, and passed
+        // on when it was the last connected client that had made changes
+        HardwareConfigMessage::Disconnect => {
+            apply_safe_levels(hardware, from, hardware_config, clients);
+        }
+        // Samples, responses, received bytes, sequence states and heartbeat responses are only
+        // sent from the hardware to clients
+        HardwareConfigMessage::AnalogSampled(..)
+        | HardwareConfigMessage::I2cResponse(..)
+        | HardwareConfigMessage::SpiResponse(..)
+        | HardwareConfigMessage::UartReceived(..)
+        | SequenceStatus(..)
+        | HardwareConfigMessage::ClientCounts(..)
+        | HardwareConfigMessage::Pong(..) => {}
+    }
+
+    Ok(())
+}
+
+/// Return true if `message` only asks for information about the device, without changing it
+fn is_query(message: &HardwareConfigMessage) -> bool {
+    matches!(
+        message,
+        HardwareConfigMessage::GetConfig
+            | HardwareConfigMessage::GetSequenceStatus(_)
+            | HardwareConfigMessage::GetClientCounts
+            | HardwareConfigMessage::Ping(_)
+    )
+}
+
+/// Set the level of output `bcm`, record it in the config and inform clients other than `from`
+fn set_output_level(
+    hardware: &mut HW,
+    from: ClientId,
+    bcm: BCMPinNumber,
+    level_change: LevelChange,
+    hardware_config: &mut HardwareConfig,
+    clients: &Clients,
+) -> anyhow::Result<()> {
+    hardware.set_output_level(bcm, level_change.new_level)?;
+    // add/replace the new pin config to the hardware config
+    hardware_config
+        .pin_functions
+        .insert(bcm, Output(Some(level_change.new_level)));
+    clients.config_changed(from, hardware_config, IOLevelChanged(bcm, level_change));
+    Ok(())
+}
+
+/// Drive each output with a safe level that is not already at it to that level, as client
+/// `from`, the last one that had made changes, has gone away or pigglet is shutting down. Failures are logged, so one output that
+/// cannot be set does not stop the others being made safe.
+fn apply_safe_levels(
+    hardware: &mut HW,
+    from: ClientId,
+    hardware_config: &mut HardwareConfig,
+    clients: &Clients,
+) {
+    let unsafe_outputs: Vec<(BCMPinNumber, PinLevel)> = hardware_config
+        .safe_levels
+        .iter()
+        .filter(|(bcm, level)| {
+            matches!(hardware_config.pin_functions.get(bcm),
+                Some(Output(current)) if *current != Some(**level))
+        })
+        .map(|(bcm, level)| (*bcm, *level))
+        .collect();
+
+    for (bcm, level) in unsafe_outputs {
+        info!("Setting pin #{bcm} to its safe level: {level}");
+        let level_change = LevelChange::new(level, hardware.get_time_since_boot());
+        if let Err(e) =
+            set_output_level(hardware, from, bcm, level_change, hardware_config, clients)
+        {
+            warn!("Could not set pin #{bcm} to its safe level: {e}");
+        }
+    }
+}
+
+/// Send the current input level for all inputs configured in the config to all clients
+fn send_current_input_levels(clients: &Clients, config: &HardwareConfig, hardware: &HW) {
+    for (bcm_pin_number, pin_function) in &config.pin_functions {
+        send_current_input_level(clients, bcm_pin_number, pin_function, hardware);
+    }
+}
+
+/// Send the current input level for one input to all clients - with a timestamp that will
+/// match with future LevelChange timestamps (time since boot)
+fn send_current_input_level(
+    clients: &Clients,
+    bcm_pin_number: &BCMPinNumber,
+    pin_function: &PinFunction,
+    hardware: &HW,
+) {
+    let now = hardware.get_time_since_boot(); // jonesy:allow(expect)
+
+    if let PinFunction::Input(_pullup) = pin_function {
+        if let Ok(initial_level) = hardware.get_input_level(*bcm_pin_number) {
+            let level_change = LevelChange::new(initial_level, now);
+            trace!("Pin #{bcm_pin_number} Input level change: {level_change:?}");
+            clients.broadcast(IOLevelChanged(*bcm_pin_number, level_change));
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod test {
+    use super::{Clients, CLIENT_QUEUE_LENGTH};
+    use futures::{FutureExt, StreamExt};
+    use pigdef::config::HardwareConfigMessage::GetConfig;
+    use pigdef::config::{ClientCounts, HardwareConfig};
+
+    #[test]
+    fn client_that_falls_behind_is_removed() {
+        let clients = Clients::new(HardwareConfig::default());
+        let (_id, mut outgoing, _config) = clients.add("test").expect("Could not add client");
+        for _ in 0..CLIENT_QUEUE_LENGTH + 2 {
+            clients.broadcast(GetConfig);
+        }
+        assert!(clients.shared.lock().expect("Lock").senders.is_empty());
+        // The messages queued before it fell behind are still delivered, then the queue ends
+        let mut queued = 0;
+        while let Some(Some(_)) = outgoing.next().now_or_never() {
+            queued += 1;
+        }
+        assert!(queued >= CLIENT_QUEUE_LENGTH);
+    }
+    #[test]
+    fn clients_counted_by_transport() {
+        let clients = Clients::new(HardwareConfig::default());
+        let _first = clients.add("tcp").expect("Could not add client");
+        let (id, _, _) = clients.add("tcp").expect("Could not add client");
+        let _websocket = clients.add("websocket").expect("Could not add client");
+        let _mqtt = clients.add("mqtt").expect("Could not add client");
+        clients.remove(id);
+        assert_eq!(
+            clients.counts(),
+            ClientCounts {
+                tcp: 1,
+                iroh: 0,
+                websocket: 1,
+                mqtt: 1,
+            }
+        );
+    }
+}
@@ -1,58 +1,139 @@
+use crate::device_net::hub::{ClientId, ClientMessage, Clients};
+use crate::device_net::{receive_within_heartbeats, write_key_file, HELLO};
+use crate::settings::{AllowedClient, IrohSettings, Role};
 use anyhow::{anyhow, bail};
-use iroh::endpoint::Connection;
-use iroh::{endpoint::presets, Endpoint, EndpointId, RelayUrl, SecretKey};
-use log::{debug, info, trace};
-use pigdef::config::HardwareConfig;
-use pigdef::config::HardwareConfigMessage::{IOLevelChanged, NewConfig, NewPinConfig};
-use pigdef::config::{HardwareConfigMessage, LevelChange};
-use pigdef::description::BCMPinNumber;
+use futures::channel::mpsc::{Receiver, UnboundedSender};
+use futures::StreamExt;
+use iroh::endpoint::{Connection, VarInt};
+use iroh::{
+    endpoint::presets, Endpoint, EndpointAddr, EndpointId, RelayMode, RelayUrl, SecretKey,
+    TransportAddr,
+};
+use log::{debug, info, trace, warn};
+use pigdef::config::HardwareConfigMessage;
 use pigdef::description::HardwareDescription;
 use pigdef::net_values::PIGGLET_ALPN;
-use pigdef::pin_function::PinFunction;
-use pigdef::pin_function::PinFunction::Output;
-use piggpio::config::store_config;
-use piggpio::HW;
+use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io::ErrorKind;
+use std::net::SocketAddr;
 use std::path::Path;
-use std::str::{FromStr, Lines};
 
+/// Name of the file, in the same directory as the config file, that the Iroh secret key is
+/// saved in, so that the endpoint id stays the same across restarts
+pub const IROH_KEY_FILENAME: &str = "pigglet.key";
+
+/// The error code a connection is closed with when the client is not allowed to connect, the
+/// reason given says why
+const NOT_ALLOWED: VarInt = VarInt::from_u32(1);
+
+#[derive(Serialize, Deserialize)]
 pub struct IrohDevice {
+    #[serde(with = "as_string")]
     pub endpoint_id: EndpointId,
+    #[serde(with = "as_string")]
     pub relay_url: RelayUrl,
+    pub direct_addresses: Vec<SocketAddr>,
+    /// The [Endpoint] accepting connections, only present in the running instance
+    #[serde(skip)]
     pub endpoint: Option<Endpoint>,
 }
 
+/// Serialize Iroh ids and URLs as the strings they are displayed and parsed as, so the info
+/// and settings files are readable
+pub(crate) mod as_string {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::fmt::Display;
+    use std::str::FromStr;
+
+    pub fn serialize<T: Display, S: Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
 impl IrohDevice {
-    /// Don't fail to parse on lack of endpoint data
-    pub fn parse(lines: &mut Lines) -> anyhow::Result<Self> {
-        let endpoint_id = lines.next().ok_or_else(|| anyhow!("Missing endpoint_id"))?;
-        let relay = lines.next().ok_or_else(|| anyhow!("Missing relayUrl"))?;
-        let _endpoint = lines.next();
-
-        Ok(IrohDevice {
-            endpoint_id: EndpointId::from_str(endpoint_id)?,
-            relay_url: RelayUrl::from_str(relay)?,
-            endpoint: None, // TODO
-        })
+    /// The [EndpointAddr] a client can use to connect to this device, restored from the info
+    /// file when the [Endpoint] itself is not available
+    pub fn endpoint_addr(&self) -> EndpointAddr {
+        match &self.endpoint {
+            Some(endpoint) => endpoint.addr(),
+            None => EndpointAddr::from_parts(
+                self.endpoint_id,
+                std::iter::once(TransportAddr::Relay(self.relay_url.clone())).chain(
+                    self.direct_addresses
+                        .iter()
+                        .map(|address| TransportAddr::Ip(*address)),
+                ),
+            ),
+        }
     }
 }
+
 impl Display for IrohDevice {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         writeln!(f, "endpoint_id:{}", self.endpoint_id)?;
         writeln!(f, "relay URL:{}", self.relay_url)?;
+        write!(f, "direct addresses:")?;
+        for address in &self.direct_addresses {
+            write!(f, " {address}")?;
+        }
+        writeln!(f)?;
         Ok(())
     }
 }
 
-pub async fn get_device() -> anyhow::Result<IrohDevice> {
+/// Load the Iroh [SecretKey] saved at `key_path`, or if there is none, or `rotate` is requested,
+/// generate a new one and save it there, readable only by its owner
+pub fn load_or_create_secret_key(key_path: &Path, rotate: bool) -> anyhow::Result<SecretKey> {
+    if !rotate {
+        match fs::read(key_path) {
+            Ok(bytes) => {
+                let bytes: [u8; 32] = bytes
+                    .try_into()
+                    .map_err(|_| anyhow!("Invalid Iroh secret key in {}", key_path.display()))?;
+                return Ok(SecretKey::from_bytes(&bytes));
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
     let secret_key = SecretKey::generate();
+    write_key_file(key_path, &secret_key.to_bytes())?;
+    Ok(secret_key)
+}
 
-    #[allow(unused_mut)]
+/// Get an [IrohDevice] with an [Endpoint] using `secret_key`, and the relay server in
+/// `settings` if one is set
+pub async fn get_device(
+    secret_key: SecretKey,
+    settings: &IrohSettings,
+) -> anyhow::Result<IrohDevice> {
     let mut builder = Endpoint::builder(presets::N0) // jonesy:allow(unknown)
         .secret_key(secret_key)
         .alpns(vec![PIGGLET_ALPN.to_vec()]); // jonesy:allow(misalign)
 
+    if let Some(relay_url) = &settings.relay_url {
+        builder = builder.relay_mode(RelayMode::custom([relay_url.clone()]));
+    }
+
     let endpoint = builder.bind().await?; // jonesy:allow(expect, assert, bounds)
 
     let endpoint_id = endpoint.id();
@@ -65,182 +146,179 @@ pub async fn get_device() -> anyhow::Result<IrohDevice> {
         .next()
         .ok_or_else(|| anyhow!("No relay url"))?
         .clone();
+    let direct_addresses = endpoint.addr().ip_addrs().copied().collect();
 
     Ok(IrohDevice {
         endpoint_id,
         relay_url,
+        direct_addresses,
         endpoint: Some(endpoint),
     })
 }
 
-/// accept incoming connections, returns a normal QUIC connection
-pub async fn accept_connection(
-    endpoint: &Endpoint,
-    desc: &HardwareDescription,
-    hardware_config: HardwareConfig,
-) -> anyhow::Result<Connection> {
-    debug!("Waiting for connection");
-    // jonesy:allow(assert)
-    if let Some(connecting) = endpoint.accept().await {
-        let connection = connecting.await?;
-        let endpoint_id = connection.remote_id();
-        debug!("New connection from endpoint_id: '{endpoint_id}'",);
-        trace!("Sending hardware description");
-        let mut gui_sender = connection.open_uni().await?;
-        let message = postcard::to_allocvec(&(&desc, hardware_config))?;
-        gui_sender.write_all(&message).await?; // jonesy:allow(bounds)
-        gui_sender.finish()?; // jonesy:allow(bounds)
-        Ok(connection)
-    } else {
-        bail!("Could not connect to iroh")
-    }
-}
-
-/// Process incoming config change messages from the GUI.
-/// On the end of the stream exit the loop
-pub async fn iroh_message_loop(
-    connection: Connection,
-    hardware_config: &mut HardwareConfig,
-    config_file_path: &Path,
-    hardware: &mut HW,
+/// Accept incoming connections for as long as the endpoint is open, serving each client in its
+/// own task so that many clients can be connected at the same time, and a slow client does not
+/// delay others from connecting. If there are `allowed_clients`, only those are served
+pub async fn accept_clients(
+    endpoint: Endpoint,
+    desc: HardwareDescription,
+    allowed_clients: Vec<AllowedClient>,
+    missed_heartbeats: u32,
+    clients: Clients,
+    to_hub: UnboundedSender<ClientMessage>,
 ) -> anyhow::Result<()> {
     loop {
-        let mut config_receiver = connection.accept_uni().await?;
-        info!("Waiting for message");
-        let payload = config_receiver.read_to_end(4096).await?;
-
-        if payload.is_empty() {
-            bail!("End of message stream");
-        }
-
-        if let Ok(config_message) = postcard::from_bytes(&payload) {
-            if apply_config_change(
-                hardware,
-                config_message,
-                hardware_config,
-                connection.clone(),
-            )
+        println!("Waiting for Iroh connection");
+        debug!("Waiting for connection");
+        // jonesy:allow(assert)
+        let connecting = endpoint
+            .accept()
             .await
-            .is_ok()
-            {
-                let _ = store_config(hardware_config, config_file_path).await;
+            .ok_or(anyhow!("Could not connect to iroh"))?;
+        let desc = desc.clone();
+        let allowed_clients = allowed_clients.clone();
+        let clients = clients.clone();
+        let to_hub = to_hub.clone();
+        tokio::spawn(async move {
+            let connection = connecting.await?;
+            let endpoint_id = connection.remote_id();
+            match role(&allowed_clients, &endpoint_id) {
+                Some(role) => {
+                    serve_client(
+                        connection,
+                        role,
+                        &desc,
+                        missed_heartbeats,
+                        &clients,
+                        &to_hub,
+                    )
+                    .await
+                }
+                None => {
+                    warn!("Refused connection from endpoint_id: '{endpoint_id}'");
+                    let reason = format!(
+                        "Endpoint '{endpoint_id}' is not allowed to connect. \
+                        Add it to the allowed clients in the pigglet settings"
+                    );
+                    connection.close(NOT_ALLOWED, reason.as_bytes());
+                    Ok(())
+                }
             }
-        }
+        });
     }
 }
 
-/// Apply a config change to the hardware
-/// NOTE: Initially the callback to Config/PinConfig change was async, and that compiles and runs
-/// but wasn't working - so this uses a sync callback again to fix that, and an async version of
-/// send_input_level() for use directly from the async context
-async fn apply_config_change(
-    hardware: &mut HW,
-    config_change: HardwareConfigMessage,
-    hardware_config: &mut HardwareConfig,
-    connection: Connection,
-) -> anyhow::Result<()> {
-    match config_change {
-        NewConfig(config) => {
-            info!("New config applied");
-            let cc = connection.clone();
-            hardware
-                .apply_config(&config, move |bcm, level_change| {
-                    let _ = send_input_level_sync(connection.clone(), bcm, level_change);
-                })
-                .await?;
-
-            send_current_input_levels(cc, &config, hardware).await?;
-            // replace the entire config with the new one
-            *hardware_config = config;
-        }
-        NewPinConfig(bcm, pin_function) => {
-            info!("New pin config for pin #{bcm}: {pin_function:?}");
-            let cc = connection.clone();
-            hardware
-                .apply_pin_config(bcm, &pin_function, move |bcm, level| {
-                    let _ = send_input_level_sync(connection.clone(), bcm, level);
-                })
-                .await?;
-
-            if let Some(function) = pin_function {
-                send_current_input_level(&bcm, &function, cc, hardware).await?;
-                // add/replace the new pin config to the hardware config
-                hardware_config.pin_functions.insert(bcm, function);
-            } else {
-                hardware_config.pin_functions.remove(&bcm);
-            }
-        }
-        IOLevelChanged(bcm, level_change) => {
-            trace!("Pin #{bcm} Output level change: {level_change:?}");
-            hardware.set_output_level(bcm, level_change.new_level)?;
-            // add/replace the new pin config to the hardware config
-            hardware_config
-                .pin_functions
-                .insert(bcm, Output(Some(level_change.new_level)));
-        }
-        HardwareConfigMessage::GetConfig => {
-            let message = postcard::to_allocvec(&NewConfig(hardware_config.clone()))?;
-            send(connection, &message).await?
-        }
-        HardwareConfigMessage::Disconnect => return Err(anyhow!("Disconnect message received")),
+/// The [Role] of the client with `endpoint_id`, or None if it is not allowed to connect. Any
+/// client is allowed to connect, as a [Role::Controller], if there are no `allowed_clients`
+fn role(allowed_clients: &[AllowedClient], endpoint_id: &EndpointId) -> Option<Role> {
+    if allowed_clients.is_empty() {
+        return Some(Role::Controller);
     }
-
-    Ok(())
+    allowed_clients
+        .iter()
+        .find(|client| client.endpoint_id == *endpoint_id)
+        .map(|client| client.role)
 }
 
-/// Send the current input level for all configured inputs
-async fn send_current_input_levels(
+/// Send a new client the [HELLO], the hardware description and current config, then forward the
+/// messages its `role` permits it to send to the hub until it disconnects, or misses
+/// `missed_heartbeats` after sending one
+async fn serve_client(
     connection: Connection,
-    config: &HardwareConfig,
-    hardware: &HW,
+    role: Role,
+    desc: &HardwareDescription,
+    missed_heartbeats: u32,
+    clients: &Clients,
+    to_hub: &UnboundedSender<ClientMessage>,
 ) -> anyhow::Result<()> {
-    for (bcm_pin_number, pin_function) in &config.pin_functions {
-        send_current_input_level(bcm_pin_number, pin_function, connection.clone(), hardware)
-            .await?;
+    let endpoint_id = connection.remote_id();
+    info!("New connection from endpoint_id: '{endpoint_id}' as {role:?}");
+
+    let (client_id, outgoing, hardware_config) =
+        clients.add("iroh").ok_or(anyhow!("Could not add client"))?;
+
+    trace!("Sending hello and hardware description");
+    let sent = async {
+        let message = postcard::to_allocvec(&HELLO)?;
+        let message = postcard::to_extend(&(desc, hardware_config), message)?;
+        send(connection.clone(), &message).await
     }
+    .await;
 
+    if sent.is_ok() {
+        println!("Connection via Iroh");
+        tokio::spawn(send_to_client(connection.clone(), outgoing));
+        if let Err(e) = iroh_message_loop(
+            connection,
+            role,
+            client_id,
+            missed_heartbeats,
+            clients,
+            to_hub,
+        )
+        .await
+        {
+            info!("Iroh client #{client_id} disconnected: {e}");
+        }
+        // However the client went away, the hub drives outputs to their safe levels
+        let _ = to_hub.unbounded_send((client_id, HardwareConfigMessage::Disconnect));
+    }
+    clients.remove(client_id);
     Ok(())
 }
 
-/// Send the current input level for one input - with a timestamp that will match with future
-/// LevelChange timestamps (time since boot)
-async fn send_current_input_level(
-    bcm_pin_number: &BCMPinNumber,
-    pin_function: &PinFunction,
+/// Forward the config change messages received from one client, that its `role` permits, to
+/// the hub until the client disconnects, the end of the stream, or it misses `missed_heartbeats`
+async fn iroh_message_loop(
     connection: Connection,
-    hardware: &HW,
+    role: Role,
+    client_id: ClientId,
+    missed_heartbeats: u32,
+    clients: &Clients,
+    to_hub: &UnboundedSender<ClientMessage>,
 ) -> anyhow::Result<()> {
-    let now = hardware.get_time_since_boot(); // jonesy:allow(expect)
-
-    // Send initial levels
-    if let PinFunction::Input(_pullup) = pin_function {
-        if let Ok(initial_level) = hardware.get_input_level(*bcm_pin_number) {
-            let level_change = LevelChange::new(initial_level, now);
-            trace!("Pin #{bcm_pin_number} Input level change: {level_change:?}");
-            let hardware_event = IOLevelChanged(*bcm_pin_number, level_change);
-            let message = postcard::to_allocvec(&hardware_event)?;
-            send(connection.clone(), &message).await?;
+    let mut heartbeat_sent = false;
+    loop {
+        let payload = receive_within_heartbeats(heartbeat_sent, missed_heartbeats, async {
+            let mut config_receiver = connection.accept_uni().await?;
+            info!("Waiting for message");
+            Ok(config_receiver.read_to_end(4096).await?)
+        })
+        .await?;
+
+        if payload.is_empty() {
+            bail!("End of message stream");
         }
-    }
 
-    Ok(())
+        match postcard::from_bytes(&payload) {
+            Ok(HardwareConfigMessage::Disconnect) => bail!("Disconnect message received"),
+            Ok(config_message) if !role.permits(&config_message) => {
+                info!("Ignored message not permitted for {role:?}: {config_message:?}");
+            }
+            Ok(config_message) => {
+                heartbeat_sent |= matches!(config_message, HardwareConfigMessage::Ping(_));
+                to_hub.unbounded_send((client_id, config_message))?
+            }
+            Err(_) => {
+                info!("Could not deserialize message");
+                clients.decode_error("iroh");
+            }
+        }
+    }
 }
 
-/// Send a detected input level change back to the GUI using `connection` [Connection],
-/// timestamping with the current time in Utc
-fn send_input_level_sync(
+/// Send the messages queued for a client to it, until the client is removed or the connection
+/// is broken. The connection is closed when the client is removed, so a client removed for not
+/// keeping up with the messages sent to it is disconnected
+async fn send_to_client(
     connection: Connection,
-    bcm: BCMPinNumber,
-    level_change: LevelChange,
+    mut outgoing: Receiver<HardwareConfigMessage>,
 ) -> anyhow::Result<()> {
-    trace!("Pin #{bcm} Input level change: {level_change:?}");
-    let hardware_event = IOLevelChanged(bcm, level_change);
-    let message = postcard::to_allocvec(&hardware_event)?;
-    // TODO avoid recreating every time?
-    let rt = tokio::runtime::Builder::new_current_thread()
-        .enable_all()
-        .build()?;
-    rt.block_on(send(connection, &message))
+    while let Some(message) = outgoing.next().await {
+        send(connection.clone(), &postcard::to_allocvec(&message)?).await?;
+    }
+    connection.close(VarInt::from_u32(0), b"disconnected");
+    Ok(())
 }
 
 /// Send a message to the GUI using `connection` [Connection]
@@ -250,3 +328,90 @@ async fn send(connection: Connection, message: &[u8]) -> anyhow::Result<()> {
     gui_sender.finish()?; // jonesy:allow(bounds)
     Ok(())
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+    use tempfile::tempdir;
+
+    #[test]
+    fn secret_key_persists() {
+        let key_path = tempdir()
+            .expect("Could not create a tempdir")
+            .keep()
+            .join(IROH_KEY_FILENAME);
+        let created = load_or_create_secret_key(&key_path, false).expect("Could not create key");
+        let loaded = load_or_create_secret_key(&key_path, false).expect("Could not load key");
+        assert_eq!(created.public(), loaded.public());
+    }
+
+    #[test]
+    fn secret_key_rotated() {
+        let key_path = tempdir()
+            .expect("Could not create a tempdir")
+            .keep()
+            .join(IROH_KEY_FILENAME);
+        let created = load_or_create_secret_key(&key_path, false).expect("Could not create key");
+        let rotated = load_or_create_secret_key(&key_path, true).expect("Could not rotate key");
+        assert_ne!(created.public(), rotated.public());
+        let loaded = load_or_create_secret_key(&key_path, false).expect("Could not load key");
+        assert_eq!(rotated.public(), loaded.public());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn secret_key_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+        let key_path = tempdir()
+            .expect("Could not create a tempdir")
+            .keep()
+            .join(IROH_KEY_FILENAME);
+        load_or_create_secret_key(&key_path, false).expect("Could not create key");
+        let mode = fs::metadata(&key_path)
+            .expect("No key file")
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn invalid_secret_key() {
+        let key_path = tempdir()
+            .expect("Could not create a tempdir")
+            .keep()
+            .join(IROH_KEY_FILENAME);
+        fs::write(&key_path, b"not a key").expect("Could not write key file");
+        assert!(load_or_create_secret_key(&key_path, false).is_err());
+    }
+
+    #[test]
+    fn allowed_client_roles() {
+        let observer = SecretKey::generate().public();
+        let unknown = SecretKey::generate().public();
+        assert_eq!(role(&[], &unknown), Some(Role::Controller));
+        let allowed_clients = [AllowedClient {
+            endpoint_id: observer,
+            role: Role::Observer,
+        }];
+        assert_eq!(role(&allowed_clients, &observer), Some(Role::Observer));
+        assert_eq!(role(&allowed_clients, &unknown), None);
+    }
+
+    #[test]
+    fn deserialize_serialized_device() {
+        let device = IrohDevice {
+            endpoint_id: SecretKey::generate().public(),
+            relay_url: RelayUrl::from_str("https://euw1-1.relay.iroh.network./").unwrap(),
+            direct_addresses: vec![SocketAddr::from_str("10.0.0.1:4242").unwrap()],
+            endpoint: None,
+        };
+        let json = serde_json::to_string(&device).expect("Could not serialize device");
+        let parsed: IrohDevice = serde_json::from_str(&json).expect("Could not parse device");
+        assert_eq!(parsed.endpoint_id, device.endpoint_id);
+        assert_eq!(parsed.relay_url, device.relay_url);
+        assert_eq!(parsed.direct_addresses, device.direct_addresses);
+        assert_eq!(parsed.endpoint_addr(), device.endpoint_addr());
+    }
+}
@@ -1,4 +1,77 @@
+#[cfg(any(feature = "iroh", feature = "tcp"))]
+use anyhow::anyhow;
+#[cfg(any(feature = "iroh", feature = "tcp"))]
+use log::info;
+#[cfg(any(feature = "iroh", feature = "tcp"))]
+use pigdef::config::HEARTBEAT_INTERVAL_MS;
+#[cfg(any(feature = "iroh", feature = "tcp"))]
+use pigdef::hello::{Capabilities, Hello};
+#[cfg(any(feature = "iroh", feature = "tcp"))]
+use std::fs;
+#[cfg(any(feature = "iroh", feature = "tcp"))]
+use std::future::Future;
+#[cfg(any(feature = "iroh", feature = "tcp"))]
+use std::io::Write;
+#[cfg(any(feature = "iroh", feature = "tcp"))]
+use std::path::Path;
+#[cfg(any(feature = "iroh", feature = "tcp"))]
+use std::time::Duration;
+
+#[cfg(feature = "http")]
+pub mod http_device;
+pub mod hub;
 #[cfg(feature = "iroh")]
 pub mod iroh_device;
+#[cfg(feature = "mqtt")]
+pub mod mqtt_device;
 #[cfg(feature = "tcp")]
 pub mod tcp_device;
+#[cfg(feature = "tls")]
+pub mod tls_device;
+
+/// The [Hello] sent as the first message on every new connection to pigglet, with the
+/// capabilities of the hardware plus the sequences run, safe levels applied, clients counted
+/// and heartbeats answered by the [hub]
+#[cfg(any(feature = "iroh", feature = "tcp"))]
+pub const HELLO: Hello = Hello::new(
+    piggpio::CAPABILITIES
+        .union(Capabilities::SEQUENCES)
+        .union(Capabilities::SAFE_LEVELS)
+        .union(Capabilities::CLIENT_COUNTS)
+        .union(Capabilities::HEARTBEAT),
+);
+
+/// Wait for the next message from a client to be `received`. Once the client has sent a
+/// heartbeat, `heartbeat_sent` is true, and if nothing is received while `missed_heartbeats`
+/// could have been sent, the link to it is declared dead. Clients that never send heartbeats,
+/// such as older versions of piggui, are waited for as long as their connection stays open
+#[cfg(any(feature = "iroh", feature = "tcp"))]
+pub(crate) async fn receive_within_heartbeats<T>(
+    heartbeat_sent: bool,
+    missed_heartbeats: u32,
+    received: impl Future<Output = anyhow::Result<T>>,
+) -> anyhow::Result<T> {
+    if !heartbeat_sent {
+        return received.await;
+    }
+    let timeout = Duration::from_millis(HEARTBEAT_INTERVAL_MS * u64::from(missed_heartbeats));
+    tokio::time::timeout(timeout, received)
+        .await
+        .map_err(|_| anyhow!("Missed {missed_heartbeats} heartbeats"))?
+}
+
+/// Write a secret key to `key_path`, readable only by its owner
+#[cfg(any(feature = "iroh", feature = "tcp"))]
+pub fn write_key_file(key_path: &Path, key: &[u8]) -> anyhow::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut key_file = options.open(key_path)?;
+    // The mode is only used when the file is created, so restrict an existing one as well
+    #[cfg(unix)]
+    key_file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+    key_file.write_all(key)?;
+    info!("New key written at: {key_path:?}");
+    Ok(())
+}
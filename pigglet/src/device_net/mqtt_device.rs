@@ -0,0 +1,402 @@
+use crate::device_net::hub::{ClientMessage, Clients, MQTT_CLIENT};
+use crate::settings::MqttSettings;
+use pigdef::config::HardwareConfigMessage::{IOLevelChanged, NewConfig, NewPinConfig};
+use pigdef::config::{HardwareConfig, HardwareConfigMessage, LevelChange};
+use pigdef::description::{BCMPinNumber, HardwareDescription, PinLevel};
+use pigdef::pin_function::PinFunction;
+use pigdef::pin_function::PinFunction::{Input, Output};
+
+use anyhow::anyhow;
+use futures::channel::mpsc;
+use futures::channel::mpsc::UnboundedSender;
+use futures::StreamExt;
+use log::{debug, info, warn};
+use rumqttc::{AsyncClient, Event, LastWill, MqttOptions, Packet, Publish, QoS};
+use serde_json::json;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The most messages that can be waiting to be sent to the broker. Messages are dropped while
+/// the broker is unreachable, and the state of all pins published again once reconnected
+const REQUEST_QUEUE_LENGTH: usize = 256;
+
+/// How long to wait before trying to connect to the broker again, after failing to
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// The Home Assistant components pins are discovered as: inputs as binary sensors and
+/// outputs as switches
+const COMPONENTS: [&str; 2] = ["binary_sensor", "switch"];
+
+/// The payloads of the state of a pin, and of commands to set an output, for each level
+const PAYLOAD_ON: &str = "ON";
+const PAYLOAD_OFF: &str = "OFF";
+
+/// The MQTT topics used for one device
+struct Topics {
+    /// `<topic_prefix>/<serial>`, under which the state of each pin is published
+    base: String,
+    discovery_prefix: String,
+    /// Identifies the device in Home Assistant, `pigg_<serial>`
+    node_id: String,
+}
+
+impl Topics {
+    fn new(settings: &MqttSettings, serial: &str) -> Self {
+        // Characters that are not valid in topics or Home Assistant ids are replaced
+        let serial: String = serial
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        Topics {
+            base: format!("{}/{serial}", settings.topic_prefix),
+            discovery_prefix: settings.discovery_prefix.clone(),
+            node_id: format!("pigg_{serial}"),
+        }
+    }
+
+    /// The topic the level of pin `bcm` is published to
+    fn state(&self, bcm: BCMPinNumber) -> String {
+        format!("{}/{bcm}/state", self.base)
+    }
+
+    /// The topic commands to set the level of output `bcm` are received on
+    fn set(&self, bcm: BCMPinNumber) -> String {
+        format!("{}/{bcm}/set", self.base)
+    }
+
+    /// The filter to subscribe to commands to set the level of all outputs
+    fn set_filter(&self) -> String {
+        format!("{}/+/set", self.base)
+    }
+
+    /// The output a command received on `topic` is for, if it is a command
+    fn parse_set(&self, topic: &str) -> Option<BCMPinNumber> {
+        topic
+            .strip_prefix(&self.base)?
+            .strip_prefix('/')?
+            .strip_suffix("/set")?
+            .parse()
+            .ok()
+    }
+
+    /// The topic `online` or `offline` is published to, as pigglet connects or disconnects
+    fn availability(&self) -> String {
+        format!("{}/availability", self.base)
+    }
+
+    /// The Home Assistant discovery topic of pin `bcm` as a `component`
+    fn discovery(&self, component: &str, bcm: BCMPinNumber) -> String {
+        format!(
+            "{}/{component}/{}/gpio{bcm}/config",
+            self.discovery_prefix, self.node_id
+        )
+    }
+}
+
+/// The Home Assistant component a pin configured with `pin_function` is discovered as, if any
+fn component(pin_function: Option<&PinFunction>) -> Option<&'static str> {
+    match pin_function {
+        Some(Input(_)) => Some(COMPONENTS[0]),
+        Some(Output(_)) => Some(COMPONENTS[1]),
+        _ => None,
+    }
+}
+
+/// The Home Assistant discovery payload of pin `bcm` as a `component`, named as in `desc`
+fn discovery_payload(
+    topics: &Topics,
+    desc: &HardwareDescription,
+    bcm: BCMPinNumber,
+    component: &str,
+) -> serde_json::Value {
+    let name = desc
+        .pins
+        .pins()
+        .iter()
+        .find(|pin| pin.bcm == Some(bcm))
+        .map_or_else(|| format!("GPIO{bcm}"), |pin| pin.name.to_string());
+    let mut payload = json!({
+        "name": name,
+        "unique_id": format!("{}_gpio{bcm}", topics.node_id),
+        "state_topic": topics.state(bcm),
+        "payload_on": PAYLOAD_ON,
+        "payload_off": PAYLOAD_OFF,
+        "availability_topic": topics.availability(),
+        "device": {
+            "identifiers": [topics.node_id],
+            "name": format!("pigglet {}", desc.details.serial),
+            "model": desc.details.model,
+            "sw_version": env!("CARGO_PKG_VERSION"),
+        },
+    });
+    if component == COMPONENTS[1] {
+        payload["command_topic"] = json!(topics.set(bcm));
+    }
+    payload
+}
+
+/// Parse the level in a command to set an output
+fn parse_level(payload: &[u8]) -> Option<PinLevel> {
+    match std::str::from_utf8(payload).ok()?.trim() {
+        p if p.eq_ignore_ascii_case(PAYLOAD_ON) || p == "1" || p == "true" => Some(true),
+        p if p.eq_ignore_ascii_case(PAYLOAD_OFF) || p == "0" || p == "false" => Some(false),
+        _ => None,
+    }
+}
+
+/// The payload of the state of a pin at `level`
+fn level_payload(level: PinLevel) -> &'static str {
+    if level {
+        PAYLOAD_ON
+    } else {
+        PAYLOAD_OFF
+    }
+}
+
+/// Bridges the hub to an MQTT broker, as a client of the hub: publishing pin levels and
+/// Home Assistant discovery payloads, and sending commands to set outputs to the hub
+struct Bridge {
+    topics: Topics,
+    desc: HardwareDescription,
+    mqtt: AsyncClient,
+    config: HardwareConfig,
+    /// The last level of each pin, published again on reconnecting to the broker
+    levels: HashMap<BCMPinNumber, PinLevel>,
+}
+
+impl Bridge {
+    /// Queue a message to be published. If it cannot be, it is dropped, as the state of all
+    /// pins is published again on reconnecting
+    fn publish(&self, topic: String, retain: bool, payload: impl Into<Vec<u8>>) {
+        if let Err(e) = self
+            .mqtt
+            .try_publish(topic, QoS::AtLeastOnce, retain, payload)
+        {
+            debug!("Could not publish MQTT message: {e}");
+        }
+    }
+
+    /// Publish the discovery payload of pin `bcm` for the component it is configured as, and
+    /// remove it from any other component, so Home Assistant has one entity for it, or none
+    fn publish_discovery(&self, bcm: BCMPinNumber) {
+        let configured = component(self.config.pin_functions.get(&bcm));
+        for component in COMPONENTS {
+            let payload = if configured == Some(component) {
+                discovery_payload(&self.topics, &self.desc, bcm, component).to_string()
+            } else {
+                String::new()
+            };
+            self.publish(self.topics.discovery(component, bcm), true, payload);
+        }
+    }
+
+    /// Publish the discovery payloads of all pins
+    fn publish_all_discovery(&self) {
+        for bcm in self.desc.pins.pins().iter().filter_map(|pin| pin.bcm) {
+            self.publish_discovery(bcm);
+        }
+    }
+
+    /// Publish the level of pin `bcm`, if it is known
+    fn publish_level(&self, bcm: BCMPinNumber) {
+        if let Some(level) = self.levels.get(&bcm) {
+            self.publish(self.topics.state(bcm), true, level_payload(*level));
+        }
+    }
+
+    /// Record the levels outputs are configured with, before any level change of them
+    fn config_levels(&mut self) {
+        for (bcm, pin_function) in &self.config.pin_functions {
+            if let Output(Some(level)) = pin_function {
+                self.levels.insert(*bcm, *level);
+            }
+        }
+    }
+
+    /// Subscribe to commands, and publish the state of all pins, on (re)connecting
+    fn connected(&self) {
+        info!("Connected to MQTT broker");
+        if let Err(e) = self
+            .mqtt
+            .try_subscribe(self.topics.set_filter(), QoS::AtLeastOnce)
+        {
+            warn!("Could not subscribe to MQTT commands: {e}");
+        }
+        self.publish(self.topics.availability(), true, "online");
+        self.publish_all_discovery();
+        for bcm in self.levels.keys() {
+            self.publish_level(*bcm);
+        }
+    }
+
+    /// Publish the changes of config and pin levels made by the hub
+    fn hub_message(&mut self, message: HardwareConfigMessage) {
+        match message {
+            IOLevelChanged(bcm, level_change) => {
+                self.levels.insert(bcm, level_change.new_level);
+                self.publish_level(bcm);
+            }
+            NewPinConfig(bcm, pin_function) => {
+                match pin_function {
+                    Some(function) => self.config.pin_functions.insert(bcm, function),
+                    None => self.config.pin_functions.remove(&bcm),
+                };
+                self.levels.remove(&bcm);
+                self.config_levels();
+                self.publish_discovery(bcm);
+                self.publish_level(bcm);
+            }
+            NewConfig(config) => {
+                self.config = config;
+                self.levels.clear();
+                self.config_levels();
+                self.publish_all_discovery();
+            }
+            _ => {}
+        }
+    }
+
+    /// Send a command received from the broker to set an output to the hub
+    fn command(&self, publish: &Publish, to_hub: &UnboundedSender<ClientMessage>) {
+        let Some(bcm) = self.topics.parse_set(&publish.topic) else {
+            return;
+        };
+        let Some(level) = parse_level(&publish.payload) else {
+            warn!("Invalid MQTT command to set pin #{bcm}");
+            return;
+        };
+        if !matches!(self.config.pin_functions.get(&bcm), Some(Output(_))) {
+            warn!("MQTT command to set pin #{bcm}, which is not an output");
+            return;
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let _ = to_hub.unbounded_send((
+            MQTT_CLIENT,
+            IOLevelChanged(bcm, LevelChange::new(level, now)),
+        ));
+    }
+}
+
+/// Connect to the MQTT broker in `settings`, reconnecting when disconnected, and bridge it to
+/// the hub, as a client, until the hub removes the client
+pub async fn run(
+    settings: MqttSettings,
+    desc: HardwareDescription,
+    clients: Clients,
+    to_hub: UnboundedSender<ClientMessage>,
+) -> anyhow::Result<()> {
+    let topics = Topics::new(&settings, &desc.details.serial);
+    let client_id = settings
+        .client_id
+        .clone()
+        .unwrap_or_else(|| format!("pigglet-{}", desc.details.serial));
+    let mut options = MqttOptions::new(client_id, &settings.host, settings.port);
+    options.set_keep_alive(Duration::from_secs(30));
+    options.set_last_will(LastWill::new(
+        topics.availability(),
+        "offline",
+        QoS::AtLeastOnce,
+        true,
+    ));
+    if let Some(username) = &settings.username {
+        options.set_credentials(username, settings.password.as_deref().unwrap_or_default());
+    }
+    let (mqtt, mut event_loop) = AsyncClient::new(options, REQUEST_QUEUE_LENGTH);
+
+    // The event loop is polled in its own task, to keep polling it while messages are published
+    let (packets_sender, mut packets) = mpsc::unbounded();
+    tokio::spawn(async move {
+        loop {
+            match event_loop.poll().await {
+                Ok(Event::Incoming(packet)) => {
+                    if packets_sender.unbounded_send(packet).is_err() {
+                        break;
+                    }
+                }
+                Ok(Event::Outgoing(_)) => {}
+                Err(e) => {
+                    warn!("MQTT connection error: {e}");
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                }
+            }
+        }
+    });
+
+    let (client_id, mut outgoing, config) = clients
+        .add("mqtt")
+        .ok_or(anyhow!("Could not add MQTT client"))?;
+    println!("MQTT broker @ {}:{}", settings.host, settings.port);
+    let mut bridge = Bridge {
+        topics,
+        desc,
+        mqtt,
+        config,
+        levels: HashMap::new(),
+    };
+    bridge.config_levels();
+
+    loop {
+        tokio::select! {
+            message = outgoing.next() => match message {
+                Some(message) => bridge.hub_message(message),
+                None => break,
+            },
+            Some(packet) = packets.next() => match packet {
+                Packet::ConnAck(_) => bridge.connected(),
+                Packet::Publish(publish) => bridge.command(&publish, &to_hub),
+                _ => {}
+            },
+        }
+    }
+
+    clients.remove(client_id);
+    warn!("MQTT bridge stopped");
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod test {
+    use super::*;
+
+    fn topics() -> Topics {
+        Topics::new(&MqttSettings::default(), "1234-abcd")
+    }
+
+    #[test]
+    fn topics_for_serial() {
+        let topics = topics();
+        assert_eq!(topics.state(17), "pigg/1234_abcd/17/state");
+        assert_eq!(topics.set_filter(), "pigg/1234_abcd/+/set");
+        assert_eq!(
+            topics.discovery("switch", 17),
+            "homeassistant/switch/pigg_1234_abcd/gpio17/config"
+        );
+        assert_eq!(topics.parse_set("pigg/1234_abcd/17/set"), Some(17));
+        assert_eq!(topics.parse_set("pigg/1234_abcd/17/state"), None);
+        assert_eq!(topics.parse_set("pigg/other/17/set"), None);
+    }
+
+    #[test]
+    fn levels() {
+        assert_eq!(parse_level(b"ON"), Some(true));
+        assert_eq!(parse_level(b"off"), Some(false));
+        assert_eq!(parse_level(b"1"), Some(true));
+        assert_eq!(parse_level(b"toggle"), None);
+    }
+
+    #[test]
+    fn switch_discovery() {
+        let desc = piggpio::HW::new().description().clone();
+        let payload = discovery_payload(&topics(), &desc, 17, "switch");
+        assert_eq!(payload["name"], "GPIO17");
+        assert_eq!(payload["command_topic"], "pigg/1234_abcd/17/set");
+        assert_eq!(payload["state_topic"], "pigg/1234_abcd/17/state");
+        let payload = discovery_payload(&topics(), &desc, 26, "binary_sensor");
+        assert!(payload.get("command_topic").is_none());
+        assert_eq!(component(Some(&Input(None))), Some("binary_sensor"));
+        assert_eq!(component(Some(&PinFunction::I2C1_SDA)), None);
+    }
+}
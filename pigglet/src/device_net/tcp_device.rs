@@ -1,46 +1,61 @@
-use pigdef::config::HardwareConfig;
-use pigdef::config::HardwareConfigMessage::{IOLevelChanged, NewConfig, NewPinConfig};
-use pigdef::config::{HardwareConfigMessage, LevelChange};
+use crate::device_net::hub::{ClientId, ClientMessage, Clients};
+use crate::device_net::{receive_within_heartbeats, write_key_file, HELLO};
+use crate::settings::TcpSettings;
+use pigdef::auth;
+use pigdef::auth::{
+    AuthKey, AuthResponse, AuthResult, Challenge, Nonce, PairingCode, PairingSecret,
+};
+use pigdef::config::{ClientCounts, HardwareConfig, HardwareConfigMessage};
 use pigdef::description::HardwareDescription;
-use pigdef::description::{BCMPinNumber, PinLevel};
-use pigdef::pin_function::PinFunction;
+use pigdef::framing;
+use pigdef::framing::{FrameDecoder, FramingError};
+use pigdef::hello::{Capabilities, Hello};
 
 use anyhow::{anyhow, bail};
+use async_std::io::{Read, Write};
 use async_std::net::TcpListener;
 use async_std::net::TcpStream;
 use async_std::prelude::*;
+use futures::channel::mpsc::{Receiver, UnboundedSender};
 use local_ip_address::local_ip;
-use log::{debug, info, trace};
-use pigdef::pin_function::PinFunction::Output;
-use piggpio::config::store_config;
-use piggpio::HW;
+use log::{debug, info, warn};
 use portpicker::pick_unused_port;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use socket2::{SockRef, TcpKeepalive};
 use std::fmt;
 use std::fmt::{Display, Formatter};
-use std::net::IpAddr;
+use std::fs;
+use std::io::ErrorKind;
+use std::net::{IpAddr, Shutdown, SocketAddr};
 use std::path::Path;
-use std::str::{FromStr, Lines};
+use std::sync::{Arc, Mutex, PoisonError};
 use std::time::Duration;
 
+/// Name of the file, in the same directory as the config file, that the key shared with TCP
+/// clients paired with this pigglet is saved in, so they stay paired across restarts
+pub const AUTH_KEY_FILENAME: &str = "pigglet_tcp.key";
+
+/// How long a connection can be idle before keepalive probes are sent, and the time between
+/// probes, so a client whose link is lost without the connection being closed is detected
+const KEEPALIVE_TIME: Duration = Duration::from_secs(10);
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long to wait for a running pigglet to report its client counts
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a new client has to reply to the authentication challenge
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize)]
 pub struct TcpDevice {
     pub ip: IpAddr,
     pub port: u16,
+    /// The [TcpListener] accepting connections, only present in the running instance
+    #[serde(skip)]
     pub listener: Option<TcpListener>,
 }
 
-impl TcpDevice {
-    pub fn parse(lines: &mut Lines) -> anyhow::Result<Self> {
-        let ip = lines.next().ok_or_else(|| anyhow!("Missing ip"))?;
-        let port = lines.next().ok_or_else(|| anyhow!("Missing port"))?;
-
-        Ok(TcpDevice {
-            ip: IpAddr::from_str(ip)?,
-            port: u16::from_str(port)?,
-            listener: None,
-        })
-    }
-}
-
 impl Display for TcpDevice {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         writeln!(f, "IP Address: {}", self.ip)?;
@@ -49,25 +64,111 @@ impl Display for TcpDevice {
     }
 }
 
-pub async fn get_device() -> anyhow::Result<TcpDevice> {
+/// The key shared with the clients paired with this pigglet, with the serial number that
+/// pairing codes are salted with, and the secret of the one-time code a new client can pair
+/// with. Clones share the same code, so it is replaced for all when a client pairs
+#[derive(Clone)]
+pub struct TcpAuth {
+    key: AuthKey,
+    serial: String,
+    secret: Arc<Mutex<PairingSecret>>,
+}
+
+impl TcpAuth {
+    /// Create a [TcpAuth] with `key` for the device with `serial`, and a new pairing code that
+    /// is shown to the user
+    pub fn new(key: AuthKey, serial: &str) -> anyhow::Result<Self> {
+        Ok(TcpAuth {
+            key,
+            serial: serial.to_string(),
+            secret: Arc::new(Mutex::new(new_pairing_secret(serial)?)),
+        })
+    }
+
+    /// Check the `response` of a client to the challenge with `nonce`. If the client paired,
+    /// the code is replaced with a new one, so it cannot be used again
+    fn check(&self, response: &AuthResponse, nonce: &Nonce) -> anyhow::Result<AuthResult> {
+        let mut secret = self.secret.lock().unwrap_or_else(PoisonError::into_inner);
+        let result = auth::check(response, &self.key, &secret, nonce);
+        if matches!(result, AuthResult::Paired(_)) {
+            *secret = new_pairing_secret(&self.serial)?;
+        }
+        Ok(result)
+    }
+}
+
+/// Create a new random [PairingCode], show it to the user and return its [PairingSecret] for
+/// the device with `serial`
+fn new_pairing_secret(serial: &str) -> anyhow::Result<PairingSecret> {
+    let code = PairingCode::new(random()?);
+    println!("Pairing code: {code}"); // Don't remove - required by integration tests
+    info!("TCP clients can pair using code: {code}");
+    Ok(code.secret(serial))
+}
+
+/// Get an array of random bytes from the operating system
+fn random<const N: usize>() -> anyhow::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    getrandom::fill(&mut bytes).map_err(|e| anyhow!("Could not get random bytes: {e}"))?;
+    Ok(bytes)
+}
+
+/// Load the key shared with paired TCP clients saved at `key_path`, or if there is none,
+/// generate a new one and save it there, readable only by its owner
+pub fn load_or_create_auth_key(key_path: &Path) -> anyhow::Result<AuthKey> {
+    match fs::read(key_path) {
+        Ok(bytes) => {
+            return bytes
+                .try_into()
+                .map_err(|_| anyhow!("Invalid TCP key in {}", key_path.display()))
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    let key = random()?;
+    write_key_file(key_path, &key)?;
+    Ok(key)
+}
+
+/// Get a [TcpDevice] listening on the address and port in `settings`, using the local IP
+/// address and an unused port for those not set
+pub async fn get_device(settings: &TcpSettings) -> anyhow::Result<TcpDevice> {
+    let (ip, port, listener) = listen(settings.bind_address, settings.port).await?;
+    println!("ip: {ip}:{port}");
+    info!("Waiting for TCP connection @ {}", listener.local_addr()?);
+    Ok(TcpDevice {
+        ip,
+        port,
+        listener: Some(listener),
+    })
+}
+
+/// Listen on `bind_address` and `port`, using the local IP address and an unused port for
+/// those not set, and return the IP address clients connect to, the port and the listener
+pub(crate) async fn listen(
+    bind_address: Option<IpAddr>,
+    port: Option<u16>,
+) -> anyhow::Result<(IpAddr, u16, TcpListener)> {
+    let port = match port {
+        Some(port) => port,
+        None => pick_unused_port().ok_or(anyhow!("Could not find a free port"))?,
+    };
     let mut retry_count = 0;
 
     // On some devices this is started as a service before networking is up and this fails,
     // so retry with a few seconds delay between each
     while retry_count < 4 {
         println!("Trying to get IP address:");
-        if let Ok(ip) = local_ip() {
-            let port = pick_unused_port().ok_or(anyhow!("Could not find a free port"))?;
-            println!("ip: {ip}:{port}");
-            let address = format!("{ip}:{port}");
-            info!("Waiting for TCP connection @ {address}");
-            let listener = TcpListener::bind(&address).await?;
-
-            return Ok(TcpDevice {
-                ip,
-                port,
-                listener: Some(listener),
-            });
+        // When listening on all interfaces, clients connect to the local IP address
+        let ip = match bind_address {
+            Some(bind_address) if !bind_address.is_unspecified() => Ok(bind_address),
+            _ => local_ip(),
+        };
+        if let Ok(ip) = ip {
+            let address = SocketAddr::new(bind_address.unwrap_or(ip), port);
+            let listener = TcpListener::bind(address).await?;
+            return Ok((ip, port, listener));
         }
         tokio::time::sleep(Duration::from_secs(10)).await;
         retry_count += 1; // jonesy:allow(overflow)
@@ -76,189 +177,292 @@ pub async fn get_device() -> anyhow::Result<TcpDevice> {
     Err(anyhow!("Could not get IP address"))
 }
 
-/// accept incoming connections, returns a TcpStream
-pub async fn accept_connection(
-    listener: &mut TcpListener,
-    desc: &HardwareDescription,
-    hardware_config: HardwareConfig,
-) -> anyhow::Result<TcpStream> {
-    debug!("Waiting for connection");
+/// Accept incoming connections for as long as the listener works, serving each client in its
+/// own task so that many clients can be connected at the same time, and a slow client does not
+/// delay others from connecting. If `auth` is set, clients must authenticate before they are
+/// sent the description and config. Clients that send heartbeats are disconnected if they miss
+/// `missed_heartbeats` of them
+pub async fn accept_clients(
+    listener: TcpListener,
+    desc: HardwareDescription,
+    auth: Option<TcpAuth>,
+    missed_heartbeats: u32,
+    clients: Clients,
+    to_hub: UnboundedSender<ClientMessage>,
+) -> anyhow::Result<()> {
     let mut incoming = listener.incoming();
-    let stream = incoming.next().await;
-    let mut stream = stream.ok_or(anyhow!("No more Tcp streams"))?;
-
-    if let Ok(st) = &mut stream {
-        debug!("Connected, sending hardware description");
-        let message = postcard::to_allocvec(&(&desc, &hardware_config))?;
-        st.write_all(&message).await?;
+    loop {
+        println!("Waiting for TCP connection");
+        debug!("Waiting for connection");
+        let stream = incoming
+            .next()
+            .await
+            .ok_or(anyhow!("No more Tcp streams"))??;
+        set_keepalive(&stream);
+        let desc = desc.clone();
+        let auth = auth.clone();
+        let clients = clients.clone();
+        let to_hub = to_hub.clone();
+        tokio::spawn(async move {
+            serve_client(
+                stream.clone(),
+                stream.clone(),
+                stream,
+                "tcp",
+                &desc,
+                auth.as_ref(),
+                missed_heartbeats,
+                &clients,
+                &to_hub,
+            )
+            .await
+        });
     }
-
-    Ok(stream?)
 }
 
-pub async fn tcp_message_loop(
-    mut stream: TcpStream,
-    hardware_config: &mut HardwareConfig,
-    config_file_path: &Path,
-    hardware: &mut HW,
-) -> anyhow::Result<()> {
-    let mut payload = vec![0u8; 1024];
-    loop {
-        info!("Waiting for message");
-        let length = stream.read(&mut payload).await?;
-        if length == 0 {
-            bail!("End of message stream");
-        }
+/// Enable keepalive probes on a client's `stream`, so a lost connection is detected
+pub(crate) fn set_keepalive(stream: &TcpStream) {
+    let keepalive = TcpKeepalive::new()
+        .with_time(KEEPALIVE_TIME)
+        .with_interval(KEEPALIVE_INTERVAL);
+    if let Err(e) = SockRef::from(stream).set_tcp_keepalive(&keepalive) {
+        warn!("Could not enable keepalive, a lost connection may not be detected: {e}");
+    }
+}
 
-        if let Ok(config_message) = postcard::from_bytes(payload.get(0..length).unwrap_or(&[])) {
-            if apply_config_change(hardware, config_message, hardware_config, stream.clone())
-                .await
-                .is_ok()
+/// Send a new client the [HELLO], and if `auth` is set check it authenticates, then send it the
+/// hardware description and current config, and forward the messages it sends to the hub until
+/// it disconnects. Messages are received on `reader` and sent on `writer`, which are the
+/// client's `stream` itself, or the halves of the TLS session on it, and it is added to
+/// `clients` counted as connected over `transport`. The client is removed, releasing its
+/// session, if it sends heartbeats then misses `missed_heartbeats` of them
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn serve_client<R, W>(
+    mut reader: R,
+    mut writer: W,
+    stream: TcpStream,
+    transport: &'static str,
+    desc: &HardwareDescription,
+    auth: Option<&TcpAuth>,
+    missed_heartbeats: u32,
+    clients: &Clients,
+    to_hub: &UnboundedSender<ClientMessage>,
+) -> anyhow::Result<()>
+where
+    R: Read + Unpin,
+    W: Write + Unpin + Send + 'static,
+{
+    let mut decoder: FrameDecoder = FrameDecoder::new();
+    debug!("Connected, sending hello");
+    match auth {
+        Some(auth) => {
+            let hello = Hello::new(HELLO.capabilities.union(Capabilities::AUTH));
+            send(&mut writer, &hello).await?;
+            if !authenticate(
+                &mut reader,
+                &mut writer,
+                &mut decoder,
+                auth,
+                &desc.details.serial,
+            )
+            .await?
             {
-                let _ = store_config(hardware_config, config_file_path).await;
+                info!("{transport} client could not authenticate, disconnecting");
+                return Ok(());
             }
         }
+        None => send(&mut writer, &HELLO).await?,
     }
-}
 
-/// Apply a config change to the hardware
-/// NOTE: Initially the callback to Config/PinConfig change was async, and that compiles and runs
-/// but wasn't working - so this uses a sync callback again to fix that, and an async version of
-/// send_input_level() for use directly from the async context
-async fn apply_config_change(
-    hardware: &mut HW,
-    config_change: HardwareConfigMessage,
-    hardware_config: &mut HardwareConfig,
-    tcp_stream: TcpStream,
-) -> anyhow::Result<()> {
-    match config_change {
-        NewConfig(config) => {
-            info!("New config applied");
-            let wc = tcp_stream.clone();
-            hardware
-                .apply_config(&config, move |bcm, level_change| {
-                    let _ = send_input_level(wc.clone(), bcm, level_change);
-                })
-                .await?;
-
-            send_current_input_states(tcp_stream.clone(), &config, hardware).await?;
-            // replace the entire config with the new one
-            *hardware_config = config;
-        }
-        NewPinConfig(bcm, pin_function) => {
-            info!("New pin config for pin #{bcm}: {pin_function:?}");
-            let wc = tcp_stream.clone();
-            hardware
-                .apply_pin_config(bcm, &pin_function, move |bcm, level_change| {
-                    let _ = send_input_level(tcp_stream.clone(), bcm, level_change);
-                })
-                .await?;
-
-            if let Some(function) = pin_function {
-                // if a new config was set, reply with the new input state for that pin
-                send_current_input_state(&bcm, &function, wc, hardware).await?;
-                // add/replace the new pin config to the hardware config
-                hardware_config.pin_functions.insert(bcm, function);
-            } else {
-                // if No new function was set (None), then remove from the current hardware_config
-                info!("Removing pin from pin_functions");
-                hardware_config.pin_functions.remove(&bcm);
-            }
-        }
-        IOLevelChanged(bcm, level_change) => {
-            trace!("Pin #{bcm} Output level change: {level_change:?}");
-            let _ = hardware.set_output_level(bcm, level_change.new_level);
-            // add/replace the new pin config to the hardware config
-            hardware_config
-                .pin_functions
-                .insert(bcm, Output(Some(level_change.new_level)));
-        }
-        HardwareConfigMessage::GetConfig => {
-            send_hardware_config(tcp_stream, hardware_config).await?;
+    // Only clients that have authenticated are added, to be sent changes
+    let (client_id, outgoing, hardware_config) = clients
+        .add(transport)
+        .ok_or(anyhow!("Could not add client"))?;
+
+    debug!("Sending hardware description");
+    if send(&mut writer, &(desc, &hardware_config)).await.is_ok() {
+        println!("Connection via {}", transport.to_uppercase());
+        tokio::spawn(send_to_client(writer, stream, outgoing));
+        if let Err(e) = tcp_message_loop(
+            reader,
+            decoder,
+            transport,
+            client_id,
+            missed_heartbeats,
+            clients,
+            to_hub,
+        )
+        .await
+        {
+            info!("{transport} client #{client_id} disconnected: {e}");
         }
-        HardwareConfigMessage::Disconnect => return Err(anyhow!("Disconnect message received")),
+        // However the client went away, the hub drives outputs to their safe levels
+        let _ = to_hub.unbounded_send((client_id, HardwareConfigMessage::Disconnect));
     }
+    clients.remove(client_id);
+    Ok(())
+}
 
+/// Send a serializable message to a client on `writer`
+async fn send<W: Write + Unpin>(writer: &mut W, message: &impl Serialize) -> anyhow::Result<()> {
+    writer.write_all(&framing::encode_to_vec(message)?).await?;
+    // TLS buffers what is written until it is flushed
+    writer.flush().await?;
     Ok(())
 }
 
-/// Send the current input state for all inputs configured in the config
-async fn send_current_input_states(
-    writer: TcpStream,
-    config: &HardwareConfig,
-    hardware: &HW,
-) -> anyhow::Result<()> {
-    for (bcm_pin_number, pin_function) in &config.pin_functions {
-        send_current_input_state(bcm_pin_number, pin_function, writer.clone(), hardware).await?;
-    }
+/// Challenge a new client to prove it has the key, or knows the pairing code, of `auth`.
+/// Return true if it did, and false if it was rejected
+async fn authenticate<R: Read + Unpin, W: Write + Unpin>(
+    reader: &mut R,
+    writer: &mut W,
+    decoder: &mut FrameDecoder,
+    auth: &TcpAuth,
+    serial: &str,
+) -> anyhow::Result<bool> {
+    let nonce: Nonce = random()?;
+    let challenge = Challenge {
+        serial: serial.to_string(),
+        nonce,
+    };
+    send(writer, &challenge).await?;
+    let response: AuthResponse = tokio::time::timeout(AUTH_TIMEOUT, receive(reader, decoder))
+        .await
+        .map_err(|_| anyhow!("Timeout waiting for client to authenticate"))??;
 
-    Ok(())
+    let result = auth.check(&response, &nonce)?;
+    send(writer, &result).await?;
+    match result {
+        AuthResult::Accepted => debug!("TCP client authenticated"),
+        AuthResult::Paired(_) => println!("Paired with a new TCP client"),
+        AuthResult::Rejected => return Ok(false),
+    }
+    Ok(true)
 }
 
-/// Send the current input state for one input
-async fn send_current_input_state(
-    bcm_pin_number: &BCMPinNumber,
-    pin_function: &PinFunction,
-    writer: TcpStream,
-    hardware: &HW,
+/// Forward the config change messages received from one client to the hub until
+/// the client disconnects, the connection is broken or it misses `missed_heartbeats`.
+/// Bytes already received from the client are in `decoder`
+async fn tcp_message_loop<R: Read + Unpin>(
+    mut reader: R,
+    mut decoder: FrameDecoder,
+    transport: &'static str,
+    client_id: ClientId,
+    missed_heartbeats: u32,
+    clients: &Clients,
+    to_hub: &UnboundedSender<ClientMessage>,
 ) -> anyhow::Result<()> {
-    let now = hardware.get_time_since_boot(); // jonesy:allow(expect)
-
-    // Send initial levels
-    if let PinFunction::Input(_pullup) = pin_function {
-        // Update UI with initial state
-        if let Ok(initial_level) = hardware.get_input_level(*bcm_pin_number) {
-            let _ =
-                send_input_level_async(writer.clone(), *bcm_pin_number, initial_level, now).await;
+    let mut heartbeat_sent = false;
+    loop {
+        info!("Waiting for message");
+        let length = receive_within_heartbeats(heartbeat_sent, missed_heartbeats, async {
+            Ok(reader.read(decoder.spare()).await?)
+        })
+        .await?;
+        if length == 0 {
+            bail!("End of message stream");
         }
-    }
+        decoder.filled(length);
 
-    Ok(())
+        // A single read may contain more than one message, or only part of one
+        loop {
+            match decoder.decode() {
+                Ok(Some(HardwareConfigMessage::Disconnect)) => {
+                    bail!("Disconnect message received")
+                }
+                Ok(Some(config_message)) => {
+                    heartbeat_sent |= matches!(config_message, HardwareConfigMessage::Ping(_));
+                    to_hub.unbounded_send((client_id, config_message))?
+                }
+                Ok(None) => break,
+                // The frame was removed, so we can continue with the next one
+                Err(FramingError::Deserialization) => {
+                    info!("Could not deserialize message");
+                    clients.decode_error(transport);
+                }
+                Err(e) => bail!(e),
+            }
+        }
+    }
 }
 
-/// Send the [HardwareConfig] via the [TcpStream]
-async fn send_hardware_config(
-    writer: TcpStream,
-    hardware_config: &HardwareConfig,
+/// Send the messages queued for a client to it on `writer`, until the client is removed or
+/// the connection is broken. The client's `stream` is closed when the client is removed, so a
+/// client removed for not keeping up with the messages sent to it is disconnected
+async fn send_to_client<W: Write + Unpin>(
+    mut writer: W,
+    stream: TcpStream,
+    mut outgoing: Receiver<HardwareConfigMessage>,
 ) -> anyhow::Result<()> {
-    let message = postcard::to_allocvec(&NewConfig(hardware_config.clone()))?;
-    send(writer, &message).await
+    while let Some(message) = outgoing.next().await {
+        send(&mut writer, &message).await?;
+    }
+    stream.shutdown(Shutdown::Both)?;
+    Ok(())
 }
 
-/// Send a detected input level change back to the GUI using `writer` [TcpStream],
-/// timestamping with the current time in Utc
-async fn send_input_level_async(
-    writer: TcpStream,
-    bcm: BCMPinNumber,
-    level: PinLevel,
-    timestamp: Duration,
-) -> anyhow::Result<()> {
-    let level_change = LevelChange::new(level, timestamp);
-    trace!("Pin #{bcm} Input level change: {level_change:?}");
-    let hardware_event = IOLevelChanged(bcm, level_change);
-    let message = postcard::to_allocvec(&hardware_event)?;
-    send(writer, &message).await
-}
+/// Connect to the pigglet listening on `ip` and `port`, as a client that only asks questions
+/// so its outputs are not driven to safe levels when it disconnects, and get the number of
+/// clients connected to it, not counting this one. If the pigglet requires clients to
+/// authenticate, its `key` is used
+pub async fn client_counts(
+    ip: IpAddr,
+    port: u16,
+    key: Option<AuthKey>,
+) -> anyhow::Result<ClientCounts> {
+    tokio::time::timeout(PROBE_TIMEOUT, async {
+        let mut stream = TcpStream::connect(SocketAddr::new(ip, port)).await?;
+        let mut decoder: FrameDecoder = FrameDecoder::new();
+        let hello: Hello = receive(&mut stream, &mut decoder).await?;
+        let capabilities = hello.negotiate()?;
+        if !capabilities.contains(Capabilities::CLIENT_COUNTS) {
+            bail!("The running pigglet cannot report its clients");
+        }
+        if capabilities.contains(Capabilities::AUTH) {
+            let key = key.ok_or(anyhow!("Could not read the key of the running pigglet"))?;
+            let challenge: Challenge = receive(&mut stream, &mut decoder).await?;
+            let response = AuthResponse::Key(auth::key_proof(&key, &challenge.nonce));
+            send(&mut stream, &response).await?;
+            if receive::<AuthResult, _>(&mut stream, &mut decoder).await? != AuthResult::Accepted {
+                bail!("The running pigglet did not accept its key");
+            }
+        }
+        let _: (HardwareDescription, HardwareConfig) = receive(&mut stream, &mut decoder).await?;
 
-/// Send a detected input level change back to the GUI using `writer` [TcpStream],
-/// timestamping with the current time in Utc
-fn send_input_level(
-    writer: TcpStream,
-    bcm: BCMPinNumber,
-    level_change: LevelChange,
-) -> anyhow::Result<()> {
-    trace!("Sending pin #{bcm} Input level change event: {level_change:?}");
-    let hardware_event = IOLevelChanged(bcm, level_change);
-    let message = postcard::to_allocvec(&hardware_event)?;
-    // TODO avoid recreating every time?
-    let rt = tokio::runtime::Builder::new_current_thread()
-        .enable_all()
-        .build()?;
-    rt.block_on(send(writer, &message))
+        let request = HardwareConfigMessage::GetClientCounts;
+        send(&mut stream, &request).await?;
+        let counts = loop {
+            // Changes made by other clients may be received before the reply
+            if let HardwareConfigMessage::ClientCounts(counts) =
+                receive(&mut stream, &mut decoder).await?
+            {
+                break counts;
+            }
+        };
+        send(&mut stream, &HardwareConfigMessage::Disconnect).await?;
+
+        Ok(ClientCounts {
+            tcp: counts.tcp.saturating_sub(1),
+            ..counts
+        })
+    })
+    .await
+    .map_err(|_| anyhow!("Timeout waiting for the running pigglet to reply"))?
 }
 
-/// Send a message to the GUI using the `writer` [TcpStream]
-async fn send(mut writer: TcpStream, message: &[u8]) -> anyhow::Result<()> {
-    writer.write_all(message).await?;
-    Ok(())
+/// Receive the next message of type `T` from `reader`, reading more into `decoder` as needed
+async fn receive<T: DeserializeOwned, R: Read + Unpin>(
+    reader: &mut R,
+    decoder: &mut FrameDecoder,
+) -> anyhow::Result<T> {
+    loop {
+        if let Some(message) = decoder.decode()? {
+            return Ok(message);
+        }
+        let length = reader.read(decoder.spare()).await?;
+        if length == 0 {
+            bail!("Connection closed");
+        }
+        decoder.filled(length);
+    }
 }
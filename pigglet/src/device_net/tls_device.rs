@@ -0,0 +1,150 @@
+use crate::device_net::hub::{ClientMessage, Clients};
+use crate::device_net::tcp_device::{self, TcpAuth};
+use crate::device_net::write_key_file;
+use crate::settings::TlsSettings;
+use pigdef::description::HardwareDescription;
+use pigdef::tls::Fingerprint;
+
+use anyhow::{anyhow, Context};
+use async_std::net::TcpListener;
+use async_std::prelude::*;
+use futures::channel::mpsc::UnboundedSender;
+use futures::AsyncReadExt;
+use futures_rustls::rustls::crypto::ring;
+use futures_rustls::rustls::pki_types::pem::PemObject;
+use futures_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use futures_rustls::rustls::ServerConfig;
+use futures_rustls::TlsAcceptor;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Names of the files, in the same directory as the config file, that the self-signed
+/// certificate served to TLS clients and its private key are saved in, so the certificate
+/// clients have pinned does not change across restarts
+pub const CERT_FILENAME: &str = "pigglet_tls.crt";
+pub const KEY_FILENAME: &str = "pigglet_tls.key";
+
+/// How long a new client has to complete the TLS handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize)]
+pub struct TlsDevice {
+    pub ip: IpAddr,
+    pub port: u16,
+    /// The fingerprint of the certificate served, that clients check it against
+    pub fingerprint: Fingerprint,
+    /// The [TcpListener] accepting connections, only present in the running instance
+    #[serde(skip)]
+    pub listener: Option<TcpListener>,
+    /// The [TlsAcceptor] with the certificate, only present in the running instance
+    #[serde(skip)]
+    pub acceptor: Option<TlsAcceptor>,
+}
+
+impl Display for TlsDevice {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "TLS Address: {}:{}", self.ip, self.port)?;
+        writeln!(f, "TLS Fingerprint: {}", self.fingerprint)?;
+        Ok(())
+    }
+}
+
+/// Load the certificate and private key saved at `cert_path` and `key_path`, or if there is no
+/// certificate, generate a new self-signed one and save it there, with the key readable only
+/// by its owner
+pub fn load_or_create_certificate(
+    cert_path: &Path,
+    key_path: &Path,
+) -> anyhow::Result<(CertificateDer<'static>, PrivateKeyDer<'static>)> {
+    if !cert_path.exists() {
+        let certified = rcgen::generate_simple_self_signed(vec!["pigglet".to_string()])?;
+        write_key_file(key_path, certified.signing_key.serialize_pem().as_bytes())?;
+        fs::write(cert_path, certified.cert.pem())?;
+        info!("New TLS certificate written at: {cert_path:?}");
+    }
+
+    let certificate = CertificateDer::from_pem_file(cert_path)
+        .with_context(|| format!("Could not read TLS certificate {}", cert_path.display()))?;
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .with_context(|| format!("Could not read TLS key {}", key_path.display()))?;
+    Ok((certificate, key))
+}
+
+/// Get a [TlsDevice] serving `certificate`, listening on `bind_address` and the port in
+/// `settings`, using the local IP address and an unused port for those not set
+pub async fn get_device(
+    certificate: CertificateDer<'static>,
+    key: PrivateKeyDer<'static>,
+    bind_address: Option<IpAddr>,
+    settings: &TlsSettings,
+) -> anyhow::Result<TlsDevice> {
+    let fingerprint = Fingerprint::of(&certificate);
+    let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(vec![certificate], key)?;
+
+    let (ip, port, listener) = tcp_device::listen(bind_address, settings.port).await?;
+    println!("TLS fingerprint: {fingerprint}"); // Don't remove - required by integration tests
+    info!("Waiting for TLS connection @ {}", listener.local_addr()?);
+    Ok(TlsDevice {
+        ip,
+        port,
+        fingerprint,
+        listener: Some(listener),
+        acceptor: Some(TlsAcceptor::from(Arc::new(config))),
+    })
+}
+
+/// Accept incoming connections for as long as the listener works, and after the TLS handshake
+/// serve each client in its own task, as [tcp_device::accept_clients] does
+pub async fn accept_clients(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    desc: HardwareDescription,
+    auth: Option<TcpAuth>,
+    missed_heartbeats: u32,
+    clients: Clients,
+    to_hub: UnboundedSender<ClientMessage>,
+) -> anyhow::Result<()> {
+    let mut incoming = listener.incoming();
+    loop {
+        debug!("Waiting for TLS connection");
+        let stream = incoming
+            .next()
+            .await
+            .ok_or(anyhow!("No more TLS streams"))??;
+        tcp_device::set_keepalive(&stream);
+        let acceptor = acceptor.clone();
+        let desc = desc.clone();
+        let auth = auth.clone();
+        let clients = clients.clone();
+        let to_hub = to_hub.clone();
+        // The handshake is done in the client's task, so a slow client does not delay others
+        tokio::spawn(async move {
+            let session = tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream.clone()))
+                .await
+                .map_err(|_| anyhow!("Timeout waiting for TLS handshake"))??;
+            let (reader, writer) = session.split();
+            tcp_device::serve_client(
+                reader,
+                writer,
+                stream,
+                "tls",
+                &desc,
+                auth.as_ref(),
+                missed_heartbeats,
+                &clients,
+                &to_hub,
+            )
+            .await
+        });
+    }
+}
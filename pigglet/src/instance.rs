@@ -2,72 +2,115 @@
 use crate::device_net::iroh_device;
 #[cfg(feature = "tcp")]
 use crate::device_net::tcp_device;
-use anyhow::{anyhow, Context};
+#[cfg(feature = "tls")]
+use crate::device_net::tls_device;
+use anyhow::bail;
 use log::info;
+use serde::{Deserialize, Serialize};
 use std::fs;
-use std::fs::File;
-use std::io::Write;
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The version of the format of the info file. Fields can be added without changing it, as
+/// readers ignore those they don't know, but it is incremented if any are changed or removed
+pub(crate) const INFO_VERSION: u32 = 1;
 
 /// The [InstanceInfo] struct captures information about network connections the instance of
-/// `pigglet` is listening on, that can be used with `piggui` to start a remote GPIO session
+/// `pigglet` is listening on, that can be used with `piggui` to start a remote GPIO session,
+/// and is written as JSON to the info file
+#[derive(Serialize, Deserialize)]
 pub(crate) struct InstanceInfo {
+    pub(crate) version: u32,
     pub(crate) process_name: String,
     pub(crate) pid: u32,
+    /// When the instance started, in seconds since the UNIX epoch
+    pub(crate) started: u64,
+    pub(crate) config_path: PathBuf,
+    /// The names of the transports enabled, e.g. "tcp", "tls", "iroh", "http" and "mqtt"
+    pub(crate) transports: Vec<String>,
+    /// Only present if the Iroh transport is enabled
     #[cfg(feature = "iroh")]
-    pub(crate) iroh_info: iroh_device::IrohDevice,
+    pub(crate) iroh_info: Option<iroh_device::IrohDevice>,
+    /// Only present if the TCP transport is enabled
     #[cfg(feature = "tcp")]
-    pub(crate) tcp_info: tcp_device::TcpDevice,
+    pub(crate) tcp_info: Option<tcp_device::TcpDevice>,
+    /// Only present if the TLS transport is enabled
+    #[cfg(feature = "tls")]
+    #[serde(default)]
+    pub(crate) tls_info: Option<tls_device::TlsDevice>,
+    /// The address the HTTP API is served on, if it is enabled
+    #[serde(default)]
+    pub(crate) http_address: Option<SocketAddr>,
+}
+
+/// Only the version is read first, so a file written by a newer pigglet can be refused before
+/// failing to parse the rest of it
+#[derive(Deserialize)]
+struct Version {
+    version: u32,
 }
 
 impl InstanceInfo {
     pub(crate) fn load_from_file(path: PathBuf) -> anyhow::Result<Self> {
         let string = fs::read_to_string(path)?;
-        let mut lines = string.lines();
-        let process_name = lines
-            .next()
-            .ok_or_else(|| anyhow!("Missing process name"))?
-            .to_string();
-        let pid = lines
-            .next()
-            .ok_or_else(|| anyhow!("Missing PID"))?
-            .parse::<u32>() // jonesy:allow(unknown)
-            .context("Invalid PID")?;
-
-        #[cfg(feature = "iroh")]
-        let iroh_info = iroh_device::IrohDevice::parse(&mut lines)?;
-
-        #[cfg(feature = "tcp")]
-        let tcp_info = tcp_device::TcpDevice::parse(&mut lines)?;
-
-        Ok(Self {
-            process_name,
-            pid,
-            #[cfg(feature = "iroh")]
-            iroh_info,
-            #[cfg(feature = "tcp")]
-            tcp_info,
-        })
+        let Version { version } = serde_json::from_str(&string)?;
+        if version > INFO_VERSION {
+            bail!(
+                "Info file version {version} is newer than this pigglet can read ({INFO_VERSION})"
+            );
+        }
+        Ok(serde_json::from_str(&string)?)
     }
 
     /// Write a [InstanceInfo] file that captures information that can be used to connect to pigglet
     pub(crate) fn write_to_file(&self, info_path: &Path) -> anyhow::Result<()> {
-        let mut output = File::create(info_path)?;
-        write!(output, "{self}")?;
+        fs::write(info_path, serde_json::to_string_pretty(self)?)?;
         info!("Info file written at: {info_path:?}");
         Ok(())
     } // jonesy:allow(invalid_enum) Display impl on InstanceInfo
+
+    /// How long the instance has been running for
+    fn uptime(&self) -> Duration {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        now.saturating_sub(Duration::from_secs(self.started))
+    }
 }
 
 impl std::fmt::Display for InstanceInfo {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "{}", self.process_name)?;
-        writeln!(f, "{}", self.pid)?;
+        writeln!(f, "Process: {}", self.process_name)?;
+        writeln!(f, "PID: {}", self.pid)?;
+        let uptime = self.uptime().as_secs();
+        writeln!(
+            f,
+            "Uptime: {}h {}m {}s",
+            uptime / 3600,
+            uptime / 60 % 60,
+            uptime % 60
+        )?;
+        writeln!(f, "Config File: {}", self.config_path.display())?;
+        writeln!(f, "Transports: {}", self.transports.join(", "))?;
         #[cfg(feature = "iroh")]
-        write!(f, "{}", self.iroh_info)?;
+        if let Some(iroh_info) = &self.iroh_info {
+            write!(f, "{iroh_info}")?;
+        }
 
         #[cfg(feature = "tcp")]
-        write!(f, "{}", self.tcp_info)?;
+        if let Some(tcp_info) = &self.tcp_info {
+            write!(f, "{tcp_info}")?;
+        }
+
+        #[cfg(feature = "tls")]
+        if let Some(tls_info) = &self.tls_info {
+            write!(f, "{tls_info}")?;
+        }
+
+        if let Some(http_address) = &self.http_address {
+            writeln!(f, "HTTP API: {http_address}")?;
+        }
 
         Ok(())
     }
@@ -76,30 +119,49 @@ impl std::fmt::Display for InstanceInfo {
 #[cfg(test)]
 #[allow(clippy::unwrap_used, clippy::expect_used)]
 mod test {
+    use super::INFO_VERSION;
     use crate::InstanceInfo;
-    use iroh::{EndpointId, RelayUrl};
     use std::path::PathBuf;
+    #[cfg(any(feature = "iroh", feature = "tcp"))]
     use std::str::FromStr;
     use std::{fs, process};
     use tempfile::tempdir;
 
-    fn listener_info(endpoint_id: &EndpointId, relay_url_str: &str) -> InstanceInfo {
+    fn listener_info() -> InstanceInfo {
         InstanceInfo {
+            version: INFO_VERSION,
             process_name: "pigglet_tests".to_string(),
             pid: process::id(),
+            started: 1_700_000_000,
+            config_path: PathBuf::from("/home/pi/pigglet.pigg"),
+            transports: vec!["tcp".to_string(), "iroh".to_string()],
             #[cfg(feature = "iroh")]
-            iroh_info: crate::iroh_device::IrohDevice {
-                endpoint_id: *endpoint_id,
-                relay_url: RelayUrl::from_str(relay_url_str).expect("Could not create Relay URL"),
+            iroh_info: Some(crate::iroh_device::IrohDevice {
+                endpoint_id: iroh::EndpointId::from_str(
+                    "rxci3kuuxljxqej7hau727aaemcjo43zvf2zefnqla4p436sqwhq",
+                )
+                .expect("Could not create endpoint_id"),
+                relay_url: iroh::RelayUrl::from_str("https://euw1-1.relay.iroh.network./")
+                    .expect("Could not create Relay URL"),
+                direct_addresses: vec![],
                 endpoint: None,
-            },
+            }),
 
             #[cfg(feature = "tcp")]
-            tcp_info: crate::tcp_device::TcpDevice {
+            tcp_info: Some(crate::tcp_device::TcpDevice {
                 ip: std::net::IpAddr::from_str("10.0.0.0").expect("Could not parse IpAddr"),
                 port: 9001,
                 listener: None,
-            },
+            }),
+            #[cfg(feature = "tls")]
+            tls_info: Some(crate::tls_device::TlsDevice {
+                ip: std::net::IpAddr::from_str("10.0.0.0").expect("Could not parse IpAddr"),
+                port: 9002,
+                fingerprint: pigdef::tls::Fingerprint::of(b"certificate"),
+                listener: None,
+                acceptor: None,
+            }),
+            http_address: None,
         }
     }
 
@@ -107,27 +169,59 @@ mod test {
     fn write_info_file() {
         let output_dir = tempdir().expect("Could not create a tempdir").keep();
         let test_file = output_dir.join("test.info");
-        let endpoint_id =
-            iroh::EndpointId::from_str("rxci3kuuxljxqej7hau727aaemcjo43zvf2zefnqla4p436sqwhq")
-                .expect("Could not create endpoint_id");
-        let listener_info = listener_info(&endpoint_id, "https://euw1-1.relay.iroh.network./ ");
+        let listener_info = listener_info();
         listener_info
             .write_to_file(&test_file)
             .expect("Writing info file failed");
         assert!(test_file.exists(), "File was not created as expected");
         let pigglet_info = fs::read_to_string(test_file).expect("Could not read info file");
-        println!("pigglet_info: {pigglet_info}");
-        assert!(pigglet_info.contains(&endpoint_id.to_string()))
+        let json: serde_json::Value =
+            serde_json::from_str(&pigglet_info).expect("Info file is not JSON");
+        assert_eq!(json["version"], INFO_VERSION);
+        assert_eq!(json["pid"], process::id());
+        #[cfg(feature = "iroh")]
+        assert_eq!(
+            json["iroh_info"]["endpoint_id"],
+            "rxci3kuuxljxqej7hau727aaemcjo43zvf2zefnqla4p436sqwhq"
+        );
+        #[cfg(feature = "tcp")]
+        assert_eq!(json["tcp_info"]["port"], 9001);
+        #[cfg(feature = "tls")]
+        assert_eq!(json["tls_info"]["port"], 9002);
+    }
+
+    #[test]
+    fn read_info_file() {
+        let output_dir = tempdir().expect("Could not create a tempdir").keep();
+        let test_file = output_dir.join("test.info");
+        let listener_info = listener_info();
+        listener_info
+            .write_to_file(&test_file)
+            .expect("Writing info file failed");
+        let read_info = InstanceInfo::load_from_file(test_file).expect("Could not read info file");
+        assert_eq!(
+            serde_json::to_value(&read_info).expect("Could not serialize info"),
+            serde_json::to_value(&listener_info).expect("Could not serialize info")
+        );
+    }
+
+    #[test]
+    fn read_newer_info_file() {
+        let output_dir = tempdir().expect("Could not create a tempdir").keep();
+        let test_file = output_dir.join("test.info");
+        let mut listener_info = listener_info();
+        listener_info.version = INFO_VERSION + 1;
+        listener_info
+            .write_to_file(&test_file)
+            .expect("Writing info file failed");
+        assert!(InstanceInfo::load_from_file(test_file).is_err());
     }
 
     #[test]
     fn write_info_file_non_existent() {
         let output_dir = PathBuf::from("/foo");
         let test_file = output_dir.join("test.info");
-        let endpoint_id =
-            iroh::EndpointId::from_str("rxci3kuuxljxqej7hau727aaemcjo43zvf2zefnqla4p436sqwhq")
-                .expect("Could not create endpoint_id");
-        let listener_info = listener_info(&endpoint_id, "https://euw1-1.relay.iroh.network./ ");
+        let listener_info = listener_info();
         assert!(listener_info.write_to_file(&test_file).is_err());
         assert!(!test_file.exists(), "File was created!");
     }
@@ -0,0 +1,245 @@
+#[cfg(feature = "http")]
+use pigdef::config::HardwareConfig;
+use pigdef::config::HardwareConfigMessage;
+use pigdef::description::{BCMPinNumber, PinLevel};
+#[cfg(feature = "http")]
+use pigdef::pin_function::PinFunction::Output;
+use std::collections::{BTreeMap, HashMap};
+#[cfg(feature = "http")]
+use std::fmt::Write;
+#[cfg(feature = "http")]
+use std::time::Instant;
+
+/// Counts of what pigglet has done since it started, to tell if it is healthy, kept by
+/// [crate::device_net::hub::Clients] and served in the Prometheus text format
+pub struct Metrics {
+    #[cfg(feature = "http")]
+    started: Instant,
+    received: BTreeMap<&'static str, u64>,
+    sent: BTreeMap<&'static str, u64>,
+    /// Rising (true) and falling (false) edges of each input
+    edges: BTreeMap<(BCMPinNumber, bool), u64>,
+    /// The last level reported for each input, to detect edges
+    input_levels: HashMap<BCMPinNumber, PinLevel>,
+    decode_errors: BTreeMap<&'static str, u64>,
+}
+
+/// The name of the variant of `message`, used as a label of message counts
+fn message_name(message: &HardwareConfigMessage) -> &'static str {
+    match message {
+        HardwareConfigMessage::NewConfig(..) => "NewConfig",
+        HardwareConfigMessage::NewPinConfig(..) => "NewPinConfig",
+        HardwareConfigMessage::IOLevelChanged(..) => "IOLevelChanged",
+        HardwareConfigMessage::SafeLevelChanged(..) => "SafeLevelChanged",
+        HardwareConfigMessage::GetConfig => "GetConfig",
+        HardwareConfigMessage::Disconnect => "Disconnect",
+        HardwareConfigMessage::DutyCycleChanged(..) => "DutyCycleChanged",
+        HardwareConfigMessage::AnalogSampled(..) => "AnalogSampled",
+        HardwareConfigMessage::I2cRequest(..) => "I2cRequest",
+        HardwareConfigMessage::I2cResponse(..) => "I2cResponse",
+        HardwareConfigMessage::SpiRequest(..) => "SpiRequest",
+        HardwareConfigMessage::SpiResponse(..) => "SpiResponse",
+        HardwareConfigMessage::UartSend(..) => "UartSend",
+        HardwareConfigMessage::UartReceived(..) => "UartReceived",
+        HardwareConfigMessage::StartSequence(..) => "StartSequence",
+        HardwareConfigMessage::StartSchedule(..) => "StartSchedule",
+        HardwareConfigMessage::StopSequence(..) => "StopSequence",
+        HardwareConfigMessage::GetSequenceStatus(..) => "GetSequenceStatus",
+        HardwareConfigMessage::SequenceStatus(..) => "SequenceStatus",
+        HardwareConfigMessage::GetClientCounts => "GetClientCounts",
+        HardwareConfigMessage::ClientCounts(..) => "ClientCounts",
+        HardwareConfigMessage::Ping(..) => "Ping",
+        HardwareConfigMessage::Pong(..) => "Pong",
+    }
+}
+
+impl Metrics {
+    /// Create [Metrics] with all counts at zero, and uptime counted from now
+    pub fn new() -> Self {
+        Metrics {
+            #[cfg(feature = "http")]
+            started: Instant::now(),
+            received: BTreeMap::new(),
+            sent: BTreeMap::new(),
+            edges: BTreeMap::new(),
+            input_levels: HashMap::new(),
+            decode_errors: BTreeMap::new(),
+        }
+    }
+
+    /// Count a message received by the hub, from a client or made by pigglet itself
+    pub fn received(&mut self, message: &HardwareConfigMessage) {
+        *self.received.entry(message_name(message)).or_default() += 1;
+    }
+
+    /// Count a message queued to be sent to a client
+    pub fn sent(&mut self, message: &HardwareConfigMessage) {
+        *self.sent.entry(message_name(message)).or_default() += 1;
+    }
+
+    /// Count an edge if input `bcm_pin_number` has changed to `level`. The first level
+    /// reported is not an edge
+    pub fn input_level(&mut self, bcm_pin_number: BCMPinNumber, level: PinLevel) {
+        if let Some(previous) = self.input_levels.insert(bcm_pin_number, level) {
+            if previous != level {
+                *self.edges.entry((bcm_pin_number, level)).or_default() += 1;
+            }
+        }
+    }
+
+    /// Forget the levels of pins reconfigured by a config change `message`, so the first
+    /// level reported after is not an edge
+    pub fn config_changed(&mut self, message: &HardwareConfigMessage) {
+        match message {
+            HardwareConfigMessage::NewConfig(_) => self.input_levels.clear(),
+            HardwareConfigMessage::NewPinConfig(bcm_pin_number, _) => {
+                self.input_levels.remove(bcm_pin_number);
+            }
+            _ => {}
+        }
+    }
+
+    /// Count a message received over `transport` that could not be decoded
+    pub fn decode_error(&mut self, transport: &'static str) {
+        *self.decode_errors.entry(transport).or_default() += 1;
+    }
+
+    /// Render the metrics in the Prometheus text format, with the number of clients
+    /// connected over each transport in `clients` and the output levels in `config`
+    #[cfg(feature = "http")]
+    pub fn render(
+        &self,
+        clients: impl Iterator<Item = &'static str>,
+        config: &HardwareConfig,
+    ) -> String {
+        let mut text = String::new();
+        let uptime = self.started.elapsed().as_secs_f64();
+        header(
+            &mut text,
+            "pigglet_uptime_seconds",
+            "gauge",
+            "Time since pigglet started",
+        );
+        let _ = writeln!(text, "pigglet_uptime_seconds {uptime:.3}");
+
+        let mut connected: BTreeMap<&'static str, u64> = BTreeMap::new();
+        for transport in clients {
+            *connected.entry(transport).or_default() += 1;
+        }
+        header(&mut text, "pigglet_clients", "gauge", "Connected clients");
+        for (transport, count) in connected {
+            let _ = writeln!(text, "pigglet_clients{{transport=\"{transport}\"}} {count}");
+        }
+
+        header(
+            &mut text,
+            "pigglet_messages_received_total",
+            "counter",
+            "Messages received from clients, by type",
+        );
+        for (message, count) in &self.received {
+            let _ = writeln!(
+                text,
+                "pigglet_messages_received_total{{message=\"{message}\"}} {count}"
+            );
+        }
+
+        header(
+            &mut text,
+            "pigglet_messages_sent_total",
+            "counter",
+            "Messages sent to clients, by type",
+        );
+        for (message, count) in &self.sent {
+            let _ = writeln!(
+                text,
+                "pigglet_messages_sent_total{{message=\"{message}\"}} {count}"
+            );
+        }
+
+        header(
+            &mut text,
+            "pigglet_input_edges_total",
+            "counter",
+            "Level changes of inputs",
+        );
+        for ((bcm, rising), count) in &self.edges {
+            let edge = if *rising { "rising" } else { "falling" };
+            let _ = writeln!(
+                text,
+                "pigglet_input_edges_total{{bcm=\"{bcm}\",edge=\"{edge}\"}} {count}"
+            );
+        }
+
+        header(
+            &mut text,
+            "pigglet_output_level",
+            "gauge",
+            "Level of outputs, 1 for high",
+        );
+        let outputs: BTreeMap<_, _> = config
+            .pin_functions
+            .iter()
+            .filter_map(|(bcm, function)| match function {
+                Output(level) => Some((bcm, u8::from(level.unwrap_or(false)))),
+                _ => None,
+            })
+            .collect();
+        for (bcm, level) in outputs {
+            let _ = writeln!(text, "pigglet_output_level{{bcm=\"{bcm}\"}} {level}");
+        }
+
+        header(
+            &mut text,
+            "pigglet_decode_errors_total",
+            "counter",
+            "Messages received that could not be decoded, by transport",
+        );
+        for (transport, count) in &self.decode_errors {
+            let _ = writeln!(
+                text,
+                "pigglet_decode_errors_total{{transport=\"{transport}\"}} {count}"
+            );
+        }
+
+        text
+    }
+}
+
+/// Write the HELP and TYPE lines of a metric
+#[cfg(feature = "http")]
+fn header(text: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(text, "# HELP {name} {help}");
+    let _ = writeln!(text, "# TYPE {name} {kind}");
+}
+
+#[cfg(all(test, feature = "http"))]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod test {
+    use super::*;
+    use pigdef::config::HardwareConfigMessage::GetConfig;
+
+    #[test]
+    fn render_metrics() {
+        let mut metrics = Metrics::new();
+        metrics.received(&GetConfig);
+        metrics.received(&GetConfig);
+        metrics.input_level(26, false);
+        metrics.input_level(26, true);
+        metrics.input_level(26, true);
+        metrics.input_level(26, false);
+        metrics.decode_error("tcp");
+        let mut config = HardwareConfig::default();
+        config.pin_functions.insert(17, Output(Some(true)));
+
+        let text = metrics.render(["tcp", "iroh", "tcp"].into_iter(), &config);
+        assert!(text.contains("pigglet_clients{transport=\"tcp\"} 2\n"));
+        assert!(text.contains("pigglet_clients{transport=\"iroh\"} 1\n"));
+        assert!(text.contains("pigglet_messages_received_total{message=\"GetConfig\"} 2\n"));
+        assert!(text.contains("pigglet_input_edges_total{bcm=\"26\",edge=\"rising\"} 1\n"));
+        assert!(text.contains("pigglet_input_edges_total{bcm=\"26\",edge=\"falling\"} 1\n"));
+        assert!(text.contains("pigglet_output_level{bcm=\"17\"} 1\n"));
+        assert!(text.contains("pigglet_decode_errors_total{transport=\"tcp\"} 1\n"));
+        assert!(text.contains("# TYPE pigglet_uptime_seconds gauge\n"));
+    }
+}
@@ -3,25 +3,39 @@
 #![cfg(not(target_arch = "wasm32"))]
 
 use anyhow::anyhow;
+#[cfg(any(feature = "iroh", feature = "tcp"))]
+use anyhow::bail;
 #[cfg(all(feature = "discovery", feature = "tcp"))]
 use anyhow::Context;
 use clap::{Arg, ArgMatches};
 use env_logger::{Builder, Target};
-#[cfg(all(feature = "iroh", feature = "tcp"))]
-use futures::FutureExt;
+use futures::channel::mpsc;
+#[cfg(all(feature = "discovery", feature = "tcp"))]
+use log::warn;
 use log::{info, trace, LevelFilter};
 #[cfg(all(feature = "discovery", feature = "tcp"))]
 use mdns_sd::{ServiceDaemon, ServiceInfo};
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::{env, env::current_exe, fs, path::PathBuf, process, process::exit, str::FromStr};
 use sysinfo::{Process, System};
 
 use piggpio::get_hardware;
 
+#[cfg(feature = "http")]
+use crate::device_net::http_device;
+use crate::device_net::hub;
+use crate::device_net::hub::Clients;
 #[cfg(feature = "iroh")]
 use crate::device_net::iroh_device;
+#[cfg(feature = "mqtt")]
+use crate::device_net::mqtt_device;
 #[cfg(feature = "tcp")]
 use crate::device_net::tcp_device;
-use crate::instance::InstanceInfo;
+#[cfg(feature = "tls")]
+use crate::device_net::tls_device;
+use crate::instance::{InstanceInfo, INFO_VERSION};
+use crate::rules::{Rules, RULES_FILENAME};
+use crate::settings::{Settings, SETTINGS_FILENAME};
 #[cfg(all(feature = "discovery", feature = "tcp"))]
 use pigdef::description::TCP_MDNS_SERVICE_TYPE;
 
@@ -30,7 +44,11 @@ use piggpio::config::{get_config, CONFIG_FILENAME};
 /// Module for performing the network transfer of config and events between GUI and pigglet
 mod device_net;
 mod instance;
+mod metrics;
+mod rules;
+mod sequencer;
 mod service;
+mod settings;
 
 /// The name of the file where information about a running instance of pigglet will be stored
 /// for other instances attempting to run can find it
@@ -46,6 +64,10 @@ async fn main() -> anyhow::Result<()> {
 
     service::manage(&exec_path, &matches)?;
 
+    if matches.get_flag("status") {
+        return status().await;
+    }
+
     // jonesy:allow(expect) sysinfo::System::new_all() uses expect internally
     match check_unique("pigglet") {
         Ok(_) => run(&matches, exec_path).await, // jonesy:allow(invalid_enum)
@@ -77,6 +99,18 @@ async fn run(matches: &ArgMatches, exec_path: PathBuf) -> anyhow::Result<()> {
         None => exec_path.with_file_name(CONFIG_FILENAME), // jonesy:allow(unknown)
     };
 
+    // Settings given on the command line override those in the settings file
+    let settings_file_path = match matches.get_one::<String>("settings") {
+        Some(settings_filename) => PathBuf::from(settings_filename),
+        None => exec_path.with_file_name(SETTINGS_FILENAME), // jonesy:allow(unknown)
+    };
+    let mut settings = Settings::load(&settings_file_path)?;
+    settings.apply_args(matches)?;
+    #[cfg(any(feature = "iroh", feature = "tcp"))]
+    if !settings.transport_enabled() {
+        bail!("No transports are enabled to accept connections on");
+    }
+
     // remove any leftover file from a previous execution - ignore any failure
     let _ = fs::remove_file(&info_path);
 
@@ -89,153 +123,344 @@ async fn run(matches: &ArgMatches, exec_path: PathBuf) -> anyhow::Result<()> {
         #[allow(unused_mut)]
         let mut hardware_config = get_config(&config_file_path); // jonesy:allow(invalid_enum)
 
+        // Messages received from all clients are sent to the hub to be applied to the hardware
+        let (to_hub, from_clients) = mpsc::unbounded();
+
+        // Clients that connect are sent all input events, from when the config is applied,
+        // after any rules loaded alongside the config have been run on them
+        let rules = rules::load(&config_file_path.with_file_name(RULES_FILENAME))?;
+        let clients = if rules.is_empty() {
+            Clients::new(hardware_config.clone())
+        } else {
+            Clients::with_rules(hardware_config.clone(), Rules::new(rules, to_hub.clone()))
+        };
+
         // Apply the initial config to the hardware, whatever it is
-        hw.apply_config(&hardware_config, |bcm_pin_number, level_change| {
-            info!("Pin #{bcm_pin_number} changed level to '{level_change}'")
-        }) // jonesy:allow(invalid_enum)
-        .await?;
+        hw.apply_config(&hardware_config, clients.input_callback()) // jonesy:allow(invalid_enum)
+            .await?;
         trace!("Configuration applied to hardware");
 
+        #[cfg(feature = "iroh")]
+        let iroh_info = if settings.iroh.enabled {
+            // Reuse the Iroh secret key saved next to the config, so the endpoint_id doesn't change
+            let secret_key = iroh_device::load_or_create_secret_key(
+                &config_file_path.with_file_name(iroh_device::IROH_KEY_FILENAME),
+                matches.get_flag("rotate_key"),
+            )?;
+            // jonesy:allow(misalign)
+            Some(iroh_device::get_device(secret_key, &settings.iroh).await?)
+        } else {
+            None
+        };
+
+        #[cfg(feature = "tcp")]
+        let tcp_info = if settings.tcp.enabled {
+            // jonesy:allow(overflow, invalid_enum)
+            Some(tcp_device::get_device(&settings.tcp).await?)
+        } else {
+            None
+        };
+
+        // Reuse the certificate saved next to the config, so it matches the one clients pinned
+        #[cfg(feature = "tls")]
+        let tls_info = if settings.tls.enabled {
+            let (certificate, key) = tls_device::load_or_create_certificate(
+                &config_file_path.with_file_name(tls_device::CERT_FILENAME),
+                &config_file_path.with_file_name(tls_device::KEY_FILENAME),
+            )?;
+            Some(
+                tls_device::get_device(certificate, key, settings.tcp.bind_address, &settings.tls)
+                    .await?,
+            )
+        } else {
+            None
+        };
+
+        // Clients connecting with TLS authenticate in the same way as with TCP
+        #[cfg(feature = "tls")]
+        let tcp_listening = tcp_info.is_some() || tls_info.is_some();
+        #[cfg(all(feature = "tcp", not(feature = "tls")))]
+        let tcp_listening = tcp_info.is_some();
+
+        // Reuse the key saved next to the config, so paired clients stay paired
+        #[cfg(feature = "tcp")]
+        let tcp_auth = if tcp_listening && settings.tcp.auth {
+            let key = tcp_device::load_or_create_auth_key(
+                &config_file_path.with_file_name(tcp_device::AUTH_KEY_FILENAME),
+            )?;
+            Some(tcp_device::TcpAuth::new(
+                key,
+                &hw.description().details.serial,
+            )?)
+        } else {
+            None
+        };
+
+        #[cfg(feature = "http")]
+        let http_listener = if settings.http.enabled {
+            Some(http_device::get_listener(&settings.http).await?)
+        } else {
+            None
+        };
+
+        #[allow(unused_mut)]
+        let mut transports: Vec<String> = vec![];
+        #[cfg(feature = "tcp")]
+        if tcp_info.is_some() {
+            transports.push("tcp".to_string());
+        }
+        #[cfg(feature = "tls")]
+        if tls_info.is_some() {
+            transports.push("tls".to_string());
+        }
+        #[cfg(feature = "iroh")]
+        if iroh_info.is_some() {
+            transports.push("iroh".to_string());
+        }
+        #[cfg(feature = "http")]
+        if http_listener.is_some() {
+            transports.push("http".to_string());
+        }
+        #[cfg(feature = "mqtt")]
+        if settings.mqtt.enabled {
+            transports.push("mqtt".to_string());
+        }
+
         let listener_info = InstanceInfo {
+            version: INFO_VERSION,
             process_name: "pigglet".to_string(), // jonesy:allow(invalid_enum)
             pid: process::id(),
+            started: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            config_path: config_file_path.clone(),
+            transports,
             #[cfg(feature = "iroh")]
-            iroh_info: iroh_device::get_device().await?, // jonesy:allow(misalign)
+            iroh_info,
             #[cfg(feature = "tcp")]
-            tcp_info: tcp_device::get_device().await?, // jonesy:allow(overflow, invalid_enum)
+            tcp_info,
+            #[cfg(feature = "tls")]
+            tls_info,
+            #[cfg(feature = "http")]
+            http_address: http_listener
+                .as_ref()
+                .and_then(|listener| listener.local_addr().ok()),
+            #[cfg(not(feature = "http"))]
+            http_address: None,
         };
 
         // write the info about the node to the info_path file for use in piggui
         listener_info.write_to_file(&info_path)?; // jonesy:allow(invalid_enum)
 
-        #[cfg(any(feature = "iroh", feature = "tcp"))]
+        #[cfg(any(feature = "iroh", feature = "tcp", feature = "http", feature = "mqtt"))]
         let desc = hw.description().clone();
-        #[cfg(any(feature = "iroh", feature = "tcp"))]
+        #[cfg(any(feature = "iroh", feature = "tcp", feature = "http", feature = "mqtt"))]
         println!("Serial Number: {}", desc.details.serial);
 
-        // Then listen for remote connections and "serve" them
-        #[cfg(all(feature = "tcp", not(feature = "iroh")))]
-        if let Some(mut listener) = listener_info.tcp_info.listener {
-            #[cfg(feature = "discovery")]
-            // The key string in TXT properties is case-insensitive.
-            let properties = [
-                ("Serial", &desc.details.serial as &str),
-                ("Model", &desc.details.model as &str),
-                ("AppName", env!("CARGO_BIN_NAME")),
-                ("AppVersion", env!("CARGO_PKG_VERSION")),
-            ];
-
-            #[cfg(feature = "discovery")]
-            let (service_info, service_daemon) = register_mdns(
-                TCP_MDNS_SERVICE_TYPE,
-                listener_info.tcp_info.port,
-                &desc.details.serial,
-                &properties,
-            )?;
+        // mDNS discovery advertises the TCP port, so is only registered when TCP is enabled
+        #[cfg(all(feature = "discovery", feature = "tcp"))]
+        let mdns_registration = match &listener_info.tcp_info {
+            Some(tcp_info) => {
+                // The key string in TXT properties is case-insensitive.
+                #[allow(unused_mut)]
+                let mut properties = vec![
+                    ("Serial".to_string(), desc.details.serial.clone()),
+                    ("Model".to_string(), desc.details.model.clone()),
+                    ("AppName".to_string(), env!("CARGO_BIN_NAME").to_string()),
+                    (
+                        "AppVersion".to_string(),
+                        env!("CARGO_PKG_VERSION").to_string(),
+                    ),
+                ];
+
+                #[cfg(feature = "iroh")]
+                if let Some(iroh_info) = &listener_info.iroh_info {
+                    properties.push(("IrohNodeID".to_string(), iroh_info.endpoint_id.to_string()));
+                    properties.push(("IrohRelayURL".to_string(), iroh_info.relay_url.to_string()));
+                }
+
+                // So clients can pin the certificate the first time they connect with TLS
+                #[cfg(feature = "tls")]
+                if let Some(tls_info) = &listener_info.tls_info {
+                    properties.push(("TlsPort".to_string(), tls_info.port.to_string()));
+                    properties.push((
+                        "TlsFingerprint".to_string(),
+                        tls_info.fingerprint.to_string(),
+                    ));
+                }
 
-            loop {
-                println!("Waiting for TCP connection");
-                if let Ok(stream) =
-                    tcp_device::accept_connection(&mut listener, &desc, hardware_config.clone())
-                        .await
-                {
-                    println!("Connection via TCP");
-                    let _ = tcp_device::tcp_message_loop(
-                        stream,
-                        &mut hardware_config,
-                        &config_file_path,
-                        &mut hw,
-                    )
-                    .await;
+                for (key, value) in &settings.mdns.properties {
+                    if properties.iter().any(|(k, _)| k.eq_ignore_ascii_case(key)) {
+                        warn!("Ignoring mDNS TXT property '{key}' that pigglet sets itself");
+                    } else {
+                        properties.push((key.clone(), value.clone()));
+                    }
                 }
+
+                let instance_name = settings
+                    .mdns
+                    .instance_name
+                    .as_deref()
+                    .unwrap_or(&desc.details.serial);
+
+                Some(register_mdns(
+                    TCP_MDNS_SERVICE_TYPE,
+                    tcp_info.port,
+                    instance_name,
+                    &desc.details.serial,
+                    &properties,
+                )?)
             }
+            None => None,
+        };
+
+        // Then listen for remote connections and "serve" them, each in its own task
+        #[cfg(feature = "tcp")]
+        if let Some(listener) = listener_info
+            .tcp_info
+            .and_then(|tcp_info| tcp_info.listener)
+        {
+            tokio::spawn(tcp_device::accept_clients(
+                listener,
+                desc.clone(),
+                tcp_auth.clone(),
+                settings.heartbeats.missed,
+                clients.clone(),
+                to_hub.clone(),
+            ));
         }
 
-        #[cfg(all(feature = "iroh", not(feature = "tcp")))]
-        if let Some(endpoint) = listener_info.iroh_info.endpoint {
-            loop {
-                println!("Waiting for Iroh connection");
-                if let Ok(connection) =
-                    iroh_device::accept_connection(&endpoint, &desc, hardware_config.clone()).await
-                {
-                    println!("Connection via Iroh");
-                    let _ = iroh_device::iroh_message_loop(
-                        connection,
-                        &mut hardware_config,
-                        &config_file_path,
-                        &mut hw,
-                    )
-                    .await;
-                }
+        #[cfg(feature = "tls")]
+        if let Some(tls_info) = listener_info.tls_info {
+            if let (Some(listener), Some(acceptor)) = (tls_info.listener, tls_info.acceptor) {
+                tokio::spawn(tls_device::accept_clients(
+                    listener,
+                    acceptor,
+                    desc.clone(),
+                    tcp_auth,
+                    settings.heartbeats.missed,
+                    clients.clone(),
+                    to_hub.clone(),
+                ));
             }
         }
 
-        // loop forever selecting the next connection made and then process those messages
-        #[cfg(all(feature = "iroh", feature = "tcp"))]
-        if let (Some(mut tcp_listener), Some(iroh_endpoint)) = (
-            listener_info.tcp_info.listener,
-            listener_info.iroh_info.endpoint,
-        ) {
-            #[cfg(feature = "discovery")]
-            // The key string in TXT properties is case-insensitive.
-            let properties = [
-                ("Serial", &desc.details.serial as &str),
-                ("Model", &desc.details.model as &str),
-                ("AppName", env!("CARGO_BIN_NAME")),
-                ("AppVersion", env!("CARGO_PKG_VERSION")),
-                (
-                    "IrohNodeID",
-                    &listener_info.iroh_info.endpoint_id.to_string(),
-                ),
-                (
-                    "IrohRelayURL",
-                    &listener_info.iroh_info.relay_url.to_string(),
-                ),
-            ];
-
-            #[cfg(feature = "discovery")]
-            let (service_info, service_daemon) = register_mdns(
-                TCP_MDNS_SERVICE_TYPE,
-                listener_info.tcp_info.port,
-                &desc.details.serial,
-                &properties,
-            )?;
+        #[cfg(feature = "iroh")]
+        if let Some(endpoint) = listener_info
+            .iroh_info
+            .and_then(|iroh_info| iroh_info.endpoint)
+        {
+            tokio::spawn(iroh_device::accept_clients(
+                endpoint,
+                desc.clone(),
+                settings.iroh.allowed_clients,
+                settings.heartbeats.missed,
+                clients.clone(),
+                to_hub.clone(),
+            ));
+        }
 
-            loop {
-                println!("Waiting for Iroh or TCP connection");
-                let fused_tcp = tcp_device::accept_connection(
-                    &mut tcp_listener,
-                    &desc,
-                    hardware_config.clone(),
-                )
-                .fuse();
-                let fused_iroh =
-                    iroh_device::accept_connection(&iroh_endpoint, &desc, hardware_config.clone())
-                        .fuse();
-
-                futures::pin_mut!(fused_tcp, fused_iroh);
-
-                futures::select! { // jonesy:allow(bounds)
-                    tcp_stream = fused_tcp => {
-                        println!("Connection via Tcp");
-                        let _ = tcp_device::tcp_message_loop(tcp_stream?, &mut hardware_config, &config_file_path, &mut hw).await; // jonesy:allow(bounds, invalid_enum)
-                    },
-                    iroh_connection = fused_iroh => {
-                        println!("Connection via Iroh");
-                        let _ =  iroh_device::iroh_message_loop(iroh_connection?, &mut hardware_config, &config_file_path, &mut hw).await; // jonesy:allow(invalid_enum)
-                    }
-                    complete => {}
-                }
-                println!("Disconnected");
-            }
+        #[cfg(feature = "http")]
+        if let Some(listener) = http_listener {
+            tokio::spawn(http_device::serve(
+                listener,
+                desc.clone(),
+                clients.clone(),
+                to_hub.clone(),
+            ));
+        }
+
+        #[cfg(feature = "mqtt")]
+        if settings.mqtt.enabled {
+            tokio::spawn(mqtt_device::run(
+                settings.mqtt,
+                desc.clone(),
+                clients.clone(),
+                to_hub.clone(),
+            ));
         }
 
+        // The hub runs until no task accepting connections remains, or forever if there are
+        // rules, as they can change outputs without any client connected, or until stopped
+        drop(to_hub);
+        hub::run(
+            &mut hw,
+            &mut hardware_config,
+            &config_file_path,
+            &clients,
+            from_clients,
+            shutdown_signal(),
+        )
+        .await;
+
         Ok(())
     } else {
         Err(anyhow!("Could not get hardware"))
     }
 }
 
+/// Report on the running instance of pigglet, from its info file and, if it accepts TCP
+/// connections, the clients connected to it. Exits with an error if none is running.
+async fn status() -> anyhow::Result<()> {
+    match check_unique("pigglet") {
+        Ok(()) => {
+            println!("pigglet is not running");
+            exit(1);
+        }
+        Err(None) => {
+            println!("pigglet is running, but we couldn't get more information");
+            exit(1);
+        }
+        Err(Some(listener_info)) => {
+            print!("{listener_info}");
+            #[cfg(feature = "tcp")]
+            if let Some(tcp_info) = &listener_info.tcp_info {
+                // The key is only readable by the user pigglet runs as
+                let key = fs::read(
+                    listener_info
+                        .config_path
+                        .with_file_name(tcp_device::AUTH_KEY_FILENAME),
+                )
+                .ok()
+                .and_then(|bytes| bytes.try_into().ok());
+                match tcp_device::client_counts(tcp_info.ip, tcp_info.port, key).await {
+                    Ok(counts) => print!("{counts}"),
+                    Err(e) => println!("Could not get the connected clients: {e}"),
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Wait for pigglet to be interrupted (Ctrl-C) or, on unix, terminated as when its service is
+/// stopped, so outputs can be driven to their safe levels before it exits
+async fn shutdown_signal() {
+    let interrupt = async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            // Without a handler for Ctrl-C, wait for termination only
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = interrupt => {}
+        () = terminate => {}
+    }
+}
+
 /// Check that this is the only instance of a process running, both user process or system process
 /// If no other instance is detected, return Ok
 /// If another version is detected, return:
@@ -282,29 +507,18 @@ fn setup_logging(matches: &ArgMatches) {
 
 /// Parse the command line arguments using clap into a set of [ArgMatches]
 fn get_matches() -> ArgMatches {
+    command().get_matches()
+}
+
+/// The clap [clap::Command] with all the command line options pigglet accepts
+fn command() -> clap::Command {
     let app = clap::Command::new(env!("CARGO_BIN_NAME")).version(env!("CARGO_PKG_VERSION"));
 
     let app = app.about(
         "'pigglet' - for making Raspberry Pi GPIO hardware accessible remotely using 'piggui'",
     );
 
-    let app = app.arg(
-        Arg::new("install")
-            .short('i')
-            .long("install")
-            .action(clap::ArgAction::SetTrue)
-            .help("Install pigglet as a System Service that restarts on reboot")
-            .conflicts_with("uninstall"),
-    );
-
-    let app = app.arg(
-        Arg::new("uninstall")
-            .short('u')
-            .long("uninstall")
-            .action(clap::ArgAction::SetTrue)
-            .help("Uninstall any pigglet System Service")
-            .conflicts_with("install"),
-    );
+    let app = service::args(app);
 
     let app = app.arg(
         Arg::new("verbosity")
@@ -328,7 +542,15 @@ fn get_matches() -> ArgMatches {
             .help("Path of a '.pigg' config file to load"),
     );
 
-    app.get_matches()
+    #[cfg(feature = "iroh")]
+    let app = app.arg(
+        Arg::new("rotate_key")
+            .long("rotate_key")
+            .action(clap::ArgAction::SetTrue)
+            .help("Generate a new Iroh secret key, changing the endpoint_id clients connect to"),
+    );
+
+    settings::args(app)
 }
 
 #[cfg(all(feature = "discovery", feature = "tcp"))]
@@ -336,8 +558,9 @@ fn get_matches() -> ArgMatches {
 fn register_mdns(
     service_type: &str,
     port: u16,
+    instance_name: &str,
     serial_number: &str,
-    properties: &[(&str, &str)],
+    properties: &[(String, String)],
 ) -> anyhow::Result<(ServiceInfo, ServiceDaemon)> {
     let service_daemon = ServiceDaemon::new().context("Could not create service daemon")?;
 
@@ -347,7 +570,7 @@ fn register_mdns(
     let service_info = ServiceInfo::new(
         // jonesy:allow(misalign)
         service_type,
-        serial_number,
+        instance_name,
         &service_hostname,
         "",
         port,
@@ -361,7 +584,7 @@ fn register_mdns(
         .context("Could not register mDNS daemon")?;
 
     println!(
-        "Registered pigglet with mDNS:\n\tInstance: {serial_number}\n\tHostname: {service_hostname}\n\tService Type: {service_type}"
+        "Registered pigglet with mDNS:\n\tInstance: {instance_name}\n\tHostname: {service_hostname}\n\tService Type: {service_type}"
     );
 
     Ok((service_info, service_daemon))
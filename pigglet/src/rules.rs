@@ -0,0 +1,410 @@
+use crate::device_net::hub::{ClientMessage, RULES_CLIENT};
+use anyhow::Context;
+use futures::channel::mpsc::UnboundedSender;
+use log::{info, warn};
+use pigdef::config::HardwareConfigMessage::{IOLevelChanged, NewConfig, NewPinConfig};
+use pigdef::config::{HardwareConfigMessage, LevelChange};
+use pigdef::description::{BCMPinNumber, PinLevel};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+use tokio::runtime::Handle;
+
+/// The name of the file, in the same directory as the config file, that rules are loaded from
+pub const RULES_FILENAME: &str = "pigglet_rules.json";
+
+/// A change in the level of an input that triggers a [Rule]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Edge {
+    Rising,
+    Falling,
+    Both,
+}
+
+impl Edge {
+    /// Is a change to `level` this edge
+    fn matches(self, level: PinLevel) -> bool {
+        match self {
+            Edge::Rising => level,
+            Edge::Falling => !level,
+            Edge::Both => true,
+        }
+    }
+}
+
+/// A rule that changes an output when an input changes level, so that the hardware does
+/// something useful even when no client is connected to it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Rule {
+    /// On each `edge` of `input` set `output` to `level` for `duration_ms`, then back again
+    Pulse {
+        input: BCMPinNumber,
+        edge: Edge,
+        output: BCMPinNumber,
+        level: PinLevel,
+        duration_ms: u64,
+    },
+    /// On each `edge` of `input` change `output` to the opposite level
+    Toggle {
+        input: BCMPinNumber,
+        edge: Edge,
+        output: BCMPinNumber,
+    },
+    /// Set `output` to the same level as `input` each time it changes, or the opposite level
+    /// if `invert` is set
+    Mirror {
+        input: BCMPinNumber,
+        output: BCMPinNumber,
+        #[serde(default)]
+        invert: bool,
+    },
+}
+
+impl Rule {
+    /// The input that triggers this rule
+    fn input(&self) -> BCMPinNumber {
+        match self {
+            Rule::Pulse { input, .. } | Rule::Toggle { input, .. } | Rule::Mirror { input, .. } => {
+                *input
+            }
+        }
+    }
+}
+
+/// Load the [Rule]s in the JSON file at `path`, there are none if there is no file
+pub fn load(path: &Path) -> anyhow::Result<Vec<Rule>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => {
+            let rules: Vec<Rule> = serde_json::from_str(&contents)
+                .with_context(|| format!("Could not parse rules file {}", path.display()))?;
+            info!("{} rules loaded from {}", rules.len(), path.display());
+            Ok(rules)
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e).with_context(|| format!("Could not read {}", path.display())),
+    }
+}
+
+/// Runs the [Rule]s on the input level changes reported by the hardware. Output changes are
+/// sent to the hub, as if from a client, so they are applied to the hardware and config in
+/// order with those from clients, and all clients are informed of them.
+pub struct Rules {
+    rules: Vec<Rule>,
+    /// The last level reported for each input, to detect edges
+    input_levels: Mutex<HashMap<BCMPinNumber, PinLevel>>,
+    /// The level each output was last toggled to, which the config does not show until the
+    /// hub has applied it, so that edges close together each toggle the output again
+    toggled_levels: Mutex<HashMap<BCMPinNumber, PinLevel>>,
+    to_hub: UnboundedSender<ClientMessage>,
+    /// Used to end pulses, as level changes are reported on threads outside the runtime
+    runtime: Handle,
+}
+
+impl Rules {
+    /// Create [Rules] that send output changes to the hub using `to_hub`.
+    /// Must be called from within the tokio runtime.
+    pub fn new(rules: Vec<Rule>, to_hub: UnboundedSender<ClientMessage>) -> Self {
+        Self {
+            rules,
+            input_levels: Mutex::new(HashMap::new()),
+            toggled_levels: Mutex::new(HashMap::new()),
+            to_hub,
+            runtime: Handle::current(),
+        }
+    }
+
+    /// Run the rules triggered by input `bcm_pin_number` changing level, using `output_level`
+    /// to get the current level of outputs that have not been toggled since a client changed them
+    pub fn input_changed(
+        &self,
+        bcm_pin_number: BCMPinNumber,
+        level_change: &LevelChange,
+        output_level: impl Fn(BCMPinNumber) -> PinLevel,
+    ) {
+        let level = level_change.new_level;
+        let previous = self
+            .input_levels
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(bcm_pin_number, level);
+        // Levels can be reported again without changing, the first reported is not an edge
+        let changed = previous != Some(level);
+        let edge = previous.is_some() && changed;
+        let timestamp = level_change.timestamp;
+
+        for rule in self
+            .rules
+            .iter()
+            .filter(|rule| rule.input() == bcm_pin_number)
+        {
+            match rule {
+                Rule::Pulse {
+                    edge: trigger,
+                    output,
+                    level: pulse_level,
+                    duration_ms,
+                    ..
+                } if edge && trigger.matches(level) => {
+                    self.set_output(*output, LevelChange::new(*pulse_level, timestamp));
+                    let duration = Duration::from_millis(*duration_ms);
+                    let end = (
+                        RULES_CLIENT,
+                        IOLevelChanged(
+                            *output,
+                            LevelChange::new(!pulse_level, timestamp + duration),
+                        ),
+                    );
+                    let to_hub = self.to_hub.clone();
+                    self.runtime.spawn(async move {
+                        tokio::time::sleep(duration).await;
+                        let _ = to_hub.unbounded_send(end);
+                    });
+                }
+                Rule::Toggle {
+                    edge: trigger,
+                    output,
+                    ..
+                } if edge && trigger.matches(level) => {
+                    let mut toggled_levels = self
+                        .toggled_levels
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner);
+                    let toggled = !toggled_levels
+                        .get(output)
+                        .copied()
+                        .unwrap_or_else(|| output_level(*output));
+                    toggled_levels.insert(*output, toggled);
+                    self.set_output(*output, LevelChange::new(toggled, timestamp));
+                }
+                Rule::Mirror { output, invert, .. } if changed => {
+                    self.set_output(*output, LevelChange::new(level != *invert, timestamp));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Forget the levels outputs were toggled to that a change made by a client in `message`
+    /// overrides, so the next toggle of them starts from the config
+    pub fn changed_by_client(&self, message: &HardwareConfigMessage) {
+        let mut toggled_levels = self
+            .toggled_levels
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        match message {
+            NewConfig(_) => toggled_levels.clear(),
+            NewPinConfig(bcm_pin_number, _) | IOLevelChanged(bcm_pin_number, _) => {
+                toggled_levels.remove(bcm_pin_number);
+            }
+            _ => {}
+        }
+    }
+
+    /// Send a change of `output` level to the hub
+    fn set_output(&self, output: BCMPinNumber, level_change: LevelChange) {
+        if self
+            .to_hub
+            .unbounded_send((RULES_CLIENT, IOLevelChanged(output, level_change)))
+            .is_err()
+        {
+            warn!("Could not send output change of pin #{output} from rules");
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod test {
+    use super::*;
+    use crate::device_net::hub;
+    use crate::device_net::hub::Clients;
+    use futures::channel::mpsc;
+    use futures::{FutureExt, StreamExt};
+    use pigdef::config::HardwareConfig;
+    use pigdef::config::HardwareConfigMessage;
+    use pigdef::pin_function::PinFunction::Output;
+    use piggpio::HW;
+    use tempfile::{tempdir, TempDir};
+
+    const INPUT: BCMPinNumber = 26;
+    const OUTPUT: BCMPinNumber = 17;
+
+    /// Fake hardware with [OUTPUT] configured as a low output, run by a hub with `rules`, plus
+    /// a callback to report input level changes with, and a connected client's queue of messages
+    struct Fixture {
+        hw: HW,
+        config: HardwareConfig,
+        clients: Clients,
+        from_clients: mpsc::UnboundedReceiver<ClientMessage>,
+        callback: Box<dyn FnMut(HardwareConfigMessage)>,
+        client: mpsc::Receiver<HardwareConfigMessage>,
+        /// Where the hub stores the config, removed when the fixture is dropped
+        config_dir: TempDir,
+    }
+
+    impl Fixture {
+        async fn new(rules: Vec<Rule>) -> Self {
+            let mut config = HardwareConfig::default();
+            config.pin_functions.insert(OUTPUT, Output(Some(false)));
+            let (to_hub, from_clients) = mpsc::unbounded();
+            let clients = Clients::with_rules(config.clone(), Rules::new(rules, to_hub));
+            let mut hw = HW::new();
+            hw.apply_config(&config, clients.input_callback())
+                .await
+                .expect("Could not apply config");
+            let (_id, client, _config) = clients.add("test").expect("Could not add client");
+            let callback = Box::new(clients.input_callback());
+            Fixture {
+                hw,
+                config,
+                clients,
+                from_clients,
+                callback,
+                client,
+                config_dir: tempdir().expect("Could not create a tempdir"),
+            }
+        }
+
+        /// Report a level change of [INPUT] at `millis` after boot
+        fn input(&mut self, level: PinLevel, millis: u64) {
+            (self.callback)(IOLevelChanged(
+                INPUT,
+                LevelChange::new(level, Duration::from_millis(millis)),
+            ));
+        }
+
+        /// Let the hub apply the output changes sent to it for `millis`, then return the
+        /// level of [OUTPUT]
+        async fn output_after(&mut self, millis: u64) -> PinLevel {
+            let config_path = self.config_dir.path().join("config");
+            let _ = tokio::time::timeout(
+                Duration::from_millis(millis),
+                hub::run(
+                    &mut self.hw,
+                    &mut self.config,
+                    &config_path,
+                    &self.clients,
+                    &mut self.from_clients,
+                    std::future::pending(),
+                ),
+            )
+            .await;
+            self.hw
+                .get_output_level(OUTPUT)
+                .expect("Output not configured")
+        }
+
+        /// The levels of [OUTPUT] the client has been informed of
+        fn output_changes(&mut self) -> Vec<PinLevel> {
+            let mut levels = vec![];
+            while let Some(Some(message)) = self.client.next().now_or_never() {
+                if let IOLevelChanged(OUTPUT, level_change) = message {
+                    levels.push(level_change.new_level);
+                }
+            }
+            levels
+        }
+    }
+
+    #[tokio::test]
+    async fn pulse_on_falling_edge() {
+        let mut fixture = Fixture::new(vec![Rule::Pulse {
+            input: INPUT,
+            edge: Edge::Falling,
+            output: OUTPUT,
+            level: true,
+            duration_ms: 200,
+        }])
+        .await;
+        fixture.input(true, 0);
+        assert!(!fixture.output_after(50).await);
+        fixture.input(false, 100);
+        assert!(fixture.output_after(50).await);
+        assert!(!fixture.output_after(400).await);
+        assert_eq!(fixture.output_changes(), vec![true, false]);
+    }
+
+    #[tokio::test]
+    async fn toggle_on_rising_edge() {
+        let mut fixture = Fixture::new(vec![Rule::Toggle {
+            input: INPUT,
+            edge: Edge::Rising,
+            output: OUTPUT,
+        }])
+        .await;
+        fixture.input(false, 0);
+        fixture.input(true, 100);
+        assert!(fixture.output_after(50).await);
+        // Levels reported again without changing are not edges
+        fixture.input(true, 150);
+        fixture.input(false, 200);
+        assert!(fixture.output_after(50).await);
+        fixture.input(true, 300);
+        assert!(!fixture.output_after(50).await);
+        assert_eq!(fixture.output_changes(), vec![true, false]);
+    }
+
+    #[tokio::test]
+    async fn toggle_on_edges_before_applied() {
+        let mut fixture = Fixture::new(vec![Rule::Toggle {
+            input: INPUT,
+            edge: Edge::Rising,
+            output: OUTPUT,
+        }])
+        .await;
+        // Both toggles are sent before the hub applies either of them
+        fixture.input(false, 0);
+        fixture.input(true, 100);
+        fixture.input(false, 150);
+        fixture.input(true, 200);
+        assert!(!fixture.output_after(50).await);
+        assert_eq!(fixture.output_changes(), vec![true, false]);
+    }
+
+    #[tokio::test]
+    async fn mirror_inverted() {
+        let mut fixture = Fixture::new(vec![Rule::Mirror {
+            input: INPUT,
+            output: OUTPUT,
+            invert: true,
+        }])
+        .await;
+        fixture.input(false, 0);
+        assert!(fixture.output_after(50).await);
+        fixture.input(true, 100);
+        assert!(!fixture.output_after(50).await);
+        assert_eq!(fixture.output_changes(), vec![true, false]);
+    }
+
+    #[test]
+    fn load_rules() {
+        let dir = tempdir().expect("Could not create a tempdir");
+        let path = dir.path().join(RULES_FILENAME);
+        assert!(load(&path)
+            .expect("Could not load missing rules")
+            .is_empty());
+        fs::write(
+            &path,
+            r#"[
+                {"Pulse": {"input": 26, "edge": "Falling", "output": 17, "level": true, "duration_ms": 500}},
+                {"Toggle": {"input": 26, "edge": "Rising", "output": 17}},
+                {"Mirror": {"input": 5, "output": 6}}
+            ]"#,
+        )
+        .expect("Could not write rules");
+        let rules = load(&path).expect("Could not load rules");
+        assert_eq!(rules.len(), 3);
+        assert_eq!(
+            rules[2],
+            Rule::Mirror {
+                input: 5,
+                output: 6,
+                invert: false
+            }
+        );
+    }
+}
@@ -0,0 +1,385 @@
+use anyhow::anyhow;
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use log::info;
+use pigdef::description::{BCMPinNumber, PinLevel};
+use pigdef::sequence::{Schedule, Sequence, SequenceId, SequenceState, SECONDS_PER_DAY};
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep_until, Instant};
+
+/// An output change made by a running sequence or schedule, or the end of a sequence, sent to
+/// the hub to be applied in order with the changes made by clients
+pub struct SequenceEvent {
+    pub id: SequenceId,
+    /// Distinguishes events from a sequence that has since been replaced or stopped
+    generation: u64,
+    /// The output to change and the level to set it to, None when the sequence has finished
+    pub step: Option<(BCMPinNumber, PinLevel)>,
+}
+
+/// A sequence or schedule that is running on the tokio runtime
+struct Running {
+    generation: u64,
+    state: SequenceState,
+    task: JoinHandle<()>,
+}
+
+/// Runs the [Sequence]s and [Schedule]s started by clients, each in its own task, that send
+/// the output changes to make as [SequenceEvent]s
+pub struct Sequencer {
+    running: HashMap<SequenceId, Running>,
+    next_generation: u64,
+    events: UnboundedSender<SequenceEvent>,
+}
+
+impl Sequencer {
+    /// Create a [Sequencer] with nothing running, and the receiver of its [SequenceEvent]s
+    pub fn new() -> (Self, UnboundedReceiver<SequenceEvent>) {
+        let (events, receiver) = unbounded();
+        (
+            Sequencer {
+                running: HashMap::new(),
+                next_generation: 0,
+                events,
+            },
+            receiver,
+        )
+    }
+
+    /// Start running `sequence` as `id`, replacing anything running with that id
+    pub fn start_sequence(&mut self, id: SequenceId, sequence: Sequence) -> anyhow::Result<()> {
+        sequence.check().map_err(|e| anyhow!(e))?;
+        info!(
+            "Starting sequence #{id} of {} steps, repeated {} times",
+            sequence.steps.len(),
+            sequence.repeat
+        );
+        let sender = self.sender(id);
+        self.start(id, SequenceState::Running, async move {
+            // Delays are counted from when the previous step was due, so they do not drift
+            let mut deadline = Instant::now();
+            let mut repeats = 0;
+            while sequence.repeat == 0 || repeats < sequence.repeat {
+                for step in &sequence.steps {
+                    sender.step(step.bcm_pin_number, step.level)?;
+                    deadline += Duration::from_millis(step.delay_ms.into());
+                    sleep_until(deadline).await;
+                }
+                repeats += 1;
+            }
+            sender.finished()
+        });
+        Ok(())
+    }
+
+    /// Start running `schedule` as `id`, replacing anything running with that id. Outputs are
+    /// first set to the levels they would be at now, had the schedule been running all along.
+    pub fn start_schedule(&mut self, id: SequenceId, schedule: Schedule) -> anyhow::Result<()> {
+        schedule.check().map_err(|e| anyhow!(e))?;
+        info!(
+            "Starting schedule #{id} of {} entries",
+            schedule.entries.len()
+        );
+        let sender = self.sender(id);
+        self.start(id, SequenceState::Scheduled, async move {
+            let mut time_of_day = schedule.now;
+            let mut deadline = Instant::now();
+            for (bcm_pin_number, level) in schedule.levels_at(time_of_day) {
+                sender.step(bcm_pin_number, level)?;
+            }
+            while let Some(seconds) = schedule.seconds_until_next(time_of_day) {
+                deadline += Duration::from_secs(seconds.into());
+                sleep_until(deadline).await;
+                time_of_day = (time_of_day + seconds) % SECONDS_PER_DAY;
+                for entry in schedule.entries_at(time_of_day) {
+                    sender.step(entry.bcm_pin_number, entry.level)?;
+                }
+            }
+            Some(())
+        });
+        Ok(())
+    }
+
+    /// Stop the sequence or schedule `id`, returning false if it was not running
+    pub fn stop(&mut self, id: SequenceId) -> bool {
+        match self.running.remove(&id) {
+            Some(running) => {
+                info!("Stopping sequence #{id}");
+                running.task.abort();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The [SequenceState] of the sequence or schedule `id`
+    pub fn status(&self, id: SequenceId) -> SequenceState {
+        self.running
+            .get(&id)
+            .map_or(SequenceState::Stopped, |running| running.state)
+    }
+
+    /// Return true if `event` is from a sequence or schedule that is still running, so should
+    /// be acted on. A sequence that has finished is no longer running after this.
+    pub fn is_current(&mut self, event: &SequenceEvent) -> bool {
+        let current = self
+            .running
+            .get(&event.id)
+            .is_some_and(|running| running.generation == event.generation);
+        if current && event.step.is_none() {
+            info!("Sequence #{} finished", event.id);
+            self.running.remove(&event.id);
+        }
+        current
+    }
+
+    /// A sender of [SequenceEvent]s for the next sequence or schedule to be started as `id`
+    fn sender(&self, id: SequenceId) -> EventSender {
+        EventSender {
+            id,
+            generation: self.next_generation,
+            events: self.events.clone(),
+        }
+    }
+
+    /// Spawn the task running a sequence or schedule as `id`, replacing anything running with
+    /// that id. The task ends early if the hub is no longer receiving its events.
+    fn start(
+        &mut self,
+        id: SequenceId,
+        state: SequenceState,
+        task: impl Future<Output = Option<()>> + Send + 'static,
+    ) {
+        self.stop(id);
+        let running = Running {
+            generation: self.next_generation,
+            state,
+            task: tokio::spawn(async move {
+                let _ = task.await;
+            }),
+        };
+        self.next_generation += 1;
+        self.running.insert(id, running);
+    }
+}
+
+impl Drop for Sequencer {
+    fn drop(&mut self) {
+        for running in self.running.values() {
+            running.task.abort();
+        }
+    }
+}
+
+/// Used by a task running a sequence or schedule to send its [SequenceEvent]s
+struct EventSender {
+    id: SequenceId,
+    generation: u64,
+    events: UnboundedSender<SequenceEvent>,
+}
+
+impl EventSender {
+    /// Send an output change, None if the hub is no longer receiving them
+    fn step(&self, bcm_pin_number: BCMPinNumber, level: PinLevel) -> Option<()> {
+        self.send(Some((bcm_pin_number, level)))
+    }
+
+    /// Send the end of the sequence
+    fn finished(&self) -> Option<()> {
+        self.send(None)
+    }
+
+    fn send(&self, step: Option<(BCMPinNumber, PinLevel)>) -> Option<()> {
+        self.events
+            .unbounded_send(SequenceEvent {
+                id: self.id,
+                generation: self.generation,
+                step,
+            })
+            .ok()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod test {
+    use crate::device_net::hub;
+    use crate::device_net::hub::Clients;
+    use futures::channel::mpsc;
+    use futures::StreamExt;
+    use pigdef::config::HardwareConfig;
+    use pigdef::config::HardwareConfigMessage;
+    use pigdef::config::HardwareConfigMessage::{
+        GetSequenceStatus, IOLevelChanged, SequenceStatus, StartSchedule, StartSequence,
+        StopSequence,
+    };
+    use pigdef::description::{BCMPinNumber, PinLevel};
+    use pigdef::pin_function::PinFunction::Output;
+    use pigdef::sequence::{Schedule, ScheduleEntry, Sequence, SequenceState, SequenceStep};
+    use piggpio::HW;
+    use std::time::Duration;
+    use tempfile::tempdir;
+    use tokio::time::{sleep_until, Instant};
+
+    const OUTPUT: BCMPinNumber = 17;
+
+    /// What a client was sent about [OUTPUT] and sequence #1
+    #[derive(Debug, PartialEq)]
+    enum Sent {
+        Level(PinLevel),
+        State(SequenceState),
+    }
+
+    fn pulse(repeat: u32) -> Sequence {
+        Sequence {
+            steps: vec![
+                SequenceStep {
+                    bcm_pin_number: OUTPUT,
+                    level: true,
+                    delay_ms: 100,
+                },
+                SequenceStep {
+                    bcm_pin_number: OUTPUT,
+                    level: false,
+                    delay_ms: 50,
+                },
+            ],
+            repeat,
+        }
+    }
+
+    /// Run a hub on fake hardware with [OUTPUT] configured as a low output for `millis`,
+    /// sending it `messages` from a client at the millisecond each is paired with. Returns what
+    /// the client was sent, paired with the millisecond it was sent at.
+    async fn run_hub(messages: Vec<(u64, HardwareConfigMessage)>, millis: u64) -> Vec<(u64, Sent)> {
+        let mut config = HardwareConfig::default();
+        config.pin_functions.insert(OUTPUT, Output(Some(false)));
+        let clients = Clients::new(config.clone());
+        let mut hw = HW::new();
+        hw.apply_config(&config, clients.input_callback())
+            .await
+            .expect("Could not apply config");
+        let (id, mut client, _config) = clients.add("test").expect("Could not add client");
+        let (to_hub, from_clients) = mpsc::unbounded();
+        let config_path = tempdir()
+            .expect("Could not create a tempdir")
+            .keep()
+            .join("config");
+
+        let start = Instant::now();
+        let send = async {
+            for (at, message) in messages {
+                sleep_until(start + Duration::from_millis(at)).await;
+                to_hub
+                    .unbounded_send((id, message))
+                    .expect("Could not send message");
+            }
+            sleep_until(start + Duration::from_millis(millis)).await;
+        };
+        let mut sent = vec![];
+        let receive = async {
+            while let Some(message) = client.next().await {
+                let at = start.elapsed().as_millis() as u64;
+                match message {
+                    IOLevelChanged(OUTPUT, level_change) => {
+                        sent.push((at, Sent::Level(level_change.new_level)))
+                    }
+                    SequenceStatus(1, state) => sent.push((at, Sent::State(state))),
+                    _ => {}
+                }
+            }
+        };
+
+        tokio::select! {
+            _ = hub::run(&mut hw, &mut config, &config_path, &clients, from_clients, std::future::pending()) => {}
+            _ = send => {}
+            _ = receive => {}
+        }
+        sent
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sequence_repeats_then_stops() {
+        let sent = run_hub(vec![(0, StartSequence(1, pulse(2)))], 1000).await;
+        assert_eq!(
+            sent,
+            vec![
+                (0, Sent::State(SequenceState::Running)),
+                (0, Sent::Level(true)),
+                (100, Sent::Level(false)),
+                (150, Sent::Level(true)),
+                (250, Sent::Level(false)),
+                (300, Sent::State(SequenceState::Stopped)),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stop_sequence() {
+        let sent = run_hub(
+            vec![
+                (0, StartSequence(1, pulse(0))),
+                (120, GetSequenceStatus(1)),
+                (220, StopSequence(1)),
+                (230, GetSequenceStatus(1)),
+            ],
+            1000,
+        )
+        .await;
+        assert_eq!(
+            sent,
+            vec![
+                (0, Sent::State(SequenceState::Running)),
+                (0, Sent::Level(true)),
+                (100, Sent::Level(false)),
+                (120, Sent::State(SequenceState::Running)),
+                (150, Sent::Level(true)),
+                (220, Sent::State(SequenceState::Stopped)),
+                (230, Sent::State(SequenceState::Stopped)),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sequence_stops_on_error() {
+        let mut sequence = pulse(1);
+        // Pin 5 is not configured as an output
+        sequence.steps[1].bcm_pin_number = 5;
+        let sent = run_hub(vec![(0, StartSequence(1, sequence))], 1000).await;
+        assert_eq!(
+            sent,
+            vec![
+                (0, Sent::State(SequenceState::Running)),
+                (0, Sent::Level(true)),
+                (100, Sent::State(SequenceState::Stopped)),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn schedule_runs_daily() {
+        const MINUTE: u64 = 60 * 1000;
+        let schedule = Schedule {
+            entries: vec![
+                ScheduleEntry::new(8, 0, OUTPUT, true),
+                ScheduleEntry::new(18, 0, OUTPUT, false),
+            ],
+            // 07:59
+            now: (7 * 60 + 59) * 60,
+        };
+        let sent = run_hub(vec![(0, StartSchedule(1, schedule))], 25 * 60 * MINUTE).await;
+        assert_eq!(
+            sent,
+            vec![
+                (0, Sent::State(SequenceState::Scheduled)),
+                // Set to the level of 18:00 yesterday when started
+                (0, Sent::Level(false)),
+                (MINUTE, Sent::Level(true)),
+                ((10 * 60 + 1) * MINUTE, Sent::Level(false)),
+                ((24 * 60 + 1) * MINUTE, Sent::Level(true)),
+            ]
+        );
+    }
+}
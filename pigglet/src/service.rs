@@ -1,8 +1,9 @@
-use clap::ArgMatches;
+use clap::{Arg, ArgMatches, Command};
 use service_manager::{
-    ServiceInstallCtx, ServiceLabel, ServiceManager, ServiceStartCtx, ServiceStopCtx,
-    ServiceUninstallCtx,
+    ServiceInstallCtx, ServiceLabel, ServiceManager, ServiceStartCtx, ServiceStatus,
+    ServiceStatusCtx, ServiceStopCtx, ServiceUninstallCtx,
 };
+use std::ffi::OsString;
 use std::io;
 use std::path::Path;
 use std::process::exit;
@@ -10,8 +11,28 @@ use std::time::Duration;
 
 const SERVICE_NAME: &str = "net.mackenzie-serres.pigg.pigglet";
 
-/// Handle any service installation or uninstallation tasks specified on the command line
-/// continue without doing anything if none were specified
+/// Options given with `--install` that are passed on to the service when it runs, and whether
+/// their value is a path that needs to be made absolute to be found by the service.
+/// Options for features pigglet was built without are skipped.
+const SERVICE_OPTIONS: [(&str, bool); 13] = [
+    ("verbosity", false),
+    ("config", true),
+    ("settings", true),
+    ("transports", false),
+    ("bind", false),
+    ("port", false),
+    ("http_bind", false),
+    ("http_port", false),
+    ("mqtt_host", false),
+    ("mqtt_port", false),
+    ("relay_url", false),
+    ("mdns_name", false),
+    ("mdns_txt", false),
+];
+
+/// Handle any service installation, uninstallation, status or restart tasks specified on the
+/// command line, exiting when done except after status, continue without doing anything if
+/// none were specified
 pub(crate) fn manage(exec_path: &Path, matches: &ArgMatches) -> anyhow::Result<()> {
     let service_name: ServiceLabel = SERVICE_NAME.parse()?;
 
@@ -22,13 +43,82 @@ pub(crate) fn manage(exec_path: &Path, matches: &ArgMatches) -> anyhow::Result<(
     }
 
     if matches.get_flag("install") {
-        install_service(&service_name, exec_path)?;
+        install_service(&service_name, exec_path, matches)?;
         exit(0);
     };
 
+    // The status of any running instance of pigglet is reported after that of the service
+    if matches.get_flag("status") {
+        if let Err(e) = service_status(&service_name) {
+            println!("Could not get the status of service '{service_name}': {e}");
+        }
+        return Ok(());
+    }
+
+    if matches.get_flag("restart") {
+        restart_service(&service_name)?;
+        exit(0);
+    }
+
     Ok(())
 }
 
+/// Add the command line options to manage the pigglet service to `app`
+pub(crate) fn args(app: Command) -> Command {
+    let app = app.arg(
+        Arg::new("install")
+            .short('i')
+            .long("install")
+            .action(clap::ArgAction::SetTrue)
+            .help("Install pigglet as a System Service that restarts on reboot, with the options given")
+            .conflicts_with("uninstall"),
+    );
+
+    let app = app.arg(
+        Arg::new("uninstall")
+            .short('u')
+            .long("uninstall")
+            .action(clap::ArgAction::SetTrue)
+            .help("Uninstall any pigglet System Service")
+            .conflicts_with("install"),
+    );
+
+    let app = app.arg(
+        Arg::new("status")
+            .long("status")
+            .action(clap::ArgAction::SetTrue)
+            .help("Show the status of the pigglet System Service, and of any running pigglet with its clients")
+            .conflicts_with_all(["install", "uninstall"]),
+    );
+
+    let app = app.arg(
+        Arg::new("restart")
+            .long("restart")
+            .action(clap::ArgAction::SetTrue)
+            .help("Restart the pigglet System Service")
+            .conflicts_with_all(["install", "uninstall", "status"]),
+    );
+
+    let app = app.arg(
+        Arg::new("user")
+            .long("user")
+            .num_args(1)
+            .number_of_values(1)
+            .value_name("USERNAME")
+            .help("User to run the pigglet System Service as")
+            .requires("install"),
+    );
+
+    app.arg(
+        Arg::new("env")
+            .long("env")
+            .action(clap::ArgAction::Append)
+            .value_name("NAME=VALUE")
+            .help("Environment variable to set for the pigglet System Service, can be repeated")
+            .requires("install"),
+    )
+}
+
 /// Get a [ServiceManager] instance to use to install or remove system services
 fn get_service_manager() -> Result<Box<dyn ServiceManager>, io::Error> {
     // Get generic service by detecting what is available on the platform
@@ -38,9 +128,31 @@ fn get_service_manager() -> Result<Box<dyn ServiceManager>, io::Error> {
     Ok(manager)
 }
 
-/// Install the binary as a user-level service and then start it
-fn install_service(service_name: &ServiceLabel, exec_path: &Path) -> Result<(), io::Error> {
-    let manager = get_service_manager()?;
+/// The arguments to run the service with, from the [SERVICE_OPTIONS] given on the command line
+fn service_args(matches: &ArgMatches) -> Result<Vec<OsString>, io::Error> {
+    let mut args = vec![];
+    for (id, is_path) in SERVICE_OPTIONS {
+        if let Ok(Some(values)) = matches.try_get_raw(id) {
+            for value in values {
+                args.push(OsString::from(format!("--{id}")));
+                if is_path {
+                    args.push(std::path::absolute(value)?.into_os_string());
+                } else {
+                    args.push(value.to_os_string());
+                }
+            }
+        }
+    }
+    Ok(args)
+}
+
+/// Create the [ServiceInstallCtx] describing the service to install, that runs `exec_path`
+/// with the options, user and environment given on the command line
+fn install_ctx(
+    service_name: &ServiceLabel,
+    exec_path: &Path,
+    matches: &ArgMatches,
+) -> Result<ServiceInstallCtx, io::Error> {
     // Run from the dir where exec is for now, so it should find the config file in the ancestor's path
     let exec_dir = exec_path // jonesy:allow(bounds)
         .parent()
@@ -50,18 +162,48 @@ fn install_service(service_name: &ServiceLabel, exec_path: &Path) -> Result<(),
         ))?
         .to_path_buf();
 
-    // Install our service using the underlying service management platform
-    manager.install(ServiceInstallCtx {
+    let environment = match matches.get_many::<String>("env") {
+        Some(variables) => Some(
+            variables
+                .map(|variable| {
+                    variable
+                        .split_once('=')
+                        .map(|(name, value)| (name.to_string(), value.to_string()))
+                        .ok_or(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!(
+                                "Invalid environment variable '{variable}', expected NAME=VALUE"
+                            ),
+                        ))
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        None => None,
+    };
+
+    Ok(ServiceInstallCtx {
         label: service_name.clone(),
         program: exec_path.to_path_buf(),
-        args: vec![],
+        args: service_args(matches)?,
         contents: None, // Optional String for system-specific service content.
-        username: None, // Optional String for an alternative user to run service.
+        username: matches.get_one::<String>("user").cloned(),
         working_directory: Some(exec_dir),
-        environment: None, // Optional list of environment variables to supply the service process.
+        environment,
         autostart: true,
         restart_policy: Default::default(),
-    })?;
+    })
+}
+
+/// Install the binary as a user-level service and then start it
+fn install_service(
+    service_name: &ServiceLabel,
+    exec_path: &Path,
+    matches: &ArgMatches,
+) -> Result<(), io::Error> {
+    let manager = get_service_manager()?;
+
+    // Install our service using the underlying service management platform
+    manager.install(install_ctx(service_name, exec_path, matches)?)?;
 
     // Start our service using the underlying service management platform
     manager.start(ServiceStartCtx {
@@ -83,6 +225,55 @@ fn install_service(service_name: &ServiceLabel, exec_path: &Path) -> Result<(),
     Ok(())
 }
 
+/// Print the status of the service
+fn service_status(service_name: &ServiceLabel) -> Result<(), io::Error> {
+    let manager = get_service_manager()?;
+
+    match manager.status(ServiceStatusCtx {
+        label: service_name.clone(),
+    })? {
+        ServiceStatus::NotInstalled => println!("service '{service_name}' is not installed"),
+        ServiceStatus::Running => println!("service '{service_name}' is running"),
+        ServiceStatus::Stopped(None) => println!("service '{service_name}' is stopped"),
+        ServiceStatus::Stopped(Some(reason)) => {
+            println!("service '{service_name}' is stopped: {reason}")
+        }
+    }
+
+    Ok(())
+}
+
+/// Stop the service if it is running, then start it again
+fn restart_service(service_name: &ServiceLabel) -> Result<(), io::Error> {
+    let manager = get_service_manager()?;
+
+    let status = manager.status(ServiceStatusCtx {
+        label: service_name.clone(),
+    })?;
+
+    if matches!(status, ServiceStatus::NotInstalled) {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("service '{service_name}' is not installed"),
+        ));
+    }
+
+    if matches!(status, ServiceStatus::Running) {
+        manager.stop(ServiceStopCtx {
+            label: service_name.clone(),
+        })?;
+        println!("service '{service_name}' stopped");
+    }
+
+    manager.start(ServiceStartCtx {
+        label: service_name.clone(),
+    })?;
+
+    println!("service '{service_name}' started");
+
+    Ok(())
+}
+
 /// Stop any running instance of the service, then uninstall it
 fn uninstall_service(service_name: &ServiceLabel) -> Result<(), io::Error> {
     let manager = get_service_manager()?;
@@ -105,3 +296,92 @@ fn uninstall_service(service_name: &ServiceLabel) -> Result<(), io::Error> {
 
     Ok(())
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod test {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn ctx(options: &[&str]) -> Result<ServiceInstallCtx, io::Error> {
+        let matches = crate::command()
+            .try_get_matches_from(std::iter::once("pigglet").chain(options.iter().copied()))
+            .expect("Could not parse options");
+        install_ctx(
+            &SERVICE_NAME.parse().expect("Invalid service name"),
+            &PathBuf::from("/usr/local/bin/pigglet"),
+            &matches,
+        )
+    }
+
+    #[test]
+    fn install_defaults() {
+        let ctx = ctx(&["--install"]).expect("Could not create install context");
+        assert_eq!(ctx.program, PathBuf::from("/usr/local/bin/pigglet"));
+        assert_eq!(ctx.working_directory, Some(PathBuf::from("/usr/local/bin")));
+        assert!(ctx.args.is_empty());
+        assert_eq!(ctx.username, None);
+        assert_eq!(ctx.environment, None);
+        assert!(ctx.autostart);
+    }
+
+    #[test]
+    fn install_captures_options() {
+        let ctx = ctx(&["--install", "-v", "debug", "--config", "/home/pi/my.pigg"])
+            .expect("Could not create install context");
+        assert_eq!(
+            ctx.args,
+            vec!["--verbosity", "debug", "--config", "/home/pi/my.pigg"]
+        );
+    }
+
+    #[test]
+    fn install_makes_paths_absolute() {
+        let ctx = ctx(&["--install", "--config", "my.pigg"]).expect("Could not create context");
+        let config_path = PathBuf::from(&ctx.args[1]);
+        assert!(config_path.is_absolute());
+        assert!(config_path.ends_with("my.pigg"));
+    }
+
+    #[cfg(feature = "tcp")]
+    #[test]
+    fn install_captures_settings() {
+        let ctx = ctx(&["--install", "--port", "4242", "--transports", "tcp"])
+            .expect("Could not create install context");
+        assert_eq!(ctx.args, vec!["--transports", "tcp", "--port", "4242"]);
+    }
+
+    #[test]
+    fn install_user_and_environment() {
+        let ctx = ctx(&[
+            "--install",
+            "--user",
+            "pi",
+            "--env",
+            "RUST_LOG=debug",
+            "--env",
+            "RUST_BACKTRACE=1",
+        ])
+        .expect("Could not create install context");
+        assert_eq!(ctx.username.as_deref(), Some("pi"));
+        assert_eq!(
+            ctx.environment,
+            Some(vec![
+                ("RUST_LOG".to_string(), "debug".to_string()),
+                ("RUST_BACKTRACE".to_string(), "1".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn invalid_environment() {
+        assert!(ctx(&["--install", "--env", "RUST_LOG"]).is_err());
+    }
+
+    #[test]
+    fn user_requires_install() {
+        assert!(crate::command()
+            .try_get_matches_from(["pigglet", "--user", "pi"])
+            .is_err());
+    }
+}
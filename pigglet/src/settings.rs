@@ -0,0 +1,797 @@
+use anyhow::Context;
+use clap::{Arg, ArgMatches, Command};
+#[cfg(feature = "iroh")]
+use iroh::{EndpointId, RelayUrl};
+#[cfg(feature = "iroh")]
+use pigdef::config::HardwareConfigMessage;
+#[cfg(any(feature = "iroh", feature = "tcp"))]
+use pigdef::config::MISSED_HEARTBEATS;
+use serde::Deserialize;
+#[cfg(any(feature = "iroh", feature = "tcp"))]
+use serde::Deserializer;
+#[cfg(all(feature = "discovery", feature = "tcp"))]
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+#[cfg(any(feature = "tcp", feature = "http"))]
+use std::net::IpAddr;
+use std::path::Path;
+#[cfg(any(feature = "iroh", feature = "tcp", feature = "http", feature = "mqtt"))]
+use std::str::FromStr;
+
+/// The name of the file, in the same directory as the pigglet executable, that settings are
+/// read from if no other file is specified on the command line
+pub(crate) const SETTINGS_FILENAME: &str = "pigglet.toml";
+
+/// Settings for how pigglet makes itself available on the network, read from a TOML file
+/// and overridden by command line options. Any setting missing from the file is left at
+/// its default value, so an empty or missing file gives the default behaviour.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub(crate) struct Settings {
+    #[cfg(feature = "tcp")]
+    pub(crate) tcp: TcpSettings,
+    #[cfg(feature = "tls")]
+    pub(crate) tls: TlsSettings,
+    #[cfg(feature = "iroh")]
+    pub(crate) iroh: IrohSettings,
+    #[cfg(all(feature = "discovery", feature = "tcp"))]
+    pub(crate) mdns: MdnsSettings,
+    #[cfg(feature = "http")]
+    pub(crate) http: HttpSettings,
+    #[cfg(feature = "mqtt")]
+    pub(crate) mqtt: MqttSettings,
+    #[cfg(any(feature = "iroh", feature = "tcp"))]
+    pub(crate) heartbeats: HeartbeatSettings,
+}
+
+/// Settings for the TCP transport
+#[cfg(feature = "tcp")]
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub(crate) struct TcpSettings {
+    pub(crate) enabled: bool,
+    /// Address to listen on, use "0.0.0.0" for all interfaces. Default is the local IP address
+    pub(crate) bind_address: Option<IpAddr>,
+    /// Port to listen on. Default is to pick an unused port
+    pub(crate) port: Option<u16>,
+    /// Require clients to authenticate, with a key from pairing or the pairing code. Also
+    /// applies to clients connecting with TLS
+    pub(crate) auth: bool,
+}
+
+#[cfg(feature = "tcp")]
+impl Default for TcpSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            bind_address: None,
+            port: None,
+            auth: true,
+        }
+    }
+}
+
+/// Settings for the TCP transport encrypted with TLS, which listens on the same address as TCP
+#[cfg(feature = "tls")]
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub(crate) struct TlsSettings {
+    pub(crate) enabled: bool,
+    /// Port to listen on. Default is to pick an unused port
+    pub(crate) port: Option<u16>,
+}
+
+#[cfg(feature = "tls")]
+impl Default for TlsSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            port: None,
+        }
+    }
+}
+
+/// Settings for the heartbeats clients connected over TCP, TLS or Iroh send to check the link
+#[cfg(any(feature = "iroh", feature = "tcp"))]
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub(crate) struct HeartbeatSettings {
+    /// The number of heartbeats a client that sends them can miss before it is disconnected.
+    /// Zero would disconnect every client that sends them, so at least one is required
+    #[serde(deserialize_with = "at_least_one")]
+    pub(crate) missed: u32,
+}
+
+/// Deserialize a count that must not be zero
+#[cfg(any(feature = "iroh", feature = "tcp"))]
+fn at_least_one<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    match u32::deserialize(deserializer)? {
+        0 => Err(serde::de::Error::custom("must be at least 1")),
+        count => Ok(count),
+    }
+}
+
+#[cfg(any(feature = "iroh", feature = "tcp"))]
+impl Default for HeartbeatSettings {
+    fn default() -> Self {
+        Self {
+            missed: MISSED_HEARTBEATS,
+        }
+    }
+}
+
+/// Settings for the Iroh transport
+#[cfg(feature = "iroh")]
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub(crate) struct IrohSettings {
+    pub(crate) enabled: bool,
+    /// Relay server to use. Default is the closest of the n0 relay servers
+    pub(crate) relay_url: Option<RelayUrl>,
+    /// The clients allowed to connect, each with the [Role] it has. Default is to allow any
+    /// client that knows the endpoint id to connect and control the device
+    pub(crate) allowed_clients: Vec<AllowedClient>,
+}
+
+/// A client allowed to connect over Iroh, identified by its endpoint id
+#[cfg(feature = "iroh")]
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub(crate) struct AllowedClient {
+    #[serde(with = "crate::device_net::iroh_device::as_string")]
+    pub(crate) endpoint_id: EndpointId,
+    pub(crate) role: Role,
+}
+
+/// What a client connected over Iroh is permitted to do
+#[cfg(feature = "iroh")]
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Role {
+    /// Receives the config and level changes of pins, but cannot change them
+    Observer,
+    /// Can also change the config and levels of pins
+    Controller,
+}
+
+#[cfg(feature = "iroh")]
+impl Role {
+    /// Is a client with this role permitted to send `message`. Observers may only ask for
+    /// information, send heartbeats, and disconnect
+    pub(crate) fn permits(self, message: &HardwareConfigMessage) -> bool {
+        match self {
+            Role::Controller => true,
+            Role::Observer => matches!(
+                message,
+                HardwareConfigMessage::GetConfig
+                    | HardwareConfigMessage::GetClientCounts
+                    | HardwareConfigMessage::GetSequenceStatus(_)
+                    | HardwareConfigMessage::Disconnect
+                    | HardwareConfigMessage::Ping(_)
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "iroh")]
+impl Default for IrohSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            relay_url: None,
+            allowed_clients: vec![],
+        }
+    }
+}
+
+/// Settings for the mDNS service registered for discovery
+#[cfg(all(feature = "discovery", feature = "tcp"))]
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub(crate) struct MdnsSettings {
+    /// The mDNS instance name. Default is the serial number of the device
+    pub(crate) instance_name: Option<String>,
+    /// Extra TXT properties to add to those pigglet registers
+    pub(crate) properties: BTreeMap<String, String>,
+}
+
+/// Settings for the HTTP API, which is not served unless enabled
+#[cfg(feature = "http")]
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub(crate) struct HttpSettings {
+    pub(crate) enabled: bool,
+    /// Address to listen on. Default is all interfaces
+    pub(crate) bind_address: IpAddr,
+    /// Port to listen on
+    pub(crate) port: u16,
+}
+
+#[cfg(feature = "http")]
+impl Default for HttpSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind_address: IpAddr::from([0, 0, 0, 0]),
+            port: 8080,
+        }
+    }
+}
+
+/// Settings for the MQTT bridge, which does not connect to a broker unless enabled
+#[cfg(feature = "mqtt")]
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub(crate) struct MqttSettings {
+    pub(crate) enabled: bool,
+    /// Host name or IP address of the broker
+    pub(crate) host: String,
+    pub(crate) port: u16,
+    /// Client id to connect with. Default is "pigglet-" followed by the serial number
+    pub(crate) client_id: Option<String>,
+    pub(crate) username: Option<String>,
+    pub(crate) password: Option<String>,
+    /// First level of the topics pin levels are published to and outputs set with
+    pub(crate) topic_prefix: String,
+    /// First level of the Home Assistant discovery topics
+    pub(crate) discovery_prefix: String,
+}
+
+#[cfg(feature = "mqtt")]
+impl Default for MqttSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: "localhost".to_string(),
+            port: 1883,
+            client_id: None,
+            username: None,
+            password: None,
+            topic_prefix: "pigg".to_string(),
+            discovery_prefix: "homeassistant".to_string(),
+        }
+    }
+}
+
+impl Settings {
+    /// Load [Settings] from the TOML file at `path`, using the defaults if there is no file
+    pub(crate) fn load(path: &Path) -> anyhow::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(contents) => toml::from_str(&contents)
+                .with_context(|| format!("Could not parse settings file {}", path.display())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Could not read {}", path.display())),
+        }
+    }
+
+    /// Is at least one of the transports used to accept connections enabled
+    #[cfg(any(feature = "iroh", feature = "tcp"))]
+    pub(crate) fn transport_enabled(&self) -> bool {
+        #[allow(unused_mut)]
+        let mut enabled = false;
+        #[cfg(feature = "tcp")]
+        {
+            enabled |= self.tcp.enabled;
+        }
+        #[cfg(feature = "tls")]
+        {
+            enabled |= self.tls.enabled;
+        }
+        #[cfg(feature = "iroh")]
+        {
+            enabled |= self.iroh.enabled;
+        }
+        enabled
+    }
+
+    /// Override settings with any given on the command line, using the options added by [args]
+    #[allow(unused_variables)]
+    pub(crate) fn apply_args(&mut self, matches: &ArgMatches) -> anyhow::Result<()> {
+        #[cfg(any(feature = "iroh", feature = "tcp"))]
+        if let Some(transports) = matches.get_many::<String>("transports") {
+            let transports: Vec<&String> = transports.collect();
+            #[cfg(feature = "tcp")]
+            {
+                self.tcp.enabled = transports.iter().any(|t| *t == "tcp");
+            }
+            #[cfg(feature = "tls")]
+            {
+                self.tls.enabled = transports.iter().any(|t| *t == "tls");
+            }
+            #[cfg(feature = "iroh")]
+            {
+                self.iroh.enabled = transports.iter().any(|t| *t == "iroh");
+            }
+        }
+
+        #[cfg(feature = "tcp")]
+        if let Some(bind_address) = matches.get_one::<String>("bind") {
+            self.tcp.bind_address = Some(
+                IpAddr::from_str(bind_address)
+                    .with_context(|| format!("Invalid bind address '{bind_address}'"))?,
+            );
+        }
+
+        #[cfg(feature = "tcp")]
+        if let Some(port) = matches.get_one::<String>("port") {
+            self.tcp.port =
+                Some(u16::from_str(port).with_context(|| format!("Invalid port number '{port}'"))?);
+        }
+
+        #[cfg(feature = "tls")]
+        if let Some(port) = matches.get_one::<String>("tls_port") {
+            self.tls.port = Some(
+                u16::from_str(port).with_context(|| format!("Invalid TLS port number '{port}'"))?,
+            );
+        }
+
+        #[cfg(feature = "tcp")]
+        if matches.get_flag("no_auth") {
+            self.tcp.auth = false;
+        }
+
+        #[cfg(any(feature = "iroh", feature = "tcp"))]
+        if let Some(missed) = matches.get_one::<u32>("missed_heartbeats") {
+            self.heartbeats.missed = *missed;
+        }
+
+        // Giving either HTTP option enables the HTTP API
+        #[cfg(feature = "http")]
+        if let Some(bind_address) = matches.get_one::<String>("http_bind") {
+            self.http.enabled = true;
+            self.http.bind_address = IpAddr::from_str(bind_address)
+                .with_context(|| format!("Invalid HTTP bind address '{bind_address}'"))?;
+        }
+
+        #[cfg(feature = "http")]
+        if let Some(port) = matches.get_one::<String>("http_port") {
+            self.http.enabled = true;
+            self.http.port = u16::from_str(port)
+                .with_context(|| format!("Invalid HTTP port number '{port}'"))?;
+        }
+
+        // Giving either MQTT option enables the MQTT bridge
+        #[cfg(feature = "mqtt")]
+        if let Some(host) = matches.get_one::<String>("mqtt_host") {
+            self.mqtt.enabled = true;
+            self.mqtt.host = host.clone();
+        }
+
+        #[cfg(feature = "mqtt")]
+        if let Some(port) = matches.get_one::<String>("mqtt_port") {
+            self.mqtt.enabled = true;
+            self.mqtt.port = u16::from_str(port)
+                .with_context(|| format!("Invalid MQTT port number '{port}'"))?;
+        }
+
+        #[cfg(feature = "iroh")]
+        if let Some(relay_url) = matches.get_one::<String>("relay_url") {
+            self.iroh.relay_url = Some(
+                RelayUrl::from_str(relay_url)
+                    .with_context(|| format!("Invalid relay URL '{relay_url}'"))?,
+            );
+        }
+
+        #[cfg(all(feature = "discovery", feature = "tcp"))]
+        if let Some(instance_name) = matches.get_one::<String>("mdns_name") {
+            self.mdns.instance_name = Some(instance_name.clone());
+        }
+
+        #[cfg(all(feature = "discovery", feature = "tcp"))]
+        if let Some(properties) = matches.get_many::<String>("mdns_txt") {
+            for property in properties {
+                let (key, value) = property.split_once('=').with_context(|| {
+                    format!("Invalid TXT property '{property}', expected KEY=VALUE")
+                })?;
+                self.mdns
+                    .properties
+                    .insert(key.to_string(), value.to_string());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Add the command line options that override [Settings] to `app`
+pub(crate) fn args(app: Command) -> Command {
+    let app = app.arg(
+        Arg::new("settings")
+            .short('s')
+            .long("settings")
+            .num_args(1)
+            .number_of_values(1)
+            .value_name("Settings File")
+            .help(
+                "Path of a TOML settings file to load, default is 'pigglet.toml' next to pigglet",
+            ),
+    );
+
+    #[cfg(any(feature = "iroh", feature = "tcp"))]
+    let app = app.arg(
+        Arg::new("transports")
+            .long("transports")
+            .action(clap::ArgAction::Append)
+            .value_delimiter(',')
+            .value_parser([
+                #[cfg(feature = "tcp")]
+                "tcp",
+                #[cfg(feature = "tls")]
+                "tls",
+                #[cfg(feature = "iroh")]
+                "iroh",
+            ])
+            .value_name("TRANSPORTS")
+            .help("Comma separated list of the transports to accept connections on"),
+    );
+
+    #[cfg(feature = "tcp")]
+    let app = app.arg(
+        Arg::new("bind")
+            .long("bind")
+            .num_args(1)
+            .number_of_values(1)
+            .value_name("IP_ADDRESS")
+            .help("IP Address to listen for TCP connections on, '0.0.0.0' for all interfaces"),
+    );
+
+    #[cfg(feature = "tcp")]
+    let app = app.arg(
+        Arg::new("port")
+            .long("port")
+            .num_args(1)
+            .number_of_values(1)
+            .value_name("PORT")
+            .help("Port to listen for TCP connections on, instead of an unused one"),
+    );
+
+    #[cfg(feature = "tls")]
+    let app = app.arg(
+        Arg::new("tls_port")
+            .long("tls_port")
+            .num_args(1)
+            .number_of_values(1)
+            .value_name("PORT")
+            .help("Port to listen for TLS connections on, instead of an unused one"),
+    );
+
+    #[cfg(feature = "tcp")]
+    let app = app.arg(
+        Arg::new("no_auth")
+            .long("no_auth")
+            .action(clap::ArgAction::SetTrue)
+            .help("Accept TCP and TLS connections from any client, without it pairing first"),
+    );
+
+    #[cfg(any(feature = "iroh", feature = "tcp"))]
+    let app = app.arg(
+        Arg::new("missed_heartbeats")
+            .long("missed_heartbeats")
+            .num_args(1)
+            .number_of_values(1)
+            .value_name("COUNT")
+            .value_parser(clap::value_parser!(u32).range(1..))
+            .help("Disconnect clients that miss this many heartbeats, instead of 3"),
+    );
+
+    #[cfg(feature = "http")]
+    let app = app.arg(
+        Arg::new("http_bind")
+            .long("http_bind")
+            .num_args(1)
+            .number_of_values(1)
+            .value_name("IP_ADDRESS")
+            .help("Serve the HTTP API, listening on this IP Address instead of all interfaces"),
+    );
+
+    #[cfg(feature = "http")]
+    let app = app.arg(
+        Arg::new("http_port")
+            .long("http_port")
+            .num_args(1)
+            .number_of_values(1)
+            .value_name("PORT")
+            .help("Serve the HTTP API, listening on this port instead of 8080"),
+    );
+
+    #[cfg(feature = "mqtt")]
+    let app = app.arg(
+        Arg::new("mqtt_host")
+            .long("mqtt_host")
+            .num_args(1)
+            .number_of_values(1)
+            .value_name("HOST")
+            .help("Bridge to the MQTT broker on this host, instead of 'localhost'"),
+    );
+
+    #[cfg(feature = "mqtt")]
+    let app = app.arg(
+        Arg::new("mqtt_port")
+            .long("mqtt_port")
+            .num_args(1)
+            .number_of_values(1)
+            .value_name("PORT")
+            .help("Bridge to the MQTT broker on this port, instead of 1883"),
+    );
+
+    #[cfg(feature = "iroh")]
+    let app = app.arg(
+        Arg::new("relay_url")
+            .long("relay_url")
+            .num_args(1)
+            .number_of_values(1)
+            .value_name("RELAY_URL")
+            .help("URL of the Iroh relay server to use"),
+    );
+
+    #[cfg(all(feature = "discovery", feature = "tcp"))]
+    let app = app.arg(
+        Arg::new("mdns_name")
+            .long("mdns_name")
+            .num_args(1)
+            .number_of_values(1)
+            .value_name("NAME")
+            .help("mDNS instance name to register, instead of the serial number"),
+    );
+
+    #[cfg(all(feature = "discovery", feature = "tcp"))]
+    let app = app.arg(
+        Arg::new("mdns_txt")
+            .long("mdns_txt")
+            .action(clap::ArgAction::Append)
+            .value_name("KEY=VALUE")
+            .help("Extra TXT property to register with mDNS, can be repeated"),
+    );
+
+    app
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod test {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn matches(options: &[&str]) -> ArgMatches {
+        args(Command::new("pigglet"))
+            .try_get_matches_from(std::iter::once("pigglet").chain(options.iter().copied()))
+            .expect("Could not parse options")
+    }
+
+    #[test]
+    fn missing_file_is_default() {
+        let path = tempdir().expect("Could not create a tempdir").keep();
+        let settings = Settings::load(&path.join(SETTINGS_FILENAME)).expect("Could not load");
+        assert_eq!(settings, Settings::default());
+    }
+
+    #[test]
+    fn invalid_file() {
+        let path = tempdir()
+            .expect("Could not create a tempdir")
+            .keep()
+            .join(SETTINGS_FILENAME);
+        fs::write(&path, "[tcp\nport = 4321\n").expect("Could not write settings");
+        assert!(Settings::load(&path).is_err());
+    }
+
+    #[cfg(feature = "tcp")]
+    #[test]
+    fn load_tcp_settings() {
+        let path = tempdir()
+            .expect("Could not create a tempdir")
+            .keep()
+            .join(SETTINGS_FILENAME);
+        fs::write(&path, "[tcp]\nbind_address = \"0.0.0.0\"\nport = 4321\n")
+            .expect("Could not write settings");
+        let settings = Settings::load(&path).expect("Could not load settings");
+        assert!(settings.tcp.enabled);
+        assert_eq!(
+            settings.tcp.bind_address,
+            Some(IpAddr::from_str("0.0.0.0").unwrap())
+        );
+        assert_eq!(settings.tcp.port, Some(4321));
+    }
+
+    #[cfg(feature = "tcp")]
+    #[test]
+    fn options_override_file() {
+        let mut settings: Settings = toml::from_str("[tcp]\nport = 4321\n").unwrap();
+        settings
+            .apply_args(&matches(&["--port", "1234", "--bind", "127.0.0.1"]))
+            .expect("Could not apply options");
+        assert_eq!(settings.tcp.port, Some(1234));
+        assert_eq!(
+            settings.tcp.bind_address,
+            Some(IpAddr::from_str("127.0.0.1").unwrap())
+        );
+    }
+
+    #[cfg(feature = "tcp")]
+    #[test]
+    fn invalid_port_option() {
+        let mut settings = Settings::default();
+        assert!(settings.apply_args(&matches(&["--port", "99999"])).is_err());
+    }
+
+    #[cfg(feature = "tcp")]
+    #[test]
+    fn auth_settings() {
+        let mut settings: Settings = toml::from_str("[tcp]\nport = 4321\n").unwrap();
+        assert!(settings.tcp.auth);
+        settings
+            .apply_args(&matches(&["--no_auth"]))
+            .expect("Could not apply options");
+        assert!(!settings.tcp.auth);
+        let settings: Settings = toml::from_str("[tcp]\nauth = false\n").unwrap();
+        assert!(!settings.tcp.auth);
+    }
+
+    #[cfg(any(feature = "iroh", feature = "tcp"))]
+    #[test]
+    fn heartbeat_settings() {
+        assert_eq!(Settings::default().heartbeats.missed, MISSED_HEARTBEATS);
+        let mut settings: Settings =
+            toml::from_str("[heartbeats]\nmissed = 5\n").expect("Could not parse settings");
+        assert_eq!(settings.heartbeats.missed, 5);
+        settings
+            .apply_args(&matches(&["--missed_heartbeats", "2"]))
+            .expect("Could not apply options");
+        assert_eq!(settings.heartbeats.missed, 2);
+    }
+
+    #[cfg(any(feature = "iroh", feature = "tcp"))]
+    #[test]
+    fn zero_missed_heartbeats_option() {
+        assert!(args(Command::new("pigglet"))
+            .try_get_matches_from(["pigglet", "--missed_heartbeats", "0"])
+            .is_err());
+    }
+
+    #[cfg(any(feature = "iroh", feature = "tcp"))]
+    #[test]
+    fn zero_missed_heartbeats_setting() {
+        let dir = tempdir().expect("Could not create a tempdir");
+        let path = dir.path().join(SETTINGS_FILENAME);
+        fs::write(&path, "[heartbeats]\nmissed = 0\n").expect("Could not write settings");
+        assert!(Settings::load(&path).is_err());
+    }
+
+    #[cfg(feature = "tcp")]
+    #[test]
+    fn select_transports() {
+        let mut settings = Settings::default();
+        settings
+            .apply_args(&matches(&["--transports", "tcp"]))
+            .expect("Could not apply options");
+        assert!(settings.tcp.enabled);
+        #[cfg(feature = "iroh")]
+        assert!(!settings.iroh.enabled);
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn tls_settings() {
+        let mut settings: Settings =
+            toml::from_str("[tls]\nport = 4322\n").expect("Could not parse settings");
+        assert!(settings.tls.enabled);
+        assert_eq!(settings.tls.port, Some(4322));
+        settings
+            .apply_args(&matches(&["--tls_port", "2234", "--transports", "tcp"]))
+            .expect("Could not apply options");
+        assert_eq!(settings.tls.port, Some(2234));
+        assert!(!settings.tls.enabled);
+        assert!(settings.transport_enabled());
+    }
+
+    #[cfg(feature = "iroh")]
+    #[test]
+    fn relay_url_setting() {
+        let mut settings: Settings =
+            toml::from_str("[iroh]\nenabled = false\nrelay_url = \"https://relay.example.com/\"\n")
+                .expect("Could not parse settings");
+        assert!(!settings.iroh.enabled);
+        assert_eq!(
+            settings.iroh.relay_url,
+            Some(RelayUrl::from_str("https://relay.example.com/").unwrap())
+        );
+        settings
+            .apply_args(&matches(&["--relay_url", "https://other.example.com/"]))
+            .expect("Could not apply options");
+        assert_eq!(
+            settings.iroh.relay_url,
+            Some(RelayUrl::from_str("https://other.example.com/").unwrap())
+        );
+    }
+
+    #[cfg(feature = "iroh")]
+    #[test]
+    fn allowed_clients_setting() {
+        let observer = iroh::SecretKey::generate().public();
+        let controller = iroh::SecretKey::generate().public();
+        let settings: Settings = toml::from_str(&format!(
+            "[[iroh.allowed_clients]]\nendpoint_id = \"{observer}\"\nrole = \"observer\"\n\
+            [[iroh.allowed_clients]]\nendpoint_id = \"{controller}\"\nrole = \"controller\"\n"
+        ))
+        .expect("Could not parse settings");
+        assert_eq!(
+            settings.iroh.allowed_clients,
+            vec![
+                AllowedClient {
+                    endpoint_id: observer,
+                    role: Role::Observer
+                },
+                AllowedClient {
+                    endpoint_id: controller,
+                    role: Role::Controller
+                }
+            ]
+        );
+        assert!(toml::from_str::<Settings>(
+            "[[iroh.allowed_clients]]\nendpoint_id = \"not an id\"\nrole = \"observer\"\n"
+        )
+        .is_err());
+    }
+
+    #[cfg(feature = "iroh")]
+    #[test]
+    fn role_permissions() {
+        use pigdef::config::HardwareConfigMessage::{Disconnect, GetConfig, NewPinConfig, Ping};
+        assert!(Role::Observer.permits(&GetConfig));
+        assert!(Role::Observer.permits(&Disconnect));
+        assert!(Role::Observer.permits(&Ping(1)));
+        assert!(!Role::Observer.permits(&NewPinConfig(2, None)));
+        assert!(Role::Controller.permits(&NewPinConfig(2, None)));
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn http_settings() {
+        let mut settings = Settings::default();
+        assert!(!settings.http.enabled);
+        settings
+            .apply_args(&matches(&["--http_port", "8181"]))
+            .expect("Could not apply options");
+        assert!(settings.http.enabled);
+        assert_eq!(settings.http.port, 8181);
+        assert!(settings.http.bind_address.is_unspecified());
+    }
+
+    #[cfg(feature = "mqtt")]
+    #[test]
+    fn mqtt_settings() {
+        let mut settings: Settings = toml::from_str(
+            "[mqtt]\nhost = \"broker.lab\"\nusername = \"pi\"\npassword = \"secret\"\n",
+        )
+        .expect("Could not parse settings");
+        assert!(!settings.mqtt.enabled);
+        assert_eq!(settings.mqtt.port, 1883);
+        assert_eq!(settings.mqtt.topic_prefix, "pigg");
+        settings
+            .apply_args(&matches(&["--mqtt_port", "1884"]))
+            .expect("Could not apply options");
+        assert!(settings.mqtt.enabled);
+        assert_eq!(settings.mqtt.host, "broker.lab");
+        assert_eq!(settings.mqtt.port, 1884);
+    }
+
+    #[cfg(all(feature = "discovery", feature = "tcp"))]
+    #[test]
+    fn mdns_settings() {
+        let mut settings: Settings = toml::from_str(
+            "[mdns]\ninstance_name = \"garage\"\n[mdns.properties]\nRoom = \"garage\"\n",
+        )
+        .expect("Could not parse settings");
+        settings
+            .apply_args(&matches(&[
+                "--mdns_txt",
+                "Floor=0",
+                "--mdns_txt",
+                "Room=shed",
+            ]))
+            .expect("Could not apply options");
+        assert_eq!(settings.mdns.instance_name.as_deref(), Some("garage"));
+        assert_eq!(settings.mdns.properties.get("Room").unwrap(), "shed");
+        assert_eq!(settings.mdns.properties.get("Floor").unwrap(), "0");
+    }
+}
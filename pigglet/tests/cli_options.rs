@@ -44,3 +44,20 @@ async fn help() {
     );
     pass(&mut pigglet);
 }
+
+#[cfg(feature = "tcp")]
+#[tokio::test]
+#[serial(pigglet)]
+async fn status() {
+    kill_all("pigglet");
+    let mut pigglet = run("pigglet", vec![], None);
+    // The info file is written before connections are accepted
+    wait_for_stdout(&mut pigglet, "Waiting for TCP connection", Some("Error:"));
+
+    let mut status = run("pigglet", vec!["--status".into()], None);
+    wait_for_stdout(&mut status, "Process: pigglet", Some("not running"));
+    wait_for_stdout(&mut status, "Transports: tcp", None);
+    wait_for_stdout(&mut status, "TCP: 0", Some("Could not get"));
+    let _ = status.wait();
+    pass(&mut pigglet);
+}
@@ -0,0 +1,166 @@
+#![cfg(feature = "http")]
+
+use crate::support::{delete_configs, kill_all, pass, run, wait_for_stdout};
+use futures::StreamExt;
+use pigdef::config::HardwareConfigMessage::IOLevelChanged;
+use pigdef::config::{HardwareConfig, HardwareConfigMessage};
+use pigdef::description::{BCMPinNumber, HardwareDescription};
+use pigdef::pin_function::PinFunction;
+use pigdef::pin_function::PinFunction::Output;
+use reqwest::StatusCode;
+use serial_test::serial;
+use std::future::Future;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::Message;
+
+#[path = "../../piggui/tests/support.rs"]
+mod support;
+
+/// Start a pigglet with no saved config, serving the HTTP API on an unused port, and run
+/// `test` with the base URL of the API, then stop the pigglet
+async fn test_http<F, Fut>(test: F)
+where
+    F: FnOnce(String) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    kill_all("pigglet");
+    delete_configs();
+    let port = portpicker::pick_unused_port().expect("Could not find a free port");
+    let mut pigglet = run(
+        "pigglet",
+        vec![
+            "--http_bind".into(),
+            "127.0.0.1".into(),
+            "--http_port".into(),
+            port.to_string(),
+        ],
+        None,
+    );
+    wait_for_stdout(&mut pigglet, "HTTP API @", None);
+    test(format!("http://127.0.0.1:{port}")).await;
+    pass(&mut pigglet);
+}
+
+/// Configure pin `bcm` with `pin_function` and wait until the change is in the config
+async fn configure_pin(url: &str, bcm: BCMPinNumber, pin_function: PinFunction) {
+    let client = reqwest::Client::new();
+    let response = client
+        .put(format!("{url}/pins/{bcm}"))
+        .json(&Some(pin_function))
+        .send()
+        .await
+        .expect("Could not configure pin");
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    wait_for_config(url, |config| {
+        config.pin_functions.get(&bcm) == Some(&pin_function)
+    })
+    .await;
+}
+
+/// Get the config until `done` is true of it
+async fn wait_for_config(url: &str, done: impl Fn(&HardwareConfig) -> bool) -> HardwareConfig {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let config: HardwareConfig = reqwest::get(format!("{url}/config"))
+                .await
+                .expect("Could not get config")
+                .json()
+                .await
+                .expect("Could not parse config");
+            if done(&config) {
+                return config;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("Timeout waiting for config change")
+}
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn get_description_and_config_http() {
+    test_http(|url| async move {
+        let desc: HardwareDescription = reqwest::get(format!("{url}/description"))
+            .await
+            .expect("Could not get description")
+            .json()
+            .await
+            .expect("Could not parse description");
+        assert!(desc.details.model.contains("Fake"));
+        let config = wait_for_config(&url, |_| true).await;
+        assert!(config.pin_functions.is_empty());
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn set_output_level_http() {
+    test_http(|url| async move {
+        configure_pin(&url, 17, Output(Some(false))).await;
+
+        let events_url = format!("{}/events", url.replace("http://", "ws://"));
+        let (mut events, _) = tokio_tungstenite::connect_async(events_url)
+            .await
+            .expect("Could not connect to events");
+
+        let response = reqwest::Client::new()
+            .post(format!("{url}/pins/17/level"))
+            .json(&true)
+            .send()
+            .await
+            .expect("Could not set output level");
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        let event = tokio::time::timeout(Duration::from_secs(5), events.next())
+            .await
+            .expect("Timeout waiting for level change")
+            .expect("Events ended")
+            .expect("Could not receive level change");
+        let Message::Text(json) = event else {
+            panic!("Expected a text message, got {event:?}");
+        };
+        let message: HardwareConfigMessage =
+            serde_json::from_str(&json).expect("Could not parse level change");
+        assert!(matches!(message, IOLevelChanged(17, level_change) if level_change.new_level));
+
+        wait_for_config(&url, |config| {
+            config.pin_functions.get(&17) == Some(&Output(Some(true)))
+        })
+        .await;
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn unknown_pin_http() {
+    test_http(|url| async move {
+        let response = reqwest::Client::new()
+            .post(format!("{url}/pins/99/level"))
+            .json(&true)
+            .send()
+            .await
+            .expect("Could not send request");
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn get_metrics_http() {
+    test_http(|url| async move {
+        configure_pin(&url, 17, Output(Some(true))).await;
+        let response = reqwest::get(format!("{url}/metrics"))
+            .await
+            .expect("Could not get metrics");
+        assert_eq!(response.status(), StatusCode::OK);
+        let metrics = response.text().await.expect("Could not read metrics");
+        assert!(metrics.contains("# TYPE pigglet_uptime_seconds gauge\n"));
+        assert!(metrics.contains("pigglet_messages_received_total{message=\"NewPinConfig\"} 1\n"));
+        assert!(metrics.contains("pigglet_output_level{bcm=\"17\"} 1\n"));
+    })
+    .await;
+}
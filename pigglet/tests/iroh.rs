@@ -1,14 +1,41 @@
-use crate::support::{connect_and_test_iroh, kill_all, parse_pigglet, pass, run};
+use crate::support::{connect_and_test_iroh, fail, kill_all, parse_pigglet, pass, run};
+use iroh::{EndpointId, SecretKey};
 use pigdef::config::HardwareConfigMessage::{GetConfig, NewConfig, NewPinConfig};
 use pigdef::config::InputPull;
 use pigdef::pin_function::PinFunction::Input;
 use pignet::iroh_host;
 use serial_test::serial;
+use std::process::Child;
 use std::time::Duration;
+use tempfile::tempdir;
 
 #[path = "../../piggui/tests/support.rs"]
 mod support;
 
+/// Start a pigglet, with a new config, that only allows the Iroh clients in `allowed_clients`,
+/// with their roles, to connect
+fn run_with_allowed_clients(allowed_clients: &[(EndpointId, &str)]) -> Child {
+    let dir = tempdir().expect("Could not create a tempdir").keep();
+    let settings_path = dir.join("pigglet.toml");
+    let settings: String = allowed_clients
+        .iter()
+        .map(|(endpoint_id, role)| {
+            format!(
+                "[[iroh.allowed_clients]]\nendpoint_id = \"{endpoint_id}\"\nrole = \"{role}\"\n"
+            )
+        })
+        .collect();
+    std::fs::write(&settings_path, settings).expect("Could not write settings");
+    run(
+        "pigglet",
+        vec![
+            "--settings".into(),
+            settings_path.to_string_lossy().to_string(),
+        ],
+        Some(dir.join("allowed_clients.pigg")),
+    )
+}
+
 #[tokio::test]
 #[serial(pigglet)]
 async fn connect_via_iroh() {
@@ -129,3 +156,86 @@ async fn reconnect_iroh() {
 
     pass(&mut pigglet);
 }
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn endpoint_id_persists_iroh() {
+    kill_all("pigglet");
+    let mut pigglet = run("pigglet", vec![], None);
+    let (_ip, _port, endpoint_id, _relay) = parse_pigglet(&mut pigglet).await;
+    pass(&mut pigglet);
+
+    // A restart keeps the same endpoint_id
+    let mut pigglet = run("pigglet", vec![], None);
+    let (_ip, _port, restarted_id, _relay) = parse_pigglet(&mut pigglet).await;
+    pass(&mut pigglet);
+    assert_eq!(endpoint_id, restarted_id);
+
+    // Rotating the key changes it
+    let mut pigglet = run("pigglet", vec!["--rotate_key".to_string()], None);
+    let (_ip, _port, rotated_id, _relay) = parse_pigglet(&mut pigglet).await;
+    pass(&mut pigglet);
+    assert_ne!(endpoint_id, rotated_id);
+}
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn refuse_unknown_client_iroh() {
+    kill_all("pigglet");
+    let mut pigglet = run_with_allowed_clients(&[(SecretKey::generate().public(), "controller")]);
+    let (_ip, _port, endpoint_id, relay) = parse_pigglet(&mut pigglet).await;
+
+    for _ in 0..10 {
+        match iroh_host::connect(&endpoint_id, &relay, SecretKey::generate()).await {
+            Ok(_) => fail(&mut pigglet, "Unknown client was allowed to connect"),
+            Err(e) if e.to_string().contains("not allowed") => {
+                pass(&mut pigglet);
+                return;
+            }
+            Err(_) => tokio::time::sleep(Duration::from_millis(500)).await,
+        }
+    }
+    fail(&mut pigglet, "Unknown client was not refused");
+}
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn observer_cannot_change_config_iroh() {
+    kill_all("pigglet");
+    let client_key = iroh_host::load_default_client_key().expect("Could not load client key");
+    let mut pigglet = run_with_allowed_clients(&[(client_key.public(), "observer")]);
+    let (_ip, _port, endpoint_id, relay) = parse_pigglet(&mut pigglet).await;
+
+    connect_and_test_iroh(
+        &mut pigglet,
+        &endpoint_id,
+        relay,
+        |_, _, mut connection| async move {
+            iroh_host::send_config_message(
+                &mut connection,
+                &NewPinConfig(2, Some(Input(Some(InputPull::PullUp)))),
+            )
+            .await
+            .expect("Could not send NewPinConfig");
+
+            iroh_host::send_config_message(&mut connection, &GetConfig)
+                .await
+                .expect("Could not send GetConfig");
+
+            match iroh_host::wait_for_remote_message(&mut connection).await {
+                Ok(NewConfig(hardware_config)) => assert_eq!(
+                    hardware_config.pin_functions.get(&2),
+                    None,
+                    "Observer changed the config of a pin"
+                ),
+                _ => panic!("Could not get response to GetConfig"),
+            }
+
+            iroh_host::disconnect(&mut connection)
+                .await
+                .expect("Could not disconnect");
+        },
+    )
+    .await;
+    pass(&mut pigglet);
+}
@@ -0,0 +1,260 @@
+#![cfg(all(feature = "mqtt", feature = "tcp"))]
+
+use crate::support::{
+    connect_and_test_tcp, delete_configs, kill_all, parse_pigglet, pass, run, send,
+    wait_for_level_change,
+};
+use pigdef::config::HardwareConfigMessage::NewPinConfig;
+use pigdef::pin_function::PinFunction::{Input, Output};
+use pignet::tcp_host;
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, Publish, QoS};
+use serial_test::serial;
+use std::time::Duration;
+
+#[path = "../../piggui/tests/support.rs"]
+mod support;
+
+/// A minimal MQTT 3.1.1 broker for tests. Publishes are forwarded at QoS 0 to clients with a
+/// matching subscription, and retained ones kept for clients that subscribe later
+mod broker {
+    use bytes::BytesMut;
+    use futures::channel::mpsc::{unbounded, UnboundedSender};
+    use futures::StreamExt;
+    use rumqttc::mqttbytes::Error;
+    use rumqttc::{
+        matches, ConnAck, ConnectReturnCode, Packet, PubAck, Publish, QoS, SubAck,
+        SubscribeReasonCode,
+    };
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    const MAX_PACKET_SIZE: usize = 64 * 1024;
+
+    #[derive(Default)]
+    struct Shared {
+        subscriptions: Vec<(String, UnboundedSender<Packet>)>,
+        retained: HashMap<String, Publish>,
+    }
+
+    /// Start the broker on an unused port, returning the port
+    pub async fn start() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Could not bind broker");
+        let port = listener.local_addr().expect("No broker address").port();
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, shared.clone()));
+            }
+        });
+        port
+    }
+
+    /// Serve one client until it disconnects
+    async fn serve(stream: TcpStream, shared: Arc<Mutex<Shared>>) {
+        let (mut reader, mut writer) = stream.into_split();
+        let (sender, mut packets) = unbounded::<Packet>();
+        tokio::spawn(async move {
+            while let Some(packet) = packets.next().await {
+                let mut buffer = BytesMut::new();
+                if packet.write(&mut buffer, MAX_PACKET_SIZE).is_err()
+                    || writer.write_all(&buffer).await.is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        let mut buffer = BytesMut::new();
+        loop {
+            let packet = match Packet::read(&mut buffer, MAX_PACKET_SIZE) {
+                Ok(packet) => packet,
+                Err(Error::InsufficientBytes(_)) => match reader.read_buf(&mut buffer).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) => continue,
+                },
+                Err(_) => break,
+            };
+            match packet {
+                Packet::Connect(_) => {
+                    let _ = sender.unbounded_send(Packet::ConnAck(ConnAck::new(
+                        ConnectReturnCode::Success,
+                        false,
+                    )));
+                }
+                Packet::Subscribe(subscribe) => {
+                    let mut shared = shared.lock().expect("Could not lock broker");
+                    let codes = subscribe
+                        .filters
+                        .iter()
+                        .map(|_| SubscribeReasonCode::Success(QoS::AtMostOnce))
+                        .collect();
+                    let _ =
+                        sender.unbounded_send(Packet::SubAck(SubAck::new(subscribe.pkid, codes)));
+                    for filter in subscribe.filters {
+                        for publish in shared.retained.values() {
+                            if matches(&publish.topic, &filter.path) {
+                                let _ = sender.unbounded_send(Packet::Publish(publish.clone()));
+                            }
+                        }
+                        shared.subscriptions.push((filter.path, sender.clone()));
+                    }
+                }
+                Packet::Publish(publish) => {
+                    if publish.qos == QoS::AtLeastOnce {
+                        let _ = sender.unbounded_send(Packet::PubAck(PubAck::new(publish.pkid)));
+                    }
+                    let forwarded = Publish::from_bytes(
+                        publish.topic.clone(),
+                        QoS::AtMostOnce,
+                        publish.payload.clone(),
+                    );
+                    let mut shared = shared.lock().expect("Could not lock broker");
+                    if publish.retain {
+                        if publish.payload.is_empty() {
+                            shared.retained.remove(&publish.topic);
+                        } else {
+                            shared
+                                .retained
+                                .insert(publish.topic.clone(), forwarded.clone());
+                        }
+                    }
+                    shared.subscriptions.retain(|(filter, subscriber)| {
+                        !matches(&publish.topic, filter)
+                            || subscriber
+                                .unbounded_send(Packet::Publish(forwarded.clone()))
+                                .is_ok()
+                    });
+                }
+                Packet::PingReq => {
+                    let _ = sender.unbounded_send(Packet::PingResp);
+                }
+                Packet::Disconnect => break,
+                _ => {}
+            }
+        }
+    }
+}
+
+/// A client of the broker, subscribed to all topics
+struct Subscriber {
+    client: AsyncClient,
+    publishes: futures::channel::mpsc::UnboundedReceiver<Publish>,
+}
+
+impl Subscriber {
+    async fn connect(port: u16) -> Self {
+        let (client, mut event_loop) =
+            AsyncClient::new(MqttOptions::new("test", "127.0.0.1", port), 16);
+        client
+            .subscribe("#", QoS::AtMostOnce)
+            .await
+            .expect("Could not subscribe");
+        let (sender, publishes) = futures::channel::mpsc::unbounded();
+        tokio::spawn(async move {
+            while let Ok(event) = event_loop.poll().await {
+                if let Event::Incoming(Packet::Publish(publish)) = event {
+                    if sender.unbounded_send(publish).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        Subscriber { client, publishes }
+    }
+
+    /// Wait for a message to be published to `topic`, skipping others and empty ones (that
+    /// remove retained messages), and return its payload
+    async fn wait_for(&mut self, topic: &str) -> String {
+        use futures::StreamExt;
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                let publish = self.publishes.next().await.expect("Subscriber ended");
+                if publish.topic == topic && !publish.payload.is_empty() {
+                    return String::from_utf8_lossy(&publish.payload).to_string();
+                }
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("Timeout waiting for a message on {topic}"))
+    }
+}
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn mqtt_bridge() {
+    let broker_port = broker::start().await;
+    kill_all("pigglet");
+    delete_configs();
+    let mut pigglet = run(
+        "pigglet",
+        vec![
+            "--no_auth".into(),
+            "--mqtt_host".into(),
+            "127.0.0.1".into(),
+            "--mqtt_port".into(),
+            broker_port.to_string(),
+        ],
+        None,
+    );
+    let (ip, port, _, _relay) = parse_pigglet(&mut pigglet).await;
+    let mut subscriber = Subscriber::connect(broker_port).await;
+
+    connect_and_test_tcp(&mut pigglet, ip, port, |desc, _, connection| async move {
+        let serial = desc.details.serial;
+        let base = format!("pigg/{serial}");
+        assert_eq!(
+            subscriber.wait_for(&format!("{base}/availability")).await,
+            "online"
+        );
+
+        // Outputs are discovered as switches, and can be set by publishing to their command topic
+        send(
+            connection.clone(),
+            NewPinConfig(17, Some(Output(Some(false)))),
+        )
+        .await;
+        let discovery = subscriber
+            .wait_for(&format!("homeassistant/switch/pigg_{serial}/gpio17/config"))
+            .await;
+        let discovery: serde_json::Value =
+            serde_json::from_str(&discovery).expect("Invalid discovery payload");
+        assert_eq!(discovery["name"], "GPIO17");
+        assert_eq!(discovery["command_topic"], format!("{base}/17/set"));
+        assert_eq!(
+            subscriber.wait_for(&format!("{base}/17/state")).await,
+            "OFF"
+        );
+
+        subscriber
+            .client
+            .publish(format!("{base}/17/set"), QoS::AtLeastOnce, false, "ON")
+            .await
+            .expect("Could not publish command");
+        assert!(
+            wait_for_level_change(connection.clone(), 17)
+                .await
+                .new_level
+        );
+        assert_eq!(subscriber.wait_for(&format!("{base}/17/state")).await, "ON");
+
+        // Inputs are discovered as binary sensors, with their level published
+        send(connection.clone(), NewPinConfig(26, Some(Input(None)))).await;
+        subscriber
+            .wait_for(&format!(
+                "homeassistant/binary_sensor/pigg_{serial}/gpio26/config"
+            ))
+            .await;
+        subscriber.wait_for(&format!("{base}/26/state")).await;
+
+        tcp_host::disconnect(connection)
+            .await
+            .expect("Could not disconnect");
+    })
+    .await;
+
+    pass(&mut pigglet);
+}
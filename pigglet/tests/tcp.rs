@@ -1,10 +1,32 @@
-use crate::support::{connect_and_test_tcp, kill_all, parse_pigglet, pass, run};
-use pigdef::config::HardwareConfigMessage::{GetConfig, NewConfig, NewPinConfig};
-use pigdef::pin_function::PinFunction::Output;
-use piggpio::config::CONFIG_FILENAME;
+use crate::support::{
+    connect_and_test_tcp, delete_configs, kill_all, parse_pigglet, pass, run, send, wait_for,
+    wait_for_level_change, wait_for_stdout,
+};
+use futures::StreamExt;
+use pigdef::auth::PairingCode;
+use pigdef::config::HardwareConfigMessage::{
+    AnalogSampled, ClientCounts, DutyCycleChanged, GetClientCounts, GetConfig, IOLevelChanged,
+    NewConfig, NewPinConfig, Ping, Pong, SafeLevelChanged, SequenceStatus, StartSequence,
+};
+use pigdef::config::{HardwareConfig, HardwareConfigMessage};
+use pigdef::description::ANALOG_MAX;
+use pigdef::hello::Capabilities;
+use pigdef::i2c::{I2cRequest, I2cResponse};
+use pigdef::pin_function::PinFunction::{
+    AnalogInput, Input, Output, Pwm, I2C1_SCL, I2C1_SDA, SPI0_MISO, SPI0_MOSI, SPI0_SCLK,
+    UART0_RXD, UART0_TXD,
+};
+use pigdef::sequence::{Sequence, SequenceState, SequenceStep};
+use pigdef::spi::{SpiRequest, SpiResponse, DEFAULT_SPI_CONFIG};
+use pigdef::uart::DEFAULT_UART_CONFIG;
+use pignet::credentials::{AuthError, Credentials};
 use pignet::tcp_host;
+use pignet::tcp_host::TcpConnection;
+use pignet::{Client, HardwareConnection};
 use serial_test::serial;
-use std::path::PathBuf;
+use std::future::Future;
+use std::net::IpAddr;
+use std::process::Child;
 use std::time::Duration;
 
 #[path = "../../piggui/tests/support.rs"]
@@ -15,10 +37,23 @@ mod support;
 #[serial(pigglet)]
 async fn connect_tcp() {
     kill_all("pigglet");
-    let mut pigglet = run("pigglet", vec![], None);
+    let mut pigglet = run("pigglet", vec!["--no_auth".into()], None);
     let (ip, port, _, _relay) = parse_pigglet(&mut pigglet).await;
 
-    connect_and_test_tcp(&mut pigglet, ip, port, |_, _, _tcp_stream| async move {}).await;
+    connect_and_test_tcp(&mut pigglet, ip, port, |_, _, tcp_stream| async move {
+        assert_eq!(
+            tcp_stream.capabilities(),
+            piggpio::CAPABILITIES
+                .union(Capabilities::SEQUENCES)
+                .union(Capabilities::SAFE_LEVELS)
+                .union(Capabilities::CLIENT_COUNTS)
+                .union(Capabilities::HEARTBEAT)
+        );
+        assert!(tcp_stream
+            .capabilities()
+            .contains(Capabilities::ANALOG_INPUT));
+    })
+    .await;
 
     pass(&mut pigglet);
 }
@@ -28,7 +63,7 @@ async fn connect_tcp() {
 #[serial(pigglet)]
 async fn disconnect_tcp() {
     kill_all("pigglet");
-    let mut pigglet = run("pigglet", vec![], None);
+    let mut pigglet = run("pigglet", vec!["--no_auth".into()], None);
     let (ip, port, _, _relay) = parse_pigglet(&mut pigglet).await;
 
     connect_and_test_tcp(&mut pigglet, ip, port, |_, _, tcp_stream| async move {
@@ -45,7 +80,7 @@ async fn disconnect_tcp() {
 #[serial(pigglet)]
 async fn reconnect_tcp() {
     kill_all("pigglet");
-    let mut pigglet = run("pigglet", vec![], None);
+    let mut pigglet = run("pigglet", vec!["--no_auth".into()], None);
     let (ip, port, _, _relay) = parse_pigglet(&mut pigglet).await;
 
     connect_and_test_tcp(&mut pigglet, ip, port, |_d, _c, tcp_stream| async move {
@@ -66,25 +101,13 @@ async fn reconnect_tcp() {
     pass(&mut pigglet);
 }
 
-#[allow(dead_code)]
-pub fn delete_configs() {
-    let crate_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-    let workspace_dir = crate_dir.parent().expect("Failed to get parent dir");
-    let config_file = workspace_dir.join(CONFIG_FILENAME);
-    println!("Deleting file: {config_file:?}");
-    let _ = std::fs::remove_file(config_file);
-    let config_file = workspace_dir.join("target/debug/").join(CONFIG_FILENAME);
-    println!("Deleting file: {config_file:?}");
-    let _ = std::fs::remove_file(config_file);
-}
-
 #[tokio::test]
 #[serial(pigglet)]
 async fn clean_config() {
     kill_all("pigglet");
     #[cfg(not(target_arch = "wasm32"))]
     delete_configs();
-    let mut pigglet = run("pigglet", vec![], None);
+    let mut pigglet = run("pigglet", vec!["--no_auth".into()], None);
     let (ip, port, _, _relay) = parse_pigglet(&mut pigglet).await;
 
     connect_and_test_tcp(
@@ -114,7 +137,7 @@ async fn config_change_returned_tcp() {
     kill_all("pigglet");
     #[cfg(not(target_arch = "wasm32"))]
     delete_configs();
-    let mut pigglet = run("pigglet", vec![], None);
+    let mut pigglet = run("pigglet", vec!["--no_auth".into()], None);
     let (ip, port, _, _relay) = parse_pigglet(&mut pigglet).await;
 
     connect_and_test_tcp(
@@ -205,7 +228,7 @@ async fn invalid_pin_config() {
     #[cfg(not(target_arch = "wasm32"))]
     delete_configs();
 
-    let mut pigglet = run("pigglet", vec![], None);
+    let mut pigglet = run("pigglet", vec!["--no_auth".into()], None);
     let (ip, port, _, _relay) = parse_pigglet(&mut pigglet).await;
 
     connect_and_test_tcp(
@@ -261,3 +284,613 @@ async fn invalid_pin_config() {
 
     pass(&mut pigglet);
 }
+
+/// Start a pigglet with no saved config, connect to it over TCP and run `test` with the initial
+/// config, the connection and the address of the pigglet, then disconnect and stop the pigglet
+async fn test_pigglet<F, Fut>(test: F)
+where
+    F: FnOnce(HardwareConfig, TcpConnection, (IpAddr, u16)) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    kill_all("pigglet");
+    #[cfg(not(target_arch = "wasm32"))]
+    delete_configs();
+    let mut pigglet = run("pigglet", vec!["--no_auth".into()], None);
+    let (ip, port, _, _relay) = parse_pigglet(&mut pigglet).await;
+
+    connect_and_test_tcp(
+        &mut pigglet,
+        ip,
+        port,
+        |_, hw_config, tcp_stream| async move {
+            test(hw_config, tcp_stream.clone(), (ip, port)).await;
+            tcp_host::disconnect(tcp_stream)
+                .await
+                .expect("Could not disconnect");
+        },
+    )
+    .await;
+
+    pass(&mut pigglet);
+}
+
+/// Wait for the next config change to be received, skipping level changes and samples of inputs
+async fn wait_for_config_change(connection: TcpConnection) -> HardwareConfigMessage {
+    wait_for(connection, |message| {
+        (!matches!(message, IOLevelChanged(..) | AnalogSampled(..))).then_some(message)
+    })
+    .await
+}
+
+/// Wait until the number of clients connected over TCP, including this one, is `count`
+async fn wait_for_tcp_clients(connection: TcpConnection, count: u32) {
+    loop {
+        send(connection.clone(), GetClientCounts).await;
+        let counts = wait_for(connection.clone(), |message| match message {
+            ClientCounts(counts) => Some(counts),
+            _ => None,
+        })
+        .await;
+        if counts.tcp == count {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+/// Request the current config of the pigglet and wait for it to be sent back
+async fn get_config(connection: TcpConnection) -> HardwareConfig {
+    send(connection.clone(), GetConfig).await;
+    wait_for(connection, |message| match message {
+        NewConfig(hardware_config) => Some(hardware_config),
+        _ => None,
+    })
+    .await
+}
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn pwm_duty_cycle_tcp() {
+    test_pigglet(|_, tcp_stream, _| async move {
+        let pwm = Pwm {
+            frequency: 50.0,
+            duty_cycle: 0.0,
+        };
+        send(tcp_stream.clone(), NewPinConfig(18, Some(pwm))).await;
+        send(tcp_stream.clone(), DutyCycleChanged(18, 0.25)).await;
+
+        // A duty cycle out of range should be refused and leave the previous one in place
+        send(tcp_stream.clone(), DutyCycleChanged(18, 1.5)).await;
+
+        assert_eq!(
+            get_config(tcp_stream).await.pin_functions.get(&18),
+            Some(&Pwm {
+                frequency: 50.0,
+                duty_cycle: 0.25
+            }),
+            "PWM pin config doesn't match the duty cycle sent"
+        );
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn analog_input_tcp() {
+    test_pigglet(|_, tcp_stream, _| async move {
+        let analog_input = AnalogInput { sample_rate: 20 };
+        send(tcp_stream.clone(), NewPinConfig(26, Some(analog_input))).await;
+
+        let sample = wait_for(tcp_stream, |message| match message {
+            AnalogSampled(26, sample) => Some(sample),
+            _ => None,
+        })
+        .await;
+        assert!(sample.value <= ANALOG_MAX, "Analog sample out of range");
+    })
+    .await;
+}
+
+/// Make a request of the I2C bus and wait for the response to it
+async fn i2c_request(connection: TcpConnection, request: I2cRequest) -> I2cResponse {
+    send(
+        connection.clone(),
+        HardwareConfigMessage::I2cRequest(request),
+    )
+    .await;
+    wait_for(connection, |message| match message {
+        HardwareConfigMessage::I2cResponse(response) => Some(response),
+        _ => None,
+    })
+    .await
+}
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn i2c_scan_and_read_tcp() {
+    test_pigglet(|_, tcp_stream, _| async move {
+        // The bus cannot be used until both of its pins are configured
+        match i2c_request(tcp_stream.clone(), I2cRequest::Scan).await {
+            I2cResponse::Error(_) => {}
+            response => panic!("Expected an error from an unconfigured bus but got {response:?}"),
+        }
+
+        for (bcm, function) in [(2, I2C1_SDA), (3, I2C1_SCL)] {
+            send(tcp_stream.clone(), NewPinConfig(bcm, Some(function))).await;
+        }
+
+        // The fake hardware has a BME280 at 0x76 on its bus, with a chip id of 0x60
+        match i2c_request(tcp_stream.clone(), I2cRequest::Scan).await {
+            I2cResponse::Devices(devices) => assert!(devices.contains(&0x76)),
+            response => panic!("Expected the devices found but got {response:?}"),
+        }
+
+        let read = I2cRequest::Read {
+            address: 0x76,
+            register: 0xD0,
+            length: 1,
+        };
+        match i2c_request(tcp_stream, read).await {
+            I2cResponse::Read { data, .. } => assert_eq!(data, vec![0x60]),
+            response => panic!("Expected the data read but got {response:?}"),
+        }
+    })
+    .await;
+}
+
+/// Make a transfer on the SPI bus and wait for the response to it
+async fn spi_request(connection: TcpConnection, request: SpiRequest) -> SpiResponse {
+    send(
+        connection.clone(),
+        HardwareConfigMessage::SpiRequest(request),
+    )
+    .await;
+    wait_for(connection, |message| match message {
+        HardwareConfigMessage::SpiResponse(response) => Some(response),
+        _ => None,
+    })
+    .await
+}
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn spi_loopback_tcp() {
+    test_pigglet(|_, tcp_stream, _| async move {
+        let written = vec![0x9F, 0x00, 0xA5];
+
+        // The bus cannot be used until all of its pins are configured
+        match spi_request(tcp_stream.clone(), SpiRequest::Transfer(written.clone())).await {
+            SpiResponse::Error(_) => {}
+            response => panic!("Expected an error from an unconfigured bus but got {response:?}"),
+        }
+
+        for (bcm, function) in [
+            (10, SPI0_MOSI),
+            (9, SPI0_MISO),
+            (11, SPI0_SCLK(DEFAULT_SPI_CONFIG)),
+        ] {
+            send(tcp_stream.clone(), NewPinConfig(bcm, Some(function))).await;
+        }
+
+        // The fake hardware has MOSI looped back to MISO, so reads what it writes
+        match spi_request(tcp_stream, SpiRequest::Transfer(written.clone())).await {
+            SpiResponse::Transferred {
+                written: sent,
+                read,
+            } => {
+                assert_eq!(sent, written);
+                assert_eq!(read, written);
+            }
+            response => panic!("Expected the bytes transferred but got {response:?}"),
+        }
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn uart_echo_tcp() {
+    test_pigglet(|_, tcp_stream, _| async move {
+        for (bcm, function) in [(14, UART0_TXD(DEFAULT_UART_CONFIG)), (15, UART0_RXD)] {
+            send(tcp_stream.clone(), NewPinConfig(bcm, Some(function))).await;
+        }
+
+        // The fake hardware echoes the bytes sent back as bytes received
+        send(
+            tcp_stream.clone(),
+            HardwareConfigMessage::UartSend(b"hello\n".to_vec()),
+        )
+        .await;
+        let received = wait_for(tcp_stream, |message| match message {
+            HardwareConfigMessage::UartReceived(bytes) => Some(bytes),
+            _ => None,
+        })
+        .await;
+        assert_eq!(received, b"hello\n");
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn sequence_tcp() {
+    test_pigglet(|_, tcp_stream, _| async move {
+        send(
+            tcp_stream.clone(),
+            NewPinConfig(17, Some(Output(Some(false)))),
+        )
+        .await;
+
+        let sequence = Sequence {
+            steps: vec![
+                SequenceStep {
+                    bcm_pin_number: 17,
+                    level: true,
+                    delay_ms: 10,
+                },
+                SequenceStep {
+                    bcm_pin_number: 17,
+                    level: false,
+                    delay_ms: 10,
+                },
+            ],
+            repeat: 2,
+        };
+        send(tcp_stream.clone(), StartSequence(1, sequence)).await;
+
+        // The client is told the sequence is running, of each level it sets, then that it ended
+        let mut levels = vec![];
+        let mut states = vec![];
+        while states.len() < 2 {
+            match wait_for(tcp_stream.clone(), |message| match message {
+                IOLevelChanged(17, level_change) => Some(Ok(level_change.new_level)),
+                SequenceStatus(1, state) => Some(Err(state)),
+                _ => None,
+            })
+            .await
+            {
+                Ok(level) => levels.push(level),
+                Err(state) => states.push(state),
+            }
+        }
+        assert_eq!(levels, vec![true, false, true, false]);
+        assert_eq!(states, vec![SequenceState::Running, SequenceState::Stopped]);
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn two_clients_tcp() {
+    test_pigglet(|hw_config, first, (ip, port)| async move {
+        assert!(hw_config.pin_functions.is_empty());
+        let (_, hw_config, second) = tcp_host::connect(ip, port, &mut Credentials::default())
+            .await
+            .expect("Could not connect second client");
+        assert!(hw_config.pin_functions.is_empty());
+
+        // A config change made by the first client is sent to the second
+        send(first.clone(), NewPinConfig(2, Some(Output(None)))).await;
+        let hw_message = wait_for_config_change(second.clone()).await;
+        assert!(
+            matches!(hw_message, NewPinConfig(2, Some(Output(None)))),
+            "Expected NewPinConfig message but got {hw_message:?}"
+        );
+
+        // An input configured by the second client is sent to the first
+        send(second.clone(), NewPinConfig(3, Some(Input(None)))).await;
+        let hw_message = wait_for_config_change(first.clone()).await;
+        assert!(
+            matches!(hw_message, NewPinConfig(3, Some(Input(None)))),
+            "Expected NewPinConfig message but got {hw_message:?}"
+        );
+
+        // Both clients see the same level changes of the input
+        let first_level = wait_for_level_change(first, 3).await;
+        let second_level = loop {
+            let level_change = wait_for_level_change(second.clone(), 3).await;
+            if level_change.timestamp == first_level.timestamp {
+                break level_change;
+            }
+        };
+        assert_eq!(first_level.new_level, second_level.new_level);
+
+        tcp_host::disconnect(second)
+            .await
+            .expect("Could not disconnect");
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn safe_level_on_dropped_connection_tcp() {
+    test_pigglet(|_, connection, (ip, port)| async move {
+        let (_, _, second) = tcp_host::connect(ip, port, &mut Credentials::default())
+            .await
+            .expect("Could not connect second client");
+        let (_, _, third) = tcp_host::connect(ip, port, &mut Credentials::default())
+            .await
+            .expect("Could not connect third client");
+        send(second.clone(), NewPinConfig(17, Some(Output(Some(true))))).await;
+        send(second.clone(), SafeLevelChanged(17, Some(false))).await;
+        wait_for(connection.clone(), |message| match message {
+            SafeLevelChanged(17, level) => Some(level),
+            _ => None,
+        })
+        .await;
+        send(third.clone(), NewPinConfig(2, Some(Output(None)))).await;
+        wait_for(connection.clone(), |message| {
+            matches!(message, NewPinConfig(2, _)).then_some(())
+        })
+        .await;
+
+        // While another client that has made changes is connected, the output is left as it is
+        drop(second);
+        wait_for_tcp_clients(connection.clone(), 2).await;
+        let config = get_config(connection.clone()).await;
+        assert_eq!(config.pin_functions.get(&17), Some(&Output(Some(true))));
+
+        // Dropping the connection of the last one, without sending a disconnect, drives the
+        // output to its safe level
+        drop(third);
+        let level_change = wait_for_level_change(connection.clone(), 17).await;
+        assert!(!level_change.new_level);
+
+        let config = get_config(connection).await;
+        assert_eq!(config.pin_functions.get(&17), Some(&Output(Some(false))));
+        assert_eq!(config.safe_levels.get(&17), Some(&false));
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn client_counts_tcp() {
+    test_pigglet(|_, connection, (ip, port)| async move {
+        let (_, _, second) = tcp_host::connect(ip, port, &mut Credentials::default())
+            .await
+            .expect("Could not connect second client");
+        send(connection.clone(), GetClientCounts).await;
+        let counts = wait_for(connection, |message| match message {
+            ClientCounts(counts) => Some(counts),
+            _ => None,
+        })
+        .await;
+        assert_eq!(counts.tcp, 2);
+        assert_eq!(counts.iroh, 0);
+
+        tcp_host::disconnect(second)
+            .await
+            .expect("Could not disconnect");
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn heartbeat_tcp() {
+    test_pigglet(|_, connection, _| async move {
+        send(connection.clone(), Ping(7)).await;
+        let sequence = wait_for(connection, |message| match message {
+            Pong(sequence) => Some(sequence),
+            _ => None,
+        })
+        .await;
+        assert_eq!(sequence, 7);
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn missed_heartbeats_tcp() {
+    kill_all("pigglet");
+    let mut pigglet = run(
+        "pigglet",
+        vec!["--no_auth".into(), "--missed_heartbeats".into(), "1".into()],
+        None,
+    );
+    let (ip, port, _, _relay) = parse_pigglet(&mut pigglet).await;
+
+    connect_and_test_tcp(&mut pigglet, ip, port, |_, _, connection| async move {
+        let (_, _, silent) = tcp_host::connect(ip, port, &mut Credentials::default())
+            .await
+            .expect("Could not connect second client");
+        send(silent.clone(), Ping(1)).await;
+        wait_for(silent.clone(), |message| {
+            matches!(message, Pong(1)).then_some(())
+        })
+        .await;
+
+        // The second client stops sending heartbeats, without closing its connection
+        tokio::time::sleep(Duration::from_secs(4)).await;
+        send(connection.clone(), GetClientCounts).await;
+        let counts = wait_for(connection.clone(), |message| match message {
+            ClientCounts(counts) => Some(counts),
+            _ => None,
+        })
+        .await;
+        assert_eq!(
+            counts.tcp, 1,
+            "Client that missed heartbeats was not disconnected"
+        );
+        drop(silent);
+
+        tcp_host::disconnect(connection)
+            .await
+            .expect("Could not disconnect");
+    })
+    .await;
+    pass(&mut pigglet);
+}
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn client_tcp() {
+    test_pigglet(|_, _, (ip, port)| async move {
+        let client = Client::connect(&HardwareConnection::Tcp(ip, port))
+            .await
+            .expect("Could not connect client");
+        assert!(client.description().details.model.contains("Fake"));
+        assert_eq!(
+            client.capabilities(),
+            piggpio::CAPABILITIES
+                .union(Capabilities::SEQUENCES)
+                .union(Capabilities::SAFE_LEVELS)
+                .union(Capabilities::CLIENT_COUNTS)
+                .union(Capabilities::HEARTBEAT)
+        );
+
+        client
+            .set_pin_function(17, Some(Output(Some(false))))
+            .await
+            .expect("Could not set pin function");
+        client
+            .set_output(17, true)
+            .await
+            .expect("Could not set output");
+        assert_eq!(
+            client.config().pin_functions.get(&17),
+            Some(&Output(Some(true)))
+        );
+        assert!(
+            client.set_output(18, true).await.is_err(),
+            "Only outputs can be set"
+        );
+
+        // The fake hardware's inputs change level, which are streamed to the client
+        client
+            .set_pin_function(3, Some(Input(None)))
+            .await
+            .expect("Could not set pin function");
+        let level_changes = client.level_changes();
+        let mut level_changes = std::pin::pin!(level_changes);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while let Some(level_change) = level_changes.next().await {
+                let (bcm, _) = level_change.expect("Could not receive level change");
+                if bcm == 3 {
+                    return;
+                }
+            }
+            panic!("Level changes ended");
+        })
+        .await
+        .expect("Timeout waiting for level change");
+
+        client.disconnect().await.expect("Could not disconnect");
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn fixed_port_tcp() {
+    kill_all("pigglet");
+    let port = portpicker::pick_unused_port().expect("Could not find a free port");
+    let mut pigglet = run(
+        "pigglet",
+        vec!["--no_auth".into(), "--port".into(), port.to_string()],
+        None,
+    );
+    let (ip, listening_port, _, _relay) = parse_pigglet(&mut pigglet).await;
+    assert_eq!(listening_port, port);
+
+    connect_and_test_tcp(&mut pigglet, ip, port, |_, _, tcp_stream| async move {
+        tcp_host::disconnect(tcp_stream)
+            .await
+            .expect("Could not disconnect");
+    })
+    .await;
+    pass(&mut pigglet);
+}
+
+/// Start a pigglet that requires clients to authenticate, listening on the loopback address,
+/// and return it with the port it is listening on and the pairing code it shows
+fn run_with_auth() -> (Child, u16, PairingCode) {
+    kill_all("pigglet");
+    let port = portpicker::pick_unused_port().expect("Could not find a free port");
+    let mut pigglet = run(
+        "pigglet",
+        vec![
+            "--bind".into(),
+            "127.0.0.1".into(),
+            "--port".into(),
+            port.to_string(),
+        ],
+        None,
+    );
+    let line = wait_for_stdout(&mut pigglet, "Pairing code:", Some("Error:"));
+    let code = line
+        .split_once(':')
+        .and_then(|(_, code)| PairingCode::parse(code.trim()))
+        .expect("Could not parse pairing code");
+    (pigglet, port, code)
+}
+
+/// Connect to the pigglet on the loopback address and `port` with `credentials`, and expect
+/// it to refuse with an [AuthError]
+async fn connect_refused(port: u16, credentials: &mut Credentials) -> AuthError {
+    let ip = IpAddr::from([127, 0, 0, 1]);
+    match tcp_host::connect(ip, port, credentials).await {
+        Ok(_) => panic!("Connected without authenticating"),
+        Err(e) => e
+            .downcast_ref::<AuthError>()
+            .cloned()
+            .unwrap_or_else(|| panic!("Unexpected error: {e}")),
+    }
+}
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn pair_tcp() {
+    let (mut pigglet, port, code) = run_with_auth();
+    let ip = IpAddr::from([127, 0, 0, 1]);
+    let mut credentials = Credentials::default();
+    credentials.set_pairing_code(code);
+    let (desc, _, connection) = tcp_host::connect(ip, port, &mut credentials)
+        .await
+        .expect("Could not pair");
+    assert!(connection.capabilities().contains(Capabilities::AUTH));
+    assert!(credentials.is_paired(&desc.details.serial));
+    tcp_host::disconnect(connection)
+        .await
+        .expect("Could not disconnect");
+
+    // The key sent when pairing is used to connect again
+    let (_, _, connection) = tcp_host::connect(ip, port, &mut credentials)
+        .await
+        .expect("Could not connect with key");
+    tcp_host::disconnect(connection)
+        .await
+        .expect("Could not disconnect");
+    pass(&mut pigglet);
+}
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn reject_unauthenticated_tcp() {
+    let (mut pigglet, port, code) = run_with_auth();
+    assert!(matches!(
+        connect_refused(port, &mut Credentials::default()).await,
+        AuthError::NotPaired(_)
+    ));
+
+    // A pairing code can only be used once
+    let mut credentials = Credentials::default();
+    credentials.set_pairing_code(code);
+    let (_, _, connection) =
+        tcp_host::connect(IpAddr::from([127, 0, 0, 1]), port, &mut credentials)
+            .await
+            .expect("Could not pair");
+    tcp_host::disconnect(connection)
+        .await
+        .expect("Could not disconnect");
+    let mut credentials = Credentials::default();
+    credentials.set_pairing_code(code);
+    assert!(matches!(
+        connect_refused(port, &mut credentials).await,
+        AuthError::CodeRejected(_)
+    ));
+    pass(&mut pigglet);
+}
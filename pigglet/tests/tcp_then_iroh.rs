@@ -12,7 +12,7 @@ mod support;
 #[serial(pigglet)]
 async fn connect_tcp_then_iroh() {
     kill_all("pigglet");
-    let mut pigglet = run("pigglet", vec![], None);
+    let mut pigglet = run("pigglet", vec!["--no_auth".into()], None);
 
     tokio::time::sleep(Duration::from_secs(1)).await;
 
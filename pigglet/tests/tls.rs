@@ -0,0 +1,91 @@
+#![cfg(feature = "tls")]
+
+use crate::support::{kill_all, pass, run, wait_for_stdout};
+use pigdef::tls::Fingerprint;
+use pignet::credentials::{Credentials, KEYS_FILENAME};
+use pignet::{tcp_host, tls_host};
+use serial_test::serial;
+use std::net::IpAddr;
+use std::process::Child;
+use std::str::FromStr;
+use tempfile::tempdir;
+
+#[path = "../../piggui/tests/support.rs"]
+mod support;
+
+const LOCALHOST: IpAddr = IpAddr::V4(std::net::Ipv4Addr::LOCALHOST);
+
+/// Start a pigglet that accepts TLS connections from any client on the loopback address, and
+/// return it with the TLS port it is listening on and the fingerprint of its certificate
+fn run_with_tls() -> (Child, u16, Fingerprint) {
+    kill_all("pigglet");
+    let port = portpicker::pick_unused_port().expect("Could not find a free port");
+    let mut pigglet = run(
+        "pigglet",
+        vec![
+            "--no_auth".into(),
+            "--bind".into(),
+            "127.0.0.1".into(),
+            "--tls_port".into(),
+            port.to_string(),
+        ],
+        None,
+    );
+    let line = wait_for_stdout(&mut pigglet, "TLS fingerprint:", Some("Error:"));
+    let fingerprint = line
+        .split_once(':')
+        .and_then(|(_, fingerprint)| Fingerprint::from_str(fingerprint.trim()).ok())
+        .expect("Could not parse fingerprint");
+    (pigglet, port, fingerprint)
+}
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn connect_tls() {
+    let (mut pigglet, port, fingerprint) = run_with_tls();
+    let (desc, _, connection) =
+        tls_host::connect(LOCALHOST, port, fingerprint, &mut Credentials::default())
+            .await
+            .expect("Could not connect with TLS");
+    assert!(desc.details.model.contains("Fake"));
+    tcp_host::disconnect(connection)
+        .await
+        .expect("Could not disconnect");
+    pass(&mut pigglet);
+}
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn reject_wrong_fingerprint_tls() {
+    let (mut pigglet, port, _) = run_with_tls();
+    let wrong = Fingerprint::of(b"some other certificate");
+    assert!(
+        tls_host::connect(LOCALHOST, port, wrong, &mut Credentials::default())
+            .await
+            .is_err()
+    );
+    pass(&mut pigglet);
+}
+
+#[tokio::test]
+#[serial(pigglet)]
+async fn pin_certificate_tls() {
+    let (mut pigglet, port, fingerprint) = run_with_tls();
+    let keys_path = tempdir()
+        .expect("Could not create a tempdir")
+        .keep()
+        .join(KEYS_FILENAME);
+    let mut credentials = Credentials::load(&keys_path).expect("Could not load credentials");
+    let (desc, _, connection) = tls_host::connect(LOCALHOST, port, fingerprint, &mut credentials)
+        .await
+        .expect("Could not connect with TLS");
+    tcp_host::disconnect(connection)
+        .await
+        .expect("Could not disconnect");
+
+    // The fingerprint is saved for the device, so a different certificate would be refused
+    let saved = std::fs::read_to_string(&keys_path).expect("Could not read saved credentials");
+    assert!(saved.contains(&desc.details.serial));
+    assert!(saved.contains(&fingerprint.to_string()));
+    pass(&mut pigglet);
+}
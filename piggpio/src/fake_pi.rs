@@ -1,21 +1,129 @@
+use std::f64::consts::TAU;
 use std::io;
 use std::time::Duration;
 
-use pigdef::config::{HardwareConfig, InputPull, LevelChange};
-use pigdef::description::{BCMPinNumber, PinLevel};
-use pigdef::pin_function::PinFunction;
+use pigdef::config::HardwareConfigMessage::{AnalogSampled, IOLevelChanged, UartReceived};
+use pigdef::config::{AnalogSample, HardwareConfig, HardwareConfigMessage, InputPull, LevelChange};
+use pigdef::description::{AnalogValue, BCMPinNumber, PinLevel, ANALOG_MAX};
+use pigdef::i2c::{I2cAddress, I2cRequest, I2cResponse};
+use pigdef::pin_function::{PinFunction, DEFAULT_SAMPLE_RATE, MAX_SAMPLE_RATE};
+use pigdef::spi::{SpiConfig, SpiRequest, SpiResponse};
+use pigdef::uart::UartConfig;
 
 use crate::pin_descriptions::*;
-use pigdef::description::{HardwareDescription, HardwareDetails, PinDescriptionSet};
+use pigdef::description::{
+    HardwareDescription, HardwareDetails, PinDescription, PinDescriptionSet,
+};
 
 use crate::fake_pi::Pin::Output;
+use crate::{
+    check_duty_cycle, check_i2c_length, check_spi_length, check_uart_length, I2C1_SCL_PIN,
+    I2C1_SDA_PIN, SPI0_DATA_PINS, SPI0_SCLK_PIN, UART0_RXD_PIN, UART0_TXD_PIN,
+};
 use rand_core::{OsRng, RngCore};
+use std::collections::HashMap;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 enum Pin {
     Input(PinLevel, std::sync::mpsc::Sender<PinLevel>),
-    #[allow(dead_code)]
     Output(PinLevel),
+    Pwm {
+        frequency: f64,
+        duty_cycle: f64,
+    },
+    AnalogInput(std::sync::mpsc::Sender<()>),
+    I2c,
+    Spi,
+    SpiClock(SpiConfig),
+    UartReceive,
+    /// Used to transmit by the fake UART, with its settings, although it echoes bytes whatever
+    /// they are
+    UartTransmit(UartConfig),
+}
+
+/// Pins of the fake hardware that can also be used as analog inputs, like the ADC pins of
+/// a Pi Pico, so that analog inputs can be tried out without one
+const FAKE_ANALOG_PINS: [BCMPinNumber; 2] = [26, 27];
+
+/// The period of the synthetic signal generated for fake analog inputs
+const FAKE_ANALOG_PERIOD: Duration = Duration::from_secs(5);
+
+/// The address of the fake TMP102 temperature sensor on the fake I2C bus
+const FAKE_TMP102_ADDRESS: I2cAddress = 0x48;
+
+/// The address of the fake BME280 environmental sensor on the fake I2C bus
+const FAKE_BME280_ADDRESS: I2cAddress = 0x76;
+
+/// A simulated I2C bus with a couple of fake devices on it, so that I2C can be tried out
+/// without real hardware. The registers of each device are modelled as 256 bytes, with the
+/// register address incremented after each byte read or written, as most devices do.
+struct FakeI2cBus {
+    devices: HashMap<I2cAddress, [u8; 256]>,
+}
+
+impl FakeI2cBus {
+    fn new() -> Self {
+        // A TMP102 reading 25.0C, with its power-on configuration
+        let mut tmp102 = [0; 256];
+        tmp102[0..4].copy_from_slice(&[0x19, 0x00, 0x60, 0xA0]);
+
+        // A BME280, which can be identified by its chip id register
+        let mut bme280 = [0; 256];
+        bme280[0xD0] = 0x60;
+
+        FakeI2cBus {
+            devices: HashMap::from([(FAKE_TMP102_ADDRESS, tmp102), (FAKE_BME280_ADDRESS, bme280)]),
+        }
+    }
+
+    fn request(&mut self, request: &I2cRequest) -> io::Result<I2cResponse> {
+        match request {
+            I2cRequest::Scan => {
+                let mut devices: Vec<I2cAddress> = self.devices.keys().copied().collect();
+                devices.sort();
+                Ok(I2cResponse::Devices(devices))
+            }
+            I2cRequest::Read {
+                address,
+                register,
+                length,
+            } => {
+                check_i2c_length(*length as usize)?;
+                let registers = self.device(*address)?;
+                let data = (0..*length)
+                    .map(|offset| registers[register.wrapping_add(offset) as usize])
+                    .collect();
+                Ok(I2cResponse::Read {
+                    address: *address,
+                    register: *register,
+                    data,
+                })
+            }
+            I2cRequest::Write {
+                address,
+                register,
+                data,
+            } => {
+                check_i2c_length(data.len())?;
+                let registers = self.device(*address)?;
+                for (offset, byte) in data.iter().enumerate() {
+                    registers[register.wrapping_add(offset as u8) as usize] = *byte;
+                }
+                Ok(I2cResponse::Written {
+                    address: *address,
+                    register: *register,
+                })
+            }
+        }
+    }
+
+    /// Get the registers of the device at `address`, failing as a real bus would when
+    /// no device acknowledges the address
+    fn device(&mut self, address: I2cAddress) -> io::Result<&mut [u8; 256]> {
+        self.devices
+            .get_mut(&address)
+            .ok_or_else(|| io::Error::other(format!("No device at address {address:#04x}")))
+    }
 }
 
 /// Fake Pi Hardware implementation for hosts (macOS, Linux, etc.) to show and develop GUI
@@ -23,6 +131,24 @@ enum Pin {
 pub struct HW {
     configured_pins: std::collections::HashMap<BCMPinNumber, Pin>,
     hardware_description: HardwareDescription,
+    i2c_bus: Option<FakeI2cBus>,
+    /// The settings of the open fake UART, and the bytes sent through it to be echoed back by
+    /// its thread
+    uart_echo: Option<(UartConfig, std::sync::mpsc::Sender<Vec<u8>>)>,
+}
+
+/// The fake SPI bus has its data out (MOSI) line looped back to its data in (MISO) line,
+/// so every byte sent in a transfer is received back at the same time
+fn fake_spi_transfer(request: &SpiRequest) -> io::Result<SpiResponse> {
+    match request {
+        SpiRequest::Transfer(written) => {
+            check_spi_length(written.len())?;
+            Ok(SpiResponse::Transferred {
+                written: written.clone(),
+                read: written.clone(),
+            })
+        }
+    }
 }
 
 /// Implementation code for fake hardware
@@ -32,8 +158,10 @@ impl HW {
             configured_pins: Default::default(),
             hardware_description: HardwareDescription {
                 details: Self::get_details(),
-                pins: PinDescriptionSet::new(&GPIO_PIN_DESCRIPTIONS),
+                pins: PinDescriptionSet::new(&fake_pin_descriptions()),
             },
+            i2c_bus: None,
+            uart_echo: None,
         }
     }
 
@@ -44,7 +172,7 @@ impl HW {
 
     pub async fn apply_config<C>(&mut self, config: &HardwareConfig, callback: C) -> io::Result<()>
     where
-        C: FnMut(BCMPinNumber, LevelChange) + Send + Sync + Clone + 'static,
+        C: FnMut(HardwareConfigMessage) + Send + Sync + Clone + 'static,
     {
         // Config only has pins that are configured
         for (bcm_pin_number, pin_function) in &config.pin_functions {
@@ -70,6 +198,110 @@ impl HW {
         Ok(())
     }
 
+    /// Read the level an output was last set to using the bcm pin number
+    pub fn get_output_level(&self, bcm_pin_number: BCMPinNumber) -> io::Result<PinLevel> {
+        match self.configured_pins.get(&bcm_pin_number) {
+            Some(Output(level)) => Ok(*level),
+            _ => Err(io::Error::other("Could not find a configured output pin")),
+        }
+    }
+
+    /// Set the duty cycle (from 0.0 to 1.0) of a PWM output using the bcm pin number
+    pub fn set_duty_cycle(&mut self, bcm_pin_number: BCMPinNumber, duty: f64) -> io::Result<()> {
+        check_duty_cycle(duty)?;
+        match self.configured_pins.get_mut(&bcm_pin_number) {
+            Some(Pin::Pwm { duty_cycle, .. }) => *duty_cycle = duty,
+            _ => return Err(io::Error::other("Could not find a configured PWM pin")),
+        }
+        Ok(())
+    }
+
+    /// Return the frequency and duty cycle a PWM output was last set to, if the pin is
+    /// configured for PWM
+    pub fn get_pwm(&self, bcm_pin_number: BCMPinNumber) -> Option<(f64, f64)> {
+        match self.configured_pins.get(&bcm_pin_number) {
+            Some(Pin::Pwm {
+                frequency,
+                duty_cycle,
+            }) => Some((*frequency, *duty_cycle)),
+            _ => None,
+        }
+    }
+
+    /// Make a request of the devices on the fake I2C bus, if its pins are configured for I2C
+    pub fn i2c_request(&mut self, request: &I2cRequest) -> io::Result<I2cResponse> {
+        self.i2c_bus
+            .as_mut()
+            .ok_or_else(|| io::Error::other("The I2C bus pins are not configured"))?
+            .request(request)
+    }
+
+    /// Make a transfer on the fake SPI bus, if its pins are configured for SPI
+    pub fn spi_request(&mut self, request: &SpiRequest) -> io::Result<SpiResponse> {
+        if self.spi_config().is_none() {
+            return Err(io::Error::other("The SPI bus pins are not configured"));
+        }
+        fake_spi_transfer(request)
+    }
+
+    /// Send bytes through the fake UART, which echoes them back as if TXD was connected to RXD
+    pub fn uart_send(&mut self, bytes: &[u8]) -> io::Result<()> {
+        check_uart_length(bytes.len())?;
+        self.uart_echo
+            .as_ref()
+            .ok_or_else(|| io::Error::other("The UART pins are not configured"))?
+            .1
+            .send(bytes.to_vec())
+            .map_err(io::Error::other)
+    }
+
+    /// Open the fake UART with the settings of its transmit pin when both of its pins are
+    /// configured, starting a thread that echoes the bytes sent back to `callback` as received
+    /// bytes, reopen it when the settings change, and close it when either of them is
+    /// configured for something else
+    fn update_uart<C>(&mut self, mut callback: C)
+    where
+        C: FnMut(HardwareConfigMessage) + Send + Sync + Clone + 'static,
+    {
+        let receive_configured = matches!(
+            self.configured_pins.get(&UART0_RXD_PIN),
+            Some(Pin::UartReceive)
+        );
+        let config = match self.configured_pins.get(&UART0_TXD_PIN) {
+            Some(Pin::UartTransmit(config)) if receive_configured => Some(*config),
+            _ => None,
+        };
+
+        if config == self.uart_echo.as_ref().map(|(open_config, _)| *open_config) {
+            return;
+        }
+
+        // Close the fake UART, stopping its thread, before it is reopened with the new settings
+        self.uart_echo = None;
+        if let Some(config) = config {
+            let (sender, receiver) = std::sync::mpsc::channel::<Vec<u8>>();
+            // The thread exits when the sender is dropped as the UART is closed
+            // jonesy:allow(expect) thread::spawn panics if OS can't create thread
+            std::thread::spawn(move || {
+                for bytes in receiver {
+                    callback(UartReceived(bytes));
+                }
+            });
+            self.uart_echo = Some((config, sender));
+        }
+    }
+
+    /// Return the settings of the SPI bus, if all of its pins are configured for SPI
+    fn spi_config(&self) -> Option<SpiConfig> {
+        let data_configured = SPI0_DATA_PINS
+            .iter()
+            .all(|pin| matches!(self.configured_pins.get(pin), Some(Pin::Spi)));
+        match self.configured_pins.get(&SPI0_SCLK_PIN) {
+            Some(Pin::SpiClock(config)) if data_configured => Some(*config),
+            _ => None,
+        }
+    }
+
     /// Return the [HardwareDetails] struct that describes a number of details about the general
     /// hardware, not GPIO specifics or pin outs or such.
     fn get_details() -> HardwareDetails {
@@ -105,16 +337,25 @@ impl HW {
         mut callback: C,
     ) -> io::Result<()>
     where
-        C: FnMut(BCMPinNumber, LevelChange) + Send + Sync + Clone + 'static,
+        C: FnMut(HardwareConfigMessage) + Send + Sync + Clone + 'static,
     {
         if bcm_pin_number > self.hardware_description.pins.pins().len() as u8 {
             return Err(io::Error::other("Invalid pin number"));
         }
 
+        let uart_callback = callback.clone();
+
         // If it was already configured, notify it to exit and remove it
-        if let Some(Pin::Input(level, sender)) = self.configured_pins.get_mut(&bcm_pin_number) {
-            let _ = sender.send(*level);
-            self.configured_pins.remove(&bcm_pin_number);
+        match self.configured_pins.get_mut(&bcm_pin_number) {
+            Some(Pin::Input(level, sender)) => {
+                let _ = sender.send(*level);
+                self.configured_pins.remove(&bcm_pin_number);
+            }
+            Some(Pin::AnalogInput(sender)) => {
+                let _ = sender.send(());
+                self.configured_pins.remove(&bcm_pin_number);
+            }
+            _ => {}
         }
 
         match pin_function {
@@ -130,8 +371,8 @@ impl HW {
                         let now = SystemTime::now()
                             .duration_since(UNIX_EPOCH) // jonesy:allow(expect) duration_since has internal expect, we use unwrap_or_default
                             .unwrap_or_default();
-                        callback(bcm_pin_number, LevelChange::new(level, now)); // jonesy:allow(invalid_enum)
-                                                                                // If we get a message, exit the thread
+                        callback(IOLevelChanged(bcm_pin_number, LevelChange::new(level, now))); // jonesy:allow(invalid_enum)
+                                                                                                // If we get a message, exit the thread
                         if receiver.recv_timeout(Duration::from_millis(666)).is_ok() {
                             return;
                         }
@@ -155,6 +396,66 @@ impl HW {
                     Some(level) => self.configured_pins.insert(bcm_pin_number, Output(*level)),
                 };
             }
+            Some(PinFunction::Pwm {
+                frequency,
+                duty_cycle,
+            }) => {
+                check_duty_cycle(*duty_cycle)?;
+                self.configured_pins.insert(
+                    bcm_pin_number,
+                    Pin::Pwm {
+                        frequency: *frequency,
+                        duty_cycle: *duty_cycle,
+                    },
+                );
+            }
+            Some(PinFunction::AnalogInput { sample_rate }) => {
+                let period = Duration::from_secs(1) / (*sample_rate).clamp(1, MAX_SAMPLE_RATE);
+                let (sender, receiver) = std::sync::mpsc::channel();
+                // jonesy:allow(expect) thread::spawn panics if OS can't create thread
+                std::thread::spawn(move || loop {
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH) // jonesy:allow(expect) duration_since has internal expect, we use unwrap_or_default
+                        .unwrap_or_default();
+                    let sample = AnalogSample::new(synthetic_analog_value(now), now);
+                    callback(AnalogSampled(bcm_pin_number, sample));
+                    // If we get a message, exit the thread
+                    if receiver.recv_timeout(period).is_ok() {
+                        return;
+                    }
+                });
+                self.configured_pins
+                    .insert(bcm_pin_number, Pin::AnalogInput(sender));
+            }
+            Some(PinFunction::I2C1_SDA) | Some(PinFunction::I2C1_SCL) => {
+                self.configured_pins.insert(bcm_pin_number, Pin::I2c);
+            }
+            Some(PinFunction::SPI0_MOSI) | Some(PinFunction::SPI0_MISO) => {
+                self.configured_pins.insert(bcm_pin_number, Pin::Spi);
+            }
+            Some(PinFunction::SPI0_SCLK(config)) => {
+                self.configured_pins
+                    .insert(bcm_pin_number, Pin::SpiClock(*config));
+            }
+            Some(PinFunction::UART0_TXD(config)) => {
+                self.configured_pins
+                    .insert(bcm_pin_number, Pin::UartTransmit(*config));
+            }
+            Some(PinFunction::UART0_RXD) => {
+                self.configured_pins
+                    .insert(bcm_pin_number, Pin::UartReceive);
+            }
+        }
+        self.update_uart(uart_callback);
+
+        // The bus can be used once both of its pins are configured for I2C
+        let i2c_configured = [I2C1_SDA_PIN, I2C1_SCL_PIN]
+            .iter()
+            .all(|pin| matches!(self.configured_pins.get(pin), Some(Pin::I2c)));
+        match (i2c_configured, self.i2c_bus.is_some()) {
+            (true, false) => self.i2c_bus = Some(FakeI2cBus::new()),
+            (false, true) => self.i2c_bus = None,
+            _ => {}
         }
 
         Ok(())
@@ -166,8 +467,203 @@ impl HW {
     }
 }
 
+/// Return the pin descriptions of a Pi, with analog inputs added on [FAKE_ANALOG_PINS]
+fn fake_pin_descriptions() -> Vec<PinDescription> {
+    GPIO_PIN_DESCRIPTIONS
+        .iter()
+        .cloned()
+        .map(|mut pin| {
+            if pin.bcm.is_some_and(|bcm| FAKE_ANALOG_PINS.contains(&bcm)) {
+                pin.options.to_mut().push(PinFunction::AnalogInput {
+                    sample_rate: DEFAULT_SAMPLE_RATE,
+                });
+            }
+            pin
+        })
+        .collect()
+}
+
+/// Generate the value of a synthetic analog signal at `time`: a sine wave over the full range of
+/// values, with a little random noise added
+fn synthetic_analog_value(time: Duration) -> AnalogValue {
+    let phase = TAU * time.as_secs_f64() / FAKE_ANALOG_PERIOD.as_secs_f64();
+    let half_range = f64::from(ANALOG_MAX) / 2.0;
+    let noise = f64::from(OsRng.next_u32() % 64) - 32.0;
+    (half_range * (1.0 + phase.sin()) + noise).clamp(0.0, f64::from(ANALOG_MAX)) as AnalogValue
+}
+
 impl Default for HW {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod test {
+    use crate::fake_pi::{
+        fake_spi_transfer, synthetic_analog_value, FakeI2cBus, Pin, FAKE_ANALOG_PERIOD,
+        FAKE_ANALOG_PINS, FAKE_BME280_ADDRESS, FAKE_TMP102_ADDRESS, HW,
+    };
+    use pigdef::config::HardwareConfigMessage::UartReceived;
+    use pigdef::description::ANALOG_MAX;
+    use pigdef::i2c::{I2cRequest, I2cResponse};
+    use pigdef::pin_function::PinFunction;
+    use pigdef::spi::{SpiRequest, SpiResponse};
+    use pigdef::uart::{UartConfig, DEFAULT_UART_CONFIG};
+    use std::time::Duration;
+
+    #[test]
+    fn analog_pins_offer_analog_input() {
+        let hw = HW::new();
+        for pin in hw.description().pins.pins() {
+            let analog = pin
+                .options
+                .iter()
+                .any(|option| matches!(option, PinFunction::AnalogInput { .. }));
+            assert_eq!(
+                analog,
+                pin.bcm.is_some_and(|bcm| FAKE_ANALOG_PINS.contains(&bcm)),
+                "Unexpected analog input option on pin {pin}"
+            );
+        }
+    }
+
+    #[test]
+    fn synthetic_analog_values_in_range() {
+        let step = FAKE_ANALOG_PERIOD / 20;
+        let values: Vec<u16> = (0..20).map(|i| synthetic_analog_value(step * i)).collect();
+        assert!(values.iter().all(|value| *value <= ANALOG_MAX));
+        // The signal should vary over a period
+        let min = values.iter().min().expect("Could not get min");
+        let max = values.iter().max().expect("Could not get max");
+        assert!(max - min > ANALOG_MAX / 2);
+    }
+
+    #[test]
+    fn i2c_not_configured() {
+        let mut hw = HW::new();
+        assert!(hw.i2c_request(&I2cRequest::Scan).is_err());
+    }
+
+    #[test]
+    fn i2c_scan_finds_fake_devices() {
+        let mut bus = FakeI2cBus::new();
+        let response = bus.request(&I2cRequest::Scan).expect("Could not scan");
+        assert_eq!(
+            response,
+            I2cResponse::Devices(vec![FAKE_TMP102_ADDRESS, FAKE_BME280_ADDRESS])
+        );
+    }
+
+    #[test]
+    fn i2c_write_then_read() {
+        let mut bus = FakeI2cBus::new();
+        let chip_id = I2cRequest::Read {
+            address: FAKE_BME280_ADDRESS,
+            register: 0xD0,
+            length: 1,
+        };
+        assert!(matches!(
+            bus.request(&chip_id).expect("Could not read"),
+            I2cResponse::Read { data, .. } if data == vec![0x60]
+        ));
+
+        bus.request(&I2cRequest::Write {
+            address: FAKE_TMP102_ADDRESS,
+            register: 0xFF,
+            data: vec![1, 2],
+        })
+        .expect("Could not write");
+        let read = I2cRequest::Read {
+            address: FAKE_TMP102_ADDRESS,
+            register: 0xFF,
+            length: 2,
+        };
+        assert!(matches!(
+            bus.request(&read).expect("Could not read"),
+            I2cResponse::Read { data, .. } if data == vec![1, 2]
+        ));
+    }
+
+    #[test]
+    fn i2c_no_device() {
+        let mut bus = FakeI2cBus::new();
+        let read = I2cRequest::Read {
+            address: 0x10,
+            register: 0,
+            length: 1,
+        };
+        assert!(bus.request(&read).is_err());
+    }
+
+    #[test]
+    fn spi_not_configured() {
+        let mut hw = HW::new();
+        assert!(hw.spi_request(&SpiRequest::Transfer(vec![1])).is_err());
+    }
+
+    #[test]
+    fn spi_loopback() {
+        let response =
+            fake_spi_transfer(&SpiRequest::Transfer(vec![0x9F, 0, 0])).expect("Could not transfer");
+        assert_eq!(
+            response,
+            SpiResponse::Transferred {
+                written: vec![0x9F, 0, 0],
+                read: vec![0x9F, 0, 0],
+            }
+        );
+    }
+
+    #[test]
+    fn uart_not_configured() {
+        let mut hw = HW::new();
+        assert!(hw.uart_send(b"hello").is_err());
+    }
+
+    #[test]
+    fn uart_echo() {
+        let mut hw = HW::new();
+        hw.configured_pins
+            .insert(crate::UART0_TXD_PIN, Pin::UartTransmit(DEFAULT_UART_CONFIG));
+        hw.configured_pins
+            .insert(crate::UART0_RXD_PIN, Pin::UartReceive);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        hw.update_uart(move |message| {
+            let _ = sender.send(message);
+        });
+
+        hw.uart_send(b"hello\n").expect("Could not send");
+        match receiver
+            .recv_timeout(Duration::from_secs(1))
+            .expect("Nothing received")
+        {
+            UartReceived(bytes) => assert_eq!(bytes, b"hello\n"),
+            message => panic!("Expected bytes received but got {message:?}"),
+        }
+    }
+
+    #[test]
+    fn uart_reopened_with_new_settings() {
+        let mut hw = HW::new();
+        hw.configured_pins
+            .insert(crate::UART0_TXD_PIN, Pin::UartTransmit(DEFAULT_UART_CONFIG));
+        hw.configured_pins
+            .insert(crate::UART0_RXD_PIN, Pin::UartReceive);
+        hw.update_uart(|_| {});
+
+        let config = UartConfig {
+            baud_rate: 9600,
+            ..DEFAULT_UART_CONFIG
+        };
+        hw.configured_pins
+            .insert(crate::UART0_TXD_PIN, Pin::UartTransmit(config));
+        hw.update_uart(|_| {});
+        assert_eq!(hw.uart_echo.as_ref().map(|(open, _)| *open), Some(config));
+
+        hw.configured_pins.remove(&crate::UART0_RXD_PIN);
+        hw.update_uart(|_| {});
+        assert!(hw.uart_echo.is_none());
+    }
+}
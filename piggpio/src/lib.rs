@@ -30,10 +30,42 @@ pub mod fake_pi;
     target_env = "gnu"
 )))]
 pub use fake_pi::HW;
-use pigdef::description::HardwareDescription;
+use pigdef::description::{BCMPinNumber, HardwareDescription};
+use pigdef::hello::Capabilities;
+use pigdef::i2c::I2C_MAX_BYTES;
+use pigdef::spi::SPI_MAX_BYTES;
+use pigdef::uart::UART_MAX_BYTES;
+use std::io;
 
 mod pin_descriptions;
 
+/// The optional features of the GPIO hardware that can be used via [HW]
+pub const CAPABILITIES: Capabilities = Capabilities::GPIO_INPUT
+    .union(Capabilities::GPIO_OUTPUT)
+    .union(Capabilities::PWM)
+    .union(Capabilities::ANALOG_INPUT)
+    .union(Capabilities::I2C)
+    .union(Capabilities::SPI)
+    .union(Capabilities::UART);
+
+/// The pin used for the data line (SDA) of I2C bus #1
+pub(crate) const I2C1_SDA_PIN: BCMPinNumber = 2;
+
+/// The pin used for the clock line (SCL) of I2C bus #1
+pub(crate) const I2C1_SCL_PIN: BCMPinNumber = 3;
+
+/// The pins used for the data out (MOSI) and data in (MISO) lines of SPI bus #0
+pub(crate) const SPI0_DATA_PINS: [BCMPinNumber; 2] = [10, 9];
+
+/// The pin used for the clock line (SCLK) of SPI bus #0
+pub(crate) const SPI0_SCLK_PIN: BCMPinNumber = 11;
+
+/// The pin used for the transmit line (TXD) of UART #0
+pub(crate) const UART0_TXD_PIN: BCMPinNumber = 14;
+
+/// The pin used for the receive line (RXD) of UART #0
+pub(crate) const UART0_RXD_PIN: BCMPinNumber = 15;
+
 pub mod config;
 
 /// Return the Description of the local GPIO hardware is available for use on this machine
@@ -61,6 +93,44 @@ pub fn local_hardware() -> Option<HardwareDescription> {
     Some(HW::new().description().clone())
 }
 
+/// Check a requested duty cycle is in the valid range before using it
+pub(crate) fn check_duty_cycle(duty_cycle: f64) -> io::Result<()> {
+    if !(0.0..=1.0).contains(&duty_cycle) {
+        return Err(io::Error::other("Duty cycle must be between 0.0 and 1.0"));
+    }
+    Ok(())
+}
+
+/// Check the number of bytes to be read or written in one I2C request is not too many
+pub(crate) fn check_i2c_length(length: usize) -> io::Result<()> {
+    if length > I2C_MAX_BYTES {
+        return Err(io::Error::other(format!(
+            "Cannot transfer more than {I2C_MAX_BYTES} bytes in one request"
+        )));
+    }
+    Ok(())
+}
+
+/// Check the number of bytes to be sent in one SPI transfer is not too many
+pub(crate) fn check_spi_length(length: usize) -> io::Result<()> {
+    if length > SPI_MAX_BYTES {
+        return Err(io::Error::other(format!(
+            "Cannot transfer more than {SPI_MAX_BYTES} bytes in one request"
+        )));
+    }
+    Ok(())
+}
+
+/// Check the number of bytes to be sent through the UART at once is not too many
+pub(crate) fn check_uart_length(length: usize) -> io::Result<()> {
+    if length > UART_MAX_BYTES {
+        return Err(io::Error::other(format!(
+            "Cannot send more than {UART_MAX_BYTES} bytes at a time"
+        )));
+    }
+    Ok(())
+}
+
 /// Create a new HW instance - should only be called once
 pub fn get_hardware() -> Option<HW> {
     // release build - Not Pi hardware
@@ -91,6 +161,8 @@ pub fn get_hardware() -> Option<HW> {
 mod test {
     use pigdef::description::{PinDescription, PinDescriptionSet};
     use pigdef::pin_function::PinFunction;
+    use pigdef::spi::DEFAULT_SPI_CONFIG;
+    use pigdef::uart::DEFAULT_UART_CONFIG;
     use std::borrow::Cow;
 
     #[test]
@@ -127,6 +199,53 @@ mod test {
         }
     }
 
+    #[test]
+    fn pwm_capable_pins() {
+        let hw = crate::get_hardware().expect("Could not get hardware");
+        let pwm_pins: Vec<_> = hw
+            .description()
+            .pins
+            .bcm_pins_sorted()
+            .into_iter()
+            .filter(|pin| {
+                pin.options
+                    .iter()
+                    .any(|option| matches!(option, PinFunction::Pwm { .. }))
+            })
+            .filter_map(|pin| pin.bcm)
+            .collect();
+        assert_eq!(pwm_pins, vec![12, 13, 18, 19]);
+    }
+
+    #[test]
+    fn bus_pins() {
+        let hw = crate::get_hardware().expect("Could not get hardware");
+        let pins = hw.description().pins.bcm_pins_sorted();
+        // Each bus function should be offered on exactly the pin the bus is opened on
+        for (function, bcm_pin_number) in [
+            (PinFunction::I2C1_SDA, crate::I2C1_SDA_PIN),
+            (PinFunction::I2C1_SCL, crate::I2C1_SCL_PIN),
+            (PinFunction::SPI0_MOSI, crate::SPI0_DATA_PINS[0]),
+            (PinFunction::SPI0_MISO, crate::SPI0_DATA_PINS[1]),
+            (
+                PinFunction::SPI0_SCLK(DEFAULT_SPI_CONFIG),
+                crate::SPI0_SCLK_PIN,
+            ),
+            (
+                PinFunction::UART0_TXD(DEFAULT_UART_CONFIG),
+                crate::UART0_TXD_PIN,
+            ),
+            (PinFunction::UART0_RXD, crate::UART0_RXD_PIN),
+        ] {
+            let pins_with: Vec<_> = pins
+                .iter()
+                .filter(|pin| pin.options.contains(&function))
+                .filter_map(|pin| pin.bcm)
+                .collect();
+            assert_eq!(pins_with, vec![bcm_pin_number], "{function} pins");
+        }
+    }
+
     #[test]
     fn display_pin_description() {
         let pin = PinDescription {
@@ -1,20 +1,61 @@
 use std::collections::HashMap;
 use std::io;
+use std::sync::mpsc::{Sender, TryRecvError};
 
 use std::time::Duration;
 
 use crate::pin_descriptions::*;
+use pigdef::config::HardwareConfigMessage::{IOLevelChanged, UartReceived};
 use pigdef::config::InputPull;
-use pigdef::config::{HardwareConfig, LevelChange};
+use pigdef::config::{HardwareConfig, HardwareConfigMessage, LevelChange};
 use pigdef::description::{BCMPinNumber, PinLevel};
 use pigdef::description::{HardwareDescription, HardwareDetails, PinDescriptionSet};
+use pigdef::i2c::{I2cAddress, I2cRequest, I2cResponse, I2C_FIRST_ADDRESS, I2C_LAST_ADDRESS};
 use pigdef::pin_function::PinFunction;
+use pigdef::spi::{SpiChipSelect, SpiConfig, SpiMode, SpiRequest, SpiResponse};
+use pigdef::uart::{UartConfig, UartParity, UartStopBits, UART_MAX_BYTES};
 
-use rppal::gpio::{Gpio, InputPin, Level, OutputPin, Trigger};
+use crate::{
+    check_duty_cycle, check_i2c_length, check_spi_length, check_uart_length, I2C1_SCL_PIN,
+    I2C1_SDA_PIN, SPI0_DATA_PINS, SPI0_SCLK_PIN, UART0_RXD_PIN, UART0_TXD_PIN,
+};
+use rppal::gpio::{Gpio, InputPin, Level, Mode as GpioMode, OutputPin, Trigger};
+use rppal::i2c::I2c;
+use rppal::pwm::{Channel, Polarity, Pwm};
+use rppal::spi::{Bus, Mode, SlaveSelect, Spi};
+use rppal::system::{DeviceInfo, Model};
+use rppal::uart::{Parity, Uart};
+
+/// How long the thread that owns the UART waits for bytes to be received, before sending any
+/// bytes queued to be sent and checking if it should stop. This is the shortest timeout the
+/// kernel's serial driver supports
+const UART_READ_TIMEOUT: Duration = Duration::from_millis(100);
 
 enum Pin {
     Input(InputPin),
     Output(OutputPin),
+    /// PWM generated by one of the SoC's PWM channels
+    HardwarePwm(Pwm),
+    /// PWM generated in software by toggling an output, with the frequency it is set to
+    SoftwarePwm(OutputPin, f64),
+    /// Used by the I2C bus, which is accessed via the kernel's I2C driver
+    I2c,
+    /// Used for data by the SPI bus, which is accessed via the kernel's spidev driver
+    Spi,
+    /// Used for the clock of the SPI bus, with the settings of the bus
+    SpiClock(SpiConfig),
+    /// Used to receive by the UART, which is accessed via the kernel's serial driver
+    UartReceive,
+    /// Used to transmit by the UART, with the settings of the UART
+    UartTransmit(UartConfig),
+}
+
+/// The open UART, which is owned by a thread that reads the bytes it receives and sends the
+/// bytes queued for it, so a read never holds up sending
+struct OpenUart {
+    config: UartConfig,
+    /// Bytes to be sent by the thread. Dropped when the UART is closed, to stop the thread
+    to_send: Sender<Vec<u8>>,
 }
 
 /// This is the Hardware implementation for the Raspberry Pi using "rppal" crate
@@ -27,6 +68,9 @@ enum Pin {
 /// at run-time when trying to access drivers and hardware for GPIO.
 pub struct HW {
     configured_pins: std::collections::HashMap<BCMPinNumber, Pin>,
+    i2c: Option<I2c>,
+    spi: Option<(SpiConfig, Spi)>,
+    uart: Option<OpenUart>,
 }
 
 /// Common implementation code for pi and fake hardware
@@ -35,6 +79,9 @@ impl HW {
     pub fn new() -> Self {
         HW {
             configured_pins: HashMap::default(),
+            i2c: None,
+            spi: None,
+            uart: None,
         }
     }
     /// Return the Pi hardware description
@@ -48,7 +95,7 @@ impl HW {
     /// This takes the GPIOConfig struct and configures all the pins in it
     pub async fn apply_config<C>(&mut self, config: &HardwareConfig, callback: C) -> io::Result<()>
     where
-        C: FnMut(BCMPinNumber, LevelChange) + Send + Sync + Clone + 'static,
+        C: FnMut(HardwareConfigMessage) + Send + Sync + Clone + 'static,
     {
         // Config only has pins that are configured
         for (bcm_pin_number, pin_function) in &config.pin_functions {
@@ -70,16 +117,121 @@ impl HW {
                 true => output_pin.write(Level::High),
                 false => output_pin.write(Level::Low),
             },
-            _ => {
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    "Could not find a configured output pin",
-                ))
+            _ => return Err(io::Error::other("Could not find a configured output pin")),
+        }
+        Ok(())
+    }
+
+    /// Read the level an output was last set to using the bcm pin number
+    pub fn get_output_level(&self, bcm_pin_number: BCMPinNumber) -> io::Result<PinLevel> {
+        match self.configured_pins.get(&bcm_pin_number) {
+            Some(Pin::Output(output_pin)) => Ok(output_pin.is_set_high()),
+            _ => Err(io::Error::other("Could not find a configured output pin")),
+        }
+    }
+
+    /// Set the duty cycle (from 0.0 to 1.0) of a PWM output using the bcm pin number
+    pub fn set_duty_cycle(
+        &mut self,
+        bcm_pin_number: BCMPinNumber,
+        duty_cycle: f64,
+    ) -> io::Result<()> {
+        check_duty_cycle(duty_cycle)?;
+        match self.configured_pins.get_mut(&bcm_pin_number) {
+            Some(Pin::HardwarePwm(pwm)) => {
+                pwm.set_duty_cycle(duty_cycle).map_err(io::Error::other)?
             }
+            Some(Pin::SoftwarePwm(output_pin, frequency)) => output_pin
+                .set_pwm_frequency(*frequency, duty_cycle)
+                .map_err(io::Error::other)?,
+            _ => return Err(io::Error::other("Could not find a configured PWM pin")),
         }
         Ok(())
     }
 
+    /// Make a request of the devices on I2C bus #1, if its pins are configured for I2C
+    pub fn i2c_request(&mut self, request: &I2cRequest) -> io::Result<I2cResponse> {
+        let i2c = self
+            .i2c
+            .as_mut()
+            .ok_or_else(|| io::Error::other("The I2C bus pins are not configured"))?;
+
+        match request {
+            I2cRequest::Scan => {
+                // Like "i2cdetect -r", a device is present if a read from its address is acknowledged
+                let devices = (I2C_FIRST_ADDRESS..=I2C_LAST_ADDRESS)
+                    .filter(|address| {
+                        i2c.set_slave_address(u16::from(*address)).is_ok()
+                            && i2c.read(&mut [0; 1]).is_ok()
+                    })
+                    .collect();
+                Ok(I2cResponse::Devices(devices))
+            }
+            I2cRequest::Read {
+                address,
+                register,
+                length,
+            } => {
+                check_i2c_length(*length as usize)?;
+                select_i2c_device(i2c, *address)?;
+                let mut data = vec![0; *length as usize];
+                i2c.write_read(&[*register], &mut data)
+                    .map_err(io::Error::other)?;
+                Ok(I2cResponse::Read {
+                    address: *address,
+                    register: *register,
+                    data,
+                })
+            }
+            I2cRequest::Write {
+                address,
+                register,
+                data,
+            } => {
+                check_i2c_length(data.len())?;
+                select_i2c_device(i2c, *address)?;
+                let mut buffer = vec![*register];
+                buffer.extend_from_slice(data);
+                i2c.write(&buffer).map_err(io::Error::other)?;
+                Ok(I2cResponse::Written {
+                    address: *address,
+                    register: *register,
+                })
+            }
+        }
+    }
+
+    /// Make a transfer on SPI bus #0, if its pins are configured for SPI
+    pub fn spi_request(&mut self, request: &SpiRequest) -> io::Result<SpiResponse> {
+        let (_, spi) = self
+            .spi
+            .as_mut()
+            .ok_or_else(|| io::Error::other("The SPI bus pins are not configured"))?;
+
+        match request {
+            SpiRequest::Transfer(written) => {
+                check_spi_length(written.len())?;
+                let mut read = vec![0; written.len()];
+                spi.transfer(&mut read, written).map_err(io::Error::other)?;
+                Ok(SpiResponse::Transferred {
+                    written: written.clone(),
+                    read,
+                })
+            }
+        }
+    }
+
+    /// Send bytes through UART #0, if its pins are configured for the UART
+    pub fn uart_send(&mut self, bytes: &[u8]) -> io::Result<()> {
+        check_uart_length(bytes.len())?;
+        self.uart
+            .as_ref()
+            .ok_or_else(|| io::Error::other("The UART pins are not configured"))?
+            .to_send
+            .send(bytes.to_vec())
+            .map_err(|_| io::Error::other("The UART could not be used"))
+    }
+
     /// Return the [HardwareDetails] struct that describes a number of details about the general
     /// hardware, not GPIO specifics or pin outs or such.
     fn get_details() -> HardwareDetails {
@@ -130,8 +282,10 @@ impl HW {
         mut callback: C,
     ) -> io::Result<()>
     where
-        C: FnMut(BCMPinNumber, LevelChange) + Send + Sync + Clone + 'static,
+        C: FnMut(HardwareConfigMessage) + Send + Sync + Clone + 'static,
     {
+        let uart_callback = callback.clone();
+
         // If it was already configured, remove it
         self.configured_pins.remove(&bcm_pin_number);
 
@@ -142,9 +296,9 @@ impl HW {
 
             Some(PinFunction::Input(pull)) => {
                 let pin = Gpio::new()
-                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+                    .map_err(io::Error::other)?
                     .get(bcm_pin_number)
-                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+                    .map_err(io::Error::other)?;
 
                 let mut input = match pull {
                     None | Some(InputPull::None) => pin.into_input(),
@@ -157,25 +311,25 @@ impl HW {
                         Trigger::Both,
                         Some(Duration::from_millis(1)),
                         move |event| {
-                            callback(
+                            callback(IOLevelChanged(
                                 bcm_pin_number,
                                 LevelChange::new(
                                     event.trigger == Trigger::RisingEdge,
                                     event.timestamp,
                                 ),
-                            );
+                            ));
                         },
                     )
-                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+                    .map_err(|e| io::Error::other(e.to_string()))?;
                 self.configured_pins
                     .insert(bcm_pin_number, Pin::Input(input));
             }
 
             Some(PinFunction::Output(value)) => {
                 let pin = Gpio::new()
-                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+                    .map_err(io::Error::other)?
                     .get(bcm_pin_number)
-                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+                    .map_err(io::Error::other)?;
                 let output_pin = match value {
                     Some(true) => pin.into_output_high(),
                     Some(false) => pin.into_output_low(),
@@ -184,8 +338,197 @@ impl HW {
                 self.configured_pins
                     .insert(bcm_pin_number, Pin::Output(output_pin));
             }
+
+            Some(PinFunction::Pwm {
+                frequency,
+                duty_cycle,
+            }) => {
+                check_duty_cycle(*duty_cycle)?;
+
+                // Prefer a hardware PWM channel, if the pin has one and it is enabled
+                if let Some(channel) = hardware_pwm_channel(bcm_pin_number) {
+                    if let Ok(pwm) = Pwm::with_frequency(
+                        channel,
+                        *frequency,
+                        *duty_cycle,
+                        Polarity::Normal,
+                        true,
+                    ) {
+                        self.configured_pins
+                            .insert(bcm_pin_number, Pin::HardwarePwm(pwm));
+                        return Ok(());
+                    }
+                }
+
+                let mut output_pin = Gpio::new()
+                    .map_err(io::Error::other)?
+                    .get(bcm_pin_number)
+                    .map_err(io::Error::other)?
+                    .into_output_low();
+                output_pin
+                    .set_pwm_frequency(*frequency, *duty_cycle)
+                    .map_err(io::Error::other)?;
+                self.configured_pins
+                    .insert(bcm_pin_number, Pin::SoftwarePwm(output_pin, *frequency));
+            }
+
+            Some(PinFunction::AnalogInput { .. }) => {
+                return Err(io::Error::other("The Raspberry Pi has no analog inputs"));
+            }
+
+            Some(PinFunction::I2C1_SDA) | Some(PinFunction::I2C1_SCL) => {
+                self.configured_pins.insert(bcm_pin_number, Pin::I2c);
+            }
+
+            Some(PinFunction::SPI0_MOSI) | Some(PinFunction::SPI0_MISO) => {
+                self.configured_pins.insert(bcm_pin_number, Pin::Spi);
+            }
+
+            Some(PinFunction::SPI0_SCLK(config)) => {
+                self.configured_pins
+                    .insert(bcm_pin_number, Pin::SpiClock(*config));
+            }
+
+            Some(PinFunction::UART0_TXD(config)) => {
+                self.configured_pins
+                    .insert(bcm_pin_number, Pin::UartTransmit(*config));
+            }
+
+            Some(PinFunction::UART0_RXD) => {
+                self.configured_pins
+                    .insert(bcm_pin_number, Pin::UartReceive);
+            }
         }
 
+        // Update each bus independently, so one that cannot be opened doesn't stop the others
+        // being updated. If one cannot be opened, undo the pin's assignment so that bus is left
+        // closed, and not retried and failed on every later change of any pin
+        let results = [
+            self.update_i2c_bus(),
+            self.update_spi_bus(),
+            self.update_uart(uart_callback),
+        ];
+        if let Some(e) = results.into_iter().find_map(Result::err) {
+            self.configured_pins.remove(&bcm_pin_number);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Open UART #0 with the settings of its transmit pin when both of its pins are configured
+    /// for the UART, reopen it when the settings change, and close it when either of them is
+    /// configured for something else. While open, a thread owns the UART, sending the bytes
+    /// queued by `uart_send` and sending the bytes received to `callback`
+    fn update_uart<C>(&mut self, mut callback: C) -> io::Result<()>
+    where
+        C: FnMut(HardwareConfigMessage) + Send + Sync + Clone + 'static,
+    {
+        let receive_configured = matches!(
+            self.configured_pins.get(&UART0_RXD_PIN),
+            Some(Pin::UartReceive)
+        );
+        let config = match self.configured_pins.get(&UART0_TXD_PIN) {
+            Some(Pin::UartTransmit(config)) if receive_configured => Some(*config),
+            _ => None,
+        };
+
+        if config == self.uart.as_ref().map(|open| open.config) {
+            return Ok(());
+        }
+
+        // Close the UART, stopping its thread, before it is reopened with the new settings
+        self.uart = None;
+        if let Some(config) = config {
+            let parity = match config.parity {
+                UartParity::None => Parity::None,
+                UartParity::Even => Parity::Even,
+                UartParity::Odd => Parity::Odd,
+            };
+            let stop_bits = match config.stop_bits {
+                UartStopBits::One => 1,
+                UartStopBits::Two => 2,
+            };
+            let mut uart =
+                Uart::new(config.baud_rate, parity, 8, stop_bits).map_err(io::Error::other)?;
+            uart.set_read_mode(0, UART_READ_TIMEOUT)
+                .map_err(io::Error::other)?;
+            uart.set_write_mode(true).map_err(io::Error::other)?;
+
+            // The thread exits when the UART fails, or when the sender is dropped as it is closed
+            let (to_send, queued) = std::sync::mpsc::channel::<Vec<u8>>();
+            std::thread::spawn(move || {
+                let mut buffer = [0; UART_MAX_BYTES];
+                loop {
+                    loop {
+                        match queued.try_recv() {
+                            Ok(bytes) => {
+                                if uart.write(&bytes).is_err() {
+                                    return;
+                                }
+                            }
+                            Err(TryRecvError::Empty) => break,
+                            Err(TryRecvError::Disconnected) => return,
+                        }
+                    }
+
+                    match uart.read(&mut buffer) {
+                        Ok(0) => {}
+                        Ok(length) => callback(UartReceived(buffer[..length].to_vec())),
+                        Err(_) => return,
+                    }
+                }
+            });
+
+            self.uart = Some(OpenUart { config, to_send });
+        }
+        Ok(())
+    }
+
+    /// Open SPI bus #0 with the settings of its clock pin when all of its pins are configured
+    /// for SPI, reopen it when the settings change, and close it when any of them is configured
+    /// for something else
+    fn update_spi_bus(&mut self) -> io::Result<()> {
+        let data_configured = SPI0_DATA_PINS
+            .iter()
+            .all(|pin| matches!(self.configured_pins.get(pin), Some(Pin::Spi)));
+        let config = match self.configured_pins.get(&SPI0_SCLK_PIN) {
+            Some(Pin::SpiClock(config)) if data_configured => Some(*config),
+            _ => None,
+        };
+
+        if config != self.spi.as_ref().map(|(open_config, _)| *open_config) {
+            // Close the bus before it is reopened with the new settings
+            self.spi = None;
+            if let Some(config) = config {
+                let slave_select = match config.chip_select {
+                    SpiChipSelect::CE0 => SlaveSelect::Ss0,
+                    SpiChipSelect::CE1 => SlaveSelect::Ss1,
+                };
+                let mode = match config.mode {
+                    SpiMode::Mode0 => Mode::Mode0,
+                    SpiMode::Mode1 => Mode::Mode1,
+                    SpiMode::Mode2 => Mode::Mode2,
+                    SpiMode::Mode3 => Mode::Mode3,
+                };
+                let spi = Spi::new(Bus::Spi0, slave_select, config.clock_speed, mode)
+                    .map_err(io::Error::other)?;
+                self.spi = Some((config, spi));
+            }
+        }
+        Ok(())
+    }
+
+    /// Open I2C bus #1 when both of its pins are configured for I2C, and close it when
+    /// either of them is configured for something else
+    fn update_i2c_bus(&mut self) -> io::Result<()> {
+        let i2c_configured = [I2C1_SDA_PIN, I2C1_SCL_PIN]
+            .iter()
+            .all(|pin| matches!(self.configured_pins.get(pin), Some(Pin::I2c)));
+        match (i2c_configured, self.i2c.is_some()) {
+            (true, false) => self.i2c = Some(I2c::new().map_err(io::Error::other)?),
+            (false, true) => self.i2c = None,
+            _ => {}
+        }
         Ok(())
     }
 
@@ -193,14 +536,39 @@ impl HW {
     pub fn get_input_level(&self, bcm_pin_number: BCMPinNumber) -> io::Result<bool> {
         match self.configured_pins.get(&bcm_pin_number) {
             Some(Pin::Input(input_pin)) => Ok(input_pin.read() == Level::High),
-            _ => Err(io::Error::new(
-                io::ErrorKind::Other,
-                "Could not find a configured input pin",
-            )),
+            _ => Err(io::Error::other("Could not find a configured input pin")),
         }
     }
 }
 
+/// Return the hardware PWM [Channel] that drives `bcm_pin_number`, if there is one and the pin
+/// is routed to it, as done by `dtoverlay=pwm-2chan` for BCM 18 and 19 or by
+/// `dtoverlay=pwm-2chan,pin=12,func=4,pin2=13,func2=4` for BCM 12 and 13
+fn hardware_pwm_channel(bcm_pin_number: BCMPinNumber) -> Option<Channel> {
+    let pi5 = DeviceInfo::new()
+        .map(|info| info.model() == Model::RaspberryPi5)
+        .unwrap_or(false);
+
+    let channel = match (bcm_pin_number, pi5) {
+        (12, _) | (18, false) => Channel::Pwm0,
+        (13, _) | (19, false) => Channel::Pwm1,
+        (18, true) => Channel::Pwm2,
+        (19, true) => Channel::Pwm3,
+        _ => return None,
+    };
+
+    // A channel can drive more than one pin, so only use it if the overlay has switched this
+    // pin from GPIO to its PWM function, otherwise the PWM would appear on another pin
+    let mode = Gpio::new().ok()?.get(bcm_pin_number).ok()?.mode();
+    (!matches!(mode, GpioMode::Input | GpioMode::Output)).then_some(channel)
+}
+
+/// Set the address of the device on the bus that following reads and writes are for
+fn select_i2c_device(i2c: &mut I2c, address: I2cAddress) -> io::Result<()> {
+    i2c.set_slave_address(u16::from(address))
+        .map_err(io::Error::other)
+}
+
 impl Default for crate::HW {
     fn default() -> Self {
         Self::new()
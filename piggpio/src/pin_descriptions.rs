@@ -1,6 +1,8 @@
 use pigdef::config::InputPull;
 use pigdef::description::PinDescription;
-use pigdef::pin_function::PinFunction;
+use pigdef::pin_function::{PinFunction, DEFAULT_PWM_FREQUENCY};
+use pigdef::spi::DEFAULT_SPI_CONFIG;
+use pigdef::uart::DEFAULT_UART_CONFIG;
 use std::borrow::Cow;
 
 /// This module codifies the descriptions if the Raspberry Pi GPIO hardware
@@ -46,6 +48,7 @@ const PIN_3: PinDescription = PinDescription {
     options: Cow::Borrowed(&[
         PinFunction::Input(Some(InputPull::PullUp)),
         PinFunction::Output(None),
+        PinFunction::I2C1_SDA,
         // ALT0::I2C1_SDA / SDA1
         // ALT1: SMI SA3
         // ALT2: DPI VSYNC / LCD_VSYNC
@@ -70,6 +73,7 @@ const PIN_5: PinDescription = PinDescription {
     options: Cow::Borrowed(&[
         PinFunction::Input(Some(InputPull::PullUp)),
         PinFunction::Output(None),
+        PinFunction::I2C1_SCL,
         // ALT0::I2C1_SCL / SCL1
         // ALT1: SMI SA2
         // ALT2: DPI_HSYNC / LCD_HSYNC
@@ -109,6 +113,7 @@ const PIN_8: PinDescription = PinDescription {
     options: Cow::Borrowed(&[
         PinFunction::Input(Some(InputPull::PullDown)),
         PinFunction::Output(None),
+        PinFunction::UART0_TXD(DEFAULT_UART_CONFIG),
         // ALT0: UART0_TXD / TXD0
         // ALT1: SMI SD6 / SD6
         // ALT2: DSI_D10
@@ -132,6 +137,7 @@ const PIN_10: PinDescription = PinDescription {
     options: Cow::Borrowed(&[
         PinFunction::Input(Some(InputPull::PullDown)),
         PinFunction::Output(None),
+        PinFunction::UART0_RXD,
         // ALT0: UART0_RXD / RXD0
         // ALT1: SMI SD7
         // ALT2: DPI_D11
@@ -164,6 +170,10 @@ const PIN_12: PinDescription = PinDescription {
     options: Cow::Borrowed(&[
         PinFunction::Input(Some(InputPull::PullDown)),
         PinFunction::Output(None),
+        PinFunction::Pwm {
+            frequency: DEFAULT_PWM_FREQUENCY,
+            duty_cycle: 0.0,
+        },
         // ALT0: PCM_CLK
         // ALT1: SMI SD10
         // ALT2: DPI_D14
@@ -259,6 +269,7 @@ const PIN_19: PinDescription = PinDescription {
     options: Cow::Borrowed(&[
         PinFunction::Input(Some(InputPull::PullDown)),
         PinFunction::Output(None),
+        PinFunction::SPI0_MOSI,
         // ALT0: SPI0_MOSI
         // ALT1: SMI SD2
         // ALT2: DPI_D6
@@ -283,6 +294,7 @@ const PIN_21: PinDescription = PinDescription {
     options: Cow::Borrowed(&[
         PinFunction::Input(Some(InputPull::PullDown)),
         PinFunction::Output(None),
+        PinFunction::SPI0_MISO,
         // ALT0:SPI0_MISO
         // ALT1: SMI SD1
         // ALT2: DPI_D5
@@ -316,6 +328,7 @@ const PIN_23: PinDescription = PinDescription {
     options: Cow::Borrowed(&[
         PinFunction::Input(Some(InputPull::PullDown)),
         PinFunction::Output(None),
+        PinFunction::SPI0_SCLK(DEFAULT_SPI_CONFIG),
         // ALT0: SPI0_SCLK
         // ALT1: SMI SD3
         // ALT2: DPI_D7
@@ -440,6 +453,10 @@ const PIN_32: PinDescription = PinDescription {
     options: Cow::Borrowed(&[
         PinFunction::Input(Some(InputPull::PullDown)),
         PinFunction::Output(None),
+        PinFunction::Pwm {
+            frequency: DEFAULT_PWM_FREQUENCY,
+            duty_cycle: 0.0,
+        },
         // ALT0: PWM0 / PWM0_0
         // ALT1: SMI SD4
         // ALT2: DPI_D8
@@ -456,6 +473,10 @@ const PIN_33: PinDescription = PinDescription {
     options: Cow::Borrowed(&[
         PinFunction::Input(Some(InputPull::PullDown)),
         PinFunction::Output(None),
+        PinFunction::Pwm {
+            frequency: DEFAULT_PWM_FREQUENCY,
+            duty_cycle: 0.0,
+        },
         // ALT0: PWM1 / PWM0_1
         // ALT1: SMI SD5
         // ALT2: DPI_D9
@@ -479,6 +500,10 @@ const PIN_35: PinDescription = PinDescription {
     options: Cow::Borrowed(&[
         PinFunction::Input(Some(InputPull::PullDown)),
         PinFunction::Output(None),
+        PinFunction::Pwm {
+            frequency: DEFAULT_PWM_FREQUENCY,
+            duty_cycle: 0.0,
+        },
         // ALT0: PCM_FS
         // ALT1: SMI SD11
         // ALT2: DPI_D15
@@ -19,6 +19,8 @@ use pigdef::description::HardwareDetails;
 use pigdef::description::SerialNumber;
 #[cfg(feature = "tcp")]
 use pigdef::description::TCP_MDNS_SERVICE_TYPE;
+#[cfg(feature = "tls")]
+use pigdef::tls::Fingerprint;
 use piggpio::local_hardware;
 use pignet::discovery::DiscoveredDevice;
 #[cfg(any(feature = "tcp", feature = "usb"))]
@@ -34,7 +36,7 @@ use pignet::HardwareConnection;
 use std::collections::HashMap;
 #[cfg(feature = "tcp")]
 use std::net::IpAddr;
-#[cfg(all(feature = "tcp", feature = "iroh"))]
+#[cfg(all(feature = "tcp", any(feature = "iroh", feature = "tls")))]
 use std::str::FromStr;
 #[cfg(feature = "usb")]
 use std::time::Duration;
@@ -142,6 +144,22 @@ fn device_from_service_info(info: &ResolvedService) -> anyhow::Result<Discovered
         HardwareConnection::Tcp(IpAddr::V4(ip), port),
     );
 
+    // The certificate is pinned the first time it is connected to with this fingerprint
+    #[cfg(feature = "tls")]
+    if let (Some(tls_port), Some(fingerprint)) = (
+        device_properties.get_property_val_str("TlsPort"),
+        device_properties.get_property_val_str("TlsFingerprint"),
+    ) {
+        hardware_connections.insert(
+            "TLS".to_string(),
+            HardwareConnection::Tls(
+                IpAddr::V4(ip),
+                u16::from_str(tls_port)?,
+                Fingerprint::from_str(fingerprint)?,
+            ),
+        );
+    }
+
     #[cfg(feature = "iroh")]
     if let Some(endpoint_id_str) = device_properties.get_property_val_str("IrohNodeID") {
         if let Ok(endpoint_id) = EndpointId::from_str(endpoint_id_str) {
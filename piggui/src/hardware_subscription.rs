@@ -1,9 +1,20 @@
 use futures::channel::mpsc::Sender;
 use std::fmt;
+use std::time::Duration;
+#[cfg(any(feature = "iroh", feature = "tcp", feature = "usb"))]
+use std::time::SystemTime;
 
+#[cfg(feature = "tcp")]
+use pigdef::auth::PairingCode;
+#[cfg(any(feature = "iroh", feature = "tcp", feature = "usb"))]
+use pigdef::config::HardwareConfigMessage::{
+    AnalogSampled, DutyCycleChanged, IOLevelChanged, NewConfig, NewPinConfig, Ping, Pong,
+    SafeLevelChanged,
+};
 #[cfg(any(feature = "iroh", feature = "tcp", feature = "usb"))]
-use pigdef::config::HardwareConfigMessage::IOLevelChanged;
-use pigdef::config::{HardwareConfig, HardwareConfigMessage, LevelChange};
+use pigdef::config::HEARTBEAT_INTERVAL_MS;
+use pigdef::config::MISSED_HEARTBEATS;
+use pigdef::config::{AnalogSample, HardwareConfig, HardwareConfigMessage, LevelChange};
 
 #[cfg(feature = "iroh")]
 use crate::hardware_subscription::HWState::ConnectedIroh;
@@ -22,6 +33,9 @@ use crate::hardware_subscription::HWState::Disconnected;
 ))]
 use crate::hardware_subscription::SubscriberMessage::Hardware;
 use crate::hardware_subscription::SubscriberMessage::NewConnection;
+#[cfg(feature = "tcp")]
+use crate::hardware_subscription::SubscriberMessage::Pair;
+use crate::hardware_subscription::SubscriberMessage::Reconnect;
 #[cfg(any(feature = "iroh", feature = "tcp", feature = "usb"))]
 use crate::hardware_subscription::SubscriptionEvent::InputChange;
 #[cfg(not(target_arch = "wasm32"))]
@@ -38,15 +52,24 @@ use iced::stream;
 #[cfg(any(feature = "iroh", feature = "tcp", feature = "usb"))]
 use iced::{futures, futures::pin_mut};
 #[cfg(feature = "iroh")]
-use iroh::{endpoint::Connection, Endpoint};
+use iroh::{endpoint::Connection, Endpoint, SecretKey};
 #[cfg(feature = "usb")]
 use log::info;
-use pigdef::description::BCMPinNumber;
 use pigdef::description::HardwareDescription;
+use pigdef::description::{BCMPinNumber, PinLevel};
+use pigdef::hello::Capabilities;
+use pigdef::i2c::I2cResponse;
+use pigdef::pin_function::PinFunction;
+use pigdef::spi::SpiResponse;
+use pigdef::uart::UartBytes;
+#[cfg(feature = "tcp")]
+use pignet::credentials::Credentials;
 #[cfg(feature = "iroh")]
 use pignet::iroh_host;
 #[cfg(feature = "tcp")]
 use pignet::tcp_host;
+#[cfg(feature = "tls")]
+use pignet::tls_host;
 #[cfg(feature = "usb")]
 use pignet::usb_host;
 #[cfg(feature = "usb")]
@@ -57,6 +80,8 @@ use pignet::HardwareConnection::Iroh;
 use pignet::HardwareConnection::NoConnection;
 #[cfg(feature = "tcp")]
 use pignet::HardwareConnection::Tcp;
+#[cfg(feature = "tls")]
+use pignet::HardwareConnection::Tls;
 #[cfg(feature = "usb")]
 use pignet::HardwareConnection::Usb;
 
@@ -66,6 +91,119 @@ pub enum SubscriberMessage {
     NewConnection(HardwareConnection),
     /// A message type to change the configuration of the connected hardware
     Hardware(HardwareConfigMessage),
+    /// Pair using this code with the next device connected to over TCP that requires it
+    #[cfg(feature = "tcp")]
+    Pair(PairingCode),
+    /// Detect that the connection to a device is lost, and reconnect to it, using this
+    /// [ReconnectPolicy]
+    Reconnect(ReconnectPolicy),
+}
+
+/// The delay before the first attempt to reconnect to a device, later ones double it
+const FIRST_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// How to detect that the connection to a device is lost, and reconnect to it after that
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReconnectPolicy {
+    /// The number of attempts made to reconnect before giving up, zero to not reconnect
+    pub attempts: u32,
+    /// The longest delay between attempts
+    pub max_delay: Duration,
+    /// The number of heartbeats a device can miss before the connection to it is lost
+    pub missed_heartbeats: u32,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 10,
+            max_delay: Duration::from_secs(30),
+            missed_heartbeats: MISSED_HEARTBEATS,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// The delay before reconnection `attempt`, counting from one, that doubles with each
+    /// attempt up to `max_delay`
+    pub fn delay(&self, attempt: u32) -> Duration {
+        FIRST_RECONNECT_DELAY
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay)
+    }
+}
+
+/// The interval between heartbeats sent to a connected device
+#[cfg(any(feature = "iroh", feature = "tcp", feature = "usb"))]
+const HEARTBEAT_INTERVAL: Duration = Duration::from_millis(HEARTBEAT_INTERVAL_MS);
+
+/// Heartbeats sent to a connected device that advertises [Capabilities::HEARTBEAT], to measure
+/// the round-trip latency of the link to it, and detect when the link is lost without the
+/// connection being closed
+#[cfg(any(feature = "iroh", feature = "tcp", feature = "usb"))]
+struct Heartbeat {
+    /// Heartbeats are only sent to devices that answer them
+    enabled: bool,
+    /// The sequence number of the last [Ping] sent
+    sequence: u32,
+    /// When the last [Ping] was sent, or the connection made if none has been
+    sent_at: SystemTime,
+    /// The number of [Ping]s sent since a [Pong] was last received
+    missed: u32,
+}
+
+#[cfg(any(feature = "iroh", feature = "tcp", feature = "usb"))]
+impl Heartbeat {
+    /// Start sending heartbeats on a new connection to a device with `capabilities`
+    fn new(capabilities: Capabilities) -> Self {
+        Self {
+            enabled: capabilities.contains(Capabilities::HEARTBEAT),
+            sequence: 0,
+            sent_at: SystemTime::now(),
+            missed: 0,
+        }
+    }
+
+    /// The time until the next [Ping] is due, or None if heartbeats are not sent
+    fn until_due(&self) -> Option<Duration> {
+        self.enabled.then(|| {
+            (self.sent_at + HEARTBEAT_INTERVAL)
+                .duration_since(SystemTime::now())
+                .unwrap_or_default()
+        })
+    }
+
+    /// Return the next [Ping] to send, or None if `missed_heartbeats` have already been sent
+    /// without a reply, and the link to the device is lost
+    fn next_ping(&mut self, missed_heartbeats: u32) -> Option<HardwareConfigMessage> {
+        if self.missed >= missed_heartbeats {
+            return None;
+        }
+        self.missed += 1;
+        self.sequence = self.sequence.wrapping_add(1);
+        self.sent_at = SystemTime::now();
+        Some(Ping(self.sequence))
+    }
+
+    /// A [Pong] with `sequence` was received, so the link is alive. Return the round-trip
+    /// latency if it is the reply to the last [Ping] sent
+    fn pong(&mut self, sequence: u32) -> Option<Duration> {
+        self.missed = 0;
+        if sequence == self.sequence {
+            self.sent_at.elapsed().ok()
+        } else {
+            None
+        }
+    }
+}
+
+/// Wait for `until_due`, the time until the next heartbeat is due, or forever if it is None
+#[cfg(any(feature = "iroh", feature = "tcp", feature = "usb"))]
+async fn heartbeat_due(until_due: Option<Duration>) {
+    match until_due {
+        Some(delay) => async_std::task::sleep(delay).await,
+        None => futures::future::pending().await,
+    }
 }
 
 /// This enum is for async events in the hardware that will be sent to the GUI
@@ -76,9 +214,34 @@ pub enum SubscriptionEvent {
     Ready(Sender<SubscriberMessage>),
     /// This event indicates that the listener is ready. It conveys a sender to the GUI
     /// that it should use to send ConfigEvents to the listener, such as an Input pin added.
-    Connected(HardwareDescription, HardwareConfig),
+    /// The [Capabilities] are the optional features of the device that can be used.
+    Connected(HardwareDescription, HardwareConfig, Capabilities),
+    /// The connection to the device was lost, and this attempt to reconnect to it will be made
+    /// after the delay
+    Reconnecting(u32, Duration),
+    /// The device was reconnected to after the connection to it was lost. The [HardwareConfig]
+    /// is the device's, which may differ from the one in the GUI
+    Reconnected(HardwareDescription, HardwareConfig, Capabilities),
     /// This event indicates that the logic level of an input has just changed
     InputChange(BCMPinNumber, LevelChange),
+    /// This event indicates that a new sample of the value of an analog input has been taken
+    AnalogInputChange(BCMPinNumber, AnalogSample),
+    /// The response from the device to an I2C request made by this client
+    I2cResponse(I2cResponse),
+    /// The response from the device to an SPI transfer made by this client
+    SpiResponse(SpiResponse),
+    /// Bytes received by the device's UART
+    UartReceived(UartBytes),
+    /// Another client connected to the same device has changed the duty cycle of a PWM output
+    DutyCycleChange(BCMPinNumber, f64),
+    /// Another client connected to the same device has set, or cleared, the safe level of an output
+    SafeLevelChange(BCMPinNumber, Option<PinLevel>),
+    /// Another client connected to the same device has applied a new config to it
+    NewConfig(HardwareConfig),
+    /// Another client connected to the same device has changed the config of a pin
+    NewPinConfig(BCMPinNumber, Option<PinFunction>),
+    /// The round-trip latency of the link to the device, measured with a heartbeat
+    Latency(Duration),
     /// There was an error in the connection to the hardware
     ConnectionError(String),
 }
@@ -99,7 +262,7 @@ enum HWState {
     ConnectedIroh(Connection, Endpoint),
     #[cfg(feature = "tcp")]
     /// The subscription is ready and will listen for config events on the channel contained
-    ConnectedTcp(async_std::net::TcpStream),
+    ConnectedTcp(pignet::tcp_host::TcpConnection),
 }
 
 impl fmt::Display for HWState {
@@ -126,6 +289,117 @@ async fn report_error(gui_sender: &mut Sender<SubscriptionEvent>, e: &str) {
         .unwrap_or_else(|e| eprintln!("{e}"));
 }
 
+/// Tell the GUI that `attempt` to reconnect to the device will be made after `delay`
+async fn report_reconnecting(
+    gui_sender: &mut Sender<SubscriptionEvent>,
+    attempt: u32,
+    delay: Duration,
+) {
+    gui_sender
+        .send(SubscriptionEvent::Reconnecting(attempt, delay))
+        .await
+        .unwrap_or_else(|e| eprintln!("{e}"));
+}
+
+/// An attempt to connect to `target` failed with `error`. If reconnecting to it, and attempts
+/// remain, then try again later, otherwise report the error and forget `target`
+async fn connect_failed(
+    gui_sender: &mut Sender<SubscriptionEvent>,
+    target: &mut HardwareConnection,
+    reconnect_attempt: &mut u32,
+    policy: &ReconnectPolicy,
+    error: &str,
+) {
+    if *reconnect_attempt == 0 || *reconnect_attempt >= policy.attempts {
+        *reconnect_attempt = 0;
+        *target = NoConnection;
+        report_error(gui_sender, error).await;
+    } else {
+        *reconnect_attempt += 1;
+        report_reconnecting(
+            gui_sender,
+            *reconnect_attempt,
+            policy.delay(*reconnect_attempt),
+        )
+        .await;
+    }
+}
+
+#[cfg(any(feature = "iroh", feature = "tcp", feature = "usb"))]
+/// The connection to `target` was lost with `error`. Start reconnecting to it, unless the
+/// [ReconnectPolicy] says not to, in which case report the error and forget `target`
+async fn connection_lost(
+    gui_sender: &mut Sender<SubscriptionEvent>,
+    target: &mut HardwareConnection,
+    reconnect_attempt: &mut u32,
+    policy: &ReconnectPolicy,
+    error: &str,
+) {
+    if policy.attempts == 0 {
+        *target = NoConnection;
+        report_error(gui_sender, error).await;
+    } else {
+        *reconnect_attempt = 1;
+        report_reconnecting(gui_sender, 1, policy.delay(1)).await;
+    }
+}
+
+/// The event to send to the GUI on connecting to a device, which is
+/// [SubscriptionEvent::Reconnected] if reconnecting to it after the connection was lost
+fn connected(
+    reconnect_attempt: u32,
+    hardware_description: HardwareDescription,
+    hardware_config: HardwareConfig,
+    capabilities: Capabilities,
+) -> SubscriptionEvent {
+    if reconnect_attempt == 0 {
+        SubscriptionEvent::Connected(hardware_description, hardware_config, capabilities)
+    } else {
+        SubscriptionEvent::Reconnected(hardware_description, hardware_config, capabilities)
+    }
+}
+
+#[cfg(any(feature = "iroh", feature = "tcp", feature = "usb"))]
+/// Forward a message received from the remote hardware to the GUI, replies to heartbeats
+/// being forwarded as the latency they measured
+async fn forward_remote_event(
+    gui_sender: &mut Sender<SubscriptionEvent>,
+    heartbeat: &mut Heartbeat,
+    remote_event: anyhow::Result<HardwareConfigMessage>,
+) {
+    let event = match remote_event {
+        Ok(Pong(sequence)) => match heartbeat.pong(sequence) {
+            Some(latency) => SubscriptionEvent::Latency(latency),
+            None => return,
+        },
+        Ok(IOLevelChanged(bcm, level_change)) => InputChange(bcm, level_change),
+        Ok(AnalogSampled(bcm, sample)) => SubscriptionEvent::AnalogInputChange(bcm, sample),
+        Ok(NewConfig(config)) => SubscriptionEvent::NewConfig(config),
+        Ok(NewPinConfig(bcm, pin_function)) => SubscriptionEvent::NewPinConfig(bcm, pin_function),
+        Ok(DutyCycleChanged(bcm, duty_cycle)) => {
+            SubscriptionEvent::DutyCycleChange(bcm, duty_cycle)
+        }
+        Ok(SafeLevelChanged(bcm, level)) => SubscriptionEvent::SafeLevelChange(bcm, level),
+        Ok(HardwareConfigMessage::I2cResponse(response)) => {
+            SubscriptionEvent::I2cResponse(response)
+        }
+        Ok(HardwareConfigMessage::SpiResponse(response)) => {
+            SubscriptionEvent::SpiResponse(response)
+        }
+        Ok(HardwareConfigMessage::UartReceived(bytes)) => SubscriptionEvent::UartReceived(bytes),
+        // Sequences are started by other clients, piggui does not show their state
+        Ok(HardwareConfigMessage::SequenceStatus(..)) => return,
+        _ => {
+            report_error(gui_sender, "Hardware event error").await;
+            return;
+        }
+    };
+
+    if let Err(e) = gui_sender.send(event).await {
+        report_error(gui_sender, &format!("Hardware error: {e}")).await;
+    }
+}
+
 /// `subscribe` implements an async sender of events from inputs, reading from the hardware and
 /// forwarding to the GUI
 pub fn subscribe() -> impl Stream<Item = SubscriptionEvent> {
@@ -135,6 +409,26 @@ pub fn subscribe() -> impl Stream<Item = SubscriptionEvent> {
         move |mut gui_sender: Sender<SubscriptionEvent>| async move {
             let mut state = Disconnected;
             let mut target = NoConnection;
+            let mut reconnect_policy = ReconnectPolicy::default();
+            // The number of the attempt being made to reconnect to `target` after losing the
+            // connection to it, zero when not reconnecting
+            let mut reconnect_attempt: u32 = 0;
+            // Heartbeats sent to the connected device, if it is remote and answers them
+            #[cfg(any(feature = "iroh", feature = "tcp", feature = "usb"))]
+            let mut heartbeat = Heartbeat::new(Capabilities::NONE);
+            // Keys of devices paired with over TCP, to authenticate with them when connecting
+            #[cfg(feature = "tcp")]
+            let mut credentials = Credentials::load_default().unwrap_or_else(|e| {
+                eprintln!("Could not load TCP credentials: {e}");
+                Credentials::default()
+            });
+            // The key identifying this client to devices that only allow some clients over Iroh,
+            // one is made up for this run if it cannot be saved, as in the browser
+            #[cfg(feature = "iroh")]
+            let iroh_key = iroh_host::load_default_client_key().unwrap_or_else(|e| {
+                eprintln!("Could not load Iroh client key: {e}");
+                SecretKey::generate()
+            });
 
             let (subscriber_sender, mut subscriber_receiver) =
                 mpsc::channel::<SubscriberMessage>(100);
@@ -158,13 +452,41 @@ pub fn subscribe() -> impl Stream<Item = SubscriptionEvent> {
 
                 match &mut state {
                     Disconnected => {
+                        // Wait before each attempt to reconnect, unless asked to connect elsewhere.
+                        // Config changes made meanwhile are reconciled on reconnecting
+                        #[cfg(any(feature = "iroh", feature = "tcp", feature = "usb"))]
+                        if reconnect_attempt > 0 {
+                            let delay =
+                                async_std::task::sleep(reconnect_policy.delay(reconnect_attempt))
+                                    .fuse();
+                            pin_mut!(delay);
+                            loop {
+                                futures::select! {
+                                    message = subscriber_receiver.next() => match message {
+                                        Some(NewConnection(new_target)) => {
+                                            target = new_target;
+                                            reconnect_attempt = 0;
+                                            break;
+                                        }
+                                        Some(Reconnect(policy)) => reconnect_policy = policy,
+                                        #[cfg(feature = "tcp")]
+                                        Some(Pair(code)) => credentials.set_pairing_code(code),
+                                        _ => {}
+                                    },
+                                    _ = delay => break,
+                                }
+                            }
+                        }
+
                         match target.clone() {
                             NoConnection => {
                                 // Wait for a message from the UI to request that we connect to a new target
-                                if let Some(NewConnection(new_target)) =
-                                    subscriber_receiver.next().await
-                                {
-                                    target = new_target;
+                                match subscriber_receiver.next().await {
+                                    Some(NewConnection(new_target)) => target = new_target,
+                                    #[cfg(feature = "tcp")]
+                                    Some(Pair(code)) => credentials.set_pairing_code(code),
+                                    Some(Reconnect(policy)) => reconnect_policy = policy,
+                                    _ => {}
                                 }
                             }
 
@@ -173,9 +495,11 @@ pub fn subscribe() -> impl Stream<Item = SubscriptionEvent> {
                                 match local_host::connect().await {
                                     Ok((hardware_description, hardware_config, local_hardware)) => {
                                         if let Err(e) = gui_sender_clone
-                                            .send(SubscriptionEvent::Connected(
+                                            .send(connected(
+                                                std::mem::take(&mut reconnect_attempt),
                                                 hardware_description,
                                                 hardware_config,
+                                                piggpio::CAPABILITIES,
                                                 // TODO send filename loaded from?
                                             ))
                                             .await
@@ -193,10 +517,11 @@ pub fn subscribe() -> impl Stream<Item = SubscriptionEvent> {
                                         }
                                     }
                                     Err(e) => {
-                                        state = Disconnected;
-                                        target = NoConnection;
-                                        report_error(
+                                        connect_failed(
                                             &mut gui_sender_clone,
+                                            &mut target,
+                                            &mut reconnect_attempt,
+                                            &reconnect_policy,
                                             &format!("LocalHW error: {e}"),
                                         )
                                         .await
@@ -209,9 +534,11 @@ pub fn subscribe() -> impl Stream<Item = SubscriptionEvent> {
                                 match usb_host::connect(&serial).await {
                                     Ok((hardware_description, hardware_config, connection)) => {
                                         if let Err(e) = gui_sender_clone
-                                            .send(SubscriptionEvent::Connected(
+                                            .send(connected(
+                                                std::mem::take(&mut reconnect_attempt),
                                                 hardware_description.clone(),
                                                 hardware_config,
+                                                connection.capabilities(),
                                             ))
                                             .await
                                         {
@@ -224,14 +551,16 @@ pub fn subscribe() -> impl Stream<Item = SubscriptionEvent> {
                                             .await;
                                         } else {
                                             // We are ready to receive messages from the GUI and send messages to it
+                                            heartbeat = Heartbeat::new(connection.capabilities());
                                             state = ConnectedUsb(connection);
                                         }
                                     }
                                     Err(e) => {
-                                        state = Disconnected;
-                                        target = NoConnection;
-                                        report_error(
+                                        connect_failed(
                                             &mut gui_sender_clone,
+                                            &mut target,
+                                            &mut reconnect_attempt,
+                                            &reconnect_policy,
                                             &format!("USB error: {e}"),
                                         )
                                         .await
@@ -241,18 +570,23 @@ pub fn subscribe() -> impl Stream<Item = SubscriptionEvent> {
 
                             #[cfg(feature = "iroh")]
                             Iroh(endpoint_id, relay) => {
-                                match iroh_host::connect(&endpoint_id, &relay).await {
+                                match iroh_host::connect(&endpoint_id, &relay, iroh_key.clone())
+                                    .await
+                                {
                                     Ok((
                                         hardware_description,
                                         hardware_config,
                                         connection,
                                         endpoint,
+                                        capabilities,
                                     )) => {
                                         // Send the sender back to the GUI
                                         if let Err(e) = gui_sender_clone
-                                            .send(SubscriptionEvent::Connected(
+                                            .send(connected(
+                                                std::mem::take(&mut reconnect_attempt),
                                                 hardware_description.clone(),
                                                 hardware_config,
+                                                capabilities,
                                             ))
                                             .await
                                         {
@@ -265,14 +599,16 @@ pub fn subscribe() -> impl Stream<Item = SubscriptionEvent> {
                                             .await;
                                         } else {
                                             // We are ready to receive messages from the GUI
+                                            heartbeat = Heartbeat::new(capabilities);
                                             state = ConnectedIroh(connection, endpoint);
                                         }
                                     }
                                     Err(e) => {
-                                        state = Disconnected;
-                                        target = NoConnection;
-                                        report_error(
+                                        connect_failed(
                                             &mut gui_sender_clone,
+                                            &mut target,
+                                            &mut reconnect_attempt,
+                                            &reconnect_policy,
                                             &format!("Iroh error: {e}"),
                                         )
                                         .await
@@ -283,13 +619,15 @@ pub fn subscribe() -> impl Stream<Item = SubscriptionEvent> {
                             #[cfg(feature = "tcp")]
                             Tcp(ip, port) => {
                                 // jonesy:allow(bounds) bounds check inside pignet::tcp_host deserialization
-                                match tcp_host::connect(ip, port).await {
+                                match tcp_host::connect(ip, port, &mut credentials).await {
                                     Ok((hardware_description, hardware_config, stream)) => {
                                         // Send the stream back to the GUI
                                         if let Err(e) = gui_sender_clone
-                                            .send(SubscriptionEvent::Connected(
+                                            .send(connected(
+                                                std::mem::take(&mut reconnect_attempt),
                                                 hardware_description.clone(),
                                                 hardware_config,
+                                                stream.capabilities(),
                                             ))
                                             .await
                                         {
@@ -302,20 +640,65 @@ pub fn subscribe() -> impl Stream<Item = SubscriptionEvent> {
                                             .await;
                                         } else {
                                             // We are ready to receive messages from the GUI
+                                            heartbeat = Heartbeat::new(stream.capabilities());
                                             state = ConnectedTcp(stream);
                                         }
                                     }
                                     Err(e) => {
-                                        state = Disconnected;
-                                        target = NoConnection;
-                                        report_error(
+                                        connect_failed(
                                             &mut gui_sender_clone,
+                                            &mut target,
+                                            &mut reconnect_attempt,
+                                            &reconnect_policy,
                                             &format!("TCP error: {e}"),
                                         )
                                         .await
                                     }
                                 }
                             }
+
+                            #[cfg(feature = "tls")]
+                            Tls(ip, port, fingerprint) => {
+                                // Once encrypted, the connection is used in the same way as TCP
+                                match tls_host::connect(ip, port, fingerprint, &mut credentials)
+                                    .await
+                                {
+                                    Ok((hardware_description, hardware_config, stream)) => {
+                                        // Send the stream back to the GUI
+                                        if let Err(e) = gui_sender_clone
+                                            .send(connected(
+                                                std::mem::take(&mut reconnect_attempt),
+                                                hardware_description.clone(),
+                                                hardware_config,
+                                                stream.capabilities(),
+                                            ))
+                                            .await
+                                        {
+                                            state = Disconnected;
+                                            target = NoConnection;
+                                            report_error(
+                                                &mut gui_sender_clone,
+                                                &format!("Send error: {e}"),
+                                            )
+                                            .await;
+                                        } else {
+                                            // We are ready to receive messages from the GUI
+                                            heartbeat = Heartbeat::new(stream.capabilities());
+                                            state = ConnectedTcp(stream);
+                                        }
+                                    }
+                                    Err(e) => {
+                                        connect_failed(
+                                            &mut gui_sender_clone,
+                                            &mut target,
+                                            &mut reconnect_attempt,
+                                            &reconnect_policy,
+                                            &format!("TLS error: {e}"),
+                                        )
+                                        .await
+                                    }
+                                }
+                            }
                         }
                     }
 
@@ -351,6 +734,9 @@ pub fn subscribe() -> impl Stream<Item = SubscriptionEvent> {
                                         .await;
                                     }
                                 }
+                                #[cfg(feature = "tcp")]
+                                Pair(code) => credentials.set_pairing_code(*code),
+                                Reconnect(policy) => reconnect_policy = *policy,
                             }
                         }
                     }
@@ -383,6 +769,9 @@ pub fn subscribe() -> impl Stream<Item = SubscriptionEvent> {
                                                     .await;
                                             }
                                         }
+                                        #[cfg(feature = "tcp")]
+                                        Pair(code) => credentials.set_pairing_code(*code),
+                                        Reconnect(policy) => reconnect_policy = *policy,
                                     }
                                 }
                             }
@@ -392,17 +781,48 @@ pub fn subscribe() -> impl Stream<Item = SubscriptionEvent> {
                                 // jonesy:allow(invalid_enum) deserialized enum from USB device
                                 info!("Remote Hw event Message received via USB: {remote_event:?}");
                                 match remote_event {
-                                     Ok(IOLevelChanged(bcm, level_change)) => {
-                                        if let Err(e) = gui_sender_clone.send(InputChange(bcm, level_change)).await {
-                                                report_error(&mut gui_sender_clone, &format!("Hardware error: {e}"))
-                                                    .await;
+                                    Err(e) => {
+                                        state = Disconnected;
+                                        connection_lost(
+                                            &mut gui_sender_clone,
+                                            &mut target,
+                                            &mut reconnect_attempt,
+                                            &reconnect_policy,
+                                            &format!("USB error: {e}"),
+                                        )
+                                        .await;
+                                    }
+                                    event => {
+                                        forward_remote_event(&mut gui_sender_clone, &mut heartbeat, event)
+                                            .await
+                                    }
+                                }
+                            }
+
+                            // send a heartbeat to the device, unless it has missed too many
+                            _ = heartbeat_due(heartbeat.until_due()).fuse() => {
+                                match heartbeat.next_ping(reconnect_policy.missed_heartbeats) {
+                                    Some(ping) => {
+                                        if let Err(e) = usb_host::send_config_message(connection, &ping).await {
+                                            report_error(&mut gui_sender_clone, &format!("USB error: {e}"))
+                                                .await;
                                         }
-                                    },
-                                    _ => {
-                                        report_error(&mut gui_sender_clone, "Hardware event error")
-                                                    .await;
                                     }
-                                 }
+                                    None => {
+                                        state = Disconnected;
+                                        connection_lost(
+                                            &mut gui_sender_clone,
+                                            &mut target,
+                                            &mut reconnect_attempt,
+                                            &reconnect_policy,
+                                            &format!(
+                                                "USB error: Missed {} heartbeats",
+                                                reconnect_policy.missed_heartbeats
+                                            ),
+                                        )
+                                        .await;
+                                    }
+                                }
                             }
                         }
                     }
@@ -435,6 +855,9 @@ pub fn subscribe() -> impl Stream<Item = SubscriptionEvent> {
                                                     .await;
                                             }
                                         }
+                                        #[cfg(feature = "tcp")]
+                                        Pair(code) => credentials.set_pairing_code(*code),
+                                        Reconnect(policy) => reconnect_policy = *policy,
                                     }
                                 }
                             }
@@ -442,15 +865,46 @@ pub fn subscribe() -> impl Stream<Item = SubscriptionEvent> {
                             // receive an input level change from remote hardware
                             remote_event = fused_wait_for_remote_message => {
                                 match remote_event {
-                                    Ok(IOLevelChanged(bcm, level_change)) => {
-                                        if let Err(e) = gui_sender_clone.send(InputChange(bcm, level_change)).await {
-                                                report_error(&mut gui_sender_clone, &format!("Hardware error: {e}"))
-                                                    .await;
+                                    Err(e) => {
+                                        state = Disconnected;
+                                        connection_lost(
+                                            &mut gui_sender_clone,
+                                            &mut target,
+                                            &mut reconnect_attempt,
+                                            &reconnect_policy,
+                                            &format!("Iroh error: {e}"),
+                                        )
+                                        .await;
+                                    }
+                                    event => {
+                                        forward_remote_event(&mut gui_sender_clone, &mut heartbeat, event)
+                                            .await
+                                    }
+                                }
+                            }
+
+                            // send a heartbeat to the device, unless it has missed too many
+                            _ = heartbeat_due(heartbeat.until_due()).fuse() => {
+                                match heartbeat.next_ping(reconnect_policy.missed_heartbeats) {
+                                    Some(ping) => {
+                                        if let Err(e) = iroh_host::send_config_message(connection, &ping).await {
+                                            report_error(&mut gui_sender_clone, &format!("Iroh error: {e}"))
+                                                .await;
                                         }
                                     }
-                                    _ => {
-                                        report_error(&mut gui_sender_clone, "Hardware event error")
-                                                    .await;
+                                    None => {
+                                        state = Disconnected;
+                                        connection_lost(
+                                            &mut gui_sender_clone,
+                                            &mut target,
+                                            &mut reconnect_attempt,
+                                            &reconnect_policy,
+                                            &format!(
+                                                "Iroh error: Missed {} heartbeats",
+                                                reconnect_policy.missed_heartbeats
+                                            ),
+                                        )
+                                        .await;
                                     }
                                 }
                             }
@@ -484,6 +938,9 @@ pub fn subscribe() -> impl Stream<Item = SubscriptionEvent> {
                                                     .await;
                                             }
                                         }
+                                        #[cfg(feature = "tcp")]
+                                        Pair(code) => credentials.set_pairing_code(*code),
+                                        Reconnect(policy) => reconnect_policy = *policy,
                                     }
                                 }
                             }
@@ -491,17 +948,48 @@ pub fn subscribe() -> impl Stream<Item = SubscriptionEvent> {
                             // receive an input level change from remote hardware
                             remote_event = fused_wait_for_remote_message => {
                                 match remote_event {
-                                    Ok(IOLevelChanged(bcm, level_change)) => {
-                                        if let Err(e) = gui_sender_clone.send(InputChange(bcm, level_change)).await {
-                                            report_error(&mut gui_sender_clone, &format!("Hardware error: {e}"))
+                                    Err(e) => {
+                                        state = Disconnected;
+                                        connection_lost(
+                                            &mut gui_sender_clone,
+                                            &mut target,
+                                            &mut reconnect_attempt,
+                                            &reconnect_policy,
+                                            &format!("Tcp error: {e}"),
+                                        )
+                                        .await;
+                                    }
+                                    event => {
+                                        forward_remote_event(&mut gui_sender_clone, &mut heartbeat, event)
+                                            .await
+                                    }
+                                }
+                            }
+
+                            // send a heartbeat to the device, unless it has missed too many
+                            _ = heartbeat_due(heartbeat.until_due()).fuse() => {
+                                match heartbeat.next_ping(reconnect_policy.missed_heartbeats) {
+                                    Some(ping) => {
+                                        if let Err(e) = tcp_host::send_config_message(stream.clone(), &ping).await {
+                                            report_error(&mut gui_sender_clone, &format!("Tcp error: {e}"))
                                                 .await;
                                         }
                                     }
-                                    _ => {
-                                        report_error(&mut gui_sender_clone, "Hardware event error")
-                                                    .await;
+                                    None => {
+                                        state = Disconnected;
+                                        connection_lost(
+                                            &mut gui_sender_clone,
+                                            &mut target,
+                                            &mut reconnect_attempt,
+                                            &reconnect_policy,
+                                            &format!(
+                                                "Tcp error: Missed {} heartbeats",
+                                                reconnect_policy.missed_heartbeats
+                                            ),
+                                        )
+                                        .await;
                                     }
-                                 }
+                                }
                             }
                         }
                     }
@@ -510,3 +998,44 @@ pub fn subscribe() -> impl Stream<Item = SubscriptionEvent> {
         },
     )
 }
+
+#[cfg(test)]
+mod test {
+    use super::ReconnectPolicy;
+    use std::time::Duration;
+
+    #[test]
+    fn reconnect_delay_doubles_up_to_max() {
+        let policy = ReconnectPolicy {
+            attempts: 10,
+            max_delay: Duration::from_secs(10),
+            ..ReconnectPolicy::default()
+        };
+        assert_eq!(policy.delay(1), Duration::from_secs(1));
+        assert_eq!(policy.delay(2), Duration::from_secs(2));
+        assert_eq!(policy.delay(4), Duration::from_secs(8));
+        assert_eq!(policy.delay(5), Duration::from_secs(10));
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(10));
+    }
+
+    #[cfg(any(feature = "iroh", feature = "tcp", feature = "usb"))]
+    #[test]
+    fn link_lost_after_missed_heartbeats() {
+        use super::Heartbeat;
+        use pigdef::config::HardwareConfigMessage::Ping;
+        use pigdef::hello::Capabilities;
+
+        assert_eq!(Heartbeat::new(Capabilities::NONE).until_due(), None);
+        let mut heartbeat = Heartbeat::new(Capabilities::HEARTBEAT);
+        assert!(heartbeat.until_due().is_some());
+        assert!(matches!(heartbeat.next_ping(2), Some(Ping(1))));
+        assert!(matches!(heartbeat.next_ping(2), Some(Ping(2))));
+        // A late reply to an earlier heartbeat shows the link is alive, but not its latency
+        assert_eq!(heartbeat.pong(1), None);
+        assert!(matches!(heartbeat.next_ping(2), Some(Ping(3))));
+        assert!(heartbeat.pong(3).is_some());
+        assert!(matches!(heartbeat.next_ping(2), Some(Ping(4))));
+        assert!(matches!(heartbeat.next_ping(2), Some(Ping(5))));
+        assert!(heartbeat.next_ping(2).is_none());
+    }
+}
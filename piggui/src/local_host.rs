@@ -1,13 +1,17 @@
 use crate::hardware_subscription::SubscriptionEvent;
-use crate::hardware_subscription::SubscriptionEvent::InputChange;
+use crate::hardware_subscription::SubscriptionEvent::{AnalogInputChange, InputChange};
 use anyhow::{anyhow, Error};
 use iced::futures::channel::mpsc::Sender;
 use log::{info, trace};
-use pigdef::config::HardwareConfigMessage::{IOLevelChanged, NewConfig, NewPinConfig};
+use pigdef::config::HardwareConfigMessage::{
+    AnalogSampled, DutyCycleChanged, IOLevelChanged, NewConfig, NewPinConfig,
+};
 use pigdef::config::{HardwareConfig, HardwareConfigMessage, LevelChange};
 use pigdef::description::HardwareDescription;
 use pigdef::description::{BCMPinNumber, PinLevel};
+use pigdef::i2c::I2cResponse;
 use pigdef::pin_function::PinFunction;
+use pigdef::spi::SpiResponse;
 use piggpio::config::{get_config, store_config, CONFIG_FILENAME};
 use piggpio::get_hardware;
 use piggpio::HW;
@@ -84,15 +88,19 @@ async fn send_input_level_async(
     Ok(())
 }
 
-/// Send a detected input level change back to the GUI using `connection` [Connection],
-/// timestamping with the current time in Utc
-fn send_input_level(
+/// Send an input event detected by the hardware, either an input level change, a new sample
+/// of an analog input or bytes received by the UART, back to the GUI
+fn send_input_event(
     mut gui_sender_clone: Sender<SubscriptionEvent>,
-    bcm: BCMPinNumber,
-    level_change: LevelChange,
+    input_event: HardwareConfigMessage,
 ) -> Result<(), Error> {
-    trace!("Pin #{bcm} Input level change: {level_change:?}");
-    let hardware_event = InputChange(bcm, level_change);
+    trace!("Input event: {input_event:?}");
+    let hardware_event = match input_event {
+        IOLevelChanged(bcm, level_change) => InputChange(bcm, level_change),
+        AnalogSampled(bcm, sample) => AnalogInputChange(bcm, sample),
+        HardwareConfigMessage::UartReceived(bytes) => SubscriptionEvent::UartReceived(bytes),
+        _ => return Ok(()),
+    };
     // jonesy:allow(invalid_enum) enum variant sent through async channel
     gui_sender_clone.try_send(hardware_event)?;
     Ok(())
@@ -102,7 +110,7 @@ fn send_input_level(
 pub async fn apply_config_change(
     local: &mut LocalConnection,
     config_change: &HardwareConfigMessage,
-    gui_sender: Sender<SubscriptionEvent>,
+    mut gui_sender: Sender<SubscriptionEvent>,
 ) -> Result<(), Error> {
     match config_change {
         NewConfig(config) => {
@@ -110,9 +118,9 @@ pub async fn apply_config_change(
             let gui_sender_clone = gui_sender.clone();
             local
                 .hw
-                // jonesy:allow(invalid_enum) callback sends enum through channel via send_input_level
-                .apply_config(config, move |bcm_pin_number, level_change| {
-                    let _ = send_input_level(gui_sender.clone(), bcm_pin_number, level_change);
+                // jonesy:allow(invalid_enum) callback sends enum through channel via send_input_event
+                .apply_config(config, move |input_event| {
+                    let _ = send_input_event(gui_sender.clone(), input_event);
                 })
                 .await?;
 
@@ -128,9 +136,9 @@ pub async fn apply_config_change(
             let gui_sender_clone = gui_sender.clone();
             local
                 .hw
-                // jonesy:allow(invalid_enum) callback sends enum through channel via send_input_level
-                .apply_pin_config(*bcm, pin_function, move |bcm_pin_number, level_change| {
-                    let _ = send_input_level(gui_sender.clone(), bcm_pin_number, level_change);
+                // jonesy:allow(invalid_enum) callback sends enum through channel via send_input_event
+                .apply_pin_config(*bcm, pin_function, move |input_event| {
+                    let _ = send_input_event(gui_sender.clone(), input_event);
                 })
                 .await?;
 
@@ -151,6 +159,47 @@ pub async fn apply_config_change(
             // jonesy:allow(invalid_enum) piggpio hardware call with enum discriminant
             local.hw.set_output_level(*bcm, level_change.new_level)?;
         }
+        DutyCycleChanged(bcm, duty_cycle) => {
+            trace!("Local hardware pin #{bcm} duty cycle changed: {duty_cycle}");
+            local.hw.set_duty_cycle(*bcm, *duty_cycle)?;
+        }
+        HardwareConfigMessage::I2cRequest(request) => {
+            trace!("Local hardware I2C request: {request:?}");
+            // Errors are shown to the user in the response, not returned
+            let response = local
+                .hw
+                .i2c_request(request)
+                .unwrap_or_else(|e| I2cResponse::Error(e.to_string()));
+            gui_sender.try_send(SubscriptionEvent::I2cResponse(response))?;
+        }
+        HardwareConfigMessage::SpiRequest(request) => {
+            trace!("Local hardware SPI request: {request:?}");
+            let response = local
+                .hw
+                .spi_request(request)
+                .unwrap_or_else(|e| SpiResponse::Error(e.to_string()));
+            gui_sender.try_send(SubscriptionEvent::SpiResponse(response))?;
+        }
+        HardwareConfigMessage::UartSend(bytes) => {
+            trace!("Local hardware UART send: {bytes:?}");
+            local.hw.uart_send(bytes)?;
+        }
+        AnalogSampled(..)
+        | HardwareConfigMessage::I2cResponse(..)
+        | HardwareConfigMessage::SpiResponse(..)
+        | HardwareConfigMessage::UartReceived(..)
+        | HardwareConfigMessage::SequenceStatus(..) => {}
+        // Safe levels are applied by pigglet when clients disconnect, not to local hardware
+        HardwareConfigMessage::SafeLevelChanged(..) => {}
+        // Sequences and schedules are run by pigglet and porky, not on local hardware
+        HardwareConfigMessage::StartSequence(..)
+        | HardwareConfigMessage::StartSchedule(..)
+        | HardwareConfigMessage::StopSequence(..)
+        | HardwareConfigMessage::GetSequenceStatus(..) => {}
+        // Local hardware has no clients other than piggui
+        HardwareConfigMessage::GetClientCounts | HardwareConfigMessage::ClientCounts(..) => {}
+        // Local hardware is always there, so there is no link to check with heartbeats
+        HardwareConfigMessage::Ping(..) | HardwareConfigMessage::Pong(..) => {}
         HardwareConfigMessage::GetConfig => {}
         HardwareConfigMessage::Disconnect => {}
     }
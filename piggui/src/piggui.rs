@@ -2,6 +2,8 @@
 #![deny(clippy::expect_used)]
 
 use crate::file_helper::{maybe_load_no_picker, pick_and_load, save};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::hardware_subscription::ReconnectPolicy;
 #[cfg(any(feature = "iroh", feature = "tcp"))]
 use crate::views::connect_dialog::{
     ConnectDialog, ConnectDialogMessage, ConnectDialogMessage::HideConnectDialog,
@@ -12,7 +14,7 @@ use crate::views::info_row::InfoRow;
 use crate::views::layout_menu::{Layout, LayoutSelector};
 #[cfg(not(target_arch = "wasm32"))]
 use crate::views::message_box::InfoMessage;
-use crate::views::message_box::InfoMessage::{Error, Info};
+use crate::views::message_box::InfoMessage::{Error, Info, Warning};
 use crate::views::message_box::MessageRowMessage;
 #[cfg(not(target_arch = "wasm32"))]
 use crate::views::message_box::MessageRowMessage::ShowStatusMessage;
@@ -30,6 +32,8 @@ use iced::widget::{container, Column};
 use iced::{window, Element, Length, Pixels, Settings, Subscription, Task, Theme};
 #[cfg(feature = "iroh")]
 use iroh::{EndpointId, RelayUrl};
+#[cfg(feature = "tcp")]
+use pigdef::auth::PairingCode;
 use pigdef::config::HardwareConfig;
 #[cfg(feature = "usb")]
 use pigdef::description::SerialNumber;
@@ -47,6 +51,7 @@ use std::collections::HashMap;
 use std::process;
 #[cfg(any(feature = "iroh", feature = "tcp"))]
 use std::str::FromStr;
+use std::time::Duration;
 #[cfg(not(target_arch = "wasm32"))]
 use sysinfo::{Process, System};
 
@@ -83,7 +88,12 @@ pub enum Message {
     #[cfg(any(feature = "iroh", feature = "tcp"))]
     ConnectDialog(ConnectDialogMessage),
     ConnectRequest(HardwareConnection),
+    #[cfg(feature = "tcp")]
+    PairRequest(PairingCode, HardwareConnection),
     Connected,
+    /// The connection to the device was lost, and this attempt to reconnect will be made after
+    /// the delay
+    Reconnecting(u32, Duration),
     Disconnect,
     ConnectionError(String),
     MenuBarButtonClicked,
@@ -215,6 +225,11 @@ impl Piggui {
         #[cfg(feature = "discovery")]
         let discovered_devices = discovery::local_discovery(local_hardware_option);
 
+        #[allow(unused_mut)]
+        let mut hardware_view = HardwareView::new(requested_connection);
+        #[cfg(not(target_arch = "wasm32"))]
+        hardware_view.set_reconnect_policy(reconnect_policy(&matches));
+
         (
             Self {
                 config_filename,
@@ -222,7 +237,7 @@ impl Piggui {
                 unsaved_changes: false,
                 info_row: InfoRow::new(),
                 modal_handler: InfoDialog::new(),
-                hardware_view: HardwareView::new(requested_connection),
+                hardware_view,
                 #[cfg(any(feature = "iroh", feature = "tcp"))]
                 connect_dialog: ConnectDialog::new(),
                 #[cfg(feature = "discovery")]
@@ -334,6 +349,13 @@ impl Piggui {
                 self.hardware_view.new_connection(new_connection);
             }
 
+            #[cfg(feature = "tcp")]
+            PairRequest(code, new_connection) => {
+                self.connect_dialog.disable_widgets_and_load_spinner();
+                self.hardware_view.pair(code);
+                self.hardware_view.new_connection(new_connection);
+            }
+
             Connected => {
                 #[cfg(any(feature = "iroh", feature = "tcp"))]
                 self.connect_dialog.enable_widgets_and_hide_spinner();
@@ -347,6 +369,15 @@ impl Piggui {
                 return self.window_size_change_request();
             }
 
+            Reconnecting(attempt, delay) => {
+                self.info_row.add_info_message(Warning(format!(
+                    "Connection lost, reconnecting in {}s (attempt {attempt})",
+                    delay.as_secs()
+                )));
+                #[cfg(debug_assertions)] // Output used in testing - DON'T REMOVE
+                println!("Reconnecting to hardware");
+            }
+
             ConnectionError(details) => {
                 #[cfg(any(feature = "iroh", feature = "tcp"))]
                 self.connect_dialog.enable_widgets_and_hide_spinner();
@@ -661,6 +692,23 @@ fn parse_ip_string(ip_str: &str) -> anyhow::Result<HardwareConnection> {
     Ok(HardwareConnection::Tcp(ip, port))
 }
 
+#[cfg(not(target_arch = "wasm32"))]
+/// Determine how to detect the connection to a device is lost, and reconnect to it after that,
+/// from command line options
+fn reconnect_policy(matches: &ArgMatches) -> ReconnectPolicy {
+    let mut policy = ReconnectPolicy::default();
+    if let Some(attempts) = matches.get_one::<u32>("reconnect_attempts") {
+        policy.attempts = *attempts;
+    }
+    if let Some(max_delay) = matches.get_one::<u64>("reconnect_max_delay") {
+        policy.max_delay = Duration::from_secs(*max_delay);
+    }
+    if let Some(missed_heartbeats) = matches.get_one::<u32>("missed_heartbeats") {
+        policy.missed_heartbeats = *missed_heartbeats;
+    }
+    policy
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 /// Parse the command line arguments using clap
 fn get_matches() -> ArgMatches {
@@ -675,6 +723,33 @@ fn get_matches() -> ArgMatches {
                 .number_of_values(1)
                 .value_name("Config File")
                 .help("Path of a '.pigg' config file to load"),
+        )
+        .arg(
+            Arg::new("reconnect_attempts")
+                .long("reconnect_attempts")
+                .num_args(1)
+                .number_of_values(1)
+                .value_name("ATTEMPTS")
+                .value_parser(clap::value_parser!(u32))
+                .help("Attempts to reconnect to a device after the connection to it is lost, 0 to not reconnect. Default is 10"),
+        )
+        .arg(
+            Arg::new("reconnect_max_delay")
+                .long("reconnect_max_delay")
+                .num_args(1)
+                .number_of_values(1)
+                .value_name("SECONDS")
+                .value_parser(clap::value_parser!(u64))
+                .help("Longest delay between attempts to reconnect, which doubles from one second. Default is 30"),
+        )
+        .arg(
+            Arg::new("missed_heartbeats")
+                .long("missed_heartbeats")
+                .num_args(1)
+                .number_of_values(1)
+                .value_name("COUNT")
+                .value_parser(clap::value_parser!(u32).range(1..))
+                .help("Heartbeats a device can miss before the connection to it is lost. Default is 3"),
         );
 
     #[cfg(feature = "iroh")]
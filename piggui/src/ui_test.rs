@@ -40,7 +40,9 @@ fn test_piggui_connected() -> Piggui {
     let _ = app
         .hardware_view
         .update(SubscriptionMessage(SubscriptionEvent::Connected(
-            hw_desc, hw_config,
+            hw_desc,
+            hw_config,
+            piggpio::CAPABILITIES,
         )));
     let _ = app.update(Message::Connected);
     app
@@ -112,6 +114,28 @@ fn pin_set_to_output() {
     ));
 }
 
+#[test]
+fn output_changed_by_other_client_updates_config() {
+    let mut app = test_piggui_connected();
+    let bcm_pin = 2;
+    let _ = app.update(Hardware(PinFunctionChanged(
+        bcm_pin,
+        Some(Output(Some(false))),
+        false,
+        true,
+    )));
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let _ = app.update(Hardware(SubscriptionMessage(
+        SubscriptionEvent::InputChange(bcm_pin, LevelChange::new(true, now)),
+    )));
+    assert_eq!(
+        app.hardware_view.get_config().pin_functions.get(&bcm_pin),
+        Some(&Output(Some(true)))
+    );
+}
+
 #[test]
 fn output_toggle_changes_value() {
     let mut app = test_piggui_connected();
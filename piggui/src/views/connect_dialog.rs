@@ -4,11 +4,14 @@ use self::ConnectDialogMessage::{
 };
 #[cfg(feature = "tcp")]
 use self::ConnectDialogMessage::{
-    ConnectionButtonPressedTcp, DisplayTcpTab, IpAddressEntered, PortNumberEntered,
+    ConnectionButtonPressedTcp, DisplayTcpTab, IpAddressEntered, PairingCodeEntered,
+    PortNumberEntered,
 };
 use self::ConnectDialogMessage::{
     ConnectionError, HideConnectDialog, ModalKeyEvent, ShowConnectDialog,
 };
+#[cfg(feature = "tcp")]
+use pigdef::auth::PairingCode;
 #[cfg(feature = "iroh")]
 use pignet::HardwareConnection::Iroh;
 #[cfg(feature = "tcp")]
@@ -31,7 +34,7 @@ use std::time::Duration;
 #[cfg(feature = "iroh")]
 const IROH_INFO_TEXT: &str = "To connect to a Pi using iroh-net, ensure pigglet is running on the remote Pi. Retrieve the endpoint_id from pigglet, enter it below, and optionally provide a Relay URL";
 #[cfg(feature = "tcp")]
-const TCP_INFO_TEXT: &str = "To connect to a Pi/Pi Pico using TCP, ensure it is reachable over the network. Retrieve the device's IP address and the port number from it (see pigglet or porky docs) and enter below.";
+const TCP_INFO_TEXT: &str = "To connect to a Pi/Pi Pico using TCP, ensure it is reachable over the network. Retrieve the device's IP address and the port number from it (see pigglet or porky docs) and enter below. The first time you connect to a device, also enter the pairing code it shows.";
 
 use crate::views::dialog_styles::{
     cancel_button, connect_button, ACTIVE_TAB_BUTTON_STYLE, CONNECTION_ERROR_DISPLAY,
@@ -59,6 +62,8 @@ pub struct ConnectDialog {
     #[cfg(feature = "tcp")]
     port_number: String,
     #[cfg(feature = "tcp")]
+    pairing_code: String,
+    #[cfg(feature = "tcp")]
     tcp_connection_error: String,
     #[cfg(feature = "iroh")]
     iroh_connection_error: String,
@@ -90,6 +95,8 @@ pub enum ConnectDialogMessage {
     IpAddressEntered(String),
     #[cfg(feature = "tcp")]
     PortNumberEntered(String),
+    #[cfg(feature = "tcp")]
+    PairingCodeEntered(String),
 }
 impl Default for ConnectDialog {
     fn default() -> Self {
@@ -116,6 +123,8 @@ impl ConnectDialog {
             ip_address: String::new(),
             #[cfg(feature = "tcp")]
             port_number: String::new(),
+            #[cfg(feature = "tcp")]
+            pairing_code: String::new(),
         }
     }
 
@@ -156,12 +165,30 @@ impl ConnectDialog {
                         // Validate port number
                         match port_num.trim().parse::<u16>() {
                             Ok(port) => {
-                                self.tcp_connection_error.clear();
+                                // Pair with the device if a pairing code was entered
+                                let pairing_code = self.pairing_code.trim();
+                                if pairing_code.is_empty() {
+                                    self.tcp_connection_error.clear();
+                                    return Task::perform(Self::empty(), move |_| {
+                                        Message::ConnectRequest(Tcp(ip, port))
+                                    });
+                                }
 
-                                // Proceed to request connection when the port number is valid
-                                Task::perform(Self::empty(), move |_| {
-                                    Message::ConnectRequest(Tcp(ip, port))
-                                })
+                                match PairingCode::parse(pairing_code) {
+                                    Some(code) => {
+                                        self.tcp_connection_error.clear();
+                                        Task::perform(Self::empty(), move |_| {
+                                            Message::PairRequest(code, Tcp(ip, port))
+                                        })
+                                    }
+                                    None => {
+                                        self.tcp_connection_error =
+                                            String::from("Invalid Pairing Code");
+                                        self.show_spinner = false;
+                                        self.disable_widgets = false;
+                                        Task::none()
+                                    }
+                                }
                             }
                             Err(e) => {
                                 self.tcp_connection_error = format!("Invalid Port Number: {e}");
@@ -288,6 +315,12 @@ impl ConnectDialog {
                 Task::none()
             }
 
+            #[cfg(feature = "tcp")]
+            PairingCodeEntered(code) => {
+                self.pairing_code = code;
+                Task::none()
+            }
+
             #[cfg(feature = "iroh")]
             NodeIdEntered(node_id) => {
                 self.endpoint_id = node_id;
@@ -331,6 +364,8 @@ impl ConnectDialog {
         self.iroh_connection_error.clear(); // Clear the error, on Cancel
         #[cfg(feature = "iroh")]
         self.relay_url.clear(); // Clear the relay url, on Cancel
+        #[cfg(feature = "tcp")]
+        self.pairing_code.clear(); // Clear the pairing code, as it can only be used once
         self.show_spinner = false; // Hide spinner, on Cancel
         self.disable_widgets = false; // Enable widgets, on Cancel
     }
@@ -546,6 +581,22 @@ impl ConnectDialog {
                         port_input
                     }]
                     .spacing(5),
+                    column![text("Pairing Code (Optional)").size(12), {
+                        let mut code_input =
+                            text_input("Enter Pairing Code (Optional)", &self.pairing_code)
+                                .padding(5)
+                                .on_submit(Message::ConnectDialog(ConnectionButtonPressedTcp(
+                                    self.ip_address.clone(),
+                                    self.port_number.clone(),
+                                )));
+                        if !self.disable_widgets {
+                            code_input = code_input.on_input(|input| {
+                                Message::ConnectDialog(PairingCodeEntered(input))
+                            });
+                        }
+                        code_input
+                    }]
+                    .spacing(5),
                     self.create_connection_row_tcp(),
                 ]
                 .spacing(10)
@@ -762,6 +813,18 @@ mod tests {
         );
     }
 
+    #[cfg(feature = "tcp")]
+    #[test]
+    fn test_connection_button_pressed_tcp_invalid_pairing_code() {
+        let mut connect_dialog = ConnectDialog::new();
+        let _ = connect_dialog.update(PairingCodeEntered("not-a-code".to_string()));
+        let _ = connect_dialog.update(ConnectionButtonPressedTcp(
+            "192.168.1.1".to_string(),
+            "8080".to_string(),
+        ));
+        assert_eq!(connect_dialog.tcp_connection_error, "Invalid Pairing Code");
+    }
+
     #[cfg(feature = "tcp")]
     #[test]
     fn test_connection_button_pressed_tcp_valid_ip_and_port() {
@@ -50,11 +50,15 @@ pub fn view<'a>(hardware_view: &'a HardwareView) -> Item<'a, Message, Theme, Ren
         #[cfg(any(feature = "iroh", feature = "tcp", not(target_arch = "wasm32")))]
         menu_items.push(disconnect);
 
-        format!(
-            "{}: {}",
-            hardware_view.get_hardware_connection().name(),
-            hardware_description.details.model.as_str()
-        )
+        if hardware_view.is_reconnecting() {
+            "reconnecting...".to_string()
+        } else {
+            format!(
+                "{}: {}",
+                hardware_view.get_hardware_connection().name(),
+                hardware_description.details.model.as_str()
+            )
+        }
     } else {
         "connecting...".to_string()
     };
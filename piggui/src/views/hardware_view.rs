@@ -1,14 +1,19 @@
 use crate::hardware_subscription;
 use crate::hardware_subscription::SubscriberMessage::Hardware;
-use crate::hardware_subscription::{SubscriberMessage, SubscriptionEvent};
+use crate::hardware_subscription::{ReconnectPolicy, SubscriberMessage, SubscriptionEvent};
 use crate::views::hardware_styles::{get_pin_style, toggler_style, TOOLTIP_STYLE};
 use crate::views::hardware_view::HardwareViewMessage::{
-    Activate, ChangeOutputLevel, MenuBarButtonClicked, NewConfig, PinFunctionChanged,
-    SubscriptionMessage, UpdateCharts,
+    Activate, ChangeDutyCycle, ChangeOutputLevel, ChangeSafeLevel, DutyCycleSet, I2c,
+    KeepDeviceConfig, KeepGuiConfig, MenuBarButtonClicked, NewConfig, PinFunctionChanged, Spi,
+    SubscriptionMessage, Uart, UpdateCharts,
 };
+use crate::views::i2c_panel::{I2cPanel, I2cPanelMessage};
+use crate::views::info_dialog::InfoDialogMessage::ConfigConflictModal;
 use crate::views::info_row::{menu_button_style, INFO_ROW_HEIGHT};
 use crate::views::layout_menu::Layout;
 use crate::views::pin_state::{PinState, CHART_UPDATES_PER_SECOND, CHART_WIDTH};
+use crate::views::spi_panel::{SpiPanel, SpiPanelMessage};
+use crate::views::uart_panel::{UartPanel, UartPanelMessage};
 use crate::widgets::led::led;
 use crate::widgets::{circle::circle, line::line};
 use crate::Message;
@@ -16,7 +21,9 @@ use iced::advanced::text::editor::Direction::{Left, Right};
 use iced::futures::channel::mpsc::Sender;
 use iced::widget::scrollable::Scrollbar;
 use iced::widget::tooltip::Position;
-use iced::widget::{button, row, scrollable, space, text, toggler, Button, Column, Row, Text};
+use iced::widget::{
+    button, row, scrollable, slider, space, text, toggler, Button, Column, Row, Text,
+};
 use iced::widget::{container, Tooltip};
 use iced::Alignment::{End, Start};
 use iced::{alignment, Alignment, Center, Element, Fill, Length, Size, Task};
@@ -24,13 +31,22 @@ use iced::{Renderer, Theme};
 use iced_aw::menu::Item;
 use iced_aw::{Menu, MenuBar};
 use iced_futures::Subscription;
+#[cfg(feature = "tcp")]
+use pigdef::auth::PairingCode;
 use pigdef::config::InputPull::{PullDown, PullUp};
 use pigdef::config::LevelChange;
 use pigdef::config::{HardwareConfig, HardwareConfigMessage};
 use pigdef::description::{BCMPinNumber, BoardPinNumber, PinLevel};
 use pigdef::description::{HardwareDescription, PinDescription, PinDescriptionSet};
+use pigdef::hello::Capabilities;
 use pigdef::pin_function::PinFunction;
-use pigdef::pin_function::PinFunction::{Input, Output};
+use pigdef::pin_function::PinFunction::{
+    AnalogInput, Input, Output, Pwm, I2C1_SCL, I2C1_SDA, SPI0_MISO, SPI0_MOSI, SPI0_SCLK,
+    UART0_RXD, UART0_TXD,
+};
+use pigdef::pin_function::PWM_FREQUENCIES;
+use pigdef::spi::{SpiChipSelect, SpiConfig, SpiMode, SPI_CLOCK_SPEEDS};
+use pigdef::uart::{UartConfig, UartParity, UartStopBits, UART_BAUD_RATES};
 use std::collections::HashMap;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
@@ -61,6 +77,7 @@ const PIN_WIDGET_ROW_WIDTH: f32 =
     (LED_RADIUS * 2.0) + WIDGET_ROW_SPACING + CHART_WIDTH + WIDGET_ROW_SPACING + TOGGLER_WIDTH;
 
 const LED_RADIUS: f32 = 14.0;
+const DUTY_CYCLE_TEXT_WIDTH: f32 = 40.0;
 
 pub(crate) const fn board_layout_size(_number_of_pins: usize) -> Size {
     Size {
@@ -111,7 +128,20 @@ pub enum HardwareViewMessage {
     NewConfig(HardwareConfig),
     SubscriptionMessage(SubscriptionEvent),
     ChangeOutputLevel(BCMPinNumber, LevelChange),
+    /// The level an output is driven to when the client disconnects was selected, or cleared
+    ChangeSafeLevel(BCMPinNumber, Option<PinLevel>),
+    /// The duty cycle slider of a PWM output is being dragged
+    ChangeDutyCycle(BCMPinNumber, f64),
+    /// The duty cycle slider of a PWM output was released, so the new duty cycle is sent
+    DutyCycleSet(BCMPinNumber),
+    I2c(I2cPanelMessage),
+    Spi(SpiPanelMessage),
+    Uart(UartPanelMessage),
     UpdateCharts,
+    /// After reconnecting, the device's config was chosen over the one in the GUI
+    KeepDeviceConfig(HardwareConfig),
+    /// After reconnecting, the config in the GUI was chosen over the device's, so is applied to it
+    KeepGuiConfig,
     MenuBarButtonClicked, // needed for highlighting to work
 }
 
@@ -120,8 +150,19 @@ pub struct HardwareView {
     hardware_config: HardwareConfig,
     subscriber_sender: Option<Sender<SubscriberMessage>>,
     hardware_description: Option<HardwareDescription>,
+    /// The optional features of the connected device, pin functions needing others are hidden
+    capabilities: Capabilities,
     /// Either the desired state of an output or the detected state of input
     pin_states: HashMap<BCMPinNumber, PinState>,
+    /// How the subscription reconnects to the device when the connection to it is lost
+    reconnect_policy: ReconnectPolicy,
+    /// True while reconnecting to the device, the description and pin states are kept meanwhile
+    reconnecting: bool,
+    /// The round-trip latency of the link to the device, if it answers heartbeats
+    latency: Option<Duration>,
+    i2c_panel: I2cPanel,
+    spi_panel: SpiPanel,
+    uart_panel: UartPanel,
 }
 
 async fn empty() {}
@@ -133,8 +174,15 @@ impl HardwareView {
             hardware_connection,
             hardware_config: HardwareConfig::default(),
             hardware_description: None, // Until the listener is ready
-            subscriber_sender: None,    // Until the listener is ready
+            capabilities: Capabilities::NONE,
+            subscriber_sender: None, // Until the listener is ready
             pin_states: HashMap::new(),
+            reconnect_policy: ReconnectPolicy::default(),
+            reconnecting: false,
+            latency: None,
+            i2c_panel: I2cPanel::default(),
+            spi_panel: SpiPanel::default(),
+            uart_panel: UartPanel::default(),
         }
     }
 
@@ -156,6 +204,26 @@ impl HardwareView {
         &self.hardware_connection
     }
 
+    /// Return true while reconnecting to the device after the connection to it was lost
+    #[must_use]
+    pub fn is_reconnecting(&self) -> bool {
+        self.reconnecting
+    }
+
+    /// Return the last measured round-trip latency of the link to the device, if known
+    #[must_use]
+    pub fn latency(&self) -> Option<Duration> {
+        self.latency
+    }
+
+    /// Set how to detect the connection to the device is lost, and reconnect to it
+    pub fn set_reconnect_policy(&mut self, reconnect_policy: ReconnectPolicy) {
+        self.reconnect_policy = reconnect_policy;
+        if let Some(ref mut subscription_sender) = &mut self.subscriber_sender {
+            let _ = subscription_sender.try_send(SubscriberMessage::Reconnect(reconnect_policy));
+        }
+    }
+
     /// Apply the [HardwareConfig] active here to the GPIO hardware
     // TODO this might cause a re-apply of same config coming _from_ the hardware?
     fn update_hw_config(&mut self) {
@@ -169,6 +237,12 @@ impl HardwareView {
     /// Send a message to request the subscription to switch connections to a new one
     pub fn new_connection(&mut self, new_connection: HardwareConnection) {
         self.hardware_description = None;
+        self.capabilities = Capabilities::NONE;
+        self.reconnecting = false;
+        self.latency = None;
+        self.i2c_panel = I2cPanel::default();
+        self.spi_panel = SpiPanel::default();
+        self.uart_panel = UartPanel::default();
         self.hardware_connection = new_connection;
         if let Some(ref mut subscription_sender) = &mut self.subscriber_sender {
             let _ = subscription_sender.try_send(SubscriberMessage::NewConnection(
@@ -177,6 +251,15 @@ impl HardwareView {
         }
     }
 
+    /// Send a message to request the subscription to pair using `code` with the next device
+    /// it connects to over TCP
+    #[cfg(feature = "tcp")]
+    pub fn pair(&mut self, code: PairingCode) {
+        if let Some(ref mut subscription_sender) = &mut self.subscriber_sender {
+            let _ = subscription_sender.try_send(SubscriberMessage::Pair(code));
+        }
+    }
+
     /// A new function has been selected for a pin via the UI, this function:
     /// - updates the pin_selected_function array for the UI
     /// - saves it in the gpio_config, so when we save later it's there
@@ -251,12 +334,77 @@ impl HardwareView {
         }
     }
 
+    /// Return true if both pins of the I2C bus are configured, so the bus can be used
+    fn i2c_configured(&self) -> bool {
+        let functions: Vec<&PinFunction> = self.hardware_config.pin_functions.values().collect();
+        functions.contains(&&I2C1_SDA) && functions.contains(&&I2C1_SCL)
+    }
+
+    /// Return true if all pins of the SPI bus are configured, so the bus can be used
+    fn spi_configured(&self) -> bool {
+        let functions: Vec<&PinFunction> = self.hardware_config.pin_functions.values().collect();
+        functions.contains(&&SPI0_MOSI)
+            && functions.contains(&&SPI0_MISO)
+            && functions
+                .iter()
+                .any(|function| matches!(function, SPI0_SCLK(_)))
+    }
+
+    /// Return true if both pins of the UART are configured, so the UART can be used
+    fn uart_configured(&self) -> bool {
+        let functions: Vec<&PinFunction> = self.hardware_config.pin_functions.values().collect();
+        functions.contains(&&UART0_RXD)
+            && functions
+                .iter()
+                .any(|function| matches!(function, UART0_TXD(_)))
+    }
+
+    /// Return true if the connected device supports configuring a pin with `pin_function`
+    fn supports(&self, pin_function: &PinFunction) -> bool {
+        self.capabilities.contains(pin_function.capability())
+    }
+
+    /// Show the new level of a pin reported by the hardware and, if it is an output changed by
+    /// another client, record the new level in the config so the toggler and config agree
+    fn level_changed(&mut self, bcm_pin_number: BCMPinNumber, level_change: LevelChange) {
+        if let Some(Output(level)) = self.hardware_config.pin_functions.get_mut(&bcm_pin_number) {
+            *level = Some(level_change.new_level);
+        }
+        self.pin_states
+            .entry(bcm_pin_number)
+            .or_insert(PinState::new())
+            .set_level(level_change);
+    }
+
+    /// Set the safe level of an output in the config, or clear it if `None`
+    fn set_safe_level(&mut self, bcm_pin_number: BCMPinNumber, level: Option<PinLevel>) {
+        match level {
+            Some(level) => self
+                .hardware_config
+                .safe_levels
+                .insert(bcm_pin_number, level),
+            None => self.hardware_config.safe_levels.remove(&bcm_pin_number),
+        };
+    }
+
+    /// Update the duty cycle of a PWM output in the config, keeping its frequency
+    fn set_duty_cycle(&mut self, bcm_pin_number: BCMPinNumber, new_duty_cycle: f64) {
+        if let Some(Pwm { duty_cycle, .. }) =
+            self.hardware_config.pin_functions.get_mut(&bcm_pin_number)
+        {
+            *duty_cycle = new_duty_cycle;
+        }
+    }
+
     pub fn update(&mut self, message: HardwareViewMessage) -> Task<Message> {
         match message {
             UpdateCharts => {
                 // Update all the charts of the pins that have an assigned function
                 for pin in self.pin_states.values_mut() {
                     pin.chart.refresh();
+                    if let Some(analog_chart) = pin.analog_chart.as_mut() {
+                        analog_chart.refresh();
+                    }
                 }
             }
 
@@ -274,26 +422,94 @@ impl HardwareView {
             }
 
             SubscriptionMessage(event) => match event {
-                SubscriptionEvent::Connected(hw_desc, hw_config) => {
+                SubscriptionEvent::Connected(hw_desc, hw_config, capabilities) => {
                     self.hardware_description = Some(hw_desc);
+                    self.capabilities = capabilities;
                     self.hardware_config = hw_config;
                     // jonesy:allow(expect) propagates through SystemTime::now and chrono::Utc::now internal expects
                     self.set_pin_states_after_load();
                     self.update_hw_config();
                     return Task::perform(empty(), |_| Message::Connected);
                 }
+                // The description and pin states are kept, so the waveforms survive reconnecting
+                SubscriptionEvent::Reconnecting(attempt, delay) => {
+                    self.reconnecting = true;
+                    self.latency = None;
+                    return Task::perform(empty(), move |_| Message::Reconnecting(attempt, delay));
+                }
+                // Ask which config to keep if the device's differs from the one in the GUI
+                SubscriptionEvent::Reconnected(hw_desc, hw_config, capabilities) => {
+                    self.reconnecting = false;
+                    self.hardware_description = Some(hw_desc);
+                    self.capabilities = capabilities;
+                    let connected = Task::perform(empty(), |_| Message::Connected);
+                    if hw_config == self.hardware_config {
+                        return connected;
+                    }
+                    let ask = Task::perform(empty(), move |_| {
+                        Message::Modal(ConfigConflictModal(hw_config.clone()))
+                    });
+                    return Task::batch(vec![connected, ask]);
+                }
+                // This is also how a change to an output made by another client is reported
                 SubscriptionEvent::InputChange(bcm_pin_number, level_change) => {
+                    self.level_changed(bcm_pin_number, level_change);
+                }
+                SubscriptionEvent::AnalogInputChange(bcm_pin_number, sample) => {
                     self.pin_states
                         .entry(bcm_pin_number)
                         .or_insert(PinState::new())
-                        .set_level(level_change);
+                        .set_analog_value(sample);
+                }
+                SubscriptionEvent::I2cResponse(response) => {
+                    self.i2c_panel.response(response);
+                }
+                SubscriptionEvent::SpiResponse(response) => {
+                    self.spi_panel.response(response);
+                }
+                SubscriptionEvent::UartReceived(bytes) => {
+                    self.uart_panel.received(&bytes);
+                }
+                // Changes made by other clients are shown, but not sent back to the hardware
+                SubscriptionEvent::DutyCycleChange(bcm_pin_number, duty_cycle) => {
+                    self.set_duty_cycle(bcm_pin_number, duty_cycle);
+                }
+                SubscriptionEvent::SafeLevelChange(bcm_pin_number, level) => {
+                    self.set_safe_level(bcm_pin_number, level);
+                }
+                SubscriptionEvent::NewConfig(hw_config) => {
+                    self.hardware_config = hw_config;
+                    self.pin_states.clear();
+                    self.set_pin_states_after_load();
+                    return Task::perform(empty(), |_| Message::ConfigChangesMade(true, true));
+                }
+                SubscriptionEvent::NewPinConfig(bcm_pin_number, pin_function) => {
+                    match pin_function {
+                        None => {
+                            self.hardware_config.pin_functions.remove(&bcm_pin_number);
+                            self.pin_states.remove(&bcm_pin_number);
+                        }
+                        Some(function) => {
+                            self.hardware_config
+                                .pin_functions
+                                .insert(bcm_pin_number, function);
+                            self.pin_states.insert(bcm_pin_number, PinState::new());
+                        }
+                    }
+                    return Task::perform(empty(), |_| Message::ConfigChangesMade(true, true));
+                }
+                SubscriptionEvent::Latency(latency) => {
+                    self.latency = Some(latency);
                 }
                 SubscriptionEvent::ConnectionError(error) => {
+                    self.latency = None;
                     return Task::perform(empty(), move |_| {
                         Message::ConnectionError(error.clone())
                     });
                 }
                 SubscriptionEvent::Ready(mut subscriber_sender) => {
+                    let _ = subscriber_sender
+                        .try_send(SubscriberMessage::Reconnect(self.reconnect_policy));
                     let _ = subscriber_sender.try_send(SubscriberMessage::NewConnection(
                         self.hardware_connection.clone(),
                     ));
@@ -314,6 +530,76 @@ impl HardwareView {
                 }
             }
 
+            ChangeSafeLevel(bcm_pin_number, level) => {
+                self.set_safe_level(bcm_pin_number, level);
+                if let Some(ref mut listener) = &mut self.subscriber_sender {
+                    let _ = listener.try_send(Hardware(HardwareConfigMessage::SafeLevelChanged(
+                        bcm_pin_number,
+                        level,
+                    )));
+                }
+                let mark_unsaved = self.hardware_connection != Local;
+                return Task::perform(empty(), move |_| {
+                    Message::ConfigChangesMade(false, mark_unsaved)
+                });
+            }
+
+            // Only the slider is updated while it is dragged, to not flood the device with changes
+            ChangeDutyCycle(bcm_pin_number, duty_cycle) => {
+                self.set_duty_cycle(bcm_pin_number, duty_cycle);
+            }
+
+            DutyCycleSet(bcm_pin_number) => {
+                if let Some(Pwm { duty_cycle, .. }) =
+                    self.hardware_config.pin_functions.get(&bcm_pin_number)
+                {
+                    if let Some(ref mut listener) = &mut self.subscriber_sender {
+                        let _ = listener.try_send(Hardware(
+                            HardwareConfigMessage::DutyCycleChanged(bcm_pin_number, *duty_cycle),
+                        ));
+                    }
+                }
+            }
+
+            I2c(panel_message) => {
+                if let Some(request) = self.i2c_panel.update(panel_message) {
+                    if let Some(ref mut listener) = &mut self.subscriber_sender {
+                        let _ =
+                            listener.try_send(Hardware(HardwareConfigMessage::I2cRequest(request)));
+                    }
+                }
+            }
+
+            Spi(panel_message) => {
+                if let Some(request) = self.spi_panel.update(panel_message) {
+                    if let Some(ref mut listener) = &mut self.subscriber_sender {
+                        let _ =
+                            listener.try_send(Hardware(HardwareConfigMessage::SpiRequest(request)));
+                    }
+                }
+            }
+
+            Uart(panel_message) => {
+                if let Some(bytes) = self.uart_panel.update(panel_message) {
+                    if let Some(ref mut listener) = &mut self.subscriber_sender {
+                        let _ = listener.try_send(Hardware(HardwareConfigMessage::UartSend(bytes)));
+                    }
+                }
+            }
+
+            KeepDeviceConfig(config) => {
+                // Keep the states, and so waveforms, of pins whose function did not change
+                self.pin_states.retain(|bcm_pin_number, _| {
+                    config.pin_functions.get(bcm_pin_number)
+                        == self.hardware_config.pin_functions.get(bcm_pin_number)
+                });
+                self.hardware_config = config;
+                self.set_pin_states_after_load();
+                return Task::perform(empty(), |_| Message::ConfigChangesMade(true, true));
+            }
+
+            KeepGuiConfig => self.update_hw_config(),
+
             Activate(pin_number) => println!("Pin {pin_number} clicked"),
             MenuBarButtonClicked => { /* For highlighting */ }
         }
@@ -332,6 +618,30 @@ impl HardwareView {
                     Layout::Compact => self.compact_layout_view(&hw_description.pins),
                 };
 
+                // Show the panels to use the I2C and SPI buses and the UART below the pins,
+                // once configured
+                let mut panels = vec![];
+                if self.i2c_configured() {
+                    panels.push(self.i2c_panel.view());
+                }
+                if self.spi_configured() {
+                    panels.push(self.spi_panel.view());
+                }
+                if self.uart_configured() {
+                    panels.push(self.uart_panel.view());
+                }
+                let pin_layout = if panels.is_empty() {
+                    pin_layout
+                } else {
+                    panels
+                        .into_iter()
+                        .fold(Column::new().push(pin_layout), |column, panel| {
+                            column.push(panel)
+                        })
+                        .spacing(SPACE_BETWEEN_PIN_ROWS * 2.0)
+                        .into()
+                };
+
                 scrollable(pin_layout)
                     .direction({
                         let scrollbar = Scrollbar::new().width(HARDWARE_VIEW_PADDING);
@@ -581,7 +891,12 @@ impl HardwareView {
     ) -> Item<'a, HardwareViewMessage, Theme, Renderer> {
         let mut pin_menu_items: Vec<Item<HardwareViewMessage, _, _>> = vec![];
         if let Some(bcm_pin_number) = pin_description.bcm {
-            for option in pin_description.options.iter() {
+            // Functions the device does not support are not offered
+            for option in pin_description
+                .options
+                .iter()
+                .filter(|option| self.supports(option))
+            {
                 match option {
                     Input(_) => {
                         let mut pullup_items = vec![];
@@ -637,6 +952,116 @@ impl HardwareView {
                             ));
                         }
                         pin_menu_items.push(Item::new(output_button));
+                        // Devices that drive outputs to a safe level offer to set it
+                        if matches!(current_option, Some(&Output(..)))
+                            && self.capabilities.contains(Capabilities::SAFE_LEVELS)
+                        {
+                            pin_menu_items.push(self.safe_level_menu(bcm_pin_number));
+                        }
+                    }
+
+                    Pwm {
+                        duty_cycle: default_duty_cycle,
+                        ..
+                    } => {
+                        // Changing the frequency keeps the duty cycle, if the pin is already PWM
+                        let duty_cycle = match current_option {
+                            Some(Pwm { duty_cycle, .. }) => *duty_cycle,
+                            _ => *default_duty_cycle,
+                        };
+                        pin_menu_items.push(self.pwm_menu(
+                            bcm_pin_number,
+                            duty_cycle,
+                            current_option,
+                            resize_window_on_change,
+                        ));
+                    }
+
+                    AnalogInput { .. } => {
+                        let mut analog_button =
+                            button("Analog Input").width(Fill).style(menu_button_style);
+                        if !matches!(current_option, Some(&AnalogInput { .. })) {
+                            analog_button = analog_button.on_press(PinFunctionChanged(
+                                bcm_pin_number,
+                                Some(*option),
+                                resize_window_on_change,
+                                self.hardware_connection != Local,
+                            ));
+                        }
+                        pin_menu_items.push(Item::new(analog_button));
+                    }
+
+                    I2C1_SDA | I2C1_SCL => {
+                        let mut i2c_button = button(text(option.to_string()))
+                            .width(Fill)
+                            .style(menu_button_style);
+                        if current_option != Some(option) {
+                            i2c_button = i2c_button.on_press(PinFunctionChanged(
+                                bcm_pin_number,
+                                Some(*option),
+                                resize_window_on_change,
+                                self.hardware_connection != Local,
+                            ));
+                        }
+                        pin_menu_items.push(Item::new(i2c_button));
+                    }
+
+                    SPI0_MOSI | SPI0_MISO => {
+                        let mut spi_button = button(text(option.to_string()))
+                            .width(Fill)
+                            .style(menu_button_style);
+                        if current_option != Some(option) {
+                            spi_button = spi_button.on_press(PinFunctionChanged(
+                                bcm_pin_number,
+                                Some(*option),
+                                resize_window_on_change,
+                                self.hardware_connection != Local,
+                            ));
+                        }
+                        pin_menu_items.push(Item::new(spi_button));
+                    }
+
+                    SPI0_SCLK(default_config) => {
+                        // Changes are made to the current settings, if the pin is already SCLK
+                        let config = match current_option {
+                            Some(SPI0_SCLK(config)) => *config,
+                            _ => *default_config,
+                        };
+                        pin_menu_items.push(self.spi_clock_menu(
+                            bcm_pin_number,
+                            config,
+                            current_option,
+                            resize_window_on_change,
+                        ));
+                    }
+
+                    UART0_RXD => {
+                        let mut uart_button = button(text(option.to_string()))
+                            .width(Fill)
+                            .style(menu_button_style);
+                        if current_option != Some(option) {
+                            uart_button = uart_button.on_press(PinFunctionChanged(
+                                bcm_pin_number,
+                                Some(*option),
+                                resize_window_on_change,
+                                self.hardware_connection != Local,
+                            ));
+                        }
+                        pin_menu_items.push(Item::new(uart_button));
+                    }
+
+                    UART0_TXD(default_config) => {
+                        // Changes are made to the current settings, if the pin is already TXD
+                        let config = match current_option {
+                            Some(UART0_TXD(config)) => *config,
+                            _ => *default_config,
+                        };
+                        pin_menu_items.push(self.uart_transmit_menu(
+                            bcm_pin_number,
+                            config,
+                            current_option,
+                            resize_window_on_change,
+                        ));
                     }
                 }
             }
@@ -658,6 +1083,266 @@ impl HardwareView {
             Menu::new(pin_menu_items).width(80.0),
         )
     }
+
+    /// Create the menu item for an output, with a sub-menu to select the level the device drives
+    /// it to when this client disconnects, or the device shuts down
+    fn safe_level_menu<'a>(
+        &self,
+        bcm_pin_number: BCMPinNumber,
+    ) -> Item<'a, HardwareViewMessage, Theme, Renderer> {
+        let current_level = self
+            .hardware_config
+            .safe_levels
+            .get(&bcm_pin_number)
+            .copied();
+
+        let level_items = [("High", Some(true)), ("Low", Some(false)), ("None", None)]
+            .into_iter()
+            .map(|(name, level)| {
+                let mut level_button = button(name).width(Fill).style(menu_button_style);
+                if current_level != level {
+                    level_button = level_button.on_press(ChangeSafeLevel(bcm_pin_number, level));
+                }
+                Item::new(level_button)
+            })
+            .collect();
+
+        Item::with_menu(
+            sub_menu_button("Safe Level", 100.0),
+            Menu::new(level_items).width(80.0),
+        )
+    }
+
+    /// Create the menu item for a PWM output, with a sub-menu to select its frequency.
+    /// Selecting one configures the pin as a PWM output with that frequency.
+    fn pwm_menu<'a>(
+        &self,
+        bcm_pin_number: BCMPinNumber,
+        duty_cycle: f64,
+        current_option: Option<&PinFunction>,
+        resize_window_on_change: bool,
+    ) -> Item<'a, HardwareViewMessage, Theme, Renderer> {
+        let current_frequency = match current_option {
+            Some(Pwm { frequency, .. }) => Some(*frequency),
+            _ => None,
+        };
+
+        let frequency_items = PWM_FREQUENCIES
+            .into_iter()
+            .map(|frequency| {
+                let new_function = Pwm {
+                    frequency,
+                    duty_cycle,
+                };
+                let mut frequency_button = button(text(frequency_name(frequency)))
+                    .width(Fill)
+                    .style(menu_button_style);
+                if current_frequency != Some(frequency) {
+                    frequency_button = frequency_button.on_press(PinFunctionChanged(
+                        bcm_pin_number,
+                        Some(new_function),
+                        resize_window_on_change,
+                        self.hardware_connection != Local,
+                    ));
+                }
+                Item::new(frequency_button)
+            })
+            .collect();
+
+        Item::with_menu(
+            sub_menu_button("PWM", 100.0),
+            Menu::new(frequency_items).width(80.0),
+        )
+    }
+
+    /// Create the menu item for the SPI clock pin, with sub-menus to select the mode, clock
+    /// speed and chip select of the bus. Selecting any of them configures the pin as SCLK.
+    fn spi_clock_menu<'a>(
+        &self,
+        bcm_pin_number: BCMPinNumber,
+        config: SpiConfig,
+        current_option: Option<&PinFunction>,
+        resize_window_on_change: bool,
+    ) -> Item<'a, HardwareViewMessage, Theme, Renderer> {
+        let current_config = match current_option {
+            Some(SPI0_SCLK(current_config)) => Some(*current_config),
+            _ => None,
+        };
+
+        let setting_item = |name: String, new_config: SpiConfig| {
+            let mut setting_button = button(text(name)).width(Fill).style(menu_button_style);
+            if current_config != Some(new_config) {
+                setting_button = setting_button.on_press(PinFunctionChanged(
+                    bcm_pin_number,
+                    Some(SPI0_SCLK(new_config)),
+                    resize_window_on_change,
+                    self.hardware_connection != Local,
+                ));
+            }
+            Item::new(setting_button)
+        };
+
+        let mode_items = [
+            SpiMode::Mode0,
+            SpiMode::Mode1,
+            SpiMode::Mode2,
+            SpiMode::Mode3,
+        ]
+        .into_iter()
+        .map(|mode| setting_item(format!("{mode:?}"), SpiConfig { mode, ..config }))
+        .collect();
+        let clock_items = SPI_CLOCK_SPEEDS
+            .into_iter()
+            .map(|clock_speed| {
+                setting_item(
+                    clock_speed_name(clock_speed),
+                    SpiConfig {
+                        clock_speed,
+                        ..config
+                    },
+                )
+            })
+            .collect();
+        let chip_select_items = [SpiChipSelect::CE0, SpiChipSelect::CE1]
+            .into_iter()
+            .map(|chip_select| {
+                setting_item(
+                    format!("{chip_select:?}"),
+                    SpiConfig {
+                        chip_select,
+                        ..config
+                    },
+                )
+            })
+            .collect();
+
+        let settings_items = vec![
+            Item::with_menu(
+                sub_menu_button("Mode", 110.0),
+                Menu::new(mode_items).width(80.0),
+            ),
+            Item::with_menu(
+                sub_menu_button("Clock", 110.0),
+                Menu::new(clock_items).width(80.0),
+            ),
+            Item::with_menu(
+                sub_menu_button("Chip Select", 110.0),
+                Menu::new(chip_select_items).width(80.0),
+            ),
+        ];
+
+        Item::with_menu(
+            sub_menu_button("SPI0_SCLK", 100.0),
+            Menu::new(settings_items).width(110.0),
+        )
+    }
+
+    /// Create the menu item for the UART transmit pin, with sub-menus to select the baud rate,
+    /// parity and stop bits of the UART. Selecting any of them configures the pin as TXD.
+    fn uart_transmit_menu<'a>(
+        &self,
+        bcm_pin_number: BCMPinNumber,
+        config: UartConfig,
+        current_option: Option<&PinFunction>,
+        resize_window_on_change: bool,
+    ) -> Item<'a, HardwareViewMessage, Theme, Renderer> {
+        let current_config = match current_option {
+            Some(UART0_TXD(current_config)) => Some(*current_config),
+            _ => None,
+        };
+
+        let setting_item = |name: String, new_config: UartConfig| {
+            let mut setting_button = button(text(name)).width(Fill).style(menu_button_style);
+            if current_config != Some(new_config) {
+                setting_button = setting_button.on_press(PinFunctionChanged(
+                    bcm_pin_number,
+                    Some(UART0_TXD(new_config)),
+                    resize_window_on_change,
+                    self.hardware_connection != Local,
+                ));
+            }
+            Item::new(setting_button)
+        };
+
+        let baud_items = UART_BAUD_RATES
+            .into_iter()
+            .map(|baud_rate| {
+                setting_item(
+                    baud_rate.to_string(),
+                    UartConfig {
+                        baud_rate,
+                        ..config
+                    },
+                )
+            })
+            .collect();
+        let parity_items = [UartParity::None, UartParity::Even, UartParity::Odd]
+            .into_iter()
+            .map(|parity| setting_item(format!("{parity:?}"), UartConfig { parity, ..config }))
+            .collect();
+        let stop_bits_items = [("1", UartStopBits::One), ("2", UartStopBits::Two)]
+            .into_iter()
+            .map(|(name, stop_bits)| {
+                setting_item(
+                    name.to_string(),
+                    UartConfig {
+                        stop_bits,
+                        ..config
+                    },
+                )
+            })
+            .collect();
+
+        let settings_items = vec![
+            Item::with_menu(
+                sub_menu_button("Baud", 110.0),
+                Menu::new(baud_items).width(80.0),
+            ),
+            Item::with_menu(
+                sub_menu_button("Parity", 110.0),
+                Menu::new(parity_items).width(80.0),
+            ),
+            Item::with_menu(
+                sub_menu_button("Stop Bits", 110.0),
+                Menu::new(stop_bits_items).width(80.0),
+            ),
+        ];
+
+        Item::with_menu(
+            sub_menu_button("UART0_TXD", 100.0),
+            Menu::new(settings_items).width(110.0),
+        )
+    }
+}
+
+/// Create a button for a menu item that opens a sub-menu
+fn sub_menu_button(name: &str, width: f32) -> Button<'_, HardwareViewMessage> {
+    button(row!(
+        text(name),
+        space::horizontal(),
+        text(" >").align_y(alignment::Vertical::Center),
+    ))
+    .width(width)
+    .on_press(MenuBarButtonClicked) // Needed for highlighting
+    .style(menu_button_style)
+}
+
+/// Name a PWM frequency in the most readable units
+fn frequency_name(frequency: f64) -> String {
+    if frequency >= 1000.0 {
+        format!("{} kHz", frequency / 1000.0)
+    } else {
+        format!("{frequency} Hz")
+    }
+}
+
+/// Name a clock speed in the most readable units
+fn clock_speed_name(clock_speed: u32) -> String {
+    if clock_speed >= 1_000_000 && clock_speed % 1_000_000 == 0 {
+        format!("{} MHz", clock_speed / 1_000_000)
+    } else {
+        format!("{} kHz", clock_speed / 1_000)
+    }
 }
 
 /// Create the widget that either shows an input pin's state
@@ -745,6 +1430,59 @@ fn get_pin_widget<'a>(
             }
         }
 
+        Some(Pwm { duty_cycle, .. }) => {
+            let duty_cycle_slider = slider(0.0..=100.0, duty_cycle * 100.0, move |percent| {
+                if let Some(bcm) = bcm_pin_number {
+                    ChangeDutyCycle(bcm, percent / 100.0)
+                } else {
+                    MenuBarButtonClicked // Fake message in case of error
+                }
+            })
+            .step(1.0)
+            .width(CHART_WIDTH + TOGGLER_WIDTH - DUTY_CYCLE_TEXT_WIDTH);
+            let duty_cycle_slider = match bcm_pin_number {
+                Some(bcm) => duty_cycle_slider.on_release(DutyCycleSet(bcm)),
+                None => duty_cycle_slider,
+            };
+
+            let slider_tooltip =
+                Tooltip::new(duty_cycle_slider, "Drag to set duty cycle", Position::Top)
+                    .gap(4.0)
+                    .style(|_| TOOLTIP_STYLE);
+
+            let percent = text(format!("{:.0}%", duty_cycle * 100.0)).width(DUTY_CYCLE_TEXT_WIDTH);
+
+            if alignment == End {
+                Row::new()
+                    .push(percent.align_x(alignment))
+                    .push(slider_tooltip)
+                    .push(space::horizontal().width(LED_RADIUS * 2.0))
+            } else {
+                Row::new()
+                    .push(space::horizontal().width(LED_RADIUS * 2.0))
+                    .push(slider_tooltip)
+                    .push(percent)
+            }
+        }
+
+        Some(AnalogInput { .. }) => {
+            let value = text(
+                pin_state
+                    .get_analog_value()
+                    .map(|value| value.to_string())
+                    .unwrap_or_default(),
+            )
+            .width(TOGGLER_WIDTH + LED_RADIUS * 2.0);
+
+            if alignment == End {
+                Row::new()
+                    .push(pin_state.view_analog(Left))
+                    .push(value.align_x(alignment))
+            } else {
+                Row::new().push(value).push(pin_state.view_analog(Right))
+            }
+        }
+
         _ => Row::new(),
     };
 
@@ -777,11 +1515,59 @@ fn pin_button(pin_description: &PinDescription) -> Button<'_, HardwareViewMessag
 #[cfg(test)]
 mod test {
     use crate::views::hardware_view::HardwareConnection::NoConnection;
-    use crate::views::hardware_view::HardwareView;
+    use crate::views::hardware_view::HardwareViewMessage::{KeepDeviceConfig, PinFunctionChanged};
+    use crate::views::hardware_view::{clock_speed_name, frequency_name, HardwareView};
+    use pigdef::hello::Capabilities;
+    use pigdef::pin_function::PinFunction::{Input, Output, I2C1_SDA};
 
     #[test]
     fn no_hardware_description() {
         let hw_view = HardwareView::new(NoConnection);
         assert!(hw_view.hardware_description.is_none());
     }
+
+    #[test]
+    fn unsupported_functions_not_offered() {
+        let mut hw_view = HardwareView::new(NoConnection);
+        hw_view.capabilities = Capabilities::GPIO_INPUT.union(Capabilities::GPIO_OUTPUT);
+        assert!(hw_view.supports(&Input(None)));
+        assert!(hw_view.supports(&Output(None)));
+        assert!(!hw_view.supports(&I2C1_SDA));
+    }
+
+    #[test]
+    fn keeping_device_config_keeps_unchanged_pin_states() {
+        let mut hw_view = HardwareView::new(NoConnection);
+        for (bcm_pin_number, function) in [(2, Input(None)), (3, Output(None))] {
+            let _ = hw_view.update(PinFunctionChanged(
+                bcm_pin_number,
+                Some(function),
+                false,
+                false,
+            ));
+        }
+
+        let mut device_config = hw_view.hardware_config.clone();
+        device_config.pin_functions.insert(3, Input(None));
+        let _ = hw_view.update(KeepDeviceConfig(device_config));
+
+        assert!(hw_view.pin_states.contains_key(&2));
+        assert!(!hw_view.pin_states.contains_key(&3));
+        assert_eq!(
+            hw_view.hardware_config.pin_functions.get(&3),
+            Some(&Input(None))
+        );
+    }
+
+    #[test]
+    fn frequency_names() {
+        assert_eq!(frequency_name(50.0), "50 Hz");
+        assert_eq!(frequency_name(10000.0), "10 kHz");
+    }
+
+    #[test]
+    fn clock_speed_names() {
+        assert_eq!(clock_speed_name(100_000), "100 kHz");
+        assert_eq!(clock_speed_name(8_000_000), "8 MHz");
+    }
 }
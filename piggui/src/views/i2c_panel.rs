@@ -0,0 +1,258 @@
+use crate::views::dialog_styles::{
+    connect_button, CONNECTION_ERROR_DISPLAY, MODAL_CONTAINER_STYLE,
+};
+use crate::views::hardware_view::HardwareViewMessage;
+use crate::views::i2c_panel::I2cPanelMessage::{
+    AddressChanged, DataChanged, DeviceSelected, LengthChanged, Read, RegisterChanged, Scan, Write,
+};
+use iced::widget::{button, column, container, row, text, text_input, Row};
+use iced::{Center, Element};
+use pigdef::i2c::{I2cAddress, I2cRequest, I2cResponse};
+
+const SMALL_INPUT_WIDTH: f32 = 60.0;
+const DATA_INPUT_WIDTH: f32 = 200.0;
+
+/// [I2cPanelMessage] covers all messages that are handled by the [I2cPanel]
+#[derive(Debug, Clone)]
+pub enum I2cPanelMessage {
+    AddressChanged(String),
+    RegisterChanged(String),
+    LengthChanged(String),
+    DataChanged(String),
+    /// A device found in a scan was clicked, to use its address for the next read or write
+    DeviceSelected(I2cAddress),
+    Scan,
+    Read,
+    Write,
+}
+
+/// A panel shown in the hardware view when the pins of the I2C bus are configured, to scan the
+/// bus for devices and to read and write the registers of a device
+#[derive(Default)]
+pub struct I2cPanel {
+    address: String,
+    register: String,
+    length: String,
+    data: String,
+    devices: Vec<I2cAddress>,
+    result: String,
+    error: String,
+}
+
+impl I2cPanel {
+    /// Handle a message from the panel's widgets, returning the [I2cRequest] to be sent to the
+    /// hardware, if the user made one
+    pub fn update(&mut self, message: I2cPanelMessage) -> Option<I2cRequest> {
+        match message {
+            AddressChanged(address) => self.address = address,
+            RegisterChanged(register) => self.register = register,
+            LengthChanged(length) => self.length = length,
+            DataChanged(data) => self.data = data,
+            DeviceSelected(address) => self.address = format!("{address:#04x}"),
+            Scan => return Some(I2cRequest::Scan),
+            Read => return self.checked(self.request(false)),
+            Write => return self.checked(self.request(true)),
+        }
+
+        None
+    }
+
+    /// Return the request if it is valid, otherwise show the error
+    fn checked(&mut self, request: Result<I2cRequest, String>) -> Option<I2cRequest> {
+        match request {
+            Ok(request) => {
+                self.error.clear();
+                Some(request)
+            }
+            Err(error) => {
+                self.error = error;
+                None
+            }
+        }
+    }
+
+    /// Create a read or write request from the values entered by the user
+    fn request(&self, write: bool) -> Result<I2cRequest, String> {
+        let address = parse_hex_byte(&self.address).map_err(|e| format!("Address {e}"))?;
+        let register = parse_hex_byte(&self.register).map_err(|e| format!("Register {e}"))?;
+        if write {
+            Ok(I2cRequest::Write {
+                address,
+                register,
+                data: parse_hex_bytes(&self.data)?,
+            })
+        } else {
+            let length = self
+                .length
+                .trim()
+                .parse()
+                .map_err(|_| "Length must be a number of bytes".to_string())?;
+            Ok(I2cRequest::Read {
+                address,
+                register,
+                length,
+            })
+        }
+    }
+
+    /// Show the response received from the hardware to the last request
+    pub fn response(&mut self, response: I2cResponse) {
+        self.error.clear();
+        match response {
+            I2cResponse::Devices(devices) => {
+                self.result = format!("{} devices found", devices.len());
+                self.devices = devices;
+            }
+            I2cResponse::Read {
+                address,
+                register,
+                data,
+            } => self.result = format!("Read {address:#04x}[{register:#04x}]: {}", hex(&data)),
+            I2cResponse::Written { address, register } => {
+                self.result = format!("Written {address:#04x}[{register:#04x}]")
+            }
+            I2cResponse::Error(error) => self.error = error,
+        }
+    }
+
+    /// Construct the view of the panel
+    pub fn view(&self) -> Element<'_, HardwareViewMessage> {
+        let devices = self.devices.iter().fold(Row::new(), |devices, address| {
+            devices.push(
+                button(text(format!("{address:#04x}")))
+                    .on_press(DeviceSelected(*address))
+                    .style(connect_button),
+            )
+        });
+
+        let panel: Element<I2cPanelMessage> = column![
+            text("I2C Bus").size(20),
+            row![
+                button("Scan").on_press(Scan).style(connect_button),
+                devices.spacing(5)
+            ]
+            .spacing(10)
+            .align_y(Center),
+            row![
+                text("Address"),
+                small_input("0x00", &self.address, AddressChanged),
+                text("Register"),
+                small_input("0x00", &self.register, RegisterChanged),
+            ]
+            .spacing(10)
+            .align_y(Center),
+            row![
+                text("Length"),
+                small_input("1", &self.length, LengthChanged),
+                button("Read").on_press(Read).style(connect_button),
+            ]
+            .spacing(10)
+            .align_y(Center),
+            row![
+                text("Data"),
+                text_input("0x01 0x02", &self.data)
+                    .on_input(DataChanged)
+                    .padding(5)
+                    .width(DATA_INPUT_WIDTH),
+                button("Write").on_press(Write).style(connect_button),
+            ]
+            .spacing(10)
+            .align_y(Center),
+            text(self.result.as_str()),
+            text(self.error.as_str()).style(move |_theme| CONNECTION_ERROR_DISPLAY),
+        ]
+        .spacing(10)
+        .into();
+
+        container(panel.map(HardwareViewMessage::I2c))
+            .style(move |_theme| MODAL_CONTAINER_STYLE)
+            .padding(15)
+            .into()
+    }
+}
+
+/// A small text input for a single value
+fn small_input<'a>(
+    placeholder: &'a str,
+    value: &'a str,
+    on_input: fn(String) -> I2cPanelMessage,
+) -> Element<'a, I2cPanelMessage> {
+    text_input(placeholder, value)
+        .on_input(on_input)
+        .padding(5)
+        .width(SMALL_INPUT_WIDTH)
+        .into()
+}
+
+/// Parse a byte entered in hex, with or without a leading "0x"
+fn parse_hex_byte(value: &str) -> Result<u8, String> {
+    let value = value.trim();
+    let digits = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .unwrap_or(value);
+    u8::from_str_radix(digits, 16).map_err(|_| format!("'{value}' is not a hex byte"))
+}
+
+/// Parse a list of bytes entered in hex, separated by spaces or commas
+pub(crate) fn parse_hex_bytes(value: &str) -> Result<Vec<u8>, String> {
+    value
+        .split([' ', ','])
+        .filter(|byte| !byte.is_empty())
+        .map(parse_hex_byte)
+        .collect()
+}
+
+/// Format bytes as hex, separated by spaces
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{byte:#04x}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod test {
+    use crate::views::i2c_panel::I2cPanelMessage::{
+        AddressChanged, DataChanged, LengthChanged, Read, RegisterChanged, Write,
+    };
+    use crate::views::i2c_panel::{hex, parse_hex_byte, parse_hex_bytes, I2cPanel};
+    use pigdef::i2c::I2cRequest;
+
+    #[test]
+    fn parse_hex() {
+        assert_eq!(parse_hex_byte("0x76"), Ok(0x76));
+        assert_eq!(parse_hex_byte(" D0 "), Ok(0xD0));
+        assert!(parse_hex_byte("0x100").is_err());
+        assert_eq!(parse_hex_bytes("0x01, 2 ff"), Ok(vec![1, 2, 0xFF]));
+        assert_eq!(hex(&[0x60, 1]), "0x60 0x01");
+    }
+
+    #[test]
+    fn read_request() {
+        let mut panel = I2cPanel::default();
+        panel.update(AddressChanged("0x76".into()));
+        panel.update(RegisterChanged("0xD0".into()));
+        panel.update(LengthChanged("1".into()));
+        assert_eq!(
+            panel.update(Read),
+            Some(I2cRequest::Read {
+                address: 0x76,
+                register: 0xD0,
+                length: 1
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_write_request() {
+        let mut panel = I2cPanel::default();
+        panel.update(AddressChanged("0x48".into()));
+        panel.update(RegisterChanged("0x01".into()));
+        panel.update(DataChanged("0x01 zz".into()));
+        assert_eq!(panel.update(Write), None);
+        assert!(!panel.error.is_empty());
+    }
+}
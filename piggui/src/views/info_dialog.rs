@@ -4,6 +4,7 @@ use crate::views::dialog_styles::{
     cancel_button, connect_button, hyperlink_button, MODAL_CONTAINER_STYLE,
 };
 use crate::views::hardware_styles::TOOLTIP_STYLE;
+use crate::views::hardware_view::HardwareViewMessage;
 use crate::Message;
 use iced::keyboard::key;
 use iced::widget::tooltip::Position;
@@ -11,6 +12,7 @@ use iced::widget::{button, column, container, space, text, Row, Space, Text, Too
 use iced::{keyboard, window, Color, Element, Event, Length, Task};
 use iced_futures::core::Alignment;
 use iced_futures::Subscription;
+use pigdef::config::HardwareConfig;
 use pigdef::description::HardwareDetails;
 use pignet::HardwareConnection;
 use std::collections::HashMap;
@@ -40,6 +42,12 @@ pub enum ModalType {
         title: String,
         body: String,
     },
+    /// The config of a reconnected device differs from the one in the GUI
+    ConfigConflict {
+        title: String,
+        body: String,
+        device_config: HardwareConfig,
+    },
     None,
 }
 
@@ -60,6 +68,10 @@ pub enum InfoDialogMessage {
     ExitApp,
     EscKeyEvent(Event),
     OpenLink(&'static str),
+    /// Ask whether to keep the config of the reconnected device, or the one in the GUI
+    ConfigConflictModal(HardwareConfig),
+    KeepDeviceConfig,
+    KeepGuiConfig,
 }
 
 impl InfoDialog {
@@ -75,11 +87,26 @@ impl InfoDialog {
 
     pub fn update(&mut self, message: InfoDialogMessage) -> Task<Message> {
         match message {
-            InfoDialogMessage::HideModal => {
-                self.modal_type = ModalType::None;
+            InfoDialogMessage::HideModal => self.hide(),
+
+            InfoDialogMessage::ConfigConflictModal(device_config) => {
+                self.modal_type = ModalType::ConfigConflict {
+                    title: "Config Changed".to_string(),
+                    body: "The config of the device differs from the one in piggui since the \
+                    connection to it was lost. Which config do you want to keep?"
+                        .to_string(),
+                    device_config,
+                };
                 Task::none()
             }
 
+            InfoDialogMessage::KeepDeviceConfig => self.hide(),
+
+            InfoDialogMessage::KeepGuiConfig => {
+                self.modal_type = ModalType::None;
+                Task::done(Message::Hardware(HardwareViewMessage::KeepGuiConfig))
+            }
+
             // Display warning for unsaved changes
             InfoDialogMessage::UnsavedChangesExitModal => {
                 self.modal_type = ModalType::Warning {
@@ -150,10 +177,18 @@ impl InfoDialog {
             InfoDialogMessage::EscKeyEvent(Event::Keyboard(keyboard::Event::KeyPressed {
                 key: keyboard::Key::Named(key::Named::Escape),
                 ..
-            })) => {
-                self.modal_type = ModalType::None;
-                Task::none()
-            }
+            })) => self.hide(),
+            _ => Task::none(),
+        }
+    }
+
+    /// Hide the modal. Hiding the config conflict modal keeps the device's config, so the GUI
+    /// shows the config the device is using
+    fn hide(&mut self) -> Task<Message> {
+        match std::mem::replace(&mut self.modal_type, ModalType::None) {
+            ModalType::ConfigConflict { device_config, .. } => Task::done(Message::Hardware(
+                HardwareViewMessage::KeepDeviceConfig(device_config),
+            )),
             _ => Task::none(),
         }
     }
@@ -283,6 +318,26 @@ impl InfoDialog {
                 Self::info_container(title, body, button_row, WHITE_TEXT)
             }
 
+            ModalType::ConfigConflict { title, body, .. } => {
+                let text_style = text::Style {
+                    color: Some(Color::from_rgba(0.988, 0.686, 0.243, 1.0)),
+                };
+
+                let mut button_row = Row::new().push(
+                    button("Keep piggui's config")
+                        .on_press(Message::Modal(InfoDialogMessage::KeepGuiConfig))
+                        .style(cancel_button),
+                );
+                button_row = button_row.push(Space::new().width(Length::Fill));
+                button_row = button_row.push(
+                    button("Keep the device's config")
+                        .on_press(Message::Modal(InfoDialogMessage::KeepDeviceConfig))
+                        .style(connect_button),
+                );
+
+                Self::info_container(title, body, button_row, text_style)
+            }
+
             ModalType::None => container(column![]).into(), // Render the empty container
         }
     }
@@ -333,6 +388,22 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_config_conflict_modal() {
+        let mut display_modal = InfoDialog::new();
+
+        let _ = display_modal.update(InfoDialogMessage::ConfigConflictModal(
+            HardwareConfig::default(),
+        ));
+        assert!(matches!(
+            display_modal.modal_type,
+            ModalType::ConfigConflict { .. }
+        ));
+
+        let _ = display_modal.update(InfoDialogMessage::KeepGuiConfig);
+        assert!(!display_modal.showing_modal());
+    }
+
     #[test]
     fn test_version_modal() {
         let mut display_modal = InfoDialog::new();
@@ -9,7 +9,7 @@ use crate::Message;
 use iced::border::Radius;
 use iced::widget::button::Status::Hovered;
 use iced::widget::button::{Status, Style};
-use iced::widget::{container, Row, Space};
+use iced::widget::{container, text, Row, Space};
 use iced::{Background, Border, Color, Element, Length, Shadow, Task, Theme};
 use iced_aw::style::menu_bar;
 use iced_aw::MenuBar;
@@ -191,11 +191,17 @@ impl InfoRow {
         .style(|_, _| MENU_BAR_STYLE)
         .into();
 
+        let mut row = Row::new()
+            .push(menu_bar)
+            .push(Space::new().width(Length::Fill)); // This takes up the remaining space
+
+        // The round-trip latency of the link to a remote device that answers heartbeats
+        if let Some(latency) = hardware_view.latency() {
+            row = row.push(container(text(format!("{} ms", latency.as_millis()))).padding(5));
+        }
+
         container(
-            Row::new()
-                .push(menu_bar)
-                .push(Space::new().width(Length::Fill)) // This takes up the remaining space
-                .push(self.message_row.view().map(Message::InfoRow))
+            row.push(self.message_row.view().map(Message::InfoRow))
                 .spacing(20.0)
                 .padding(0),
         )
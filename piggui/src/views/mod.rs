@@ -8,11 +8,14 @@ pub mod devices_menu;
 mod dialog_styles;
 mod hardware_styles;
 pub mod hardware_view;
+pub mod i2c_panel;
 pub mod info_dialog;
 pub mod info_row;
 pub mod layout_menu;
 pub mod message_box;
 pub mod pin_state;
+pub mod spi_panel;
+pub mod uart_panel;
 #[cfg(feature = "usb")]
 pub mod ssid_dialog;
 pub mod waveform;
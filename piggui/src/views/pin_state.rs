@@ -2,13 +2,14 @@ use chrono::DateTime;
 use std::time::Duration;
 
 use iced::advanced::text::editor::Direction;
+use iced::widget::space;
 use iced::Element;
 use plotters::prelude::{RGBAColor, ShapeStyle};
 
 use crate::views::hardware_view::HardwareViewMessage;
 use crate::views::waveform::{ChartType, Sample, Waveform};
-use pigdef::config::LevelChange;
-use pigdef::description::PinLevel;
+use pigdef::config::{AnalogSample, LevelChange};
+use pigdef::description::{AnalogValue, PinLevel, ANALOG_MAX};
 
 pub const CHART_UPDATES_PER_SECOND: u64 = 4;
 pub const CHART_WIDTH: f32 = 256.0;
@@ -25,11 +26,16 @@ const CHART_LINE_STYLE: ShapeStyle = ShapeStyle {
     stroke_width: 2,
 };
 
-/// PinState captures the logical level of a pin, including a history of previous states
+/// PinState captures the logical level of a pin, or the value of an analog input,
+/// including a history of previous states
 pub struct PinState {
     // Cache the level of the last recorded level_change as the current level
     current_level: Option<PinLevel>,
     pub(crate) chart: Waveform<PinLevel>,
+    // Cache the value of the last recorded analog sample as the current value
+    current_value: Option<AnalogValue>,
+    // Only created when the first sample is recorded, as only analog input pins need it
+    pub(crate) analog_chart: Option<Waveform<AnalogValue>>,
 }
 
 impl TryFrom<LevelChange> for Sample<PinLevel> {
@@ -49,8 +55,25 @@ impl TryFrom<LevelChange> for Sample<PinLevel> {
     }
 }
 
+impl TryFrom<AnalogSample> for Sample<AnalogValue> {
+    type Error = &'static str;
+
+    fn try_from(analog_sample: AnalogSample) -> Result<Self, Self::Error> {
+        // jonesy:allow(div_zero, overflow)
+        let time = DateTime::from_timestamp(
+            analog_sample.timestamp.as_secs() as i64,
+            analog_sample.timestamp.subsec_nanos(),
+        )
+        .ok_or("Could not create timestamp")?;
+        Ok(Self {
+            time,
+            value: analog_sample.value,
+        })
+    }
+}
+
 impl PinState {
-    /// Create a new PinState with an unknown level and a new Waveform chart of it
+    /// Create a new PinState with an unknown level and value and a new Waveform chart of the level
     pub fn new() -> Self {
         PinState {
             current_level: None,
@@ -61,6 +84,8 @@ impl PinState {
                 CHART_HEIGHT,
                 CHART_DURATION,
             ),
+            current_value: None,
+            analog_chart: None,
         }
     }
 
@@ -68,6 +93,14 @@ impl PinState {
         self.chart.view(direction)
     }
 
+    /// View the chart of the values sampled from an analog input
+    pub fn view_analog(&self, direction: Direction) -> Element<'_, HardwareViewMessage> {
+        match &self.analog_chart {
+            Some(analog_chart) => analog_chart.view(direction),
+            None => space::horizontal().width(CHART_WIDTH).into(),
+        }
+    }
+
     /// Try and get the last reported level of the pin, which could be considered "current level"
     /// if everything is working correctly.
     pub fn get_level(&self) -> Option<PinLevel> {
@@ -87,13 +120,40 @@ impl PinState {
             }
         }
     }
+
+    /// Try and get the last value sampled from an analog input
+    pub fn get_analog_value(&self) -> Option<AnalogValue> {
+        self.current_value
+    }
+
+    /// Add an AnalogSample to the history of this pin's analog values
+    pub fn set_analog_value(&mut self, analog_sample: AnalogSample) {
+        self.current_value = Some(analog_sample.value);
+
+        let analog_chart = self.analog_chart.get_or_insert_with(|| {
+            Waveform::new(
+                ChartType::Verbatim(0, ANALOG_MAX),
+                CHART_LINE_STYLE,
+                CHART_WIDTH,
+                CHART_HEIGHT,
+                CHART_DURATION,
+            )
+        });
+        if let Ok(dt) = analog_chart.date_time(analog_sample.timestamp) {
+            let result: Result<Sample<AnalogValue>, _> = analog_sample.try_into();
+            if let Ok(mut sample) = result {
+                sample.time = dt;
+                analog_chart.push_data(sample)
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 #[allow(clippy::unwrap_used, clippy::expect_used)]
 mod test {
     use crate::views::pin_state::PinState;
-    use pigdef::config::LevelChange;
+    use pigdef::config::{AnalogSample, LevelChange};
     use std::time::{SystemTime, UNIX_EPOCH};
 
     #[test]
@@ -108,4 +168,18 @@ mod test {
         state.set_level(LevelChange::new(true, now));
         assert_eq!(state.get_level(), Some(true));
     }
+
+    #[test]
+    fn analog_value_stores_last() {
+        let mut state = PinState::new();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Could not get System time");
+        assert_eq!(state.get_analog_value(), None);
+        assert!(state.analog_chart.is_none());
+        state.set_analog_value(AnalogSample::new(100, now));
+        state.set_analog_value(AnalogSample::new(4000, now));
+        assert_eq!(state.get_analog_value(), Some(4000));
+        assert!(state.analog_chart.is_some());
+    }
 }
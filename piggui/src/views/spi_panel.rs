@@ -0,0 +1,137 @@
+use crate::views::dialog_styles::{
+    connect_button, CONNECTION_ERROR_DISPLAY, MODAL_CONTAINER_STYLE,
+};
+use crate::views::hardware_view::HardwareViewMessage;
+use crate::views::i2c_panel::{hex, parse_hex_bytes};
+use crate::views::spi_panel::SpiPanelMessage::{DataChanged, Transfer};
+use iced::widget::{button, column, container, row, text, text_input};
+use iced::{Center, Element};
+use pigdef::spi::{SpiRequest, SpiResponse, SPI_MAX_BYTES};
+
+const DATA_INPUT_WIDTH: f32 = 200.0;
+
+/// [SpiPanelMessage] covers all messages that are handled by the [SpiPanel]
+#[derive(Debug, Clone)]
+pub enum SpiPanelMessage {
+    DataChanged(String),
+    Transfer,
+}
+
+/// A panel shown in the hardware view when the pins of the SPI bus are configured, to send
+/// bytes to the selected device and show the bytes received from it at the same time
+#[derive(Default)]
+pub struct SpiPanel {
+    data: String,
+    written: String,
+    read: String,
+    error: String,
+}
+
+impl SpiPanel {
+    /// Handle a message from the panel's widgets, returning the [SpiRequest] to be sent to the
+    /// hardware, if the user made one
+    pub fn update(&mut self, message: SpiPanelMessage) -> Option<SpiRequest> {
+        match message {
+            DataChanged(data) => self.data = data,
+            Transfer => match self.request() {
+                Ok(request) => {
+                    self.error.clear();
+                    return Some(request);
+                }
+                Err(error) => self.error = error,
+            },
+        }
+
+        None
+    }
+
+    /// Create a transfer request from the bytes entered by the user
+    fn request(&self) -> Result<SpiRequest, String> {
+        let data = parse_hex_bytes(&self.data)?;
+        if data.is_empty() {
+            return Err("Enter the bytes to send".into());
+        }
+        if data.len() > SPI_MAX_BYTES {
+            return Err(format!(
+                "Cannot transfer more than {SPI_MAX_BYTES} bytes at a time"
+            ));
+        }
+        Ok(SpiRequest::Transfer(data))
+    }
+
+    /// Show the response received from the hardware to the last transfer
+    pub fn response(&mut self, response: SpiResponse) {
+        self.error.clear();
+        match response {
+            SpiResponse::Transferred { written, read } => {
+                self.written = format!("Written: {}", hex(&written));
+                self.read = format!("Read: {}", hex(&read));
+            }
+            SpiResponse::Error(error) => self.error = error,
+        }
+    }
+
+    /// Construct the view of the panel
+    pub fn view(&self) -> Element<'_, HardwareViewMessage> {
+        let panel: Element<SpiPanelMessage> = column![
+            text("SPI Bus").size(20),
+            row![
+                text("Data"),
+                text_input("0x9F 0x00 0x00", &self.data)
+                    .on_input(DataChanged)
+                    .on_submit(Transfer)
+                    .padding(5)
+                    .width(DATA_INPUT_WIDTH),
+                button("Transfer").on_press(Transfer).style(connect_button),
+            ]
+            .spacing(10)
+            .align_y(Center),
+            text(self.written.as_str()),
+            text(self.read.as_str()),
+            text(self.error.as_str()).style(move |_theme| CONNECTION_ERROR_DISPLAY),
+        ]
+        .spacing(10)
+        .into();
+
+        container(panel.map(HardwareViewMessage::Spi))
+            .style(move |_theme| MODAL_CONTAINER_STYLE)
+            .padding(15)
+            .into()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod test {
+    use crate::views::spi_panel::SpiPanel;
+    use crate::views::spi_panel::SpiPanelMessage::{DataChanged, Transfer};
+    use pigdef::spi::{SpiRequest, SpiResponse};
+
+    #[test]
+    fn transfer_request() {
+        let mut panel = SpiPanel::default();
+        panel.update(DataChanged("0x9F 0 0".into()));
+        assert_eq!(
+            panel.update(Transfer),
+            Some(SpiRequest::Transfer(vec![0x9F, 0, 0]))
+        );
+    }
+
+    #[test]
+    fn empty_transfer_request() {
+        let mut panel = SpiPanel::default();
+        assert_eq!(panel.update(Transfer), None);
+        assert!(!panel.error.is_empty());
+    }
+
+    #[test]
+    fn transfer_response() {
+        let mut panel = SpiPanel::default();
+        panel.response(SpiResponse::Transferred {
+            written: vec![0x9F],
+            read: vec![0xEF],
+        });
+        assert_eq!(panel.written, "Written: 0x9f");
+        assert_eq!(panel.read, "Read: 0xef");
+    }
+}
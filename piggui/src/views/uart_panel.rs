@@ -0,0 +1,153 @@
+use crate::views::dialog_styles::{
+    cancel_button, connect_button, CONNECTION_ERROR_DISPLAY, MODAL_CONTAINER_STYLE,
+    TEXT_BOX_CONTAINER_STYLE,
+};
+use crate::views::hardware_view::HardwareViewMessage;
+use crate::views::uart_panel::UartPanelMessage::{Clear, LineChanged, Send};
+use iced::widget::{button, column, container, row, scrollable, text, text_input};
+use iced::{Center, Element, Fill, Font};
+use pigdef::uart::UART_MAX_BYTES;
+
+const TERMINAL_WIDTH: f32 = 400.0;
+const TERMINAL_HEIGHT: f32 = 160.0;
+
+/// The most characters received that are kept for showing in the terminal, older ones
+/// are discarded
+const MAX_RECEIVED_CHARS: usize = 8192;
+
+/// Sent after each line typed by the user
+const LINE_ENDING: &str = "\n";
+
+/// [UartPanelMessage] covers all messages that are handled by the [UartPanel]
+#[derive(Debug, Clone)]
+pub enum UartPanelMessage {
+    LineChanged(String),
+    Send,
+    Clear,
+}
+
+/// A terminal-style panel shown in the hardware view when the pins of the UART are configured,
+/// showing the bytes received as they arrive and sending each line typed by the user
+#[derive(Default)]
+pub struct UartPanel {
+    line: String,
+    received: String,
+    error: String,
+}
+
+impl UartPanel {
+    /// Handle a message from the panel's widgets, returning the bytes to be sent through the
+    /// UART, if the user sent a line
+    pub fn update(&mut self, message: UartPanelMessage) -> Option<Vec<u8>> {
+        match message {
+            LineChanged(line) => self.line = line,
+            Send => {
+                let bytes = format!("{}{LINE_ENDING}", self.line).into_bytes();
+                if bytes.len() > UART_MAX_BYTES {
+                    self.error = format!("Cannot send more than {UART_MAX_BYTES} bytes at a time");
+                    return None;
+                }
+                self.error.clear();
+                self.line.clear();
+                return Some(bytes);
+            }
+            Clear => self.received.clear(),
+        }
+
+        None
+    }
+
+    /// Add bytes received by the UART to the terminal, discarding the oldest ones if there
+    /// are too many to keep
+    pub fn received(&mut self, bytes: &[u8]) {
+        self.received
+            .push_str(&String::from_utf8_lossy(bytes).replace('\r', ""));
+        let excess = self
+            .received
+            .chars()
+            .count()
+            .saturating_sub(MAX_RECEIVED_CHARS);
+        if excess > 0 {
+            self.received = self.received.chars().skip(excess).collect();
+        }
+    }
+
+    /// Construct the view of the panel
+    pub fn view(&self) -> Element<'_, HardwareViewMessage> {
+        let terminal = container(
+            scrollable(text(self.received.as_str()).font(Font::MONOSPACE))
+                .anchor_bottom()
+                .width(Fill)
+                .height(Fill),
+        )
+        .style(move |_theme| TEXT_BOX_CONTAINER_STYLE)
+        .padding(5)
+        .width(TERMINAL_WIDTH)
+        .height(TERMINAL_HEIGHT);
+
+        let panel: Element<UartPanelMessage> = column![
+            text("UART").size(20),
+            terminal,
+            row![
+                text_input("Type a line to send", &self.line)
+                    .on_input(LineChanged)
+                    .on_submit(Send)
+                    .font(Font::MONOSPACE)
+                    .padding(5),
+                button("Send").on_press(Send).style(connect_button),
+                button("Clear").on_press(Clear).style(cancel_button),
+            ]
+            .spacing(10)
+            .align_y(Center)
+            .width(TERMINAL_WIDTH),
+            text(self.error.as_str()).style(move |_theme| CONNECTION_ERROR_DISPLAY),
+        ]
+        .spacing(10)
+        .into();
+
+        container(panel.map(HardwareViewMessage::Uart))
+            .style(move |_theme| MODAL_CONTAINER_STYLE)
+            .padding(15)
+            .into()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod test {
+    use crate::views::uart_panel::UartPanelMessage::{LineChanged, Send};
+    use crate::views::uart_panel::{UartPanel, MAX_RECEIVED_CHARS};
+
+    #[test]
+    fn send_line() {
+        let mut panel = UartPanel::default();
+        panel.update(LineChanged("AT".into()));
+        assert_eq!(panel.update(Send), Some(b"AT\n".to_vec()));
+        assert!(panel.line.is_empty());
+    }
+
+    #[test]
+    fn line_too_long() {
+        let mut panel = UartPanel::default();
+        panel.update(LineChanged("x".repeat(200)));
+        assert_eq!(panel.update(Send), None);
+        assert!(!panel.error.is_empty());
+    }
+
+    #[test]
+    fn received_bytes_shown() {
+        let mut panel = UartPanel::default();
+        panel.received(b"OK\r\n");
+        panel.received(b"READY");
+        assert_eq!(panel.received, "OK\nREADY");
+    }
+
+    #[test]
+    fn oldest_received_discarded() {
+        let mut panel = UartPanel::default();
+        panel.received("a".repeat(MAX_RECEIVED_CHARS).as_bytes());
+        panel.received(b"b");
+        assert_eq!(panel.received.len(), MAX_RECEIVED_CHARS);
+        assert!(panel.received.ends_with('b'));
+    }
+}
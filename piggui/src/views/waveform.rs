@@ -47,7 +47,6 @@ where
     T: Clone + Into<u32> + PartialEq,
 {
     SquareWave(T, T),
-    #[allow(dead_code)]
     Verbatim(T, T),
 }
 
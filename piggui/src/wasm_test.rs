@@ -34,7 +34,9 @@ fn test_piggui_connected() -> Piggui {
     let _ = app
         .hardware_view
         .update(SubscriptionMessage(SubscriptionEvent::Connected(
-            hw_desc, hw_config,
+            hw_desc,
+            hw_config,
+            piggpio::CAPABILITIES,
         )));
     let _ = app.update(Message::Connected);
     app
@@ -1,9 +1,13 @@
 #![cfg(not(target_arch = "wasm32"))]
 
+#[cfg(feature = "tcp")]
+use crate::support::kill;
 use crate::support::{kill_all, parse_pigglet};
 use serial_test::serial;
 use std::time::Duration;
 use support::{pass, run, wait_for_stdout};
+#[cfg(feature = "tcp")]
+use tempfile::tempdir;
 
 mod support;
 
@@ -52,7 +56,7 @@ async fn connect_to_pigglet_via_iroh() {
 #[serial(piggui, pigglet)]
 async fn connect_to_pigglet_tcp() {
     kill_all("pigglet");
-    let mut pigglet = run("pigglet", vec![], None);
+    let mut pigglet = run("pigglet", vec!["--no_auth".into()], None);
 
     tokio::time::sleep(Duration::from_secs(1)).await;
 
@@ -71,3 +75,45 @@ async fn connect_to_pigglet_tcp() {
     pass(&mut piggui);
     pass(&mut pigglet);
 }
+
+#[cfg(feature = "tcp")]
+#[tokio::test]
+#[serial(piggui, pigglet)]
+async fn reconnect_to_restarted_pigglet_tcp() {
+    kill_all("pigglet");
+    let mut pigglet = run("pigglet", vec!["--no_auth".into()], None);
+
+    let (ip, port, _, _relay) = parse_pigglet(&mut pigglet).await;
+
+    let mut piggui = run(
+        "piggui",
+        vec!["--ip".to_string(), format!("{}:{}", ip, port)],
+        None,
+    );
+
+    wait_for_stdout(&mut piggui, "Connected to hardware", Some("Error:"));
+
+    // Losing the connection makes piggui start reconnecting
+    kill(&mut pigglet);
+    wait_for_stdout(&mut piggui, "Reconnecting to hardware", Some("Error:"));
+
+    // Restart pigglet on the same port, and piggui should reconnect to it
+    let dir = tempdir().expect("Could not create a tempdir");
+    let settings_path = dir.path().join("pigglet.toml");
+    std::fs::write(&settings_path, format!("[tcp]\nport = {port}\n"))
+        .expect("Could not write settings");
+    let mut pigglet = run(
+        "pigglet",
+        vec![
+            "--no_auth".into(),
+            "--settings".into(),
+            settings_path.to_string_lossy().to_string(),
+        ],
+        None,
+    );
+
+    wait_for_stdout(&mut piggui, "Connected to hardware", Some("Error:"));
+
+    pass(&mut piggui);
+    pass(&mut pigglet);
+}
@@ -1,10 +1,13 @@
 #![cfg(not(target_arch = "wasm32"))]
 
-use async_std::net::TcpStream;
 use iroh::endpoint::Connection;
 use iroh::{EndpointId, RelayUrl};
-use pigdef::config::HardwareConfig;
-use pigdef::description::HardwareDescription;
+use pigdef::config::HardwareConfigMessage::IOLevelChanged;
+use pigdef::config::{HardwareConfig, HardwareConfigMessage, LevelChange};
+use pigdef::description::{BCMPinNumber, HardwareDescription};
+use piggpio::config::CONFIG_FILENAME;
+use pignet::credentials::Credentials;
+use pignet::tcp_host::TcpConnection;
 use pignet::{iroh_host, tcp_host};
 use std::future::Future;
 use std::io::prelude::*;
@@ -13,8 +16,22 @@ use std::net::IpAddr;
 use std::path::PathBuf;
 use std::process::{Child, Command, Stdio};
 use std::str::FromStr;
+use std::time::Duration;
 use sysinfo::System;
 
+/// Optional features of pigglet that are not built by default, but are needed by its tests
+const PIGGLET_FEATURES: &str = "pigglet/tls,pigglet/http,pigglet/mqtt";
+
+/// Cargo arguments to select `binary`, and the optional features it is tested with
+fn bin_args(binary: &str) -> Vec<String> {
+    let mut args = vec!["--bin".to_string(), binary.to_string()];
+    if binary == "pigglet" {
+        args.push("--features".into());
+        args.push(PIGGLET_FEATURES.into());
+    }
+    args
+}
+
 #[allow(dead_code)] // for piggui
 pub fn build(binary: &str) {
     let crate_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -23,12 +40,9 @@ pub fn build(binary: &str) {
         .expect("Could not get workspace directory");
     let mut command = Command::new(env!("CARGO"));
 
-    let args = vec![
-        "build".to_string(),
-        "--bin".to_string(),
-        binary.to_string(),
-        "--".into(),
-    ];
+    let mut args = vec!["build".to_string()];
+    args.extend(bin_args(binary));
+    args.push("--".into());
 
     println!("Running Command: cargo {}", args.join(" "));
 
@@ -55,12 +69,9 @@ pub fn run(binary: &str, options: Vec<String>, config: Option<PathBuf>) -> Child
         .expect("Could not get workspace directory");
     let mut command = Command::new(env!("CARGO"));
 
-    let mut args = vec![
-        "run".to_string(),
-        "--bin".to_string(),
-        binary.to_string(),
-        "--".into(),
-    ];
+    let mut args = vec!["run".to_string()];
+    args.extend(bin_args(binary));
+    args.push("--".into());
 
     args.extend(options);
 
@@ -217,13 +228,13 @@ pub async fn parse_pigglet(child: &mut Child) -> (IpAddr, u16, EndpointId, Optio
 #[allow(dead_code)]
 pub async fn connect_and_test_tcp<F, Fut>(child: &mut Child, ip: IpAddr, port: u16, test: F)
 where
-    F: FnOnce(HardwareDescription, HardwareConfig, TcpStream) -> Fut,
+    F: FnOnce(HardwareDescription, HardwareConfig, TcpConnection) -> Fut,
     Fut: Future<Output = ()>,
 {
     let mut failures = 0;
 
     while failures < 3 {
-        match tcp_host::connect(ip, port).await {
+        match tcp_host::connect(ip, port, &mut Credentials::default()).await {
             Ok((hw_desc, hw_config, tcp_stream)) => {
                 if !hw_desc.details.model.contains("Fake") {
                     fail(child, "Didn't connect to fake hardware pigglet");
@@ -245,6 +256,58 @@ where
     )
 }
 
+/// Delete any config saved by a previous test, so the pigglet starts with no pins configured
+#[allow(dead_code)]
+pub fn delete_configs() {
+    let crate_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let workspace_dir = crate_dir.parent().expect("Failed to get parent dir");
+    let config_file = workspace_dir.join(CONFIG_FILENAME);
+    println!("Deleting file: {config_file:?}");
+    let _ = std::fs::remove_file(config_file);
+    let config_file = workspace_dir.join("target/debug/").join(CONFIG_FILENAME);
+    println!("Deleting file: {config_file:?}");
+    let _ = std::fs::remove_file(config_file);
+}
+
+/// Send a message to the pigglet over TCP
+#[allow(dead_code)]
+pub async fn send(connection: TcpConnection, message: HardwareConfigMessage) {
+    tcp_host::send_config_message(connection, &message)
+        .await
+        .expect("Could not send message");
+}
+
+/// Wait for the first message received over TCP that `select` picks a value out of, skipping
+/// others
+#[allow(dead_code)]
+pub async fn wait_for<T>(
+    connection: TcpConnection,
+    mut select: impl FnMut(HardwareConfigMessage) -> Option<T>,
+) -> T {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let message = tcp_host::wait_for_remote_message(connection.clone())
+                .await
+                .expect("Could not receive message");
+            if let Some(value) = select(message) {
+                return value;
+            }
+        }
+    })
+    .await
+    .expect("Timeout waiting for message")
+}
+
+/// Wait for the next level change of pin `bcm` to be received over TCP
+#[allow(dead_code)]
+pub async fn wait_for_level_change(connection: TcpConnection, bcm: BCMPinNumber) -> LevelChange {
+    wait_for(connection, |message| match message {
+        IOLevelChanged(pin, level_change) if pin == bcm => Some(level_change),
+        _ => None,
+    })
+    .await
+}
+
 #[allow(dead_code)]
 pub async fn connect_and_test_iroh<F, Fut>(
     child: &mut Child,
@@ -256,10 +319,11 @@ pub async fn connect_and_test_iroh<F, Fut>(
     Fut: Future<Output = ()>,
 {
     let mut failures = 0;
+    let secret_key = iroh_host::load_default_client_key().expect("Could not load client key");
 
     while failures < 10 {
-        match iroh_host::connect(endpoint_id, &relay_url).await {
-            Ok((hw_desc, hw_config, connection, _endpoint)) => {
+        match iroh_host::connect(endpoint_id, &relay_url, secret_key.clone()).await {
+            Ok((hw_desc, hw_config, connection, _endpoint, _capabilities)) => {
                 if !hw_desc.details.model.contains("Fake") {
                     fail(child, "Didn't connect to fake hardware pigglet")
                 } else {
@@ -0,0 +1,300 @@
+#[cfg(feature = "tcp")]
+use crate::credentials::Credentials;
+#[cfg(feature = "iroh")]
+use crate::iroh_host;
+#[cfg(feature = "tcp")]
+use crate::tcp_host;
+#[cfg(feature = "tcp")]
+use crate::tcp_host::TcpConnection;
+#[cfg(feature = "tls")]
+use crate::tls_host;
+#[cfg(feature = "usb")]
+use crate::usb_host;
+#[cfg(feature = "usb")]
+use crate::usb_host::UsbConnection;
+use crate::HardwareConnection;
+use anyhow::bail;
+use futures::stream::{self, Stream};
+#[cfg(feature = "iroh")]
+use iroh::{endpoint::Connection, Endpoint};
+use pigdef::config::HardwareConfigMessage::{
+    IOLevelChanged, NewConfig, NewPinConfig, SafeLevelChanged,
+};
+use pigdef::config::{HardwareConfig, HardwareConfigMessage, LevelChange};
+use pigdef::description::{BCMPinNumber, HardwareDescription, PinLevel};
+use pigdef::hello::Capabilities;
+use pigdef::pin_function::PinFunction;
+#[cfg(feature = "tcp")]
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The transport used to communicate with a device
+#[derive(Clone)]
+enum Transport {
+    /// Connections encrypted with TLS are used in the same way
+    #[cfg(feature = "tcp")]
+    Tcp(TcpConnection),
+    /// The [Endpoint] must be kept alive for as long as the connection is in use
+    #[cfg(feature = "iroh")]
+    Iroh(Connection, Endpoint),
+    #[cfg(feature = "usb")]
+    Usb(UsbConnection),
+}
+
+impl Transport {
+    /// Send a [HardwareConfigMessage] to the device
+    async fn send(&self, message: &HardwareConfigMessage) -> anyhow::Result<()> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Transport::Tcp(connection) => {
+                tcp_host::send_config_message(connection.clone(), message).await
+            }
+            #[cfg(feature = "iroh")]
+            Transport::Iroh(connection, _) => {
+                iroh_host::send_config_message(&mut connection.clone(), message).await
+            }
+            #[cfg(feature = "usb")]
+            Transport::Usb(connection) => usb_host::send_config_message(connection, message).await,
+        }
+    }
+
+    /// Wait until a [HardwareConfigMessage] is received from the device
+    async fn receive(&self) -> anyhow::Result<HardwareConfigMessage> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Transport::Tcp(connection) => {
+                tcp_host::wait_for_remote_message(connection.clone()).await
+            }
+            #[cfg(feature = "iroh")]
+            Transport::Iroh(connection, _) => {
+                iroh_host::wait_for_remote_message(&mut connection.clone()).await
+            }
+            #[cfg(feature = "usb")]
+            Transport::Usb(connection) => usb_host::wait_for_remote_message(connection).await,
+        }
+    }
+
+    /// Inform the device we are disconnecting, and close the connection
+    async fn disconnect(self) -> anyhow::Result<()> {
+        match self {
+            #[cfg(feature = "tcp")]
+            Transport::Tcp(connection) => tcp_host::disconnect(connection).await,
+            #[cfg(feature = "iroh")]
+            Transport::Iroh(mut connection, _endpoint) => {
+                iroh_host::disconnect(&mut connection).await
+            }
+            #[cfg(feature = "usb")]
+            Transport::Usb(connection) => usb_host::disconnect(&connection).await,
+        }
+    }
+}
+
+/// A client of a remote device, connected to over any of the transports pignet was built with,
+/// so that pigg control can be embedded in other programs without knowing which is used.
+///
+/// The client keeps a copy of the device's config, updated with the changes it makes and
+/// those received from the device. Clones share the same connection and config, so one clone
+/// can wait for [LevelChange]s while another makes changes.
+#[derive(Clone)]
+pub struct Client {
+    transport: Transport,
+    description: HardwareDescription,
+    capabilities: Capabilities,
+    config: Arc<Mutex<HardwareConfig>>,
+}
+
+impl Client {
+    /// Connect to the device described by `target`, checking it is compatible and getting its
+    /// [HardwareDescription] and current [HardwareConfig]. Devices connected to over TCP that
+    /// require authentication, or with TLS, use the [Credentials] saved next to the executable,
+    /// and those connected to over Iroh the client key saved there
+    pub async fn connect(target: &HardwareConnection) -> anyhow::Result<Self> {
+        let (description, config, capabilities, transport) = match target {
+            #[cfg(feature = "tcp")]
+            HardwareConnection::Tcp(ip, port) => {
+                let mut credentials = Credentials::load_default()?;
+                let (description, config, connection) =
+                    tcp_host::connect(*ip, *port, &mut credentials).await?;
+                let capabilities = connection.capabilities();
+                (
+                    description,
+                    config,
+                    capabilities,
+                    Transport::Tcp(connection),
+                )
+            }
+            #[cfg(feature = "tls")]
+            HardwareConnection::Tls(ip, port, fingerprint) => {
+                let mut credentials = Credentials::load_default()?;
+                let (description, config, connection) =
+                    tls_host::connect(*ip, *port, *fingerprint, &mut credentials).await?;
+                let capabilities = connection.capabilities();
+                (
+                    description,
+                    config,
+                    capabilities,
+                    Transport::Tcp(connection),
+                )
+            }
+            #[cfg(feature = "iroh")]
+            HardwareConnection::Iroh(endpoint_id, relay) => {
+                let secret_key = iroh_host::load_default_client_key()?;
+                let (description, config, connection, endpoint, capabilities) =
+                    iroh_host::connect(endpoint_id, relay, secret_key).await?;
+                let transport = Transport::Iroh(connection, endpoint);
+                (description, config, capabilities, transport)
+            }
+            #[cfg(feature = "usb")]
+            HardwareConnection::Usb(serial_number) => {
+                let (description, config, connection) = usb_host::connect(serial_number).await?;
+                let capabilities = connection.capabilities();
+                (
+                    description,
+                    config,
+                    capabilities,
+                    Transport::Usb(connection),
+                )
+            }
+            _ => bail!("Cannot connect to '{target}'"),
+        };
+
+        Ok(Self {
+            transport,
+            description,
+            capabilities,
+            config: Arc::new(Mutex::new(config)),
+        })
+    }
+
+    /// Connect to the device listening on TCP `ip` and `port`, authenticating with
+    /// `credentials` if it requires that, and getting its [HardwareDescription] and current
+    /// [HardwareConfig]
+    #[cfg(feature = "tcp")]
+    pub async fn connect_tcp(
+        ip: IpAddr,
+        port: u16,
+        credentials: &mut Credentials,
+    ) -> anyhow::Result<Self> {
+        let (description, config, connection) = tcp_host::connect(ip, port, credentials).await?;
+        Ok(Self {
+            capabilities: connection.capabilities(),
+            transport: Transport::Tcp(connection),
+            description,
+            config: Arc::new(Mutex::new(config)),
+        })
+    }
+
+    /// The [HardwareDescription] of the device
+    pub fn description(&self) -> &HardwareDescription {
+        &self.description
+    }
+
+    /// The [Capabilities] of the device that can be used on this connection
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    /// A copy of the current [HardwareConfig] of the device
+    pub fn config(&self) -> HardwareConfig {
+        self.config
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Configure a pin with a new [PinFunction], or none to leave it unused
+    pub async fn set_pin_function(
+        &self,
+        bcm_pin_number: BCMPinNumber,
+        pin_function: Option<PinFunction>,
+    ) -> anyhow::Result<()> {
+        self.send(&NewPinConfig(bcm_pin_number, pin_function)).await
+    }
+
+    /// Set the level of a pin configured as an output
+    pub async fn set_output(
+        &self,
+        bcm_pin_number: BCMPinNumber,
+        level: PinLevel,
+    ) -> anyhow::Result<()> {
+        if !matches!(
+            self.config().pin_functions.get(&bcm_pin_number),
+            Some(PinFunction::Output(_))
+        ) {
+            bail!("Pin #{bcm_pin_number} is not configured as an output");
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        self.send(&IOLevelChanged(
+            bcm_pin_number,
+            LevelChange::new(level, now),
+        ))
+        .await
+    }
+
+    /// Send a [HardwareConfigMessage] to the device, updating the copy of its config
+    pub async fn send(&self, message: &HardwareConfigMessage) -> anyhow::Result<()> {
+        self.transport.send(message).await?;
+        self.update_config(message);
+        Ok(())
+    }
+
+    /// Wait until a [HardwareConfigMessage] is received from the device, updating the copy of
+    /// its config
+    pub async fn receive(&self) -> anyhow::Result<HardwareConfigMessage> {
+        let message = self.transport.receive().await?;
+        self.update_config(&message);
+        Ok(message)
+    }
+
+    /// A [Stream] of the [LevelChange]s of the device's pins, such as inputs changing or
+    /// outputs being set by other clients. Other messages received are used to keep the copy
+    /// of the config up to date. The stream ends after returning an error receiving.
+    pub fn level_changes(&self) -> impl Stream<Item = anyhow::Result<(BCMPinNumber, LevelChange)>> {
+        stream::unfold(Some(self.clone()), |client| async move {
+            let client = client?;
+            loop {
+                match client.receive().await {
+                    Ok(IOLevelChanged(bcm_pin_number, level_change)) => {
+                        return Some((Ok((bcm_pin_number, level_change)), Some(client)))
+                    }
+                    Ok(_) => {}
+                    Err(e) => return Some((Err(e), None)),
+                }
+            }
+        })
+    }
+
+    /// Inform the device we are disconnecting, and close the connection
+    pub async fn disconnect(self) -> anyhow::Result<()> {
+        self.transport.disconnect().await
+    }
+
+    /// Update the copy of the device's config with a change sent to, or received from, it
+    fn update_config(&self, message: &HardwareConfigMessage) {
+        let mut config = self.config.lock().unwrap_or_else(PoisonError::into_inner);
+        match message {
+            NewConfig(new_config) => *config = new_config.clone(),
+            NewPinConfig(bcm_pin_number, None) => {
+                config.pin_functions.remove(bcm_pin_number);
+            }
+            NewPinConfig(bcm_pin_number, Some(pin_function)) => {
+                config.pin_functions.insert(*bcm_pin_number, *pin_function);
+            }
+            IOLevelChanged(bcm_pin_number, level_change) => {
+                if let Some(PinFunction::Output(level)) =
+                    config.pin_functions.get_mut(bcm_pin_number)
+                {
+                    *level = Some(level_change.new_level);
+                }
+            }
+            SafeLevelChanged(bcm_pin_number, None) => {
+                config.safe_levels.remove(bcm_pin_number);
+            }
+            SafeLevelChanged(bcm_pin_number, Some(level)) => {
+                config.safe_levels.insert(*bcm_pin_number, *level);
+            }
+            _ => {}
+        }
+    }
+}
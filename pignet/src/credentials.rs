@@ -0,0 +1,151 @@
+use pigdef::auth::{AuthKey, PairingCode};
+use pigdef::description::SerialNumber;
+#[cfg(feature = "tls")]
+use pigdef::tls::Fingerprint;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env::current_exe;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/// The name of the file, in the same directory as the executable, that the keys of devices
+/// paired with over TCP, and the fingerprints of the certificates of those connected to with
+/// TLS, are saved in
+pub const KEYS_FILENAME: &str = "pigg_keys.json";
+
+/// The keys of the devices a client has paired with, by serial number, used to authenticate
+/// with them when connecting over TCP, and a pairing code to pair with a new one.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Credentials {
+    keys: BTreeMap<SerialNumber, AuthKey>,
+    /// The fingerprints of the TLS certificates of devices, pinned when first connected to
+    #[cfg(feature = "tls")]
+    #[serde(default)]
+    fingerprints: BTreeMap<SerialNumber, Fingerprint>,
+    /// The file the keys are saved in when a new one is added, if any
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    #[serde(skip)]
+    pairing_code: Option<PairingCode>,
+}
+
+/// Reasons a device that requires authentication refused a connection
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuthError {
+    /// There is no key for the device, so a pairing code is needed to pair with it
+    NotPaired(SerialNumber),
+    /// The device did not accept the pairing code
+    CodeRejected(SerialNumber),
+    /// The device did not accept the key saved for it, so it needs to be paired with again
+    KeyRejected(SerialNumber),
+    /// The device presented a different TLS certificate than when it was first connected to
+    #[cfg(feature = "tls")]
+    CertificateChanged(SerialNumber),
+}
+
+impl Display for AuthError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthError::NotPaired(serial) => write!(
+                f,
+                "Device '{serial}' requires pairing. Please enter the pairing code it shows"
+            ),
+            AuthError::CodeRejected(serial) => write!(
+                f,
+                "Device '{serial}' did not accept the pairing code. Please check the code it shows"
+            ),
+            AuthError::KeyRejected(serial) => write!(
+                f,
+                "Device '{serial}' did not accept the saved key. Please pair with it again"
+            ),
+            #[cfg(feature = "tls")]
+            AuthError::CertificateChanged(serial) => write!(
+                f,
+                "Device '{serial}' presented a different TLS certificate than before. \
+                If it was regenerated, remove the device from {KEYS_FILENAME}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+impl Credentials {
+    /// Load the [Credentials] saved at `path`, or none if there is no file there yet. Keys of
+    /// devices paired with are saved to it
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let mut credentials = match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => Credentials::default(),
+            Err(e) => return Err(e.into()),
+        };
+        credentials.path = Some(path.to_path_buf());
+        Ok(credentials)
+    }
+
+    /// Load the [Credentials] saved in [KEYS_FILENAME] next to the executable
+    pub fn load_default() -> anyhow::Result<Self> {
+        Self::load(&current_exe()?.with_file_name(KEYS_FILENAME))
+    }
+
+    /// Pair using `code` with the next device connected to that requires authentication,
+    /// replacing any key saved for it
+    pub fn set_pairing_code(&mut self, code: PairingCode) {
+        self.pairing_code = Some(code);
+    }
+
+    /// Return true if there is a key saved for the device with `serial`
+    pub fn is_paired(&self, serial: &str) -> bool {
+        self.keys.contains_key(serial)
+    }
+
+    /// The key saved for the device with `serial`
+    pub(crate) fn key(&self, serial: &str) -> Option<&AuthKey> {
+        self.keys.get(serial)
+    }
+
+    /// Take the pairing code, so it is only used once
+    pub(crate) fn take_pairing_code(&mut self) -> Option<PairingCode> {
+        self.pairing_code.take()
+    }
+
+    /// Save the `key` of the device with `serial`, after pairing with it
+    pub(crate) fn add_key(&mut self, serial: SerialNumber, key: AuthKey) -> anyhow::Result<()> {
+        self.keys.insert(serial, key);
+        self.save()
+    }
+
+    /// Check `fingerprint` is that of the certificate pinned for the device with `serial`, or
+    /// pin it if none is
+    #[cfg(feature = "tls")]
+    pub(crate) fn pin_fingerprint(
+        &mut self,
+        serial: &str,
+        fingerprint: Fingerprint,
+    ) -> anyhow::Result<()> {
+        match self.fingerprints.get(serial) {
+            Some(pinned) if *pinned == fingerprint => Ok(()),
+            Some(_) => Err(AuthError::CertificateChanged(serial.to_string()).into()),
+            None => {
+                self.fingerprints.insert(serial.to_string(), fingerprint);
+                self.save()
+            }
+        }
+    }
+
+    /// Save the keys and fingerprints to the file they were loaded from, if any
+    fn save(&self) -> anyhow::Result<()> {
+        if let Some(path) = &self.path {
+            let mut options = fs::OpenOptions::new();
+            options.write(true).create(true).truncate(true);
+            // The keys let anyone that has them control the devices, so only the owner can read them
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            let mut file = options.open(path)?;
+            file.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,5 @@
-use anyhow::ensure;
-use iroh::endpoint::VarInt;
+use anyhow::{anyhow, ensure};
+use iroh::endpoint::{ConnectionError, VarInt};
 use iroh::{
     endpoint::{presets, Connection},
     Endpoint, EndpointAddr, EndpointId, RelayUrl, SecretKey, TransportAddr,
@@ -7,8 +7,56 @@ use iroh::{
 use pigdef::config::HardwareConfigMessage::Disconnect;
 use pigdef::config::{HardwareConfig, HardwareConfigMessage};
 use pigdef::description::HardwareDescription;
+use pigdef::hello::{Capabilities, HandshakeError, Hello};
 use pigdef::net_values::PIGGLET_ALPN;
+use std::env::current_exe;
+use std::fs;
 use std::io;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+
+/// The name of the file, in the same directory as the executable, that the Iroh secret key of
+/// this client is saved in, so its endpoint id stays the same and can be allowed by devices
+pub const CLIENT_KEY_FILENAME: &str = "pigg_iroh.key";
+
+/// Load the Iroh [SecretKey] of this client saved at `key_path`, or if there is none generate a
+/// new one and save it there, readable only by its owner
+pub fn load_client_key(key_path: &Path) -> anyhow::Result<SecretKey> {
+    match fs::read(key_path) {
+        Ok(bytes) => {
+            let bytes: [u8; 32] = bytes
+                .try_into()
+                .map_err(|_| anyhow!("Invalid Iroh secret key in {}", key_path.display()))?;
+            Ok(SecretKey::from_bytes(&bytes))
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            let secret_key = SecretKey::generate();
+            let mut options = fs::OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            options.open(key_path)?.write_all(&secret_key.to_bytes())?;
+            Ok(secret_key)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Load the Iroh [SecretKey] of this client saved in [CLIENT_KEY_FILENAME] next to the executable
+pub fn load_default_client_key() -> anyhow::Result<SecretKey> {
+    load_client_key(&current_exe()?.with_file_name(CLIENT_KEY_FILENAME))
+}
+
+/// Use the reason the device gave for closing the connection as the error, if it gave one, as
+/// it does when this client is not allowed to connect
+fn closed_reason(error: ConnectionError) -> anyhow::Error {
+    match error {
+        ConnectionError::ApplicationClosed(close) if !close.reason.is_empty() => {
+            anyhow!(String::from_utf8_lossy(&close.reason).into_owned())
+        }
+        error => error.into(),
+    }
+}
 
 /// Wait until we receive a message from remote hardware
 pub async fn wait_for_remote_message(
@@ -42,16 +90,24 @@ pub async fn send_config_message(
     Ok(())
 }
 
-/// Connect to an Iroh-Net node using the [NodeId] and an optional [RelayUrl]
+/// Connect to an Iroh-Net node using the [NodeId] and an optional [RelayUrl], and check
+/// the [Hello] it sends is compatible. This client is identified to the node by `secret_key`,
+/// so the node can check it is allowed to connect
 ///
 /// Returns the Endpoint along with the connection — the Endpoint must be kept
-/// alive for as long as the Connection is in use.
+/// alive for as long as the Connection is in use — and the [Capabilities] of the device
+/// that can be used on the connection.
 pub async fn connect(
     endpoint_id: &EndpointId,
     relay: &Option<RelayUrl>,
-) -> anyhow::Result<(HardwareDescription, HardwareConfig, Connection, Endpoint)> {
-    let secret_key = SecretKey::generate();
-
+    secret_key: SecretKey,
+) -> anyhow::Result<(
+    HardwareDescription,
+    HardwareConfig,
+    Connection,
+    Endpoint,
+    Capabilities,
+)> {
     let endpoint = Endpoint::builder(presets::N0)
         .secret_key(secret_key)
         .alpns(vec![PIGGLET_ALPN.to_vec()])
@@ -79,11 +135,15 @@ pub async fn connect(
     let connection = endpoint.connect(addr, PIGGLET_ALPN).await?;
 
     // create a uni receiver to receive the hardware description on
-    let mut gui_receiver = connection.accept_uni().await?;
+    let mut gui_receiver = connection.accept_uni().await.map_err(closed_reason)?;
     let message = gui_receiver.read_to_end(4096).await?;
-    let reply: (HardwareDescription, HardwareConfig) = postcard::from_bytes(&message)?;
+    // The Hello is sent first, followed by the hardware description and config
+    let (hello, rest): (Hello, &[u8]) =
+        postcard::take_from_bytes(&message).map_err(|_| HandshakeError::MissingHello)?;
+    let capabilities = hello.negotiate()?;
+    let reply: (HardwareDescription, HardwareConfig) = postcard::from_bytes(rest)?;
 
-    Ok((reply.0, reply.1, connection, endpoint))
+    Ok((reply.0, reply.1, connection, endpoint, capabilities))
 }
 
 /// Inform the device that we are disconnecting from the Iroh connection and close it
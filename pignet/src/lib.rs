@@ -5,19 +5,30 @@
 use iroh::{EndpointId, RelayUrl};
 #[cfg(feature = "usb")]
 use pigdef::description::SerialNumber;
+#[cfg(feature = "tls")]
+use pigdef::tls::Fingerprint;
 use std::fmt::{Display, Formatter};
 #[cfg(feature = "tcp")]
 use std::net::IpAddr;
 
+#[cfg(any(feature = "tcp", feature = "iroh", feature = "usb"))]
+pub mod client;
+#[cfg(feature = "tcp")]
+pub mod credentials;
 #[cfg(feature = "discovery")]
 pub mod discovery;
 #[cfg(feature = "iroh")]
 pub mod iroh_host;
 #[cfg(feature = "tcp")]
 pub mod tcp_host;
+#[cfg(feature = "tls")]
+pub mod tls_host;
 #[cfg(feature = "usb")]
 pub mod usb_host;
 
+#[cfg(any(feature = "tcp", feature = "iroh", feature = "usb"))]
+pub use client::Client;
+
 /// A type of connection to a piece of hardware
 #[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
 pub enum HardwareConnection {
@@ -30,6 +41,9 @@ pub enum HardwareConnection {
     Iroh(EndpointId, Option<RelayUrl>),
     #[cfg(feature = "tcp")]
     Tcp(IpAddr, u16),
+    /// TCP encrypted with TLS, to a device whose certificate has the [Fingerprint]
+    #[cfg(feature = "tls")]
+    Tls(IpAddr, u16, Fingerprint),
 }
 
 impl HardwareConnection {
@@ -44,6 +58,8 @@ impl HardwareConnection {
             Self::Iroh(_, _) => "Iroh",
             #[cfg(feature = "tcp")]
             Self::Tcp(_, _) => "TCP",
+            #[cfg(feature = "tls")]
+            Self::Tls(_, _, _) => "TLS",
         }
     }
 }
@@ -59,6 +75,8 @@ impl Display for HardwareConnection {
             Self::Iroh(endpoint_id, _relay_url) => write!(f, "Iroh: {endpoint_id}"),
             #[cfg(feature = "tcp")]
             Self::Tcp(ip, port) => write!(f, "TCP: {ip}:{port}"),
+            #[cfg(feature = "tls")]
+            Self::Tls(ip, port, _) => write!(f, "TLS: {ip}:{port}"),
         }
     }
 }
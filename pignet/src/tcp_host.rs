@@ -1,56 +1,201 @@
-use anyhow::ensure;
-use async_std::io::ReadExt;
+use async_std::io::{Read, Write};
 use async_std::net::TcpStream;
 use async_std::prelude::*;
+use async_std::sync::Mutex;
+use pigdef::auth;
+use pigdef::auth::{AuthResponse, AuthResult, Challenge, Nonce};
 use pigdef::description::HardwareDescription;
+use pigdef::framing;
+use pigdef::framing::{FrameDecoder, FramingError};
+use pigdef::hello::{Capabilities, HandshakeError, Hello};
+#[cfg(feature = "tls")]
+use pigdef::tls::Fingerprint;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use std::io;
 use std::net::IpAddr;
+use std::sync::Arc;
 
+use crate::credentials::{AuthError, Credentials};
 use pigdef::config::HardwareConfigMessage::Disconnect;
 use pigdef::config::{HardwareConfig, HardwareConfigMessage};
 
-/// Wait until we receive a message from remote hardware over `stream`[TcpStream]
+/// The half of a connection that messages are received from
+pub(crate) type Reader = Box<dyn Read + Send + Unpin>;
+
+/// The half of a connection that messages are sent on
+pub(crate) type Writer = Box<dyn Write + Send + Unpin>;
+
+/// A TCP connection to a remote device, encrypted with TLS or not. Clones share the same
+/// underlying stream and the [FrameDecoder] that holds any bytes received that have not been
+/// decoded into a message yet
+#[derive(Clone)]
+pub struct TcpConnection {
+    reader: Arc<Mutex<(Reader, FrameDecoder)>>,
+    writer: Arc<Mutex<Writer>>,
+    capabilities: Capabilities,
+    /// The fingerprint of the certificate the device presented, if the connection uses TLS
+    #[cfg(feature = "tls")]
+    pub(crate) fingerprint: Option<Fingerprint>,
+}
+
+impl TcpConnection {
+    pub(crate) fn new(reader: Reader, writer: Writer) -> Self {
+        Self {
+            reader: Arc::new(Mutex::new((reader, FrameDecoder::new()))),
+            writer: Arc::new(Mutex::new(writer)),
+            capabilities: Capabilities::NONE,
+            #[cfg(feature = "tls")]
+            fingerprint: None,
+        }
+    }
+
+    /// The [Capabilities] of the remote device that can be used on this connection
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    /// Wait until a complete message has been received and return it.
+    /// Cancelling this future (e.g. in a `select!`) does not lose any bytes already received
+    async fn receive<T: DeserializeOwned>(&self) -> anyhow::Result<T> {
+        let mut reader = self.reader.lock().await;
+        let (stream, decoder) = &mut *reader;
+        loop {
+            if let Some(message) = decoder.decode()? {
+                return Ok(message);
+            }
+
+            let length = stream.read(decoder.spare()).await?;
+            if length == 0 {
+                return Err(io::Error::new(io::ErrorKind::BrokenPipe, "Connection closed").into());
+            }
+            decoder.filled(length);
+        }
+    }
+
+    /// Send a serializable message to the device
+    async fn send(&self, message: &impl Serialize) -> anyhow::Result<()> {
+        let mut writer = self.writer.lock().await;
+        writer.write_all(&framing::encode_to_vec(message)?).await?;
+        // TLS buffers what is written until it is flushed
+        writer.flush().await?;
+        Ok(())
+    }
+
+    /// Check the certificate of a TLS connection is the one pinned for the device with
+    /// `serial` in `credentials`, or pin it if this is the first connection to the device
+    #[cfg(feature = "tls")]
+    fn pin(&self, serial: &str, credentials: &mut Credentials) -> anyhow::Result<()> {
+        match self.fingerprint {
+            Some(fingerprint) => credentials.pin_fingerprint(serial, fingerprint),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Wait until we receive a message from remote hardware over the [TcpConnection]
 pub async fn wait_for_remote_message(
-    mut stream: TcpStream,
+    connection: TcpConnection,
 ) -> Result<HardwareConfigMessage, anyhow::Error> {
-    let mut payload = vec![0u8; 1024];
-    let length = stream.read(&mut payload).await?;
-    ensure!(
-        length != 0,
-        io::Error::new(io::ErrorKind::BrokenPipe, "Connection closed")
-    );
-
-    // jonesy:allow(bounds) length bounded by buffer size from stream.read
-    Ok(postcard::from_bytes(&payload[0..length])?)
+    connection.receive().await
 }
 
-/// Send config change received form the GUI to the remote hardware over `stream`[TcpStream]
+/// Send config change received form the GUI to the remote hardware over the [TcpConnection]
 pub async fn send_config_message(
-    mut stream: TcpStream,
+    connection: TcpConnection,
     config_change_message: &HardwareConfigMessage,
 ) -> anyhow::Result<()> {
-    stream
-        .write_all(&postcard::to_allocvec(&config_change_message)?)
-        .await?;
-    Ok(())
+    connection.send(config_change_message).await
 }
 
-/// Connect to a remote pigglet and get the initial message with the [HardwareDescription],
-/// return that description plus the [TcpStream] to be used to communicate with it.
+/// Connect to a remote pigglet, check its [Hello] is compatible, authenticate with it using
+/// `credentials` if it requires that, and get the initial message with the
+/// [HardwareDescription], return that description plus the [TcpConnection] to be used to
+/// communicate with it.
 pub async fn connect(
     ip: IpAddr,
     port: u16,
-) -> anyhow::Result<(HardwareDescription, HardwareConfig, TcpStream)> {
-    let mut stream = TcpStream::connect(format!("{ip}:{port}")).await?;
-    // This array needs to be big enough for HardwareDescription
-    let mut payload = vec![0u8; 1024];
-    let length = stream.read(&mut payload).await?;
-    // jonesy:allow(bounds) length bounded by buffer size from stream.read
-    let (hw_description, hw_config) = postcard::from_bytes(&payload[0..length])?;
-    Ok((hw_description, hw_config, stream))
+    credentials: &mut Credentials,
+) -> anyhow::Result<(HardwareDescription, HardwareConfig, TcpConnection)> {
+    let stream = TcpStream::connect(format!("{ip}:{port}")).await?;
+    let connection = TcpConnection::new(Box::new(stream.clone()), Box::new(stream));
+    handshake(connection, credentials).await
+}
+
+/// Check the [Hello] received on a new `connection` is compatible, authenticate using
+/// `credentials` if the device requires that, and get the [HardwareDescription] and
+/// [HardwareConfig] it sends
+pub(crate) async fn handshake(
+    mut connection: TcpConnection,
+    credentials: &mut Credentials,
+) -> anyhow::Result<(HardwareDescription, HardwareConfig, TcpConnection)> {
+    let hello: Hello =
+        connection
+            .receive()
+            .await
+            .map_err(|e| match e.downcast_ref::<FramingError>() {
+                Some(FramingError::Deserialization) => HandshakeError::MissingHello.into(),
+                _ => e,
+            })?;
+    connection.capabilities = hello.negotiate()?;
+    if connection.capabilities.contains(Capabilities::AUTH) {
+        let challenge: Challenge = connection.receive().await?;
+        // Only prove we have the key to the device the certificate was pinned for
+        #[cfg(feature = "tls")]
+        connection.pin(&challenge.serial, credentials)?;
+        authenticate(&connection, challenge, credentials).await?;
+    }
+    let (hw_description, hw_config): (HardwareDescription, HardwareConfig) =
+        connection.receive().await?;
+    #[cfg(feature = "tls")]
+    connection.pin(&hw_description.details.serial, credentials)?;
+    Ok((hw_description, hw_config, connection))
+}
+
+/// Reply to the challenge of a device that requires authentication. If `credentials` has a
+/// pairing code, pair with the device and save the key it sends, otherwise prove we have the
+/// key saved for it
+async fn authenticate(
+    connection: &TcpConnection,
+    challenge: Challenge,
+    credentials: &mut Credentials,
+) -> anyhow::Result<()> {
+    let serial = challenge.serial;
+    let pairing = match credentials.take_pairing_code() {
+        Some(code) => {
+            let mut client_nonce: Nonce = [0; 16];
+            getrandom::fill(&mut client_nonce)
+                .map_err(|e| anyhow::anyhow!("Could not get random bytes: {e}"))?;
+            let secret = code.secret(&serial);
+            let proof = auth::pair_proof(&secret, &challenge.nonce, &client_nonce);
+            connection
+                .send(&AuthResponse::Pair(client_nonce, proof))
+                .await?;
+            Some((secret, client_nonce))
+        }
+        None => {
+            let key = credentials
+                .key(&serial)
+                .ok_or(AuthError::NotPaired(serial.clone()))?;
+            let proof = auth::key_proof(key, &challenge.nonce);
+            connection.send(&AuthResponse::Key(proof)).await?;
+            None
+        }
+    };
+
+    match (connection.receive().await?, pairing) {
+        (AuthResult::Accepted, None) => Ok(()),
+        (AuthResult::Paired(sealed), Some((secret, client_nonce))) => {
+            let key = auth::open_key(&sealed, &secret, &challenge.nonce, &client_nonce);
+            credentials.add_key(serial, key)
+        }
+        (AuthResult::Rejected, Some(_)) => Err(AuthError::CodeRejected(serial).into()),
+        (AuthResult::Rejected, None) => Err(AuthError::KeyRejected(serial).into()),
+        _ => anyhow::bail!("Unexpected reply from device '{serial}' when authenticating"),
+    }
 }
 
 /// Inform the device that we are disconnecting from TCP connection
-pub async fn disconnect(stream: TcpStream) -> anyhow::Result<()> {
-    send_config_message(stream, &Disconnect).await
+pub async fn disconnect(connection: TcpConnection) -> anyhow::Result<()> {
+    send_config_message(connection, &Disconnect).await
 }
@@ -0,0 +1,101 @@
+use crate::credentials::Credentials;
+use crate::tcp_host;
+use crate::tcp_host::TcpConnection;
+use async_std::net::TcpStream;
+use futures::AsyncReadExt;
+use futures_rustls::rustls::client::danger::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use futures_rustls::rustls::crypto::{
+    ring, verify_tls12_signature, verify_tls13_signature, WebPkiSupportedAlgorithms,
+};
+use futures_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use futures_rustls::rustls::{
+    CertificateError, ClientConfig, DigitallySignedStruct, Error, SignatureScheme,
+};
+use futures_rustls::TlsConnector;
+use pigdef::config::HardwareConfig;
+use pigdef::description::HardwareDescription;
+use pigdef::tls::Fingerprint;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+/// Accepts only the certificate with the [Fingerprint] expected, as devices use self-signed
+/// certificates that cannot be checked against a certificate authority
+#[derive(Debug)]
+struct PinnedCertificate {
+    fingerprint: Fingerprint,
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl ServerCertVerifier for PinnedCertificate {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, Error> {
+        if Fingerprint::of(end_entity) == self.fingerprint {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure,
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+/// Connect to a remote pigglet over TLS, checking its certificate has `fingerprint`, and
+/// that it is the certificate pinned in `credentials` for the device, then continue as
+/// [tcp_host::connect] does. The [TcpConnection] returned is used in the same way.
+pub async fn connect(
+    ip: IpAddr,
+    port: u16,
+    fingerprint: Fingerprint,
+    credentials: &mut Credentials,
+) -> anyhow::Result<(HardwareDescription, HardwareConfig, TcpConnection)> {
+    let provider = Arc::new(ring::default_provider());
+    let verifier = PinnedCertificate {
+        fingerprint,
+        algorithms: provider.signature_verification_algorithms,
+    };
+    let config = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+
+    let stream = TcpStream::connect(format!("{ip}:{port}")).await?;
+    let stream = TlsConnector::from(Arc::new(config))
+        .connect(ServerName::from(ip), stream)
+        .await
+        .map_err(|e| anyhow::anyhow!("TLS handshake with {ip}:{port} failed: {e}"))?;
+    let (reader, writer) = stream.split();
+    let mut connection = TcpConnection::new(Box::new(reader), Box::new(writer));
+    connection.fingerprint = Some(fingerprint);
+    tcp_host::handshake(connection, credentials).await
+}
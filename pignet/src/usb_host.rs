@@ -8,6 +8,7 @@ use anyhow::{anyhow, Error};
 use nusb::transfer::Buffer;
 use nusb::transfer::In;
 use nusb::transfer::Interrupt;
+use nusb::transfer::TransferError;
 use nusb::transfer::{ControlIn, ControlOut, ControlType, Recipient};
 use nusb::Interface;
 use nusb::MaybeFuture;
@@ -18,13 +19,14 @@ use pigdef::description::HardwareDetails;
 #[cfg(feature = "discovery")]
 use pigdef::description::WiFiDetails;
 use pigdef::description::{HardwareDescription, SerialNumber, SsidSpec};
+use pigdef::hello::{Capabilities, HandshakeError, Hello};
 #[cfg(feature = "discovery")]
 use pigdef::usb_values::GET_HARDWARE_DETAILS_VALUE;
 #[cfg(feature = "discovery")]
 use pigdef::usb_values::GET_WIFI_VALUE;
 use pigdef::usb_values::{
-    GET_HARDWARE_DESCRIPTION_VALUE, HW_CONFIG_MESSAGE, PIGGUI_REQUEST, RESET_SSID_VALUE,
-    SET_SSID_VALUE,
+    GET_HARDWARE_DESCRIPTION_VALUE, GET_HELLO_VALUE, HW_CONFIG_MESSAGE, PIGGUI_REQUEST,
+    RESET_SSID_VALUE, SET_SSID_VALUE,
 };
 use serde::de::DeserializeOwned;
 use serde::Deserialize;
@@ -37,6 +39,16 @@ use std::time::Duration;
 const CONTROL_IN_TIMEOUT: Duration = Duration::from_secs(10);
 const CONTROL_OUT_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// [ControlIn] "command" to request the [Hello]
+const GET_HELLO: ControlIn = ControlIn {
+    control_type: ControlType::Vendor,
+    recipient: Recipient::Interface,
+    request: PIGGUI_REQUEST,
+    value: GET_HELLO_VALUE,
+    index: 0,
+    length: 64,
+};
+
 /// [ControlIn] "command" to request the [HardwareDescription]
 const GET_HARDWARE_DESCRIPTION: ControlIn = ControlIn {
     control_type: ControlType::Vendor,
@@ -91,6 +103,15 @@ where
     Ok(postcard::from_bytes(&data[0..length])?)
 }
 
+/// Request the [Hello] from a device over USB [ControlIn] and check it is compatible.
+/// Devices that predate the handshake reject the request, so they are reported as too old
+async fn get_capabilities(porky: &Interface) -> Result<Capabilities, Error> {
+    let hello: Hello = receive_control_in(porky, GET_HELLO)
+        .await
+        .map_err(|_| HandshakeError::MissingHello)?;
+    Ok(hello.negotiate()?)
+}
+
 /// Request [HardwareDescription] from compatible device over USB [ControlIn]
 async fn get_hardware_description(porky: &Interface) -> Result<HardwareDescription, Error> {
     // jonesy:allow(bounds) propagates from receive_control_in
@@ -178,6 +199,14 @@ pub async fn reset_ssid_spec(serial_number: SerialNumber) -> Result<(), Error> {
 #[derive(Clone)]
 pub struct UsbConnection {
     interface: Interface,
+    capabilities: Capabilities,
+}
+
+impl UsbConnection {
+    /// The [Capabilities] of the connected device that can be used on this connection
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
 }
 
 /// Wait until we receive a message from device over USB Interrupt In
@@ -195,9 +224,14 @@ where
         // jonesy:allow(div_zero, expect, invalid_enum) nusb/postcard deserialization internals
         endpoint.submit(Buffer::new(1024));
         let completion = endpoint.next_complete().await;
-        if completion.status.is_ok() {
-            let msg = postcard::from_bytes(&completion.buffer)?;
-            return Ok(msg);
+        match completion.status {
+            Ok(()) => {
+                let msg = postcard::from_bytes(&completion.buffer)?;
+                return Ok(msg);
+            }
+            // The device was unplugged or reset, so no more messages will arrive from it
+            Err(TransferError::Disconnected) => return Err(anyhow!("USB device disconnected")),
+            Err(_) => {}
         }
         tokio::time::sleep(Duration::from_secs(1)).await;
     }
@@ -229,13 +263,17 @@ pub async fn disconnect(porky: &UsbConnection) -> Result<(), Error> {
     //    receive_control_in(&porky.interface, DISCONNECT).await
 }
 
-/// Connect to a device by USB with the specified `serial_number` [SerialNumber]
-/// Return the [HardwareDescription] and [HardwareConfig] along with the [Interface] to use
+/// Connect to a device by USB with the specified `serial_number` [SerialNumber], checking it
+/// is compatible. Return the [HardwareDescription] and [HardwareConfig] along with the [Interface] to use
 pub async fn connect(
     serial_number: &SerialNumber,
 ) -> Result<(HardwareDescription, HardwareConfig, UsbConnection), Error> {
     let interface = interface_from_serial(serial_number).await?;
-    let connection = UsbConnection { interface };
+    let capabilities = get_capabilities(&interface).await?;
+    let connection = UsbConnection {
+        interface,
+        capabilities,
+    };
     let hardware_description = get_hardware_description(&connection.interface).await?;
     send_config_message(&connection, &HardwareConfigMessage::GetConfig).await?;
     let hardware_config: HardwareConfig = wait_for_remote_message(&connection).await?;
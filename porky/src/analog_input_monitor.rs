@@ -0,0 +1,117 @@
+use crate::HARDWARE_EVENT_CHANNEL;
+use defmt::{debug, error};
+use embassy_futures::select::{select, Either};
+use embassy_rp::adc::{Adc, Async, Channel};
+use embassy_rp::gpio::{Flex, Pull};
+use embassy_rp::peripherals::{PIN_26, PIN_27, PIN_28};
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::mutex::Mutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Ticker};
+use pigdef::config::AnalogSample;
+use pigdef::config::HardwareConfigMessage::AnalogSampled;
+use pigdef::description::BCMPinNumber;
+use pigdef::pin_function::MAX_SAMPLE_RATE;
+
+/// The pins that are connected to a channel of the ADC: GP26 (ADC0), GP27 (ADC1) and GP28 (ADC2)
+const ANALOG_PINS: [BCMPinNumber; 3] = [26, 27, 28];
+
+/// The ADC, shared by the tasks sampling each of the analog inputs
+pub static ADC: Mutex<ThreadModeRawMutex, Option<Adc<'static, Async>>> = Mutex::new(None);
+
+/// Signals to the sampling task of each analog pin to stop sampling
+static STOP: [Signal<ThreadModeRawMutex, ()>; 3] = [Signal::new(), Signal::new(), Signal::new()];
+
+/// Signals from the sampling task of each analog pin that it has stopped and released the pin
+static STOPPED: [Signal<ThreadModeRawMutex, ()>; 3] = [Signal::new(), Signal::new(), Signal::new()];
+
+/// Return the index of the ADC channel connected to the pin, if there is one
+fn analog_index(bcm_pin_number: BCMPinNumber) -> Option<usize> {
+    ANALOG_PINS.iter().position(|pin| *pin == bcm_pin_number)
+}
+
+/// Return true if the pin can be used as an analog input
+pub fn is_analog_pin(bcm_pin_number: BCMPinNumber) -> bool {
+    analog_index(bcm_pin_number).is_some()
+}
+
+/// Create an ADC [Channel] for an analog pin. The [Flex] previously used for the pin must have
+/// been dropped, so that only one driver is using the pin at a time
+pub fn analog_channel(bcm_pin_number: BCMPinNumber) -> Option<Channel<'static>> {
+    // SAFETY: The Flex that owned the pin has been dropped by the caller
+    match bcm_pin_number {
+        26 => Some(Channel::new_pin(unsafe { PIN_26::steal() }, Pull::None)),
+        27 => Some(Channel::new_pin(unsafe { PIN_27::steal() }, Pull::None)),
+        28 => Some(Channel::new_pin(unsafe { PIN_28::steal() }, Pull::None)),
+        _ => None,
+    }
+}
+
+/// Stop sampling an analog input and recover a [Flex] for the pin, once the sampling task
+/// has dropped the ADC [Channel] that was using it
+pub async fn stop_analog_input(bcm_pin_number: BCMPinNumber) -> Option<Flex<'static>> {
+    let index = analog_index(bcm_pin_number)?;
+    STOPPED[index].reset();
+    STOP[index].signal(());
+    STOPPED[index].wait().await;
+    analog_flex(bcm_pin_number)
+}
+
+/// Create a [Flex] for an analog pin, once the ADC [Channel] that was using it has been dropped
+pub fn analog_flex(bcm_pin_number: BCMPinNumber) -> Option<Flex<'static>> {
+    // SAFETY: The ADC Channel that used the pin has been dropped by the caller
+    match bcm_pin_number {
+        26 => Some(Flex::new(unsafe { PIN_26::steal() })),
+        27 => Some(Flex::new(unsafe { PIN_27::steal() })),
+        28 => Some(Flex::new(unsafe { PIN_28::steal() })),
+        _ => None,
+    }
+}
+
+/// Sample an analog input `sample_rate` times per second, sending each sample to the GUI,
+/// until signalled to stop, when the ADC [Channel] is dropped so the pin can be reused
+#[embassy_executor::task(pool_size = 3)]
+pub async fn monitor_analog_input(
+    bcm_pin_number: BCMPinNumber,
+    sample_rate: u32,
+    mut channel: Channel<'static>,
+) {
+    let Some(index) = analog_index(bcm_pin_number) else {
+        return;
+    };
+    STOP[index].reset();
+    let mut ticker = Ticker::every(Duration::from_hz(
+        sample_rate.clamp(1, MAX_SAMPLE_RATE) as u64
+    ));
+
+    loop {
+        match select(ticker.next(), STOP[index].wait()).await {
+            Either::First(()) => {
+                let value = match ADC.lock().await.as_mut() {
+                    Some(adc) => adc.read(&mut channel).await.ok(),
+                    None => None,
+                };
+                match value {
+                    Some(value) => send_analog_sample(bcm_pin_number, value).await,
+                    None => error!("Pin #{} - Could not read ADC", bcm_pin_number),
+                }
+            }
+            Either::Second(()) => {
+                debug!("Analog Input Monitor releasing Pin");
+                break;
+            }
+        }
+    }
+
+    drop(channel);
+    STOPPED[index].signal(());
+}
+
+/// Send a sample of an analog input to the GUI, timestamping with the Duration since boot
+async fn send_analog_sample(bcm: BCMPinNumber, value: u16) {
+    let sample = AnalogSample::new(value, Instant::now().duration_since(Instant::MIN).into());
+    HARDWARE_EVENT_CHANNEL
+        .sender()
+        .send(AnalogSampled(bcm, sample))
+        .await;
+}
@@ -1,9 +1,14 @@
+use crate::analog_input_monitor::{
+    analog_channel, analog_flex, is_analog_pin, monitor_analog_input, stop_analog_input, ADC,
+};
 use crate::gpio::GPIOPin::Available;
 use crate::gpio_input_monitor::monitor_input;
+use crate::sequencer;
 #[cfg(feature = "wifi")]
 use cyw43::Control;
 use defmt::{debug, error, info};
 use embassy_executor::Spawner;
+use embassy_rp::adc::{Adc, Async};
 use embassy_rp::gpio::Flex;
 use embassy_rp::gpio::Level;
 use embassy_rp::gpio::Pull;
@@ -19,11 +24,13 @@ use embassy_sync::channel::{Receiver, Sender};
 use heapless::index_map::FnvIndexMap;
 use pigdef::config::HardwareConfig;
 use pigdef::config::HardwareConfigMessage;
-use pigdef::config::HardwareConfigMessage::{IOLevelChanged, NewConfig, NewPinConfig};
+use pigdef::config::HardwareConfigMessage::{
+    AnalogSampled, DutyCycleChanged, IOLevelChanged, NewConfig, NewPinConfig,
+};
 use pigdef::config::InputPull;
 use pigdef::description::{BCMPinNumber, PinLevel};
 use pigdef::pin_function::PinFunction;
-use pigdef::pin_function::PinFunction::{Input, Output};
+use pigdef::pin_function::PinFunction::{AnalogInput, Input, Output};
 use static_cell::StaticCell;
 
 /// The configured/not-configured state of the GPIO Pins on the Pi Pico, and how to access them
@@ -41,6 +48,7 @@ enum GPIOPin<'a> {
     #[cfg(feature = "wifi")]
     CYW43Output,
     GPIOOutput(Flex<'a>),
+    AnalogInput,
 }
 
 fn into_level(value: PinLevel) -> Level {
@@ -77,6 +85,7 @@ impl Gpio {
     /// Put them all into the GPIO_PINS map, marking them as available
     /// NOTE: All pin numbers are GPIO (BCM) Pin Numbers, not physical pin numbers
     /// Take the following pins out of peripherals for use a GPIO
+    /// GP26, GP27 and GP28 can also be used as analog inputs, sampled using the `adc` ADC
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         pin_0: Peri<'static, PIN_0>,
@@ -105,6 +114,7 @@ impl Gpio {
         pin_26: Peri<'static, PIN_26>,
         pin_27: Peri<'static, PIN_27>,
         pin_28: Peri<'static, PIN_28>,
+        adc: Adc<'static, Async>,
     ) -> Self {
         let mut pins = FnvIndexMap::new();
 
@@ -137,6 +147,10 @@ impl Gpio {
         let _ = pins.insert(27, Available(Flex::new(pin_27)));
         let _ = pins.insert(28, Available(Flex::new(pin_28)));
 
+        if let Ok(mut converter) = ADC.try_lock() {
+            *converter = Some(adc);
+        }
+
         static RETURNER: StaticCell<Channel<ThreadModeRawMutex, Flex<'static>, 1>> =
             StaticCell::new();
         let returner = RETURNER.init(Channel::new());
@@ -196,6 +210,8 @@ impl Gpio {
                 Some(GPIOPin::Available(flex)) => Some(flex),
                 // Was assigned as an output - recover the Flex
                 Some(GPIOPin::GPIOOutput(flex)) => Some(flex),
+                // Was sampled as an analog input - stop sampling and recover a Flex for it
+                Some(GPIOPin::AnalogInput) => stop_analog_input(bcm_pin_number).await,
                 // The cyw43 pins cannot be changed - just used
                 #[cfg(feature = "wifi")]
                 Some(GPIOPin::CYW43Input) | Some(GPIOPin::CYW43Output) => None,
@@ -281,6 +297,69 @@ impl Gpio {
                     }
                 }
             }
+
+            Some(PinFunction::Pwm { .. }) => {
+                // PWM is not offered in the pin descriptions, so leave the pin Available
+                if let Some(flex) = flex_pin {
+                    let _ = self.pins.insert(bcm_pin_number, GPIOPin::Available(flex));
+                }
+                error!("Pin #{} - PWM is not supported", bcm_pin_number);
+            }
+
+            Some(PinFunction::I2C1_SDA) | Some(PinFunction::I2C1_SCL) => {
+                // I2C is not offered in the pin descriptions, so leave the pin Available
+                if let Some(flex) = flex_pin {
+                    let _ = self.pins.insert(bcm_pin_number, GPIOPin::Available(flex));
+                }
+                error!("Pin #{} - I2C is not supported", bcm_pin_number);
+            }
+
+            Some(PinFunction::SPI0_MOSI)
+            | Some(PinFunction::SPI0_MISO)
+            | Some(PinFunction::SPI0_SCLK(_)) => {
+                // SPI is not offered in the pin descriptions, so leave the pin Available
+                if let Some(flex) = flex_pin {
+                    let _ = self.pins.insert(bcm_pin_number, GPIOPin::Available(flex));
+                }
+                error!("Pin #{} - SPI is not supported", bcm_pin_number);
+            }
+
+            Some(PinFunction::UART0_TXD(_)) | Some(PinFunction::UART0_RXD) => {
+                // The UART is not offered in the pin descriptions, so leave the pin Available
+                if let Some(flex) = flex_pin {
+                    let _ = self.pins.insert(bcm_pin_number, GPIOPin::Available(flex));
+                }
+                error!("Pin #{} - UART is not supported", bcm_pin_number);
+            }
+
+            Some(AnalogInput { sample_rate }) => {
+                if !is_analog_pin(bcm_pin_number) {
+                    if let Some(flex) = flex_pin {
+                        let _ = self.pins.insert(bcm_pin_number, GPIOPin::Available(flex));
+                    }
+                    error!("Pin #{} - Is not an analog input", bcm_pin_number);
+                    return;
+                }
+
+                // The ADC channel takes over the pin, so the Flex using it must be dropped first
+                drop(flex_pin);
+                if let Some(channel) = analog_channel(bcm_pin_number) {
+                    match spawner.spawn(monitor_analog_input(bcm_pin_number, *sample_rate, channel))
+                    {
+                        Ok(()) => {
+                            debug!("Pin #{} Configured as analog input", bcm_pin_number);
+                            let _ = self.pins.insert(bcm_pin_number, GPIOPin::AnalogInput);
+                        }
+                        Err(e) => {
+                            // The Channel was dropped with the task, so the pin can be reused
+                            error!("Spawn Error: {}", e);
+                            if let Some(flex) = analog_flex(bcm_pin_number) {
+                                let _ = self.pins.insert(bcm_pin_number, GPIOPin::Available(flex));
+                            }
+                        }
+                    }
+                }
+            }
         }
     }
 
@@ -360,6 +439,54 @@ impl Gpio {
                     .pin_functions
                     .insert(*bcm, Output(Some(level_change.new_level)));
             }
+            HardwareConfigMessage::SafeLevelChanged(bcm, level) => {
+                // Kept in the config sent to clients, but not applied when they disconnect
+                match level {
+                    Some(level) => {
+                        let _ = hardware_config.safe_levels.insert(*bcm, *level);
+                    }
+                    None => {
+                        let _ = hardware_config.safe_levels.remove(bcm);
+                    }
+                }
+            }
+            DutyCycleChanged(bcm, _) => {
+                error!("Pin #{} - PWM is not supported", bcm);
+            }
+            HardwareConfigMessage::I2cRequest(_) => {
+                error!("I2C is not supported");
+            }
+            HardwareConfigMessage::SpiRequest(_) => {
+                error!("SPI is not supported");
+            }
+            HardwareConfigMessage::UartSend(_) => {
+                error!("UART is not supported");
+            }
+            HardwareConfigMessage::StartSequence(id, sequence) => {
+                if let Err(e) = sequencer::start_sequence(spawner, *id, sequence).await {
+                    error!("Sequence #{} - {}", id, e);
+                }
+            }
+            HardwareConfigMessage::StartSchedule(id, schedule) => {
+                if let Err(e) = sequencer::start_schedule(spawner, *id, schedule).await {
+                    error!("Schedule #{} - {}", id, e);
+                }
+            }
+            HardwareConfigMessage::StopSequence(id) => sequencer::stop(*id).await,
+            HardwareConfigMessage::GetSequenceStatus(_) => { /* Replied to by the message loop */ }
+            AnalogSampled(..)
+            | HardwareConfigMessage::I2cResponse(..)
+            | HardwareConfigMessage::SpiResponse(..)
+            | HardwareConfigMessage::UartReceived(..)
+            | HardwareConfigMessage::SequenceStatus(..) => {
+                /* Samples, responses, received bytes and sequence states are only sent from the hardware to the GUI */
+            }
+            HardwareConfigMessage::GetClientCounts | HardwareConfigMessage::ClientCounts(..) => {
+                /* Client counts are only reported by pigglet */
+            }
+            HardwareConfigMessage::Ping(..) | HardwareConfigMessage::Pong(..) => {
+                /* Heartbeats are answered by the message loop */
+            }
             HardwareConfigMessage::GetConfig => { /* Nothing to do in GPIO */ }
             HardwareConfigMessage::Disconnect => { /* Nothing to do in GPIO */ }
         }
@@ -0,0 +1,32 @@
+use embassy_time::{Duration, Instant, Timer};
+use pigdef::config::{HardwareConfigMessage, HEARTBEAT_INTERVAL_MS, MISSED_HEARTBEATS};
+
+/// How long to wait for the next message from a client that sends heartbeats
+const HEARTBEAT_TIMEOUT: Duration =
+    Duration::from_millis(HEARTBEAT_INTERVAL_MS * MISSED_HEARTBEATS as u64);
+
+/// Tracks when the next message must be received from a client, once it has sent a heartbeat,
+/// for the link to it not to be declared dead. Clients that never send heartbeats, such as older
+/// versions of piggui, are waited for as long as the connection stays open
+#[derive(Default)]
+pub struct Heartbeat {
+    deadline: Option<Instant>,
+}
+
+impl Heartbeat {
+    /// Record that `message` was received from the client, restarting the wait for the next one
+    pub fn received(&mut self, message: &HardwareConfigMessage) {
+        if self.deadline.is_some() || matches!(message, HardwareConfigMessage::Ping(_)) {
+            self.deadline = Some(Instant::now() + HEARTBEAT_TIMEOUT);
+        }
+    }
+
+    /// Wait until the client has missed [MISSED_HEARTBEATS], which never happens if it does
+    /// not send them
+    pub async fn missed(&self) {
+        match self.deadline {
+            Some(deadline) => Timer::at(deadline).await,
+            None => core::future::pending().await,
+        }
+    }
+}
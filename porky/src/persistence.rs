@@ -2,20 +2,27 @@ use crate::flash;
 use crate::flash::DbFlash;
 #[cfg(feature = "wifi")]
 use crate::ssid;
-#[cfg(feature = "wifi")]
+#[cfg(any(feature = "wifi", feature = "tcp"))]
 use defmt::error;
 use defmt::info;
 use ekv::Database;
 #[cfg(feature = "wifi")]
 use ekv::ReadError;
+#[cfg(feature = "tcp")]
+use embassy_rp::clocks::RoscRng;
 use embassy_rp::flash::{Blocking, Flash};
 use embassy_rp::peripherals::FLASH;
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use heapless::index_map::FnvIndexMap;
+#[cfg(feature = "tcp")]
+use pigdef::auth::AuthKey;
 use pigdef::config::HardwareConfig;
 use pigdef::config::HardwareConfigMessage;
 use pigdef::config::HardwareConfigMessage::{
-    Disconnect, GetConfig, IOLevelChanged, NewConfig, NewPinConfig,
+    AnalogSampled, ClientCounts, Disconnect, DutyCycleChanged, GetClientCounts, GetConfig,
+    GetSequenceStatus, I2cRequest, I2cResponse, IOLevelChanged, NewConfig, NewPinConfig, Ping,
+    Pong, SafeLevelChanged, SequenceStatus, SpiRequest, SpiResponse, StartSchedule, StartSequence,
+    StopSequence, UartReceived, UartSend,
 };
 use pigdef::description::BCMPinNumber;
 #[cfg(feature = "wifi")]
@@ -27,6 +34,10 @@ use pigdef::pin_function::PinFunction::Output;
 /// [SSID_SPEC_KEY] is the key to a possible entry in the Flash DB for SsidSpec override
 const SSID_SPEC_KEY: &[u8] = b"ssid_spec";
 
+#[cfg(feature = "tcp")]
+/// [AUTH_KEY_KEY] is the key to the entry in the Flash DB for the key TCP clients authenticate with
+const AUTH_KEY_KEY: &[u8] = b"auth_key";
+
 /// Load any pre-existing config from flash, if there is none then just return a default config
 pub async fn get_config(
     db: &Database<DbFlash<Flash<'_, FLASH, Blocking, { flash::FLASH_SIZE }>>, NoopRawMutex>,
@@ -53,7 +64,11 @@ pub async fn get_config(
         ),
     }
 
-    HardwareConfig { pin_functions }
+    // Safe levels are only applied by pigglet, so porky does not persist them
+    HardwareConfig {
+        pin_functions,
+        safe_levels: FnvIndexMap::new(),
+    }
 }
 
 pub async fn store_config_change(
@@ -93,6 +108,19 @@ pub async fn store_config_change(
                 postcard::to_slice(&pin_function, &mut buf).map_err(|_| "Deserialization error")?;
             wtx.write(&[*bcm], bytes).await.map_err(|_| "Write Error")?;
         }
+        SafeLevelChanged(..) => { /* Safe levels are not applied by porky, so not persisted */ }
+        DutyCycleChanged(..) => { /* PWM is not supported, so never persisted */ }
+        AnalogSampled(..) => { /* Samples are not part of the config */ }
+        I2cRequest(..) | I2cResponse(..) => { /* I2C requests do not change the config */ }
+        SpiRequest(..) | SpiResponse(..) => { /* SPI transfers do not change the config */ }
+        UartSend(..) | UartReceived(..) => { /* UART bytes do not change the config */ }
+        StartSequence(..)
+        | StartSchedule(..)
+        | StopSequence(..)
+        | GetSequenceStatus(..)
+        | SequenceStatus(..) => { /* Sequences are not persisted */ }
+        GetClientCounts | ClientCounts(..) => { /* Client counts are not part of the config */ }
+        Ping(..) | Pong(..) => { /* Heartbeats are not part of the config */ }
         GetConfig => { /* Nothing to do in persistence */ }
         Disconnect => { /* Nothing to do in persistence */ }
     }
@@ -156,3 +184,29 @@ pub async fn delete_ssid_spec(
         .map_err(|_| "Delete error")?;
     wtx.commit().await.map_err(|_| "Commit error")
 }
+
+#[cfg(feature = "tcp")]
+/// Return the [AuthKey] TCP clients authenticate with from the flash database, creating a new
+/// random one and storing it if there is none yet. If it cannot be stored, clients will need to
+/// pair again after a restart
+pub async fn get_or_create_auth_key(
+    db: &Database<DbFlash<Flash<'_, FLASH, Blocking, { flash::FLASH_SIZE }>>, NoopRawMutex>,
+) -> AuthKey {
+    let mut key: AuthKey = [0; 32];
+    {
+        let rtx = db.read_transaction().await;
+        if let Ok(size) = rtx.read(AUTH_KEY_KEY, &mut key).await {
+            if size == key.len() {
+                return key;
+            }
+        }
+    }
+
+    info!("No TCP auth key found in Flash database, creating a new one");
+    RoscRng.fill_bytes(&mut key);
+    let mut wtx = db.write_transaction().await;
+    if wtx.write(AUTH_KEY_KEY, &key).await.is_err() || wtx.commit().await.is_err() {
+        error!("Could not store TCP auth key in Flash database");
+    }
+    key
+}
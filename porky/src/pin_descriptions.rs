@@ -1,6 +1,6 @@
 use pigdef::config::InputPull;
 use pigdef::description::PinDescription;
-use pigdef::pin_function::PinFunction;
+use pigdef::pin_function::{PinFunction, DEFAULT_SAMPLE_RATE};
 
 #[cfg(feature = "debug-probe")]
 const PIN_1: PinDescription = PinDescription {
@@ -358,6 +358,9 @@ const PIN_31: PinDescription = PinDescription {
         PinFunction::Output(None),
         PinFunction::Input(Some(InputPull::PullUp)),
         // ADC0
+        PinFunction::AnalogInput {
+            sample_rate: DEFAULT_SAMPLE_RATE,
+        },
         // I2C1 SDA
     ],
 };
@@ -370,6 +373,9 @@ const PIN_32: PinDescription = PinDescription {
         PinFunction::Output(None),
         PinFunction::Input(Some(InputPull::PullUp)),
         // ADC1
+        PinFunction::AnalogInput {
+            sample_rate: DEFAULT_SAMPLE_RATE,
+        },
         // I2C1 SCL
     ],
 };
@@ -389,6 +395,9 @@ const PIN_34: PinDescription = PinDescription {
         PinFunction::Output(None),
         PinFunction::Input(Some(InputPull::PullUp)),
         // ADC2
+        PinFunction::AnalogInput {
+            sample_rate: DEFAULT_SAMPLE_RATE,
+        },
     ],
 };
 
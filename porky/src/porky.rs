@@ -20,6 +20,7 @@ use ekv::Database;
 use embassy_executor::Spawner;
 #[cfg(all(feature = "usb", feature = "wifi"))]
 use embassy_futures::select::{select, Either};
+use embassy_rp::adc::{Adc, Config as AdcConfig, InterruptHandler as AdcInterruptHandler};
 use embassy_rp::bind_interrupts;
 #[cfg(feature = "pico2")]
 use embassy_rp::block::ImageDef;
@@ -44,6 +45,7 @@ use pigdef::config::{HardwareConfig, HardwareConfigMessage};
 use pigdef::description::{HardwareDescription, HardwareDetails, PinDescriptionSet};
 #[cfg(all(feature = "discovery", feature = "tcp"))]
 use pigdef::description::{TCP_MDNS_SERVICE_NAME, TCP_MDNS_SERVICE_PROTOCOL};
+use pigdef::hello::{Capabilities, Hello};
 use static_cell::StaticCell;
 
 #[cfg(not(any(feature = "usb", feature = "wifi")))]
@@ -70,12 +72,21 @@ mod tcp;
 mod gpio;
 mod gpio_input_monitor;
 
+/// Sampling of analog inputs using the ADC
+mod analog_input_monitor;
+
 /// Functions for interacting with the Flash ROM
 mod flash;
 
 /// Persistence layer built on top of flash
 mod persistence;
 
+/// Running output sequences and schedules
+mod sequencer;
+
+/// Detecting clients that stop sending heartbeats
+mod heartbeat;
+
 #[cfg(all(feature = "discovery", feature = "wifi"))]
 /// Discovery via mDNS
 mod mdns;
@@ -92,16 +103,35 @@ pub static IMAGE_DEF: ImageDef = ImageDef::secure_exe();
 bind_interrupts!(struct Irqs {
     PIO0_IRQ_0 => PioInterruptHandler<PIO0>;
     USBCTRL_IRQ => USBInterruptHandler<USB>;
+    ADC_IRQ_FIFO => AdcInterruptHandler;
 });
 
 #[cfg(not(feature = "usb"))]
 bind_interrupts!(struct Irqs {
     PIO0_IRQ_0 => PioInterruptHandler<PIO0>;
+    ADC_IRQ_FIFO => AdcInterruptHandler;
 });
 
 pub static HARDWARE_EVENT_CHANNEL: Channel<ThreadModeRawMutex, HardwareConfigMessage, 1> =
     Channel::new();
 
+#[cfg(not(feature = "wifi"))]
+const CAPABILITIES: Capabilities = Capabilities::GPIO_INPUT
+    .union(Capabilities::GPIO_OUTPUT)
+    .union(Capabilities::ANALOG_INPUT)
+    .union(Capabilities::SEQUENCES)
+    .union(Capabilities::HEARTBEAT);
+#[cfg(feature = "wifi")]
+const CAPABILITIES: Capabilities = Capabilities::GPIO_INPUT
+    .union(Capabilities::GPIO_OUTPUT)
+    .union(Capabilities::ANALOG_INPUT)
+    .union(Capabilities::SEQUENCES)
+    .union(Capabilities::HEARTBEAT)
+    .union(Capabilities::WIFI);
+
+/// The [Hello] sent as the first message on every new connection to porky
+pub const HELLO: Hello = Hello::new(CAPABILITIES);
+
 /// Create a [HardwareDescription] for this device with the provided serial number
 fn hardware_description(serial: &str) -> HardwareDescription<'_> {
     let details = HardwareDetails {
@@ -246,6 +276,7 @@ async fn main(spawner: Spawner) {
         peripherals.PIN_26,
         peripherals.PIN_27,
         peripherals.PIN_28,
+        Adc::new(peripherals.ADC, Irqs, AdcConfig::default()),
     );
 
     // create hardware description
@@ -327,6 +358,13 @@ async fn main(spawner: Spawner) {
                 )
                 .await;
 
+                // Clients must authenticate with this key, or pair using the code logged
+                #[cfg(feature = "wifi")]
+                let mut tcp_auth = tcp::TcpAuth::new(
+                    persistence::get_or_create_auth_key(db).await,
+                    hw_desc.details.serial,
+                );
+
                 #[cfg(feature = "wifi")]
                 let mut wifi_tx_buffer = [0; 4096];
                 #[cfg(feature = "wifi")]
@@ -341,16 +379,18 @@ async fn main(spawner: Spawner) {
                             &mut wifi_rx_buffer,
                             hw_desc,
                             &hardware_config,
+                            &mut tcp_auth,
                         ),
                         usb::accept_connection(&mut usb_connection, &hardware_config),
                     )
                     .await
                     {
                         Either::First(socket_select) => match socket_select {
-                            Ok(socket) => {
+                            Ok((socket, decoder)) => {
                                 if let Err(e) = tcp::message_loop(
                                     &mut gpio,
                                     socket,
+                                    decoder,
                                     &mut hardware_config,
                                     &spawner,
                                     &mut control,
@@ -361,7 +401,7 @@ async fn main(spawner: Spawner) {
                                     error!("Could tcp::message_loop error: {}", e);
                                 }
                             }
-                            Err(_) => error!("TCP accept error"),
+                            Err(e) => error!("TCP accept error: {}", e),
                         },
                         Either::Second(_) => {
                             let _ = usb::message_loop(
@@ -383,14 +423,17 @@ async fn main(spawner: Spawner) {
                         network_stack,
                         &mut wifi_tx_buffer,
                         &mut wifi_rx_buffer,
+                        hw_desc,
+                        &hardware_config,
+                        &mut tcp_auth,
                     )
                     .await
                     {
-                        Ok(socket) => {
+                        Ok((socket, decoder)) => {
                             if let Err(e) = tcp::message_loop(
                                 &mut gpio,
                                 socket,
-                                hw_desc,
+                                decoder,
                                 &mut hardware_config,
                                 &spawner,
                                 &mut control,
@@ -401,7 +444,7 @@ async fn main(spawner: Spawner) {
                                 error!("Could tcp::message_loop error: {}", e);
                             }
                         }
-                        Err(_) => error!("TCP accept error"),
+                        Err(e) => error!("TCP accept error: {}", e),
                     }
                 }
             }
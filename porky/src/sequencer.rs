@@ -0,0 +1,212 @@
+use core::cell::RefCell;
+use defmt::{debug, info};
+use embassy_executor::Spawner;
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Timer};
+use pigdef::config::HardwareConfigMessage::{IOLevelChanged, SequenceStatus};
+use pigdef::config::{HardwareConfigMessage, LevelChange};
+use pigdef::description::{BCMPinNumber, PinLevel};
+use pigdef::sequence::{Schedule, Sequence, SequenceId, SequenceState, SECONDS_PER_DAY};
+
+/// The most sequences and schedules that can be running at once
+const MAX_SEQUENCES: usize = 4;
+
+/// Output changes made by running sequences and schedules, and the ends of sequences, to be
+/// applied to the hardware by the message loop and sent on to the GUI. Sequences wait for a
+/// message loop to receive them, so only advance while a GUI is connected.
+pub static SEQUENCE_CHANNEL: Channel<ThreadModeRawMutex, HardwareConfigMessage, 1> = Channel::new();
+
+/// Signals to the task running in each slot to stop
+static STOP: [Signal<ThreadModeRawMutex, ()>; MAX_SEQUENCES] =
+    [Signal::new(), Signal::new(), Signal::new(), Signal::new()];
+
+/// Signals from the task running in each slot that it has stopped and freed the slot
+static STOPPED: [Signal<ThreadModeRawMutex, ()>; MAX_SEQUENCES] =
+    [Signal::new(), Signal::new(), Signal::new(), Signal::new()];
+
+/// The id and state of the sequence or schedule running in each slot, if any
+static SLOTS: Mutex<
+    ThreadModeRawMutex,
+    RefCell<[Option<(SequenceId, SequenceState)>; MAX_SEQUENCES]>,
+> = Mutex::new(RefCell::new([None; MAX_SEQUENCES]));
+
+/// Return the slot the sequence or schedule `id` is running in, if it is running
+fn slot_of(id: SequenceId) -> Option<usize> {
+    SLOTS.lock(|slots| {
+        slots
+            .borrow()
+            .iter()
+            .position(|slot| matches!(slot, Some((slot_id, _)) if *slot_id == id))
+    })
+}
+
+/// Reserve a free slot for the sequence or schedule `id`
+fn reserve(id: SequenceId, state: SequenceState) -> Result<usize, &'static str> {
+    SLOTS.lock(|slots| {
+        let mut slots = slots.borrow_mut();
+        let slot = slots
+            .iter()
+            .position(Option::is_none)
+            .ok_or("Too many sequences are running")?;
+        slots[slot] = Some((id, state));
+        // Reset before the task is spawned, so a stop requested before it runs is not missed
+        STOP[slot].reset();
+        Ok(slot)
+    })
+}
+
+/// Free `slot` once the task running in it has stopped
+fn release(slot: usize) {
+    SLOTS.lock(|slots| slots.borrow_mut()[slot] = None);
+    STOPPED[slot].signal(());
+}
+
+/// The [SequenceState] of the sequence or schedule `id`
+pub fn status(id: SequenceId) -> SequenceState {
+    SLOTS.lock(|slots| {
+        slots
+            .borrow()
+            .iter()
+            .find_map(|slot| match slot {
+                Some((slot_id, state)) if *slot_id == id => Some(*state),
+                _ => None,
+            })
+            .unwrap_or(SequenceState::Stopped)
+    })
+}
+
+/// The id of the sequence or schedule a message from the GUI starts, stops or asks the state
+/// of, which the GUI is sent the new [SequenceState] of in reply
+pub fn sequence_id(message: &HardwareConfigMessage) -> Option<SequenceId> {
+    match message {
+        HardwareConfigMessage::StartSequence(id, _)
+        | HardwareConfigMessage::StartSchedule(id, _)
+        | HardwareConfigMessage::StopSequence(id)
+        | HardwareConfigMessage::GetSequenceStatus(id) => Some(*id),
+        _ => None,
+    }
+}
+
+/// Stop the sequence or schedule `id` if it is running, and wait for its task to end
+pub async fn stop(id: SequenceId) {
+    if let Some(slot) = slot_of(id) {
+        info!("Stopping sequence #{}", id);
+        STOPPED[slot].reset();
+        STOP[slot].signal(());
+        STOPPED[slot].wait().await;
+    }
+}
+
+/// Start running `sequence` as `id`, replacing any running with that id
+pub async fn start_sequence(
+    spawner: &Spawner,
+    id: SequenceId,
+    sequence: &Sequence,
+) -> Result<(), &'static str> {
+    sequence.check()?;
+    stop(id).await;
+    let slot = reserve(id, SequenceState::Running)?;
+    spawner
+        .spawn(run_sequence(slot, id, sequence.clone()))
+        .map_err(|_| {
+            release(slot);
+            "Could not spawn sequence"
+        })
+}
+
+/// Start running `schedule` as `id`, replacing any running with that id
+pub async fn start_schedule(
+    spawner: &Spawner,
+    id: SequenceId,
+    schedule: &Schedule,
+) -> Result<(), &'static str> {
+    schedule.check()?;
+    stop(id).await;
+    let slot = reserve(id, SequenceState::Scheduled)?;
+    spawner
+        .spawn(run_schedule(slot, schedule.clone()))
+        .map_err(|_| {
+            release(slot);
+            "Could not spawn schedule"
+        })
+}
+
+/// Run the steps of `sequence` in `slot`, then tell the GUI it has finished, unless stopped
+#[embassy_executor::task(pool_size = MAX_SEQUENCES)]
+async fn run_sequence(slot: usize, id: SequenceId, sequence: Sequence) {
+    if sequence_steps(slot, &sequence).await.is_some() {
+        info!("Sequence #{} finished", id);
+        let _ = send(slot, SequenceStatus(id, SequenceState::Stopped)).await;
+    }
+    release(slot);
+}
+
+/// Run `schedule` in `slot` until stopped
+#[embassy_executor::task(pool_size = MAX_SEQUENCES)]
+async fn run_schedule(slot: usize, schedule: Schedule) {
+    let _ = schedule_entries(slot, &schedule).await;
+    release(slot);
+}
+
+/// Set the outputs in the steps of `sequence`, returning None if stopped before the end.
+/// Delays are counted from when the previous step was due, so they do not drift.
+async fn sequence_steps(slot: usize, sequence: &Sequence) -> Option<()> {
+    let mut deadline = Instant::now();
+    let mut repeats = 0;
+    while sequence.repeat == 0 || repeats < sequence.repeat {
+        for step in &sequence.steps {
+            set_output(slot, step.bcm_pin_number, step.level).await?;
+            deadline += Duration::from_millis(step.delay_ms.into());
+            wait_until(slot, deadline).await?;
+        }
+        repeats += 1;
+    }
+    Some(())
+}
+
+/// Set the outputs to the levels they would be at now, had `schedule` been running all along,
+/// then set them at the time of each entry until stopped, when None is returned
+async fn schedule_entries(slot: usize, schedule: &Schedule) -> Option<()> {
+    let mut time_of_day = schedule.now;
+    let mut deadline = Instant::now();
+    for (bcm_pin_number, level) in schedule.levels_at(time_of_day) {
+        set_output(slot, bcm_pin_number, level).await?;
+    }
+    while let Some(seconds) = schedule.seconds_until_next(time_of_day) {
+        deadline += Duration::from_secs(seconds.into());
+        wait_until(slot, deadline).await?;
+        time_of_day = (time_of_day + seconds) % SECONDS_PER_DAY;
+        for entry in schedule.entries_at(time_of_day) {
+            set_output(slot, entry.bcm_pin_number, entry.level).await?;
+        }
+    }
+    Some(())
+}
+
+/// Wait until `deadline`, returning None if the task in `slot` is stopped first
+async fn wait_until(slot: usize, deadline: Instant) -> Option<()> {
+    match select(Timer::at(deadline), STOP[slot].wait()).await {
+        Either::First(()) => Some(()),
+        Either::Second(()) => None,
+    }
+}
+
+/// Send an output change to the message loop, timestamped with the Duration since boot
+async fn set_output(slot: usize, bcm_pin_number: BCMPinNumber, level: PinLevel) -> Option<()> {
+    debug!("Sequence setting Pin #{} to {}", bcm_pin_number, level);
+    let level_change = LevelChange::new(level, Instant::now().duration_since(Instant::MIN).into());
+    send(slot, IOLevelChanged(bcm_pin_number, level_change)).await
+}
+
+/// Send `message` to the message loop, returning None if the task in `slot` is stopped first,
+/// as the message loop may be waiting for it to stop
+async fn send(slot: usize, message: HardwareConfigMessage) -> Option<()> {
+    match select(SEQUENCE_CHANNEL.sender().send(message), STOP[slot].wait()).await {
+        Either::First(()) => Some(()),
+        Either::Second(()) => None,
+    }
+}
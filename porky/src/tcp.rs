@@ -1,24 +1,68 @@
 use crate::flash::DbFlash;
 use crate::gpio::Gpio;
-use crate::{flash, persistence, HARDWARE_EVENT_CHANNEL};
+use crate::heartbeat::Heartbeat;
+use crate::sequencer::SEQUENCE_CHANNEL;
+use crate::{flash, persistence, sequencer, HARDWARE_EVENT_CHANNEL, HELLO};
 use cyw43::Control;
-use defmt::info;
+use defmt::{error, info};
 use ekv::Database;
 use embassy_executor::Spawner;
-use embassy_futures::select::{select, Either};
+use embassy_futures::select::{select4, Either4};
 use embassy_net::tcp::client::{TcpClient, TcpClientState};
 use embassy_net::tcp::TcpSocket;
 use embassy_net::Stack;
+use embassy_rp::clocks::RoscRng;
 use embassy_rp::flash::{Blocking, Flash};
 use embassy_rp::peripherals::FLASH;
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_time::{with_timeout, Duration};
 use embedded_io_async::Write;
+use pigdef::auth::{
+    AuthKey, AuthResponse, AuthResult, Challenge, Nonce, PairingCode, PairingSecret,
+};
 use pigdef::config::{HardwareConfig, HardwareConfigMessage};
 use pigdef::description::HardwareDescription;
+use pigdef::framing;
+use pigdef::framing::{FrameDecoder, FramingError};
+use pigdef::hello::{Capabilities, Hello};
+use serde::de::DeserializeOwned;
 use serde::Serialize;
 
 pub const TCP_PORT: u16 = 1234;
 
+/// The [Hello] sent to TCP clients, that must authenticate before they are sent anything else
+const TCP_HELLO: Hello = Hello::new(HELLO.capabilities.union(Capabilities::AUTH));
+
+/// How long a new client has to reply to the [Challenge]
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The key TCP clients authenticate with, stored in Flash, and the secret of the pairing code
+/// new clients can pair with, which is shown in the logs
+pub struct TcpAuth {
+    key: AuthKey,
+    secret: PairingSecret,
+}
+
+impl TcpAuth {
+    /// Create a [TcpAuth] for `key`, with a new pairing code for the device with `serial`
+    pub fn new(key: AuthKey, serial: &str) -> Self {
+        TcpAuth {
+            key,
+            secret: new_pairing_secret(serial),
+        }
+    }
+}
+
+/// Create a new random [PairingCode] and log it, so the user can enter it in the client, then
+/// return its [PairingSecret] for the device with `serial`
+fn new_pairing_secret(serial: &str) -> PairingSecret {
+    let mut random = [0; 8];
+    RoscRng.fill_bytes(&mut random);
+    let code = PairingCode::new(random);
+    info!("Pairing code: {}", code.as_str());
+    code.secret(serial)
+}
+
 /// Send the [HardwareDescription] and [HardwareConfig] over the [TcpSocket]
 async fn send_hardware_description_and_config(
     socket: &mut TcpSocket<'_>,
@@ -26,7 +70,7 @@ async fn send_hardware_description_and_config(
     hw_config: &HardwareConfig,
 ) -> Result<(), &'static str> {
     let mut hw_buf = [0; 2048];
-    let slice = postcard::to_slice(&(hw_desc, hw_config), &mut hw_buf)
+    let slice = framing::encode(&(hw_desc, hw_config), &mut hw_buf)
         .map_err(|_| "Could not serialize hw config and desc")?;
     info!("Sending hardware description (length: {})", slice.len());
     socket
@@ -38,35 +82,79 @@ async fn send_hardware_description_and_config(
 /// Send a serializable message over TCP to the GUI
 async fn send(socket: &mut TcpSocket<'_>, msg: impl Serialize) -> Result<(), &'static str> {
     let mut buf = [0; 1024];
-    let gui_message = postcard::to_slice(&msg, &mut buf).map_err(|_| "Serialization error")?;
+    let gui_message = framing::encode(&msg, &mut buf).map_err(|_| "Serialization error")?;
     socket
         .write_all(gui_message)
         .await
         .map_err(|_| "TCP Write error")
 }
 
-/// Wait until a config message in received on the [TcpSocket] then deserialize it and return it
-/// or return `None` if the connection was broken
-async fn wait_message(socket: &mut TcpSocket<'_>) -> Option<HardwareConfigMessage> {
-    let mut buf = [0; 4096];
+/// Wait until a message is received on the [TcpSocket] then deserialize it and return it
+/// or return `None` if the connection was broken. Bytes received are accumulated in `decoder`,
+/// so none are lost if this is cancelled, and messages that arrive together are all returned
+async fn wait_message<T: DeserializeOwned>(
+    socket: &mut TcpSocket<'_>,
+    decoder: &mut FrameDecoder,
+) -> Option<T> {
+    loop {
+        match decoder.decode() {
+            Ok(Some(message)) => return Some(message),
+            Ok(None) => {}
+            Err(FramingError::Deserialization) => error!("Could not deserialize message"),
+            Err(_) => {
+                error!("Framing error, closing connection");
+                return None;
+            }
+        }
 
-    let n = socket.read(&mut buf).await.ok()?;
-    if n == 0 {
-        info!("Connection broken");
-        return None;
+        let n = socket.read(decoder.spare()).await.ok()?;
+        if n == 0 {
+            info!("Connection broken");
+            return None;
+        }
+        decoder.filled(n);
     }
+}
+
+/// Challenge a new client to prove it has the key, or knows the pairing code, of `auth`.
+/// A new pairing code is created after a client pairs, so each code can only be used once
+async fn authenticate(
+    socket: &mut TcpSocket<'_>,
+    decoder: &mut FrameDecoder,
+    auth: &mut TcpAuth,
+    serial: &str,
+) -> Result<(), &'static str> {
+    let mut nonce: Nonce = [0; 16];
+    RoscRng.fill_bytes(&mut nonce);
+    send(socket, Challenge { serial, nonce }).await?;
+    let response: AuthResponse = with_timeout(AUTH_TIMEOUT, wait_message(socket, decoder))
+        .await
+        .map_err(|_| "Timeout waiting for client to authenticate")?
+        .ok_or("Connection broken while authenticating")?;
 
-    postcard::from_bytes(&buf[..n]).ok()
+    let result = pigdef::auth::check(&response, &auth.key, &auth.secret, &nonce);
+    send(socket, result).await?;
+    match result {
+        AuthResult::Accepted => Ok(()),
+        AuthResult::Paired(_) => {
+            info!("Paired with a new TCP client");
+            auth.secret = new_pairing_secret(serial);
+            Ok(())
+        }
+        AuthResult::Rejected => Err("TCP client could not authenticate"),
+    }
 }
 
-/// Accept a TCP connection to this device, then respond to it with the [HardwareDescription]
+/// Accept a TCP connection to this device, check it authenticates, then respond to it with the
+/// [HardwareDescription]. The [FrameDecoder] returned holds any bytes already received from it
 pub async fn accept_connection<'a>(
     wifi_stack: Stack<'static>,
     wifi_rx_buffer: &'a mut [u8],
     wifi_tx_buffer: &'a mut [u8],
     hw_desc: &HardwareDescription<'_>,
     hw_config: &HardwareConfig,
-) -> Result<TcpSocket<'a>, &'static str> {
+    auth: &mut TcpAuth,
+) -> Result<(TcpSocket<'a>, FrameDecoder), &'static str> {
     // TODO check these are needed
     let client_state: TcpClientState<2, 1024, 1024> = TcpClientState::new();
     let _client = TcpClient::new(wifi_stack, &client_state);
@@ -76,17 +164,23 @@ pub async fn accept_connection<'a>(
         .accept(TCP_PORT)
         .await
         .map_err(|_| "TCP Accept error")?;
+    send(&mut tcp_socket, TCP_HELLO).await?;
+    let mut decoder: FrameDecoder = FrameDecoder::new();
+    authenticate(&mut tcp_socket, &mut decoder, auth, hw_desc.details.serial).await?;
     send_hardware_description_and_config(&mut tcp_socket, hw_desc, hw_config).await?;
-    Ok(tcp_socket)
+    Ok((tcp_socket, decoder))
 }
 
-/// Enter a loop waiting for messages either via TCP (from Piggui) or from the Hardware.
+/// Enter a loop waiting for messages via TCP (from Piggui), from the Hardware, or from running
+/// sequences.
 /// - When receive a message over TCP from Piggui, apply it to the hardware, save in Flash
 /// - When receive a message from hardware, send the message to Piggui over TCP
+/// - When receive an output change from a sequence, apply it to the hardware and send it to Piggui
 /// - Exit when receive the Disconnect message
 pub async fn message_loop(
     gpio: &mut Gpio,
     mut socket: TcpSocket<'_>,
+    mut decoder: FrameDecoder,
     hw_config: &mut HardwareConfig,
     spawner: &Spawner,
     control: &mut Control<'_>,
@@ -96,19 +190,30 @@ pub async fn message_loop(
     >,
 ) -> Result<(), &'static str> {
     info!("Entering TCP message loop");
+    let mut heartbeat = Heartbeat::default();
     loop {
-        match select(
-            wait_message(&mut socket),
+        match select4(
+            wait_message::<HardwareConfigMessage>(&mut socket, &mut decoder),
             HARDWARE_EVENT_CHANNEL.receiver().receive(),
+            SEQUENCE_CHANNEL.receiver().receive(),
+            heartbeat.missed(),
         )
         .await
         {
-            Either::First(config_message) => match config_message {
+            Either4::First(config_message) => match config_message {
                 None => break,
                 Some(hardware_config_message) => {
-                    if matches!(hardware_config_message, HardwareConfigMessage::Disconnect) {
-                        info!("TCP Disconnect, exiting TCP Message loop");
-                        return Ok(());
+                    heartbeat.received(&hardware_config_message);
+                    match hardware_config_message {
+                        HardwareConfigMessage::Disconnect => {
+                            info!("TCP Disconnect, exiting TCP Message loop");
+                            return Ok(());
+                        }
+                        HardwareConfigMessage::Ping(sequence) => {
+                            send(&mut socket, HardwareConfigMessage::Pong(sequence)).await?;
+                            continue;
+                        }
+                        _ => {}
                     }
                     gpio.apply_config_change(control, spawner, &hardware_config_message, hw_config)
                         .await;
@@ -116,11 +221,23 @@ pub async fn message_loop(
                     if matches!(hardware_config_message, HardwareConfigMessage::GetConfig) {
                         send(&mut socket, hw_config.clone()).await?;
                     }
+                    if let Some(id) = sequencer::sequence_id(&hardware_config_message) {
+                        let status =
+                            HardwareConfigMessage::SequenceStatus(id, sequencer::status(id));
+                        send(&mut socket, status).await?;
+                    }
                 }
             },
-            Either::Second(hardware_config_message) => {
+            Either4::Second(hardware_config_message) => {
                 send(&mut socket, hardware_config_message.clone()).await?;
             }
+            Either4::Third(sequence_message) => {
+                // Output changes made by sequences are too frequent to save in Flash
+                gpio.apply_config_change(control, spawner, &sequence_message, hw_config)
+                    .await;
+                send(&mut socket, sequence_message).await?;
+            }
+            Either4::Fourth(()) => return Err("Missed heartbeats, exiting TCP Message loop"),
         }
     }
     info!("Exiting Message Loop");
@@ -1,8 +1,11 @@
 use crate::flash;
 use crate::flash::DbFlash;
 use crate::gpio::Gpio;
+use crate::heartbeat::Heartbeat;
 use crate::persistence;
-use crate::HARDWARE_EVENT_CHANNEL;
+use crate::sequencer;
+use crate::sequencer::SEQUENCE_CHANNEL;
+use crate::{HARDWARE_EVENT_CHANNEL, HELLO};
 use core::str;
 #[cfg(feature = "wifi")]
 use cyw43::Control;
@@ -10,7 +13,7 @@ use defmt::{error, info, unwrap};
 use ekv::Database;
 use embassy_executor::Spawner;
 use embassy_futures::block_on;
-use embassy_futures::select::{select, Either};
+use embassy_futures::select::{select4, Either4};
 use embassy_rp::flash::{Blocking, Flash};
 use embassy_rp::peripherals::FLASH;
 use embassy_rp::peripherals::USB;
@@ -26,14 +29,14 @@ use embassy_usb::msos::windows_version;
 use embassy_usb::types::InterfaceNumber;
 use embassy_usb::{msos, Handler, UsbDevice};
 use embassy_usb::{Builder, Config};
-use pigdef::config::HardwareConfigMessage::{Disconnect, GetConfig};
+use pigdef::config::HardwareConfigMessage::{Disconnect, GetConfig, Ping, Pong, SequenceStatus};
 use pigdef::config::{HardwareConfig, HardwareConfigMessage};
 use pigdef::description::HardwareDescription;
 #[cfg(feature = "wifi")]
 use pigdef::description::{SsidSpec, WiFiDetails};
 use pigdef::usb_values::{
-    GET_HARDWARE_DESCRIPTION_VALUE, GET_HARDWARE_DETAILS_VALUE, HW_CONFIG_MESSAGE, PIGGUI_REQUEST,
-    USB_PACKET_SIZE,
+    GET_HARDWARE_DESCRIPTION_VALUE, GET_HARDWARE_DETAILS_VALUE, GET_HELLO_VALUE, HW_CONFIG_MESSAGE,
+    PIGGUI_REQUEST, USB_PACKET_SIZE,
 };
 #[cfg(feature = "wifi")]
 use pigdef::usb_values::{GET_WIFI_VALUE, RESET_SSID_VALUE, SET_SSID_VALUE};
@@ -77,7 +80,8 @@ fn get_usb_builder(
 
     static CONFIG_DESC: StaticCell<[u8; 256]> = StaticCell::new();
     static BOS_DESC: StaticCell<[u8; 256]> = StaticCell::new();
-    static CONTROL_BUF: StaticCell<[u8; 128]> = StaticCell::new();
+    // Large enough for a HardwareConfigMessage starting a sequence with the most steps
+    static CONTROL_BUF: StaticCell<[u8; 512]> = StaticCell::new();
     static MSOS_DESC: StaticCell<[u8; 256]> = StaticCell::new();
     Builder::new(
         driver,
@@ -85,7 +89,7 @@ fn get_usb_builder(
         &mut CONFIG_DESC.init([0; 256])[..],
         &mut BOS_DESC.init([0; 256])[..],
         &mut MSOS_DESC.init([0; 256])[..],
-        &mut CONTROL_BUF.init([0; 128])[..],
+        &mut CONTROL_BUF.init([0; 512])[..],
     )
 }
 
@@ -190,6 +194,7 @@ impl Handler for ControlHandler<'_> {
 
         // Respond to valid requests from piggui
         let msg = match (req.request, req.value) {
+            (PIGGUI_REQUEST, GET_HELLO_VALUE) => postcard::to_slice(&HELLO, &mut self.buf).ok()?,
             (PIGGUI_REQUEST, GET_HARDWARE_DESCRIPTION_VALUE) => {
                 postcard::to_slice(self.hardware_description, &mut self.buf).ok()?
             }
@@ -338,7 +343,8 @@ pub async fn accept_connection(
 /// Enter a loop waiting for messages either via USB (from Piggui) or from the Hardware.
 /// - When receive a message over USB from Piggui, apply it to the hardware, save in Flash
 /// - When receive a message from hardware, send the message to Piggui over USB
-/// - Exit when receive the Disconnect message
+/// - When receive a Ping from Piggui, send back a Pong
+/// - Exit when receive the Disconnect message, or Piggui stops sending Pings
 pub async fn message_loop(
     gpio: &mut Gpio,
     usb_connection: &mut UsbConnection<Endpoint<'static, USB, In>, Endpoint<'static, USB, Out>>,
@@ -355,17 +361,28 @@ pub async fn message_loop(
     // Clear out any level change messages sent before the GUI app connected
     HARDWARE_EVENT_CHANNEL.clear();
 
+    let mut heartbeat = Heartbeat::default();
     loop {
-        match select(
+        match select4(
             USB_MESSAGE_CHANNEL.receiver().receive(),
             HARDWARE_EVENT_CHANNEL.receiver().receive(),
+            SEQUENCE_CHANNEL.receiver().receive(),
+            heartbeat.missed(),
         )
         .await
         {
-            Either::First(hardware_config_message) => {
-                if matches!(hardware_config_message, Disconnect) {
-                    info!("USB Disconnect, exiting USB Message loop");
-                    return Ok(());
+            Either4::First(hardware_config_message) => {
+                heartbeat.received(&hardware_config_message);
+                match hardware_config_message {
+                    Disconnect => {
+                        info!("USB Disconnect, exiting USB Message loop");
+                        return Ok(());
+                    }
+                    Ping(sequence) => {
+                        usb_connection.send(Pong(sequence)).await?;
+                        continue;
+                    }
+                    _ => {}
                 }
                 gpio.apply_config_change(
                     #[cfg(feature = "wifi")]
@@ -379,10 +396,28 @@ pub async fn message_loop(
                 if matches!(hardware_config_message, GetConfig) {
                     usb_connection.send(&hw_config).await?;
                 }
+                if let Some(id) = sequencer::sequence_id(&hardware_config_message) {
+                    usb_connection
+                        .send(SequenceStatus(id, sequencer::status(id)))
+                        .await?;
+                }
             }
-            Either::Second(hardware_event) => {
+            Either4::Second(hardware_event) => {
                 usb_connection.send(hardware_event).await?;
             }
+            Either4::Third(sequence_message) => {
+                // Output changes made by sequences are too frequent to save in Flash
+                gpio.apply_config_change(
+                    #[cfg(feature = "wifi")]
+                    control,
+                    spawner,
+                    &sequence_message,
+                    hw_config,
+                )
+                .await;
+                usb_connection.send(sequence_message).await?;
+            }
+            Either4::Fourth(()) => return Err("Missed heartbeats, exiting USB Message loop"),
         }
     }
 }